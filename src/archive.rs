@@ -0,0 +1,152 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl Format {
+    pub const NAMES: [&'static str; 3] = ["tar", "tar.gz", "zip"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tar" => Some(Format::Tar),
+            "tar.gz" | "tgz" => Some(Format::TarGz),
+            "zip" => Some(Format::Zip),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the file name of `path`.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_lowercase();
+        [".tar.gz", ".tgz", ".tar", ".zip"]
+            .iter()
+            .find(|x| name.ends_with(*x))
+            .and_then(|x| Self::from_name(&x[1..]))
+    }
+}
+
+enum Inner {
+    Tar(tar::Builder<File>),
+    TarGz(Box<tar::Builder<GzEncoder<File>>>),
+    Zip(Box<ZipWriter<File>>),
+}
+
+/// Writes files into an archive under their relative destination paths.
+pub struct ArchiveWriter {
+    inner: Inner,
+}
+
+impl ArchiveWriter {
+    pub fn create(path: &Path, format: Format) -> io::Result<Self> {
+        let f = File::create(path)?;
+        let inner = match format {
+            Format::Tar => Inner::Tar(tar::Builder::new(f)),
+            Format::TarGz => {
+                let gz = GzEncoder::new(f, Compression::default());
+                Inner::TarGz(Box::new(tar::Builder::new(gz)))
+            }
+            Format::Zip => Inner::Zip(Box::new(ZipWriter::new(f))),
+        };
+
+        Ok(Self { inner })
+    }
+
+    /// Add `src` as `name`, returns the number of bytes stored.
+    pub fn append(&mut self, src: &Path, name: &Path) -> io::Result<u64> {
+        let meta = std::fs::metadata(src)?;
+
+        match &mut self.inner {
+            Inner::Tar(b) => b.append_path_with_name(src, name)?,
+            Inner::TarGz(b) => b.append_path_with_name(src, name)?,
+            Inner::Zip(z) => {
+                let options =
+                    SimpleFileOptions::default().unix_permissions(crate::copy::mode(&meta));
+                z.start_file(entry_name(name), options)?;
+                io::copy(&mut File::open(src)?, z.as_mut())?;
+            }
+        }
+
+        Ok(meta.len())
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self.inner {
+            Inner::Tar(b) => b.into_inner().map(|_| ()),
+            Inner::TarGz(b) => b.into_inner()?.finish().map(|_| ()),
+            Inner::Zip(z) => z.finish().map(|_| ()).map_err(io::Error::from),
+        }
+    }
+}
+
+/// Entry name as stored in zip archives, always separated by `/`.
+fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter(|x| !matches!(x, Component::CurDir))
+        .map(|x| x.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Read the entries `names` out of the archive at `path` without unpacking
+/// anything else; `write` is called with the index of the name and the
+/// entry content. Entries missing from the archive have no result.
+pub fn extract<F>(
+    path: &Path,
+    format: Format,
+    names: &[PathBuf],
+    mut write: F,
+) -> io::Result<Vec<Option<io::Result<u64>>>>
+where
+    F: FnMut(usize, &mut dyn Read) -> io::Result<u64>,
+{
+    let index: HashMap<_, _> = names
+        .iter()
+        .enumerate()
+        .map(|(i, x)| (entry_name(x), i))
+        .collect();
+    let mut results: Vec<_> = names.iter().map(|_| None).collect();
+
+    let f = File::open(path)?;
+    let tar = match format {
+        Format::Tar => Some(Box::new(f) as Box<dyn Read>),
+        Format::TarGz => Some(Box::new(GzDecoder::new(f)) as Box<dyn Read>),
+        Format::Zip => {
+            let mut archive = ZipArchive::new(f)?;
+            for (name, &i) in &index {
+                match archive.by_name(name) {
+                    Ok(mut entry) => results[i] = Some(write(i, &mut entry)),
+                    Err(zip::result::ZipError::FileNotFound) => {}
+                    Err(e) => results[i] = Some(Err(e.into())),
+                }
+            }
+            None
+        }
+    };
+
+    if let Some(reader) = tar {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            if let Some(&i) = index.get(&entry_name(&entry.path()?)) {
+                results[i] = Some(write(i, &mut entry));
+            }
+        }
+    }
+
+    Ok(results)
+}
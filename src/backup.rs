@@ -0,0 +1,159 @@
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Suffix of --backup without one, as cp has it.
+pub const SUFFIX: &str = "~";
+
+/// Where a destination file goes before it is overwritten or deleted,
+/// --backup and --trash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backup {
+    /// Renamed to its name with this suffix, replacing an older backup.
+    Suffix(String),
+    /// Moved to the trash of the user.
+    Trash,
+}
+
+impl Backup {
+    /// Move the file `dst` out of the way, returns where to.
+    pub fn keep(&self, dst: &Path) -> io::Result<PathBuf> {
+        let to = match self {
+            Backup::Suffix(suffix) => {
+                let mut name = dst.as_os_str().to_owned();
+                name.push(suffix);
+                PathBuf::from(name)
+            }
+            Backup::Trash => trash_path(dst)?,
+        };
+        if let Err(e) = move_file(dst, &to) {
+            // free the name again
+            if let Backup::Trash = self {
+                let _ = std::fs::remove_file(reservation(&to));
+            }
+            return Err(e);
+        }
+        Ok(to)
+    }
+
+    /// Whether `path` is a backup made by --backup, which --delete leaves.
+    pub fn is_backup(&self, path: &Path) -> bool {
+        match self {
+            Backup::Suffix(suffix) => path.to_string_lossy().ends_with(suffix.as_str()),
+            Backup::Trash => false,
+        }
+    }
+}
+
+/// Rename `from` to `to`, or where they are on different filesystems
+/// copy and remove it.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)
+}
+
+/// Directory the files of the trash are in, and beside it for the
+/// Freedesktop trash that of their `.trashinfo`.
+fn trash_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "macos") {
+        return dirs::home_dir().map(|x| x.join(".Trash"));
+    }
+    if cfg!(unix) {
+        return dirs::data_dir().map(|x| x.join("Trash").join("files"));
+    }
+    Some(crate::state::state_dir().join("trash"))
+}
+
+/// Whether the trash is the Freedesktop one, with `.trashinfo` files.
+fn freedesktop() -> bool {
+    cfg!(unix) && !cfg!(target_os = "macos")
+}
+
+/// The file created to reserve the name `trashed`, its `.trashinfo` in
+/// the Freedesktop trash, else an empty file the trashed one replaces.
+fn reservation(trashed: &Path) -> PathBuf {
+    match freedesktop() {
+        true => info_path(trashed),
+        false => trashed.to_path_buf(),
+    }
+}
+
+/// A name in the trash for `dst` that no other file has, reserved so
+/// workers trashing files of the same name at once each get their own.
+fn trash_path(dst: &Path) -> io::Result<PathBuf> {
+    let dir = trash_dir().ok_or_else(|| io::Error::other("no home directory for the trash"))?;
+    std::fs::create_dir_all(&dir)?;
+    if freedesktop() {
+        std::fs::create_dir_all(dir.with_file_name("info"))?;
+    }
+
+    let name = dst.file_name().unwrap_or(dst.as_os_str());
+    for i in 1..=10_000 {
+        let mut x = OsString::from(name);
+        if i > 1 {
+            x.push(format!(".{}", i));
+        }
+        let to = dir.join(x);
+        // a file trashed without its `.trashinfo`
+        if freedesktop() && std::fs::symlink_metadata(&to).is_ok() {
+            continue;
+        }
+
+        let reserved = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(reservation(&to));
+        match reserved {
+            Ok(mut f) => {
+                if freedesktop() {
+                    if let Err(e) = write_info(dst, &mut f) {
+                        let _ = std::fs::remove_file(reservation(&to));
+                        return Err(e);
+                    }
+                }
+                return Ok(to);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::other(format!(
+        "no free name for {} in the trash {}",
+        name.to_string_lossy(),
+        dir.display()
+    )))
+}
+
+/// The `.trashinfo` of the file `trashed` of the Freedesktop trash.
+fn info_path(trashed: &Path) -> PathBuf {
+    let mut name = trashed.file_name().unwrap_or_default().to_owned();
+    name.push(".trashinfo");
+    let files = trashed.parent().unwrap_or(trashed);
+    files.with_file_name("info").join(name)
+}
+
+/// Record to `info` where `dst` was trashed from, so file managers can
+/// restore it.
+fn write_info(dst: &Path, info: &mut File) -> io::Result<()> {
+    let dst = std::path::absolute(dst)?;
+    let path: String = dst
+        .to_string_lossy()
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect();
+    let t = crate::schedule::local_time(crate::history::now());
+    write!(
+        info,
+        "[Trash Info]\nPath={}\nDeletionDate={:04}-{:02}-{:02}T{:02}:{:02}:{:02}\n",
+        path, t.year, t.month, t.mday, t.hour, t.minute, t.second
+    )
+}
@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::copy::{Copier, Engine};
+use crate::profile::FileProfile;
+
+/// Parallel copies `cpx bench` tries without --levels.
+pub const LEVELS: [usize; 4] = [1, 2, 4, 8];
+
+/// One way of copying the files, timed by `cpx bench`.
+#[derive(Debug, Clone, Copy)]
+pub struct Trial {
+    pub engine: Engine,
+    pub jobs: usize,
+}
+
+/// Each engine copying one file at a time, then auto at each of
+/// `levels` parallel copies.
+pub fn trials(levels: &[usize]) -> Vec<Trial> {
+    let engines = Engine::NAMES.iter().map(|x| Trial {
+        engine: Engine::from_name(x).unwrap(),
+        jobs: 1,
+    });
+    let levels = levels.iter().filter(|x| **x > 1).map(|x| Trial {
+        engine: Engine::Auto,
+        jobs: *x,
+    });
+    engines.chain(levels).collect()
+}
+
+/// Read the sources of `files` once, so each trial reads them from the
+/// page cache alike, and return their size.
+pub fn warm(files: &[(PathBuf, PathBuf)]) -> io::Result<u64> {
+    let mut total = 0;
+    for (src, _) in files {
+        total += io::copy(&mut File::open(src)?, &mut io::sink())?;
+    }
+    Ok(total)
+}
+
+/// Copy `files`, each a source and its path below `scratch`, by `copier`
+/// with `jobs` copies at a time into `scratch`, which is removed again.
+/// The directories are made before the clock starts.
+pub fn run(
+    copier: &Copier,
+    files: &[(PathBuf, PathBuf)],
+    scratch: &Path,
+    jobs: usize,
+) -> io::Result<Duration> {
+    for (_, relative) in files {
+        if let Some(dir) = scratch.join(relative).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let error = Mutex::new(None);
+    let start = Instant::now();
+    std::thread::scope(|s| {
+        for _ in 0..jobs.max(1) {
+            s.spawn(|| {
+                while let Some((src, relative)) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let mut prof = FileProfile::new(src);
+                    if let Err(e) = copier.copy(src, &scratch.join(relative), &mut prof) {
+                        *error.lock().unwrap() = Some(e);
+                        // the other threads stop at their next file
+                        next.store(files.len(), Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+    let elapsed = start.elapsed();
+
+    let removed = std::fs::remove_dir_all(scratch);
+    match error.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => removed.map(|_| elapsed),
+    }
+}
@@ -0,0 +1,309 @@
+use std::convert::TryInto;
+
+const IV: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+const PERMUTATION: [usize; 16] = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
+
+const CHUNK_START: u32 = 1;
+const CHUNK_END: u32 = 2;
+const PARENT: u32 = 4;
+const ROOT: u32 = 8;
+
+const BLOCK_LEN: usize = 64;
+const CHUNK_LEN: usize = 1024;
+
+fn g(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(x);
+    s[d] = (s[d] ^ s[a]).rotate_right(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(12);
+    s[a] = s[a].wrapping_add(s[b]).wrapping_add(y);
+    s[d] = (s[d] ^ s[a]).rotate_right(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_right(7);
+}
+
+fn compress(cv: &[u32; 8], block: &[u32; 16], counter: u64, len: u32, flags: u32) -> [u32; 16] {
+    let mut s = [
+        cv[0],
+        cv[1],
+        cv[2],
+        cv[3],
+        cv[4],
+        cv[5],
+        cv[6],
+        cv[7],
+        IV[0],
+        IV[1],
+        IV[2],
+        IV[3],
+        counter as u32,
+        (counter >> 32) as u32,
+        len,
+        flags,
+    ];
+    let mut m = *block;
+
+    for round in 0..7 {
+        g(&mut s, 0, 4, 8, 12, m[0], m[1]);
+        g(&mut s, 1, 5, 9, 13, m[2], m[3]);
+        g(&mut s, 2, 6, 10, 14, m[4], m[5]);
+        g(&mut s, 3, 7, 11, 15, m[6], m[7]);
+        g(&mut s, 0, 5, 10, 15, m[8], m[9]);
+        g(&mut s, 1, 6, 11, 12, m[10], m[11]);
+        g(&mut s, 2, 7, 8, 13, m[12], m[13]);
+        g(&mut s, 3, 4, 9, 14, m[14], m[15]);
+        if round < 6 {
+            let mut permuted = [0; 16];
+            for (x, i) in permuted.iter_mut().zip(PERMUTATION) {
+                *x = m[i];
+            }
+            m = permuted;
+        }
+    }
+
+    for i in 0..8 {
+        s[i] ^= s[i + 8];
+        s[i + 8] ^= cv[i];
+    }
+    s
+}
+
+fn first_8(x: [u32; 16]) -> [u32; 8] {
+    x[..8].try_into().unwrap()
+}
+
+fn words(block: &[u8; BLOCK_LEN]) -> [u32; 16] {
+    let mut out = [0; 16];
+    for (x, bytes) in out.iter_mut().zip(block.chunks_exact(4)) {
+        *x = u32::from_le_bytes(bytes.try_into().unwrap());
+    }
+    out
+}
+
+/// What is compressed last for a chunk or a parent, either into the
+/// chaining value of its parent or into the hash.
+struct Node {
+    cv: [u32; 8],
+    block: [u32; 16],
+    counter: u64,
+    len: u32,
+    flags: u32,
+}
+
+impl Node {
+    fn chaining_value(&self) -> [u32; 8] {
+        first_8(compress(
+            &self.cv,
+            &self.block,
+            self.counter,
+            self.len,
+            self.flags,
+        ))
+    }
+
+    fn root(&self) -> [u8; 32] {
+        let words = compress(&self.cv, &self.block, 0, self.len, self.flags | ROOT);
+        let mut out = [0; 32];
+        for (bytes, x) in out.chunks_exact_mut(4).zip(words) {
+            bytes.copy_from_slice(&x.to_le_bytes());
+        }
+        out
+    }
+
+    fn parent(left: [u32; 8], right: [u32; 8]) -> Self {
+        let mut block = [0; 16];
+        block[..8].copy_from_slice(&left);
+        block[8..].copy_from_slice(&right);
+        Self {
+            cv: IV,
+            block,
+            counter: 0,
+            len: BLOCK_LEN as u32,
+            flags: PARENT,
+        }
+    }
+}
+
+struct Chunk {
+    cv: [u32; 8],
+    counter: u64,
+    block: [u8; BLOCK_LEN],
+    block_len: usize,
+    blocks_compressed: usize,
+}
+
+impl Chunk {
+    fn new(counter: u64) -> Self {
+        Self {
+            cv: IV,
+            counter,
+            block: [0; BLOCK_LEN],
+            block_len: 0,
+            blocks_compressed: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.blocks_compressed * BLOCK_LEN + self.block_len
+    }
+
+    fn start_flag(&self) -> u32 {
+        match self.blocks_compressed {
+            0 => CHUNK_START,
+            _ => 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // the last block is compressed by `node`, with CHUNK_END
+            if self.block_len == BLOCK_LEN {
+                let block = words(&self.block);
+                let flags = self.start_flag();
+                self.cv = first_8(compress(
+                    &self.cv,
+                    &block,
+                    self.counter,
+                    BLOCK_LEN as u32,
+                    flags,
+                ));
+                self.blocks_compressed += 1;
+                self.block = [0; BLOCK_LEN];
+                self.block_len = 0;
+            }
+
+            let n = (BLOCK_LEN - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+        }
+    }
+
+    fn node(&self) -> Node {
+        Node {
+            cv: self.cv,
+            block: words(&self.block),
+            counter: self.counter,
+            len: self.block_len as u32,
+            flags: self.start_flag() | CHUNK_END,
+        }
+    }
+}
+
+/// BLAKE3 with its default 32 byte output, unkeyed.
+pub struct Blake3 {
+    chunk: Chunk,
+    /// Chaining values of the complete subtrees on the left, one per set
+    /// bit of the number of chunks so far.
+    stack: Vec<[u32; 8]>,
+}
+
+impl Default for Blake3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Blake3 {
+    pub fn new() -> Self {
+        Self {
+            chunk: Chunk::new(0),
+            stack: vec![],
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // a full chunk is only closed once more data follows, the last
+            // one is the root when it is the only one
+            if self.chunk.len() == CHUNK_LEN {
+                let mut cv = self.chunk.node().chaining_value();
+                let mut chunks = self.chunk.counter + 1;
+                while chunks & 1 == 0 {
+                    cv = Node::parent(self.stack.pop().unwrap(), cv).chaining_value();
+                    chunks >>= 1;
+                }
+                self.stack.push(cv);
+                self.chunk = Chunk::new(self.chunk.counter + 1);
+            }
+
+            let n = (CHUNK_LEN - self.chunk.len()).min(data.len());
+            self.chunk.update(&data[..n]);
+            data = &data[n..];
+        }
+    }
+
+    pub fn finish(self) -> [u8; 32] {
+        let mut node = self.chunk.node();
+        for cv in self.stack.iter().rev() {
+            node = Node::parent(*cv, node.chaining_value());
+        }
+        node.root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::hex;
+
+    /// The official vectors hash the bytes `0, 1, .., 250, 0, 1, ..`.
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn known_answers() {
+        let vectors = [
+            (
+                0,
+                "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            ),
+            (
+                1,
+                "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+            ),
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+            (
+                8193,
+                "bab6c09cb8ce8cf459261398d2e7aef35700bf488116ceb94a36d0f5f1b7bc3b",
+            ),
+        ];
+        for (len, expected) in vectors {
+            let mut hasher = Blake3::new();
+            hasher.update(&input(len));
+            assert_eq!(hex(&hasher.finish()), expected, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn updates_split_anywhere() {
+        let data = input(8193);
+        let mut whole = Blake3::new();
+        whole.update(&data);
+        let whole = whole.finish();
+        for split in [1, 63, 64, 1023, 1024, 4097] {
+            let mut parts = Blake3::new();
+            for part in data.chunks(split) {
+                parts.update(part);
+            }
+            assert_eq!(parts.finish(), whole, "updates of {} bytes", split);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Counts failures per destination root and opens once the error budget
+/// is spent, so the remaining files to a dead destination are skipped.
+pub struct CircuitBreaker {
+    budget: Option<usize>,
+    failures: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(budget: Option<usize>) -> Self {
+        Self {
+            budget,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_open(&self, root: &Path) -> bool {
+        match self.budget {
+            Some(budget) => {
+                self.failures
+                    .lock()
+                    .unwrap()
+                    .get(root)
+                    .copied()
+                    .unwrap_or(0)
+                    >= budget
+            }
+            None => false,
+        }
+    }
+
+    /// Record a failure, returns `true` when this failure opened the circuit.
+    pub fn record_failure(&self, root: &Path) -> bool {
+        let mut failures = self.failures.lock().unwrap();
+        let count = failures.entry(root.to_path_buf()).or_insert(0);
+        *count += 1;
+
+        self.budget == Some(*count)
+    }
+}
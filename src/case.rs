@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// What a copy does with destinations that differ only by case, on a
+/// filesystem that takes them for the same file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CaseCollision {
+    /// Copy nothing and report them.
+    #[default]
+    Error,
+    /// Report them and copy anyway, the last one written wins.
+    Warn,
+}
+
+impl CaseCollision {
+    pub const NAMES: [&'static str; 2] = ["error", "warn"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(CaseCollision::Error),
+            "warn" => Some(CaseCollision::Warn),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the filesystem of `dir`, or of the nearest parent that exists,
+/// ignores case. Known by looking up a name of it in the other case,
+/// by the platform where no name has one.
+pub fn insensitive(dir: &Path) -> bool {
+    let dir = crate::doctor::existing(dir).and_then(|x| x.canonicalize().ok());
+    for path in dir.iter().flat_map(|x| x.ancestors()) {
+        let name = match path.file_name().and_then(|x| x.to_str()) {
+            Some(x) => x,
+            None => continue,
+        };
+        let flipped: String = name.chars().map(flip).collect();
+        if flipped == name {
+            continue;
+        }
+        return same_file(path, &path.with_file_name(flipped));
+    }
+
+    cfg!(any(target_os = "macos", target_os = "ios", windows))
+}
+
+fn flip(c: char) -> char {
+    match c {
+        _ if c.is_ascii_lowercase() => c.to_ascii_uppercase(),
+        _ => c.to_ascii_lowercase(),
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(_a: &Path, b: &Path) -> bool {
+    b.exists()
+}
+
+/// Pairs of `paths` that differ only by case, the first of them with
+/// each later one.
+pub fn collisions(paths: &[PathBuf]) -> Vec<(&Path, &Path)> {
+    let mut seen: HashMap<String, &Path> = HashMap::new();
+    let mut found = vec![];
+    for path in paths {
+        let key = path.to_string_lossy().to_lowercase();
+        match seen.get(&key) {
+            Some(first) if *first != path.as_path() => found.push((*first, path.as_path())),
+            Some(_) => {}
+            None => {
+                seen.insert(key, path);
+            }
+        }
+    }
+    found
+}
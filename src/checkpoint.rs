@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::state::state_dir;
+
+/// How often progress is written out while files are being copied.
+const INTERVAL: Duration = Duration::from_secs(2);
+
+/// Progress of an unfinished run, enough to start it again and skip what
+/// it already did.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointFile {
+    pub run_id: String,
+    pub started: u64,
+    pub cwd: PathBuf,
+    /// Command line arguments without the program name.
+    pub args: Vec<String>,
+    /// Processes that worked on the run, their temporary files are orphans.
+    pub pids: Vec<u32>,
+    /// Destination files that were completely written.
+    pub done: BTreeSet<PathBuf>,
+}
+
+fn checkpoint_root() -> PathBuf {
+    state_dir().join("checkpoint")
+}
+
+fn checkpoint_path(run_id: &str) -> PathBuf {
+    checkpoint_root().join(format!("{}.yaml", run_id))
+}
+
+/// Periodically persisted progress of the current run, removed again when
+/// the run completes.
+pub struct Checkpoint {
+    file: Mutex<CheckpointFile>,
+    /// Previous processes of a resumed run.
+    orphans: Vec<u32>,
+    saved: Mutex<Instant>,
+}
+
+impl Checkpoint {
+    pub fn new(mut file: CheckpointFile) -> Self {
+        let orphans = file.pids.clone();
+        file.pids.push(std::process::id());
+
+        Self {
+            file: Mutex::new(file),
+            orphans,
+            saved: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Whether a previous attempt of the run already wrote `dst`.
+    pub fn is_done(&self, dst: &Path) -> bool {
+        self.file.lock().unwrap().done.contains(dst)
+    }
+
+    /// Temporary files of `dst` crashed attempts of the run may have left.
+    pub fn orphans(&self, dst: &Path) -> Vec<PathBuf> {
+        self.orphans
+            .iter()
+            .map(|&pid| crate::copy::temp_path(dst, pid))
+            .collect()
+    }
+
+    /// Mark `dst` as written, returns whether it is time to save.
+    pub fn done(&self, dst: &Path) -> bool {
+        self.file.lock().unwrap().done.insert(dst.to_path_buf());
+
+        let mut saved = self.saved.lock().unwrap();
+        if saved.elapsed() < INTERVAL {
+            return false;
+        }
+        *saved = Instant::now();
+        true
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let file = self.file.lock().unwrap();
+        // a crash while saving must not lose the previous checkpoint
+        let data = serde_yaml::to_vec(&*file).map_err(io::Error::other)?;
+        crate::store::write(&checkpoint_path(&file.run_id), &data)
+    }
+
+    /// The run completed, nothing is left to resume.
+    pub fn remove(&self) -> io::Result<()> {
+        match std::fs::remove_file(checkpoint_path(&self.file.lock().unwrap().run_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether a process of the run is still alive, a crashed run has none.
+pub fn is_running(run_id: &str) -> bool {
+    load(Some(run_id)).is_ok_and(|x| x.pids.iter().any(|&pid| pid_alive(pid)))
+}
+
+#[cfg(unix)]
+pub fn pid_alive(pid: u32) -> bool {
+    let ret = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    ret == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a way to tell, every process counts as alive.
+#[cfg(not(unix))]
+pub fn pid_alive(_pid: u32) -> bool {
+    true
+}
+
+pub fn remove_orphans(paths: &[PathBuf]) {
+    for tmp in paths {
+        match std::fs::remove_file(tmp) {
+            Ok(()) => log::info!("Remove orphaned {}", tmp.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Remove orphaned {} failed, {}", tmp.display(), e),
+        }
+    }
+}
+
+/// Load the checkpoint of `run_id`, or the most recent one.
+pub fn load(run_id: Option<&str>) -> io::Result<CheckpointFile> {
+    let paths: Vec<PathBuf> = match run_id {
+        Some(id) => vec![checkpoint_path(id)],
+        None => std::fs::read_dir(checkpoint_root())?
+            .filter_map(|x| x.ok().map(|x| x.path()))
+            .filter(|x| x.extension().is_some_and(|x| x == "yaml"))
+            .collect(),
+    };
+
+    paths
+        .into_iter()
+        .filter_map(|x| serde_yaml::from_reader::<_, CheckpointFile>(File::open(x).ok()?).ok())
+        .max_by_key(|x| x.started)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no unfinished run found"))
+}
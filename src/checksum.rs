@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::blake3::Blake3;
+use crate::manifest::hex;
+use crate::xxh3::Xxh3;
+
+/// Hash of the content of local files, for the hash cache, --dedup and
+/// manifests. Hosts and buckets are compared by SHA-256 whatever it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Blake3,
+    Sha256,
+    /// Fastest, but not meant to resist a forged collision.
+    Xxh3,
+}
+
+impl Algorithm {
+    pub const NAMES: [&'static str; 3] = ["blake3", "sha256", "xxh3"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "blake3" => Some(Algorithm::Blake3),
+            "sha256" => Some(Algorithm::Sha256),
+            "xxh3" => Some(Algorithm::Xxh3),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// Hex encoded hash of the file at `path`.
+    pub fn file(self, path: &Path) -> io::Result<String> {
+        let mut f = File::open(path)?;
+        let mut hasher = Hasher::new(self);
+        let mut buf = vec![0; 128 * 1024];
+
+        loop {
+            let n = f.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+
+        Ok(hasher.finish())
+    }
+}
+
+/// Hash a file must have, `sha256:<hex>` in the config.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expected {
+    pub algorithm: Algorithm,
+    /// Lowercase.
+    pub hex: String,
+}
+
+impl TryFrom<String> for Expected {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, String> {
+        let (name, hex) = text
+            .split_once(':')
+            .ok_or_else(|| format!("`{}` is not of the form algorithm:hex", text))?;
+        let algorithm = Algorithm::from_name(name).ok_or_else(|| {
+            format!(
+                "unknown algorithm {}, one of {}",
+                name,
+                Algorithm::NAMES.join(", ")
+            )
+        })?;
+        if algorithm == Algorithm::Xxh3 {
+            return Err("xxh3 is not tamper-resistant, pin a blake3 or sha256 hash".to_owned());
+        }
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("`{}` is not a hex hash", hex));
+        }
+        Ok(Self {
+            algorithm,
+            hex: hex.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl From<Expected> for String {
+    fn from(x: Expected) -> Self {
+        format!("{}:{}", x.algorithm.name(), x.hex)
+    }
+}
+
+enum Hasher {
+    Blake3(Box<Blake3>),
+    Sha256(Sha256),
+    Xxh3(Box<Xxh3>),
+}
+
+impl Hasher {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Blake3 => Hasher::Blake3(Box::default()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Blake3(x) => x.update(data),
+            Hasher::Sha256(x) => x.update(data),
+            Hasher::Xxh3(x) => x.update(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Hasher::Blake3(x) => hex(&x.finish()),
+            Hasher::Sha256(x) => hex(&x.finalize()),
+            Hasher::Xxh3(x) => format!("{:016x}", x.finish()),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Mutex;
+
+use crate::state::state_dir;
+
+/// Temporary files of this process not yet renamed into place, and the
+/// registry file listing them for when the process dies without cleaning
+/// up.
+struct Temps {
+    paths: Vec<PathBuf>,
+    registry: Option<File>,
+}
+
+/// Global, as the exit paths reaching it have no other state at hand.
+static TEMPS: Mutex<Temps> = Mutex::new(Temps {
+    paths: Vec::new(),
+    registry: None,
+});
+
+/// Whether a copy is running that a first signal stops gracefully.
+static GRACEFUL: AtomicBool = AtomicBool::new(false);
+
+/// The signal that interrupted the copy, 0 for none.
+static INTERRUPTED: AtomicI32 = AtomicI32::new(0);
+
+/// While alive, a first SIGINT, SIGTERM or SIGHUP lets the files being
+/// copied finish and the run wind down instead of exiting right away.
+pub struct Graceful(());
+
+impl Drop for Graceful {
+    fn drop(&mut self) {
+        GRACEFUL.store(false, Ordering::Relaxed);
+    }
+}
+
+pub fn graceful() -> Graceful {
+    GRACEFUL.store(true, Ordering::Relaxed);
+    Graceful(())
+}
+
+/// The signal a graceful copy was interrupted by.
+pub fn interrupted() -> Option<i32> {
+    Some(INTERRUPTED.load(Ordering::Relaxed)).filter(|x| *x != 0)
+}
+
+/// Exit with the status of a process killed by the signal, if one
+/// interrupted the copy.
+#[cfg(all(unix, feature = "daemon"))]
+pub fn exit_if_interrupted() {
+    if let Some(sig) = interrupted() {
+        std::process::exit(128 + sig);
+    }
+}
+
+fn registry_path(pid: u32) -> PathBuf {
+    state_dir().join(format!("temps-{}.list", pid))
+}
+
+/// Remove what the registries of dead processes list, then make sure this
+/// process cleans up after itself on exit, panic and SIGINT, SIGTERM or
+/// SIGHUP, a second one during a graceful copy.
+pub fn install() {
+    remove_stale();
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        // worker panics reach the main thread, temps of the others are
+        // still being written until then
+        if std::thread::current().name() == Some("main") {
+            clean();
+        }
+    }));
+
+    #[cfg(unix)]
+    signals::install();
+}
+
+/// `path` is about to be created.
+pub fn register(path: &Path) {
+    let mut temps = TEMPS.lock().unwrap();
+    if temps.registry.is_none() {
+        let _ = std::fs::create_dir_all(state_dir());
+        temps.registry = File::create(registry_path(std::process::id())).ok();
+    }
+    if let Some(f) = &mut temps.registry {
+        let _ = writeln!(f, "{}", path.display());
+    }
+    temps.paths.push(path.to_path_buf());
+}
+
+/// `path` was renamed into place or removed.
+pub fn release(path: &Path) {
+    TEMPS.lock().unwrap().paths.retain(|x| x != path);
+}
+
+/// Remove the temporary files still registered, and the registry.
+pub fn clean() {
+    // a thread holding the lock is stopped mid-update, better leave the
+    // files to the next run than to wait forever
+    let mut temps = match TEMPS.try_lock() {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+    for x in temps.paths.drain(..) {
+        let _ = std::fs::remove_file(x);
+    }
+    if temps.registry.take().is_some() {
+        let _ = std::fs::remove_file(registry_path(std::process::id()));
+    }
+}
+
+/// Temporary files listed by registries of processes that are gone.
+fn remove_stale() {
+    let entries = match std::fs::read_dir(state_dir()) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let pid = name
+            .strip_prefix("temps-")
+            .and_then(|x| x.strip_suffix(".list"))
+            .and_then(|x| x.parse().ok());
+        if pid.is_none_or(crate::checkpoint::pid_alive) {
+            continue;
+        }
+
+        let list = std::fs::read_to_string(entry.path()).unwrap_or_default();
+        // only what cpx names its temporary files, whatever the list says
+        for path in list.lines().filter(|x| x.ends_with(".cpx-tmp")) {
+            if std::fs::remove_file(path).is_ok() {
+                log::info!("Remove leftover {}", path);
+            }
+        }
+        let _ = std::fs::remove_file(entry.path());
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use super::{GRACEFUL, INTERRUPTED};
+
+    /// Write end of the pipe waking the cleanup thread.
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    /// Runs in the signal handler, only async-signal-safe calls.
+    extern "C" fn on_signal(sig: libc::c_int) {
+        let byte = sig as u8;
+        unsafe {
+            libc::write(
+                PIPE.load(Ordering::Relaxed),
+                &byte as *const u8 as *const _,
+                1,
+            )
+        };
+    }
+
+    extern "C" fn on_exit() {
+        super::clean();
+    }
+
+    pub fn install() {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            log::warn!(
+                "Cleanup on signals unavailable, {}",
+                std::io::Error::last_os_error()
+            );
+            return;
+        }
+        PIPE.store(fds[1], Ordering::Relaxed);
+
+        std::thread::spawn(move || loop {
+            let mut byte = 0u8;
+            let n = unsafe { libc::read(fds[0], &mut byte as *mut u8 as *mut _, 1) };
+            if n != 1 {
+                break;
+            }
+            let sig = i32::from(byte);
+            let first = INTERRUPTED
+                .compare_exchange(0, sig, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok();
+            if first && GRACEFUL.load(Ordering::Relaxed) {
+                log::warn!("Interrupted, finishing the files being copied, again to stop now");
+                continue;
+            }
+            super::clean();
+            // the exit status of a process killed by the signal
+            std::process::exit(128 + sig);
+        });
+
+        let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        for sig in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            unsafe { libc::signal(sig, handler) };
+        }
+        // std::process::exit and returning from main
+        unsafe { libc::atexit(on_exit) };
+    }
+}
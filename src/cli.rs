@@ -0,0 +1,1434 @@
+use clap::{App, AppSettings, Arg, SubCommand};
+
+use crate::checksum::Algorithm;
+use crate::compare::Strategy;
+use crate::copy::Engine;
+use crate::dedup::Dedup;
+use crate::eol::Eol;
+use crate::export;
+#[cfg(feature = "remote")]
+use crate::rsync::Transport;
+
+fn manifest_arg() -> Arg<'static, 'static> {
+    Arg::with_name("manifest")
+        .long("manifest")
+        .takes_value(true)
+        .value_name("FILE")
+        .help("Manifest file, defaults to cpx.<checksum> in the destination")
+}
+
+fn checksum_arg() -> Arg<'static, 'static> {
+    Arg::with_name("checksum")
+        .long("checksum")
+        .takes_value(true)
+        .value_name("ALGORITHM")
+        .possible_values(&Algorithm::NAMES)
+        .help("Hash of local files, over the `checksum` of the config, blake3 by default")
+}
+
+fn path_key_arg() -> Arg<'static, 'static> {
+    Arg::with_name("path-key")
+        .long("path-key")
+        .takes_value(true)
+        .value_name("KEY")
+        .env("CPX_PATH_KEY")
+        .hide_env_values(true)
+        .help("Record paths as keyed hashes, so the manifest does not reveal the layout")
+}
+
+/// Worked examples and the config layout, shown after `--help` and in the
+/// man page.
+pub const EXAMPLES: &str = r#"EXAMPLES:
+    cpx src:dst --tag core
+        Copy the files of tag `core` from path `src` to path `dst`
+    cpx src:dst --tag core --file readme --dry-run
+        Show what would be copied, adding the single file `readme`
+    cpx src:dst --tag core --check
+        Fail with exit code 2 unless `dst` is up to date, 1 on errors
+    cpx src:web --tag deploy --env DEPLOY_ENV=production
+        Deploy with the hooks seeing DEPLOY_ENV=production over the config's
+    cpx src:backup --tag media --low-priority
+        Copy `media` in the background without slowing down the machine
+    cpx src:dst --tag core --changed-only --newer-than 1d
+        Copy only files of `core` changed within a day that differ at `dst`
+    cpx src:web --tag core
+        Upload the files of `core` to a remote path over ssh
+    cpx src:sandbox --tag core --changed-only
+        Push the changed files of `core` into the container `app-dev`
+    cpx src:assets --tag core --jobs 8
+        Upload the files of `core` to an S3 bucket, 8 at a time
+    cpx watch src:dst --tag core
+        Copy `core` again, changed files only, whenever its sources change
+    cpx daemon src:dst --jobs 4 & cpx trigger core
+        Keep a daemon for `src:dst` and have it copy what changed in `core`
+    cpx boards:logs --tag logs
+        Gather the files of `logs` from every path of `boards` into `logs`
+    cpx src/debug:dst/bin --tag core
+        Copy `core` from the debug directory of `src` into bin of `dst`
+    cpx src:dst --select 'deploy & !core'
+        Copy the files of `deploy` that are not also in `core`
+    cpx src:dst --match '^js/.*\.js$'
+        Copy the files of `file_list` under js ending in .js
+    git diff --name-only | cpx src:dst --files-from -
+        Copy the files git sees changed, with their `file_list` entries
+    cpx src:dst --tag web --since v1.2.0
+        Copy the files of `web` changed since the v1.2.0 tag
+    cpx src:remote --tag web --since-last-run
+        Copy the files modified since the last successful copy of `web`
+    cpx src:dst --tag core --changed-only --print0 | xargs -0 sha256sum
+        Hash the files the copy wrote, whatever their names
+    cpx -c https://ci.example.com/cpx.yaml src:dst --tag core
+        Copy with a config hosted in one place for every CI job
+    cpx src:dst src:web --tag core
+        Copy `core` to both `dst` and `web`, with one summary
+    cpx src:artifacts --tag outputs --flatten --on-collision rename
+        Gather the files of `outputs` in the root of `artifacts`
+    cpx src:web --tag core --notify --webhook https://hooks.example.com/cpx
+        Show a desktop notification and post the summary as JSON when done
+    cpx src:release --tag core --report report.html
+        Copy `core` and write a table of the files with their statuses, sizes
+        and hashes to report.html, or to a Markdown file of another name
+    cpx src:nightly --tag core --rotate
+        Copy `core` into a new snapshot of `nightly`, keeping the last 10
+    cpx nfs:backup --tag logs --timeout 60 --run-timeout 3600
+        Fail any file taking over a minute, like one on a dead mount, and
+        stop after an hour, listing the files that timed out
+    cpx src:backup --tag logs --on-source-change retry
+        Copy again the logs written to while they were copied
+    cpx src:web --tag core --delete --backup=.orig
+        Deploy `core`, keeping each replaced or deleted file as <name>.orig
+    cpx src:dst --tag core --reverse
+        Copy back from `dst` to `src`
+    cpx src:dst --tag core --ask
+        Decide for each changed destination file, after a look at the diff
+    cpx src:dst --tag core --progress-events 3 3>events.jsonl
+        Log run-start, file-start, bytes-progress, file-done and run-done
+        events as JSON lines, as a frontend reading fd 3 would get them
+    cpx explain readme src:dst --normalize lowercase
+        Show where `readme` comes from and what copying it would do
+    cpx doctor src:web --tag core
+        Check the config, its paths and that `core` fits on `web`
+    cpx tree --tag web --src src
+        Show what `web` is made of, marking the files missing in `src`
+    cpx lint
+        Report the files, groups, scripts and paths of the config no tag,
+        profile or schedule uses, and the tags selecting nothing, exiting
+        with 1 for them too with --strict
+    cpx du src:web --tag core --tag assets
+        Show the files and bytes of `core`, `assets` and both, in `src` and
+        in `web`
+    cpx scan src
+        List the files of `src` matching `discover` that file_list lacks
+    cpx tag save release --tag core --file readme
+        Add the tag `release` selecting `core` and `readme` to the config
+    cpx export src:dst --tag web --format sh > deploy.sh
+        Write the copies as `mkdir -p` and `cp` lines, for hosts without cpx
+    cpx bench src:web --tag core --levels 1,4,16
+        Time copying `core` to `web` by each engine and with 4 and 16 jobs
+    cpx sync laptop:nas --tag shared
+        Copy the files of `shared` changed on either side to the other
+    cpx base+prod:www --tag deploy
+        Copy the files of `deploy` from prod, or from base where prod lacks them
+    cpx mirror src:backup --delete --prune-empty-dirs
+        Make `backup` a copy of all of `src`, removing what `src` lacks
+    cpx undo
+        Restore the destination files the last run changed
+    cpx resume
+        Continue a copy stopped by Ctrl-C, which lets the files in flight
+        finish first, a second Ctrl-C stops at once removing partial files
+
+SPEC:
+    `<src>:<dst>` names the source and destination entries of `path_list`,
+    files are copied to the same relative path under the destination.
+    Either name can be followed by a subpath below its path, `src/debug:dst/bin`
+    copies from debug under `src` into bin under `dst`. Subpaths have no
+    empty names, `.` or `..`, and `collect` and `merge` paths none at all.
+    Sources joined by `+`, `base+prod:site`, are merged like a `merge` path.
+
+CONFIG:
+    The config is YAML, by default the first cpx.yaml or .cpx.yaml in the
+    current directory or one above it over the user config, which is
+    $XDG_CONFIG_HOME/cpx/config.yaml if there is one, else ~/cpx.yaml.
+    -c - reads a config from stdin and -c with an http:// or https:// URL
+    fetches it, kept in the data directory for when the server cannot be
+    reached and fetched again only once the server has a newer one.
+    Several -c are layered the same way, each over the ones before it:
+    mappings are merged by key, lists and other values replaced:
+
+    version: 2
+    path_list:
+      src: { path: "/build/out" }
+      dst: { path: "/srv/app", owner: "app:app" }
+      www: { path: "/srv/www", owner: ":www-data", file_mode: 0640, dir_mode: 0750, umask: 0027 }
+      web:
+        path: "deploy@web1:/srv/app"
+        ssh: { port: 22, connect_timeout: 30, keepalive_interval: 15 }
+        transport: rsync
+      sandbox: { path: "docker://app-dev:/srv/app" }
+      assets:
+        path: "s3://assets/app"
+        s3: { endpoint_url: "http://minio:9000", content_types: { map: "application/json" } }
+        compare: etag
+      share:
+        path: "davs://cloud.example.com/remote.php/dav/files/alice/Docs"
+        webdav: { credentials_file: "/home/alice/.config/cpx/netrc" }
+      vault:
+        path: "/mnt/shared/app"
+        encryption: { tool: age, recipients: ["age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"] }
+      boards: { collect: ["board1", "board2"] }
+      site: { merge: ["base", "prod"] }
+      backup: { path: "/backups/{hostname}/{date}" }
+      nightly: { path: "/backups/app", keep: 10 }
+      systemd: { path: "/etc/systemd/system" }
+    file_list:
+      core: { relative_path: "bin/core.so", post: "strip" }
+      start: { relative_path: "bin/start.sh", mode: 0755 }
+      unit: { relative_path: "app.service" }
+      readme: { relative_path: "README", filter: minify }
+      access: { relative_path: "logs/access.log", compress: zstd }
+      env: { relative_path: "etc/app.env", template: true, eol: lf }
+      jquery: { relative_path: "js/jquery.js", url: "https://code.jquery.com/jquery-3.7.1.min.js" }
+      app: { relative_path: "bin/app", hash: "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08" }
+    group_list:
+      runtime: ["core", "readme"]
+    tag_list:
+      core: { file_list: ["core"], mode: 0644 }
+      logs: { file_list: ["access"], compress: gzip }
+      restore-logs: { file_list: ["access"], decompress: auto }
+      deploy:
+        files_from_group: ["runtime"]
+        script_list: ["restart"]
+        hooks: { pre: ["systemctl stop app"], post: ["systemctl start app"] }
+        env: { SERVICE: "app" }
+      assets: { files_from_cmd: "git -C /build ls-files assets" }
+      units: { file_list: ["unit"], to: "systemd" }
+      release: { tag_list: ["core", "deploy"], file_list: ["env"] }
+    script_list:
+      migrate: { from: "/build/migrate.sh", to: "/srv/migrate.sh" }
+      restart: { from: "/build/restart.sh", to: "/srv/restart.sh", depends_on: ["migrate"] }
+    vars: { APP_PORT: "8080", APP_ENV: "staging" }
+    env: { DEPLOY_ENV: "staging" }
+    filters: { minify: "tr -s ' \\n'", strip-secrets: "grep -v '^secret='" }
+    checksum: blake3
+    exclude: ["*~", "*.tmp"]
+    discover: ["/bin/*.sh", "*.service"]
+    hooks: { post: ["notify-send 'cpx copied $CPX_DST'"] }
+    retention: { backups: "30d", part_files: "7d" }
+    schedule:
+      - { cron: "30 2 * * 1-5", spec: "src:web", tags: ["core"], args: ["--changed-only"] }
+    overrides:
+      - { host: "buildbox", path_list: { src: { path: "/mnt/build" } } }
+      - { env: "CI", path_list: { web: { path: "/tmp/ci-web" } } }
+    profiles:
+      deploy-web: { from: src, to: web, tags: ["core", "deploy"], changed_only: true }
+      nightly: { from: src, to: nightly, rotate: true, notify_on: failure, webhook: "https://hooks.example.com/cpx" }
+    update:
+      endpoint: "https://releases.example.com/cpx/{target}.json"
+      allowed_signers: "/etc/cpx/allowed_signers"
+
+    `version` is the schema of the config, 2 now. Older configs are read
+    as they are, `cpx config migrate --write` updates them, a newer one
+    needs a newer cpx.
+
+    Keys the config does not know are errors, reported with the file and
+    line they are on, so a typo is not silently ignored.
+
+    A tag with a `from` or `to`, path names like those of a spec, copies
+    its files from or to that path instead of the one of the spec, so one
+    run can put each tag in its own place. A spec is then needed only for
+    the tags and --file without them.
+
+    A source with `collect` copies the selection from each of the listed
+    paths into a subdirectory of the destination named after the path,
+    `cpx boards:logs` puts board1's files under logs/board1.
+
+    A source with `merge` copies each file of the selection from the last
+    of the listed paths that has it, so overrides go after what they
+    override, `cpx site:www` copies prod's files and base's others. The
+    listed paths are local directories, a merge is never the destination.
+
+    A `path` may have `{date}` (2024-05-31), `{time}` (143000), `{hostname}`
+    and `{tag}`, the selected tags joined by `+`, filled in when the run
+    starts. `cpx src:backup` so lands in a new directory each day.
+
+    --rotate copies into a new `{date}_{time}` directory of the
+    destination and, when the copy succeeded, points the symlink `latest`
+    in the destination at it and removes the oldest such directories
+    beyond the `keep` of the destination.
+
+    `cpx sync` compares both sides of each selected file with how the
+    last sync of the same spec and selection left them: a file changed on
+    one side is copied to the other, one changed on both is a conflict
+    and left alone, unless the contents are the same. On the first sync
+    the newer file wins. Deletions are not synced, a file missing on one
+    side is copied from the other.
+
+    `cpx mirror` copies every file below the source that is not excluded
+    and differs from its copy, whether file_list has it or not, those it
+    has with their options. Its --delete removes the files of the whole
+    destination the source lacks, except excluded ones, and
+    --prune-empty-dirs the directories left empty.
+
+    The scripts of a tag, those of its `script_list` and the one named
+    like the tag, are copied after its files. Each is copied after the
+    scripts of its `depends_on`, which are copied with it, scripts that
+    depend on each other are an error.
+
+    `exclude` and a .cpxignore file in the source path take gitignore
+    style patterns, matching files are never copied. --exclude-gitignore
+    adds the .gitignore files of the source and --filter-from the `+` and
+    `-` rules of an rsync filter file, the first matching rule winning as
+    in rsync. `retention` is how long `cpx gc` keeps undo backups and
+    resumable .part files.
+
+    `discover` takes gitignore style patterns of the files that belong in
+    `file_list`. `cpx scan src` prints entries for the files of `src`
+    matching them that it lacks, ready to paste, and warns of entries not
+    on disk. --check makes either exit with 1, for CI.
+
+    `cpx schedule run` starts each `schedule` copy when its cron fields
+    (minute, hour, day of month, month, day of week, in local time) match.
+    One copy runs at a time, a due time passing during a copy is skipped.
+
+    `overrides` entries are merged over the config, like a later -c, on
+    the machines they match: `host` is the hostname, with or without its
+    domain, and `env` a variable that is set, or `NAME=value` for one with
+    that value. One config can so give each machine its own mount points.
+
+    `cpx run deploy-web` copies the `profiles` entry deploy-web: `from` and
+    `to` name the paths, `tags` and `files` the selection, any other key
+    is a long option, `changed_only: true` for --changed-only or `jobs: 4`
+    for --jobs 4. Options after the name are added to those of the profile.
+
+    `hooks` of the config, then those of the selected tags, run in the
+    shell before (`pre`) and after (`post`) each copy, with CPX_SRC,
+    CPX_DST and CPX_RUN_ID set. Post hooks also get CPX_FILES, the
+    destination files one per line, and CPX_FAILED, the number of failed
+    files, and run even if the copy failed.
+    A failing pre hook cancels the copy. --no-hooks skips them all.
+
+    `env` of the config, then that of the selected tags, is set for the
+    hooks, filters and `post` commands of a copy, --env KEY=VAL over
+    them, so one script serves several stages.
+
+    `filters` are shell commands given the content of a file on stdin,
+    what they print is written to the copy, like git clean and smudge
+    filters. The `filter` of a file, or of its tag without one of its own,
+    names one of them. CPX_FILE is the source file, a filter failing fails
+    the file.
+
+    `compress` of a file, or of its tag without one of its own, writes the
+    copy compressed with `gzip` or `zstd` and its extension appended,
+    logs/access.log as logs/access.log.zst. zstd needs the zstd tool.
+    `decompress: auto` expands sources ending in .gz or .zst, or found
+    only with one of them appended, into the path without it, over any
+    `compress`, so the same files are restored from the archive by
+    another tag.
+
+    A tag's `tag_list` selects those tags with it, their files, scripts,
+    hooks and options as if they were given too. `cpx tag save` adds such
+    a tag on one line below `tag_list:` of the last config, or of the user
+    config with --user, which is layered under the project config when
+    there is no -c. A config it cannot edit that way is written anew, the
+    old one kept as .bak.
+
+    `files_from_cmd` runs a shell command in the current directory and
+    adds each line it prints as a path relative to the source, so a tag
+    can follow the build graph.
+
+    --select combines tags with `&`, `|`, `!` and parentheses, `!` taking
+    the files of `file_list` outside a tag. It selects files only, by
+    the `file_list` and `files_from_group` of the tags, without their
+    scripts, hooks or `files_from_cmd`.
+
+    A file with a `hash`, `algorithm:hex` by blake3 or sha256, is copied
+    only while its source has that hash, so a deploy copies the build it
+    was pinned to and no other. A source that differs fails its file, dry
+    runs and --check included. The source is hashed anew each time, and a
+    local copy is hashed again before it replaces the destination.
+
+    A `relative_path` is written with `/` on every platform and stays
+    below its path, a leading `/` or, on Windows, a drive is dropped.
+    --link on Windows links directories by junctions where making
+    symlinks needs Developer Mode.
+
+    Where the destination ignores case, as on macOS and Windows by
+    default, destinations differing only by case fail the copy before
+    anything is written, one would overwrite the other.
+    --on-case-collision warn copies them anyway.
+
+    Files copied to a path with `encryption` are encrypted by `age` or
+    `gpg` for its `recipients`, files copied from it are decrypted, by
+    age with the `identity` file. Encrypted files always differ from
+    their sources, --changed-only copies them again.
+
+    A `post` command of a `file_list` entry runs in the shell each time
+    the file is written, with the destination path as its last argument
+    and in CPX_FILE. If it fails the file counts as failed.
+
+    `mode` of a `file_list` entry, or else of its tag, sets the permission
+    bits of the copy instead of those of the source, --chmod does for the
+    rest. It needs a local destination not copied by rsync.
+
+    `owner` of the destination path, `user:group`, `user` or `:group`,
+    is given to the copied files, --chown overrides it. Without the
+    privileges for it a warning is logged and owners stay as they are.
+    `file_mode` of the destination path is the mode of the files without
+    one of their own or --chmod, `dir_mode` that of the directories it
+    creates, --dir-mode overrides it. Its `umask` clears bits from the
+    modes of all of them, those kept from the source included.
+    --xattrs copies extended attributes along, which holds POSIX ACLs,
+    SELinux labels and capabilities on Linux and resource forks on macOS.
+    Those the destination refuses are skipped with a warning.
+
+    `eol` converts the line endings of a text file to `lf`, `crlf` or
+    those of the platform, `native`, like --eol does for all files.
+
+    A `template` file has each `{{NAME}}` replaced while copied, with the
+    environment variable NAME or else NAME from `vars`. A name with no
+    value fails the file, files that are not text are copied as is.
+
+    A `file_list` entry with a `url` is downloaded with curl to its
+    relative path instead of copied, with --changed-only only if the
+    server has a newer version.
+
+    `transport: rsync` hands the files for a destination to one rsync
+    run, for delta transfers and partial files, --via overrides it.
+
+    `compare` picks how --changed-only and --dry-run find a destination
+    file identical: `size-mtime`, `hash` of the whole content, the S3
+    `etag` or `always` copying, --compare overrides it. By default local
+    files and hosts are compared by content, buckets by the hash cpx
+    stored and WebDAV by size and time. An `etag` of an object uploaded
+    in parts of another size than the `part_size` is compared by size and
+    time.
+
+    Hashes of local files are kept per destination between runs and
+    trusted while size and modification time stay the same, so repeated
+    --changed-only runs only read files that changed. `hash` always reads
+    both sides in full, --no-hash-cache hashes everything again.
+
+    `checksum` picks how local files are hashed for this, --dedup and
+    manifests: `blake3` by default, `sha256` where it is required or
+    `xxh3`, fastest but not collision resistant. Hosts and buckets are
+    compared by SHA-256 whatever it is. --checksum overrides it.
+
+    `docker://container:/path` paths are in a running container, files
+    are written into it with `docker exec` like to a host over ssh, so
+    no image is rebuilt. The container needs a POSIX shell, and its
+    permissions decide what can be written.
+
+    `s3://bucket/prefix` paths go through the aws cli and its credentials,
+    `profile`, `region` and `storage_class` can be set next to the
+    endpoint. Large files are uploaded in `part_size` parts (8M, at least
+    5M), `concurrency` of them at once (4), and an upload failing part way
+    is aborted.
+
+    `davs://host/path` paths (`dav://` for plain HTTP) are uploaded to a
+    WebDAV server with curl. The user and password come from
+    CPX_WEBDAV_USER and CPX_WEBDAV_PASSWORD, names that `user_env` and
+    `password_env` can change, or else from the netrc `credentials_file`
+    or ~/.netrc.
+
+    `cpx self-update` reads `{"version", "url", "signature_url"}` from the
+    `update` endpoint, `{target}` being like x86_64-linux. The new binary
+    must carry an `ssh-keygen -Y sign -n cpx` signature by `identity`
+    (cpx-release by default) in `allowed_signers`."#;
+
+pub fn app(default_config: &str) -> App<'_, '_> {
+    let app = App::new("Help you copy files")
+        .version("0.1")
+        .author("sbw <sbw@sbw.so>")
+        .about("Help you copy files!")
+        .after_help(EXAMPLES)
+        .arg(
+            Arg::with_name("spec")
+                .help("source and destination path, `src:dst`, each with an optional subpath, sources joined by `+` are merged, several copy the same files to each")
+                .takes_value(true)
+                .multiple(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("files")
+                .help("copy files")
+                .long("file")
+                .takes_value(true)
+                .required_unless_one(&["tags", "tag-file", "select", "match", "files-from", "interactive", "mirror"])
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("tags")
+                .help("copy files of tags")
+                .long("tag")
+                .takes_value(true)
+                .required_unless_one(&["files", "tag-file", "select", "match", "files-from", "interactive", "mirror"])
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("tag-file")
+                .long("tag-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Read tags and files to copy from FILE, one per line"),
+        )
+        .arg(
+            Arg::with_name("select")
+                .long("select")
+                .takes_value(true)
+                .value_name("EXPR")
+                .help("Copy the files of a tag expression, `web & !tests | (docs & release)`"),
+        )
+        .arg(
+            Arg::with_name("match")
+                .long("match")
+                .takes_value(true)
+                .value_name("REGEX")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Copy the files of file_list whose relative_path matches REGEX"),
+        )
+        .arg(
+            Arg::with_name("files-from")
+                .long("files-from")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Copy the file keys or relative paths in FILE, - for stdin, one per line or NUL separated"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .help("Increase log verbosity, repeat for more")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .conflicts_with("verbose")
+                .help("Only log errors")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Append a log of the run to FILE")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .short("c")
+                .takes_value(true)
+                .default_value(default_config)
+                .env("CPX_CONFIG")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Config file, see CONFIG below, later ones go over earlier ones")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .env("CPX_COLOR")
+                .help("Color warnings, errors and the status of each file")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Dry run, also enabled by a non-empty CPX_DRY_RUN other than 0 or false"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .help("Dry run exiting with 2 if anything would change, 0 if all is up to date, also for cpx sync"),
+        )
+        .arg(
+            Arg::with_name("changed-only")
+                .long("changed-only")
+                .help("Skip files whose destination is already identical"),
+        )
+        .arg(
+            Arg::with_name("reverse")
+                .long("reverse")
+                .help("Swap source and destination"),
+        )
+        .arg(
+            Arg::with_name("link")
+                .long("link")
+                .help("Build a tree of symlinks pointing into the source instead of copying"),
+        )
+        .arg(
+            Arg::with_name("hardlink")
+                .long("hardlink")
+                .conflicts_with("link")
+                .help("Hard link files to the source instead of copying them, where on the same filesystem"),
+        )
+        .arg(
+            Arg::with_name("snapshot")
+                .long("snapshot")
+                .takes_value(true)
+                .possible_values(&crate::snapshot::Kind::NAMES)
+                .help("Copy from a btrfs or zfs snapshot of the source taken at start"),
+        )
+        .arg(
+            Arg::with_name("notify")
+                .long("notify")
+                .help("Show a desktop notification when the copy is done"),
+        )
+        .arg(
+            Arg::with_name("webhook")
+                .long("webhook")
+                .takes_value(true)
+                .value_name("URL")
+                .help("Post the summary of the copy as JSON to URL when it is done"),
+        )
+        .arg(
+            Arg::with_name("notify-on")
+                .long("notify-on")
+                .takes_value(true)
+                .possible_values(&crate::notify::When::NAMES)
+                .default_value("always")
+                .help("Whether --notify and --webhook report every copy or failed ones only"),
+        )
+        .arg(
+            Arg::with_name("ignore-missing")
+                .long("ignore-missing")
+                .help("Skip selected sources that do not exist, instead of copying nothing"),
+        )
+        .arg(
+            Arg::with_name("no-space-check")
+                .long("no-space-check")
+                .help("Copy even when the destination looks too full, warning only"),
+        )
+        .arg(
+            Arg::with_name("flatten")
+                .long("flatten")
+                .help("Copy every file into the destination root, without its directories"),
+        )
+        .arg(
+            Arg::with_name("on-collision")
+                .long("on-collision")
+                .takes_value(true)
+                .possible_values(&crate::flatten::Collision::NAMES)
+                .requires("flatten")
+                .help("What --flatten does with files of the same name, error by default"),
+        )
+        .arg(
+            Arg::with_name("mirror")
+                .long("mirror")
+                .hidden(true)
+                .help("Copy every file of the source, set by cpx mirror"),
+        )
+        .arg(
+            Arg::with_name("on-case-collision")
+                .long("on-case-collision")
+                .takes_value(true)
+                .possible_values(&crate::case::CaseCollision::NAMES)
+                .help("What to do with destinations differing only by case where the destination ignores case, error by default"),
+        )
+        .arg(
+            Arg::with_name("backup")
+                .long("backup")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                // an empty one renames files onto themselves
+                .empty_values(false)
+                .value_name("SUFFIX")
+                .help("Rename destination files before they are overwritten or deleted, to their name with SUFFIX, ~ by default"),
+        )
+        .arg(
+            Arg::with_name("trash")
+                .long("trash")
+                .conflicts_with("backup")
+                .help("Move destination files to the trash before they are overwritten or deleted"),
+        )
+        .arg(
+            Arg::with_name("rotate")
+                .long("rotate")
+                .help("Copy into a new timestamped directory of the destination, linked as `latest`"),
+        )
+        .arg(
+            Arg::with_name("delete")
+                .long("delete")
+                .help("Delete extraneous files from managed destination directories"),
+        )
+        .arg(
+            Arg::with_name("prune-empty-dirs")
+                .long("prune-empty-dirs")
+                .help("Remove empty directories below managed destination directories"),
+        )
+        .arg(
+            Arg::with_name("normalize")
+                .long("normalize")
+                .takes_value(true)
+                .value_name("POLICY")
+                .help("Normalize destination keys, e.g. lowercase,spaces=_,safe"),
+        )
+        .arg(
+            Arg::with_name("jobs")
+                .short("j")
+                .long("jobs")
+                .takes_value(true)
+                .default_value("1")
+                .env("CPX_JOBS")
+                .help("Number of parallel copy workers"),
+        )
+        .arg(
+            Arg::with_name("low-priority")
+                .long("low-priority")
+                .help("Copy with lowered CPU and IO priority, like nice and ionice -c 3, for background copies"),
+        )
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .takes_value(true)
+                .possible_values(&crate::order::Order::NAMES)
+                .help("Order the files are copied in, the largest first by default or by destination path"),
+        )
+        .arg(
+            Arg::with_name("prefetch")
+                .long("prefetch")
+                .takes_value(true)
+                .value_name("FILES")
+                .help("Read the next FILES source files ahead while copying, for slow disks"),
+        )
+        .arg(
+            Arg::with_name("debounce")
+                .long("debounce")
+                .takes_value(true)
+                .value_name("MS")
+                .default_value("300")
+                .help("With cpx watch, wait until sources are unchanged for MS milliseconds"),
+        )
+        .arg(
+            Arg::with_name("sample")
+                .long("sample")
+                .takes_value(true)
+                .value_name("N%")
+                .help("Copy only a deterministic N percent subset of the selected files"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .default_value("0")
+                .help("Seed for --sample, a different seed picks a different subset"),
+        )
+        .arg(
+            Arg::with_name("min-size")
+                .long("min-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .help("Copy only selected files of at least SIZE, e.g. 100K"),
+        )
+        .arg(
+            Arg::with_name("max-size")
+                .long("max-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .help("Copy only selected files of at most SIZE, e.g. 10M"),
+        )
+        .arg(
+            Arg::with_name("newer-than")
+                .long("newer-than")
+                .takes_value(true)
+                .value_name("AGE|TIME")
+                .help("Copy only selected files modified within AGE (e.g. 12h, 7d) or since a UTC TIME (YYYY-MM-DD[ HH:MM[:SS]])"),
+        )
+        .arg(
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("REV")
+                .help("Copy only selected files changed since the git revision REV, by `git diff --name-only REV`"),
+        )
+        .arg(
+            Arg::with_name("since-last-run")
+                .long("since-last-run")
+                .help("Copy only selected files modified since the last successful run of the same job"),
+        )
+        .arg(
+            Arg::with_name("filter-from")
+                .long("filter-from")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("FILE")
+                .help("Exclude files by the `+` and `-` rules of an rsync filter file"),
+        )
+        .arg(
+            Arg::with_name("exclude-gitignore")
+                .long("exclude-gitignore")
+                .help("Exclude files ignored by the .gitignore files of the source"),
+        )
+        .arg(
+            Arg::with_name("ext")
+                .long("ext")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("EXT")
+                .help("Copy only selected files with one of these extensions, repeatable or comma separated"),
+        )
+        .arg(
+            Arg::with_name("error-budget")
+                .long("error-budget")
+                .takes_value(true)
+                .value_name("N")
+                .help("Stop copying to a destination after N failures"),
+        )
+        .arg(
+            Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .conflicts_with("keep-going")
+                .help("Stop at the first file that fails to copy"),
+        )
+        .arg(
+            Arg::with_name("keep-going")
+                .long("keep-going")
+                .help("Copy the other files when one fails, the default"),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .takes_value(true)
+                .value_name("N")
+                .help("Try a copy failing with a transient error up to N more times, waiting longer each time"),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .value_name("SECS")
+                .help("Fail a file still copying after SECS seconds, like one on a dead mount, and copy the others"),
+        )
+        .arg(
+            Arg::with_name("run-timeout")
+                .long("run-timeout")
+                .takes_value(true)
+                .value_name("SECS")
+                .help("Stop the run after SECS seconds, failing the files still copying"),
+        )
+        .arg(
+            Arg::with_name("on-source-change")
+                .long("on-source-change")
+                .takes_value(true)
+                .possible_values(&crate::retry::OnChange::NAMES)
+                .help("Check that each local source is unchanged after it is copied: copy one that changed again, up to --retries times or once, or flag it as changed"),
+        )
+        .arg(
+            Arg::with_name("engine")
+                .long("engine")
+                .takes_value(true)
+                .visible_alias("copy-backend")
+                .possible_values(&Engine::NAMES)
+                .default_value("auto")
+                .help("Copy engine, auto picks one per file, copy_file_range on Linux"),
+        )
+        .arg(
+            Arg::with_name("compare")
+                .long("compare")
+                .takes_value(true)
+                .value_name("STRATEGY")
+                .possible_values(&Strategy::NAMES)
+                .help("How destination files are found identical, over the destination's `compare`"),
+        )
+        .arg(
+            Arg::with_name("dedup")
+                .long("dedup")
+                .takes_value(true)
+                .value_name("ACTION")
+                .possible_values(&Dedup::NAMES)
+                .help("Skip destination files with the content of their source whatever their times, link copies of content already at the destination"),
+        )
+        .arg(
+            Arg::with_name("eol")
+                .long("eol")
+                .takes_value(true)
+                .value_name("EOL")
+                .possible_values(&Eol::NAMES)
+                .help("Convert the line endings of text files, a file's own `eol` goes first"),
+        )
+        .arg(
+            Arg::with_name("chmod")
+                .long("chmod")
+                .takes_value(true)
+                .value_name("MODE")
+                .help("Octal mode for copied files, e.g. 0644, a file's or tag's `mode` goes first"),
+        )
+        .arg(
+            Arg::with_name("chown")
+                .long("chown")
+                .takes_value(true)
+                .value_name("USER:GROUP")
+                .help("Owner for copied files when running as root, over the destination's `owner`"),
+        )
+        .arg(
+            Arg::with_name("xattrs")
+                .long("xattrs")
+                .help("Copy extended attributes, ACLs and macOS resource forks along with the data"),
+        )
+        .arg(
+            Arg::with_name("ask")
+                .long("ask")
+                .help("Ask whether to overwrite, skip or diff each destination file that differs"),
+        )
+        .arg(
+            Arg::with_name("allow-overlap")
+                .long("allow-overlap")
+                .help("Copy even if the destination is the source, inside it or contains it"),
+        )
+        .arg(
+            Arg::with_name("no-lock")
+                .long("no-lock")
+                .help("Do not lock the destination against other cpx runs"),
+        )
+        .arg(
+            Arg::with_name("wait")
+                .long("wait")
+                .conflicts_with("no-lock")
+                .help("Wait for another cpx run copying to the destination instead of failing"),
+        )
+        .arg(
+            Arg::with_name("env")
+                .long("env")
+                .takes_value(true)
+                .value_name("KEY=VAL")
+                .multiple(true)
+                .number_of_values(1)
+                .help("Set KEY for the hooks, filters and post commands, over the `env` of the config and the tags"),
+        )
+        .arg(
+            Arg::with_name("no-hooks")
+                .long("no-hooks")
+                .help("Copy without running the pre and post hooks of the config and the tags"),
+        )
+        .arg(checksum_arg())
+        .arg(
+            Arg::with_name("no-hash-cache")
+                .long("no-hash-cache")
+                .help("Hash every compared file again instead of trusting hashes of earlier runs"),
+        )
+        .arg(
+            Arg::with_name("bwlimit")
+                .long("bwlimit")
+                .takes_value(true)
+                .value_name("RATE")
+                .help("Limit total copy throughput, e.g. 500K or 10M per second"),
+        )
+        .arg(
+            Arg::with_name("chunk-size")
+                .long("chunk-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .help("Copy files of 4 chunks or more in chunks of SIZE at once, 64M by default"),
+        )
+        .arg(
+            Arg::with_name("sync")
+                .long("sync")
+                .help("Fsync every destination file and its directory after copying"),
+        )
+        .arg(
+            Arg::with_name("no-atomic")
+                .long("no-atomic")
+                .help("Write destination files in place instead of renaming a temporary file"),
+        )
+        .arg(
+            Arg::with_name("temp-prefix")
+                .long("temp-prefix")
+                .takes_value(true)
+                .value_name("PREFIX")
+                .help("Start the names of temporary files with PREFIX instead of `.`, which hides them"),
+        )
+        .arg(
+            Arg::with_name("dir-mode")
+                .long("dir-mode")
+                .takes_value(true)
+                .help("Octal mode for created destination directories, e.g. 0755"),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .help("Print files copied, skipped and failed, bytes and throughput at the end"),
+        )
+        .arg(
+            Arg::with_name("progress-events")
+                .long("progress-events")
+                .takes_value(true)
+                .value_name("FD")
+                .help("Write the progress of the run as JSON lines to the file descriptor FD, for frontends"),
+        )
+        .arg(
+            Arg::with_name("stats-json")
+                .long("stats-json")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write the end of run statistics to FILE as JSON"),
+        )
+        .arg(
+            Arg::with_name("report")
+                .long("report")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write a report of the run with a table of its files to FILE, HTML for .html, else Markdown"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write per-file, per-phase copy timings to FILE"),
+        )
+        .arg(
+            Arg::with_name("profile-format")
+                .long("profile-format")
+                .takes_value(true)
+                .possible_values(&["csv", "folded"])
+                .default_value("csv")
+                .help("Profile output format, folded is flamegraph compatible"),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Output format, json emits one record per line"),
+        )
+        .arg(
+            Arg::with_name("print0")
+                .long("print0")
+                .alias("porcelain")
+                .conflicts_with_all(&["output", "format"])
+                .help("Print only the destination paths of written files, each ended by a NUL"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .takes_value(true)
+                .value_name("TEMPLATE")
+                .help("Text line per file, e.g. \"{status} {src} -> {dst} ({bytes})\""),
+        )
+        .arg(
+            Arg::with_name("no-journal")
+                .long("no-journal")
+                .help("Do not keep backups for cpx undo"),
+        )
+        .arg(
+            Arg::with_name("summary-diff")
+                .long("summary-diff")
+                .help("Show what changed since the previous run of the same job"),
+        )
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Restore destinations to their state before a run")
+                .arg(Arg::with_name("run-id").help("Run to undo, defaults to the most recent one"))
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Only show what would be restored"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("manifest")
+                .about("Write a checksum manifest of the selected files at the destination")
+                .arg(
+                    Arg::with_name("spec")
+                        .required(true)
+                        .help("destination path, or source and destination path"),
+                )
+                .arg(
+                    Arg::with_name("files")
+                        .help("copy files")
+                        .long("file")
+                        .takes_value(true)
+                        .required_unless("tags")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("tags")
+                        .help("copy files of tags")
+                        .long("tag")
+                        .takes_value(true)
+                        .required_unless("files")
+                        .multiple(true),
+                )
+                .arg(manifest_arg())
+                .arg(checksum_arg())
+                .arg(path_key_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Check a destination against its manifest")
+                .arg(
+                    Arg::with_name("spec")
+                        .required(true)
+                        .help("destination path, or source and destination path"),
+                )
+                .arg(manifest_arg())
+                .arg(checksum_arg().help("Algorithm of the default manifest file, the manifest names its own"))
+                .arg(path_key_arg().help("Key the manifest paths were hashed with")),
+        )
+        .subcommand(
+            SubCommand::with_name("resume")
+                .about("Continue a run that was interrupted, skipping the files it already copied")
+                .arg(Arg::with_name("run-id").help("Run to resume, defaults to the most recent one")),
+        )
+        .subcommand(SubCommand::with_name("man").about("Print the man page in roff format"))
+        .subcommand(
+            SubCommand::with_name("gc")
+                .about("Remove temporary files, old undo backups and snapshots cpx left behind")
+                .arg(
+                    Arg::with_name("spec")
+                        .required(true)
+                        .help("destination path, or source and destination path for snapshots"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Only show what would be removed"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Copy again whenever the selected source files change")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("args")
+                        .multiple(true)
+                        .help("spec and options of the copy, as without watch"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("schedule")
+                .about("Run the copies of the `schedule` config section")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("run")
+                        .about("Start the scheduled copies when due, until killed"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("explain")
+                .about("Show how a file would be resolved and copied, without copying")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .help("key of the file in file_list"),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .required(true)
+                        .multiple(true)
+                        .help("spec and options of the copy"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Check the config, its paths and the copy of a spec, without copying")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("args")
+                        .multiple(true)
+                        .help("spec and options of a copy to check"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Report the config entries nothing uses and the tags selecting nothing")
+                .arg(
+                    Arg::with_name("strict")
+                        .long("strict")
+                        .help("Exit with 1 when anything is reported, not only for errors"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("du")
+                .about("Show the files and bytes a copy selects, at its source and destination")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("args")
+                        .required(true)
+                        .multiple(true)
+                        .help("spec and options of the copy"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tree")
+                .about("Show the tags with their groups, files and scripts")
+                .arg(
+                    Arg::with_name("tags")
+                        .help("tags to show, all by default")
+                        .long("tag")
+                        .takes_value(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("src")
+                        .long("src")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Mark the files missing in this path of path_list"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("scan")
+                .about("List the files of a path matching `discover` that file_list lacks")
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .help("name of the path in path_list to scan"),
+                )
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Exit with 1 unless file_list matches the files on disk"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Print the copies of a run as a shell script, without copying")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&export::Shell::NAMES)
+                        .help("sh script or Windows batch file, that of the platform by default; may also follow the args"),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .required(true)
+                        .multiple(true)
+                        .help("spec and options of the copy"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Time copies of the selection by each engine and parallel level, into a scratch directory of the destination")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("levels")
+                        .long("levels")
+                        .takes_value(true)
+                        .value_name("LIST")
+                        .help("Parallel copies to try, 1,2,4,8 by default; may also follow the args"),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .required(true)
+                        .multiple(true)
+                        .help("spec and options of the copy"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("sync")
+                .about("Copy the files changed on either side of a spec to the other")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("args")
+                        .required(true)
+                        .multiple(true)
+                        .help("spec and options of the copy"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mirror")
+                .about("Copy every file of a spec's source that differs, listed in the config or not")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("args")
+                        .required(true)
+                        .multiple(true)
+                        .help("spec and options of the copy"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Work on the config files")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("migrate")
+                        .about("Print the configs in the current schema version")
+                        .arg(
+                            Arg::with_name("write")
+                                .long("write")
+                                .help("Replace the configs instead, keeping each old one as .bak"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tag")
+                .about("Work on the tags of the config")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("save")
+                        .about("Save the files and tags given as a new tag of the config")
+                        .arg(Arg::with_name("name").required(true).help("name of the tag"))
+                        .arg(
+                            Arg::with_name("files")
+                                .long("file")
+                                .takes_value(true)
+                                .multiple(true)
+                                .required_unless("tags")
+                                .help("files of the tag"),
+                        )
+                        .arg(
+                            Arg::with_name("tags")
+                                .long("tag")
+                                .takes_value(true)
+                                .multiple(true)
+                                .help("tags selected with the tag"),
+                        )
+                        .arg(
+                            Arg::with_name("user")
+                                .long("user")
+                                .help("Save to the user config instead of the last config"),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Replace a tag of the name in the config"),
+                        ),
+                ),
+        )
+        .subcommand(SubCommand::with_name("history").about("List previous runs"))
+        .subcommand(
+            SubCommand::with_name("rerun")
+                .about("Run a previous copy again with the same arguments")
+                .arg(
+                    Arg::with_name("n")
+                        .required(true)
+                        .help("Number of the run as shown by cpx history"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Run a copy of the profiles section by name")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("profile")
+                        .required(true)
+                        .help("name of the profile"),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .multiple(true)
+                        .help("more options of the copy, after those of the profile"),
+                ),
+        );
+
+    #[cfg(feature = "remote")]
+    let app = app.arg(
+        Arg::with_name("via")
+            .long("via")
+            .takes_value(true)
+            .value_name("TRANSPORT")
+            .possible_values(&Transport::NAMES)
+            .help("Transfer with cpx itself or a single rsync run, over the destination's `transport`"),
+    );
+
+    #[cfg(feature = "self-update")]
+    let app = app.subcommand(
+        SubCommand::with_name("self-update")
+            .about("Replace this binary with the latest signed release")
+            .arg(
+                Arg::with_name("check")
+                    .long("check")
+                    .help("Only show whether an update is available"),
+            )
+            .arg(
+                Arg::with_name("force")
+                    .long("force")
+                    .help("Install the release even if it is not newer"),
+            ),
+    );
+
+    #[cfg(all(unix, feature = "daemon"))]
+    let app = app
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("Serve cpx trigger with the config parsed and the sources watched")
+                .setting(AppSettings::TrailingVarArg)
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(
+                    Arg::with_name("args")
+                        .required(true)
+                        .multiple(true)
+                        .help("spec and options of the copies, tags come from cpx trigger"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("trigger")
+                .about("Have the daemon of the config copy tags")
+                .arg(
+                    Arg::with_name("tags")
+                        .required(true)
+                        .multiple(true)
+                        .help("tags to copy"),
+                ),
+        );
+
+    #[cfg(feature = "tui")]
+    let app = app.arg(
+        Arg::with_name("interactive")
+            .short("i")
+            .long("interactive")
+            .help("Pick tags and files interactively"),
+    );
+
+    #[cfg(feature = "archive")]
+    let app = app.arg(
+        Arg::with_name("archive")
+            .long("archive")
+            .takes_value(true)
+            .possible_values(&crate::archive::Format::NAMES)
+            .help("Write the destination as an archive, by default detected from its extension"),
+    );
+
+    #[cfg(feature = "completions")]
+    let app = app
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Generate shell completion scripts")
+                .arg(Arg::with_name("shell").required(true).possible_values(&[
+                    "bash",
+                    "zsh",
+                    "fish",
+                    "powershell",
+                ])),
+        )
+        .subcommand(
+            SubCommand::with_name("complete")
+                .setting(AppSettings::Hidden)
+                .about("List config keys for shell completion")
+                .arg(
+                    Arg::with_name("kind")
+                        .required(true)
+                        .possible_values(&["tags", "files", "paths", "profiles"]),
+                ),
+        );
+
+    app
+}
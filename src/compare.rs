@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::hashcache::HashCache;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    New,
+    Differs,
+    Identical,
+}
+
+/// How a destination file is found identical to its source, by default
+/// what the destination supports best.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Same size and the destination not older than the source.
+    SizeMtime,
+    /// Same content, read in full on both sides.
+    Hash,
+    /// The ETag of an S3 object matches the local file.
+    Etag,
+    /// Never identical, every file is copied.
+    Always,
+}
+
+impl Strategy {
+    pub const NAMES: [&'static str; 4] = ["size-mtime", "hash", "etag", "always"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "size-mtime" => Some(Strategy::SizeMtime),
+            "hash" => Some(Strategy::Hash),
+            "etag" => Some(Strategy::Etag),
+            "always" => Some(Strategy::Always),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::SizeMtime => "size-mtime",
+            Strategy::Hash => "hash",
+            Strategy::Etag => "etag",
+            Strategy::Always => "always",
+        }
+    }
+}
+
+/// Compare what a local copy of `src` would do to `dst` with `strategy`,
+/// by default the hashes in `hashes` or without a cache the content.
+pub fn compare_with(
+    src: &Path,
+    dst: &Path,
+    strategy: Option<Strategy>,
+    hashes: &HashCache,
+) -> io::Result<FileStatus> {
+    match strategy {
+        Some(Strategy::SizeMtime) => {
+            let dst_meta = match std::fs::metadata(dst) {
+                Ok(x) => x,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FileStatus::New),
+                Err(e) => return Err(e),
+            };
+            let src_meta = std::fs::metadata(src)?;
+            match src_meta.len() == dst_meta.len() && dst_meta.modified()? >= src_meta.modified()? {
+                true => Ok(FileStatus::Identical),
+                false => Ok(FileStatus::Differs),
+            }
+        }
+        Some(Strategy::Always) => match std::fs::symlink_metadata(dst) {
+            Ok(_) => Ok(FileStatus::Differs),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(FileStatus::New),
+            Err(e) => Err(e),
+        },
+        None if hashes.enabled() => {
+            let dst_meta = match std::fs::metadata(dst) {
+                Ok(x) => x,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FileStatus::New),
+                Err(e) => return Err(e),
+            };
+            if std::fs::metadata(src)?.len() != dst_meta.len() {
+                return Ok(FileStatus::Differs);
+            }
+            match hashes.hash(src)? == hashes.hash(dst)? {
+                true => Ok(FileStatus::Identical),
+                false => Ok(FileStatus::Differs),
+            }
+        }
+        _ => compare(src, dst),
+    }
+}
+
+/// Compare what a copy of `src` would do to `dst`.
+pub fn compare(src: &Path, dst: &Path) -> io::Result<FileStatus> {
+    let dst_meta = match std::fs::metadata(dst) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FileStatus::New),
+        Err(e) => return Err(e),
+    };
+
+    if std::fs::metadata(src)?.len() != dst_meta.len() {
+        return Ok(FileStatus::Differs);
+    }
+
+    if same_content(src, dst)? {
+        Ok(FileStatus::Identical)
+    } else {
+        Ok(FileStatus::Differs)
+    }
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let mut a = File::open(a)?;
+    let mut b = File::open(b)?;
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+
+    loop {
+        let n = read_full(&mut a, &mut buf_a)?;
+        let m = read_full(&mut b, &mut buf_b)?;
+        if n != m || buf_a[..n] != buf_b[..m] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match r.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+
+    Ok(filled)
+}
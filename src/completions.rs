@@ -0,0 +1,99 @@
+use clap::Shell;
+
+use crate::cli;
+
+const BASH_DYNAMIC: &str = r#"
+_cpx_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" i kind=""
+    if [[ "${cur}" != -* ]]; then
+        for ((i = COMP_CWORD - 1; i > 0; i--)); do
+            case "${COMP_WORDS[i]}" in
+                --tag) kind=tags; break ;;
+                --file) kind=files; break ;;
+                run) [[ ${i} -eq $((COMP_CWORD - 1)) ]] && kind=profiles; break ;;
+                -*) break ;;
+            esac
+        done
+    fi
+
+    if [[ -n "${kind}" ]]; then
+        COMPREPLY=($(compgen -W "$(cpx complete ${kind} 2>/dev/null)" -- "${cur}"))
+        return 0
+    fi
+
+    _cpx "$@"
+}
+
+complete -F _cpx_dynamic -o bashdefault -o default cpx
+"#;
+
+const ZSH_DYNAMIC: &str = r#"
+_cpx_config_keys() {
+    local -a keys
+    keys=(${(f)"$(cpx complete "$1" 2>/dev/null)"})
+    compadd -a keys
+}
+"#;
+
+const FISH_DYNAMIC: &str = r#"complete -c cpx -n "__fish_use_subcommand" -l tag -r -f -a "(cpx complete tags 2>/dev/null)"
+complete -c cpx -n "__fish_use_subcommand" -l file -r -f -a "(cpx complete files 2>/dev/null)"
+complete -c cpx -n "__fish_seen_subcommand_from run" -f -a "(cpx complete profiles 2>/dev/null)"
+"#;
+
+const POWERSHELL_DYNAMIC: &str = r#"
+    $option = $null
+    foreach ($element in $commandElements) {
+        if ($element.Extent.StartOffset -ge $cursorPosition) { break }
+        $text = $element.ToString()
+        if ($text -ne $wordToComplete -and $text.StartsWith('-')) { $option = $text }
+    }
+    if ($option -eq '--tag' -or $option -eq '--file') {
+        $kind = if ($option -eq '--tag') { 'tags' } else { 'files' }
+        $completions = @(cpx complete $kind 2>$null | ForEach-Object {
+            [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)
+        })
+    }
+
+    $completions.Where"#;
+
+/// Print a completion script for `shell`.
+///
+/// The scripts clap generates only know about flags, so values for `--tag`
+/// and `--file` are looked up at completion time through `cpx complete`.
+pub fn generate(shell: &str, default_config: &str) {
+    let mut script = vec![];
+    cli::app(default_config).gen_completions_to(
+        "cpx",
+        shell.parse::<Shell>().unwrap(),
+        &mut script,
+    );
+    let script = String::from_utf8(script).unwrap();
+
+    let script = match shell {
+        "bash" => script.replace(
+            "\ncomplete -F _cpx -o bashdefault -o default cpx\n",
+            BASH_DYNAMIC,
+        ),
+        "zsh" => script
+            .replacen("\n_cpx() {", &format!("{}\n_cpx() {{", ZSH_DYNAMIC), 1)
+            .replace(
+                "'*--file=[copy files]' \\",
+                "'*--file=[copy files]: :_cpx_config_keys files' \\",
+            )
+            .replace(
+                "'*--tag=[copy files of tags]' \\",
+                "'*--tag=[copy files of tags]: :_cpx_config_keys tags' \\",
+            ),
+        "fish" => {
+            script
+                .lines()
+                .filter(|x| !x.ends_with(" -l tag") && !x.contains(" -l file "))
+                .map(|x| format!("{}\n", x))
+                .collect::<String>()
+                + FISH_DYNAMIC
+        }
+        _ => script.replacen("\n    $completions.Where", POWERSHELL_DYNAMIC, 1),
+    };
+
+    print!("{}", script);
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Which sources are expanded while copied, `decompress` of a file or its
+/// tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decompress {
+    /// Those ending in `.gz` or `.zst`, and those found only with one of
+    /// the extensions appended.
+    Auto,
+}
+
+/// How the copy of a file is compressed, `compress` of the file or its
+/// tag. The copy gets the extension of the format appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    const ALL: [Compression; 2] = [Compression::Gzip, Compression::Zstd];
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    /// The format `path` is compressed in by its extension.
+    pub fn detect(path: &Path) -> Option<Self> {
+        let ext = path.extension()?;
+        Self::ALL.iter().copied().find(|x| ext == x.extension())
+    }
+
+    /// `path` with the extension of its format removed, as it is.
+    pub fn strip(path: &Path) -> PathBuf {
+        match Self::detect(path) {
+            Some(_) => path.with_extension(""),
+            None => path.to_path_buf(),
+        }
+    }
+
+    /// `path` with the extension of a format appended, if `root` has such
+    /// a file and not `path` itself.
+    pub fn find(root: &Path, path: &Path) -> Option<PathBuf> {
+        if Self::detect(path).is_some() || root.join(path).exists() {
+            return None;
+        }
+        Self::ALL
+            .iter()
+            .map(|x| x.path(path))
+            .find(|x| root.join(x).is_file())
+    }
+
+    /// `path` with the extension appended, `app.log` as `app.log.gz`.
+    pub fn path(self, path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".");
+        name.push(self.extension());
+        PathBuf::from(name)
+    }
+
+    /// `data` compressed, the same output for the same input so unchanged
+    /// files are found identical.
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "archive")]
+            Compression::Gzip => {
+                use std::io::Write;
+
+                // without a name and time in the header
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            #[cfg(not(feature = "archive"))]
+            Compression::Gzip => {
+                let mut cmd = Command::new("gzip");
+                cmd.args(["-c", "-n"]);
+                crate::crypt::pipe(cmd, "gzip", data)
+            }
+            Compression::Zstd => {
+                let mut cmd = Command::new("zstd");
+                cmd.args(["-c", "-q"]);
+                crate::crypt::pipe(cmd, "zstd", data)
+            }
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "archive")]
+            Compression::Gzip => {
+                use std::io::Read;
+
+                let mut out = vec![];
+                flate2::read::MultiGzDecoder::new(data).read_to_end(&mut out)?;
+                Ok(out)
+            }
+            #[cfg(not(feature = "archive"))]
+            Compression::Gzip => {
+                let mut cmd = Command::new("gzip");
+                cmd.args(["-d", "-c"]);
+                crate::crypt::pipe(cmd, "gzip", data)
+            }
+            Compression::Zstd => {
+                let mut cmd = Command::new("zstd");
+                cmd.args(["-d", "-c", "-q"]);
+                crate::crypt::pipe(cmd, "zstd", data)
+            }
+        }
+    }
+}
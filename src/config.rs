@@ -0,0 +1,524 @@
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::state::{fnv1a, state_dir};
+
+/// Names of a project config, looked for from the current directory up.
+const NAMES: [&str; 2] = ["cpx.yaml", ".cpx.yaml"];
+
+/// The -c reading the config from stdin.
+pub const STDIN: &str = "-";
+
+/// Texts of the configs read from stdin and URLs, which are read once
+/// however often the run loads its configs.
+static TEXTS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// The config used without -c: the nearest project config in the
+/// current directory or above it, like git finds its repository, or
+/// else the user config.
+pub fn default_path() -> String {
+    let found = std::env::current_dir().ok().and_then(|x| find_up(&x));
+    let path = found.or_else(user_config);
+    path.and_then(|x| x.to_str().map(|x| x.to_owned()))
+        .unwrap_or_else(|| NAMES[0].to_owned())
+}
+
+/// The configs of a run, each over the ones before it: those given by
+/// -c in order, or else the user config under the default one.
+pub fn paths(m: &clap::ArgMatches) -> Vec<String> {
+    let mut paths: Vec<String> = m
+        .values_of("config")
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    if m.occurrences_of("config") > 0 || std::env::var_os("CPX_CONFIG").is_some() {
+        return paths;
+    }
+
+    // a project config goes over the config of the machine
+    let user = user_config()
+        .filter(|x| x.is_file())
+        .and_then(|x| x.to_str().map(String::from))
+        .filter(|x| !paths.contains(x));
+    paths.splice(0..0, user);
+    paths
+}
+
+/// `$XDG_CONFIG_HOME/cpx/config.yaml` if there is one, else ~/cpx.yaml.
+fn user_config() -> Option<PathBuf> {
+    xdg_config()
+        .filter(|x| x.is_file())
+        .or_else(|| dirs::home_dir().map(|x| x.join(NAMES[0])))
+}
+
+fn xdg_config() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|x| x.is_absolute())
+        .or_else(|| dirs::home_dir().map(|x| x.join(".config")))
+        .map(|x| x.join("cpx").join("config.yaml"))
+}
+
+/// The user config to write to, a new one under `$XDG_CONFIG_HOME` when
+/// there is none.
+pub fn user_path() -> Option<PathBuf> {
+    user_config().filter(|x| x.is_file()).or_else(xdg_config)
+}
+
+fn find_up(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|x| NAMES.iter().map(move |name| x.join(name)))
+        .find(|x| x.is_file())
+}
+
+/// The config of `paths` layered. A config that does not fit names the
+/// file and line of the value at fault with its place in the config, like
+/// `cpx.yaml:12: tag_list.web: unknown field `file_lst``.
+pub fn load<T: DeserializeOwned>(paths: &[String]) -> Result<T, String> {
+    let merged = read(paths).map_err(|e| e.to_string())?;
+    // the YAML text tells where the error is, a Value does not
+    let text = serde_yaml::to_string(&merged).map_err(|e| e.to_string())?;
+    let e = match serde_yaml::from_str(&text) {
+        Ok(x) => return Ok(x),
+        Err(e) => e.to_string(),
+    };
+
+    // `<path>: <message> at line <n> column <n>`, the line is of the text
+    let e = match e.rsplit_once(" at line ") {
+        Some((e, _)) => e,
+        None => &e,
+    };
+    let (at, message) = match e.split_once(": ") {
+        Some((at, message)) if !at.contains(' ') => (Some(at), message),
+        _ => (None, e),
+    };
+
+    // the place in the last config that has it, an unknown field under it
+    let mut keys: Vec<_> = at
+        .into_iter()
+        .flat_map(|x| x.split('.'))
+        .map(|x| x.split('[').next().unwrap())
+        .collect();
+    let unknown = message
+        .strip_prefix("unknown field `")
+        .and_then(|x| x.split('`').next());
+    keys.extend(unknown);
+    let found = paths.iter().rev().find_map(|path| {
+        let text = self::text(path).ok()?;
+        line_of(&text, &keys).map(|line| format!("{}:{}", path, line))
+    });
+    let at = at.map(|x| format!("{}: ", x)).unwrap_or_default();
+    match found {
+        Some(file) => Err(format!("{}: {}{}", file, at, message)),
+        None => Err(format!("{}{}", at, message)),
+    }
+}
+
+/// Line, from 1, of the mapping key at `keys` in the YAML `text`, each
+/// key looked for after the one before it.
+fn line_of(text: &str, keys: &[&str]) -> Option<usize> {
+    if keys.is_empty() {
+        return None;
+    }
+    let lines: Vec<_> = text.lines().collect();
+    let mut line = 0;
+    for key in keys {
+        let quoted = [
+            format!("{}:", key),
+            format!("\"{}\":", key),
+            format!("'{}':", key),
+        ];
+        let is_key = |x: &str| {
+            quoted.iter().any(|q| {
+                x.match_indices(q.as_str()).any(|(i, _)| {
+                    let before = x[..i].trim_end();
+                    before.is_empty() || before.ends_with(['{', ',', '-'])
+                })
+            })
+        };
+        line += lines[line..].iter().position(|x| is_key(x))?;
+    }
+
+    Some(line + 1)
+}
+
+/// The YAML of `paths` layered: mappings are merged key by key, any
+/// other value of a later config replaces the earlier one.
+pub fn read(paths: &[String]) -> io::Result<Value> {
+    let mut merged = Value::Null;
+    for path in paths {
+        let value: io::Result<Value> = text(path).and_then(|text| {
+            let mut value = serde_yaml::from_str(&text)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            migrate(&mut value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Ok(value)
+        });
+        let value = value.map_err(|e| io::Error::new(e.kind(), format!("{}, {}", path, e)))?;
+        // an empty config adds nothing
+        if !value.is_null() {
+            merge(&mut merged, value);
+        }
+    }
+
+    apply_overrides(&mut merged)?;
+    Ok(merged)
+}
+
+/// The YAML of the config `path`, a file, `-` for stdin or an HTTP(S)
+/// URL.
+pub fn text(path: &str) -> io::Result<String> {
+    let remote = crate::http::url(Path::new(path)).is_some();
+    if path != STDIN && !remote {
+        return std::fs::read_to_string(path);
+    }
+    if let Some(x) = TEXTS.lock().unwrap().get_or_insert_default().get(path) {
+        return Ok(x.clone());
+    }
+
+    let text = match remote {
+        true => fetch(path)?,
+        false => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+    let mut texts = TEXTS.lock().unwrap();
+    texts
+        .get_or_insert_default()
+        .insert(path.to_owned(), text.clone());
+    Ok(text)
+}
+
+/// The config at `url`, sent by the server only when it changed since
+/// the copy cpx keeps of it. The copy is used when the server cannot be
+/// reached.
+fn fetch(url: &str) -> io::Result<String> {
+    let cache = state_dir()
+        .join("configs")
+        .join(format!("{:016x}.yaml", fnv1a(url)));
+    let cached = cache.is_file();
+    let fetched =
+        crate::http::get(url, Some(cache.as_path()).filter(|_| cached)).and_then(|mut child| {
+            let mut body = vec![];
+            if let Some(mut x) = child.stdout.take() {
+                x.read_to_end(&mut body)?;
+            }
+            Ok(crate::http::finish(child)?.then_some(body))
+        });
+
+    match fetched {
+        Ok(Some(body)) => {
+            if let Err(e) = crate::store::write(&cache, &body) {
+                log::warn!("Keep a copy of config {} failed, {}", url, e);
+            }
+            String::from_utf8(body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        Ok(None) => std::fs::read_to_string(&cache),
+        Err(e) if cached => {
+            log::warn!(
+                "Fetch config {} failed, {}, using the copy of {}",
+                url,
+                e,
+                cache.display()
+            );
+            std::fs::read_to_string(&cache)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Merge the `overrides` entries matching this machine over the rest of
+/// the config, in order. An entry matches when `host` is the hostname,
+/// with or without its domain, and `env` is a variable that is set, or
+/// `NAME=value` for one with that value.
+fn apply_overrides(config: &mut Value) -> io::Result<()> {
+    let overrides = match config {
+        Value::Mapping(x) => x.remove(&Value::from("overrides")),
+        _ => None,
+    };
+    let invalid = |e: &str| io::Error::new(io::ErrorKind::InvalidData, format!("overrides, {}", e));
+    let overrides = match overrides {
+        Some(Value::Sequence(x)) => x,
+        Some(Value::Null) | None => return Ok(()),
+        Some(_) => return Err(invalid("not a list")),
+    };
+
+    let host = hostname();
+    for entry in overrides {
+        let mut entry = match entry {
+            Value::Mapping(x) => x,
+            _ => return Err(invalid("an entry is not a mapping")),
+        };
+        let mut condition = |key: &str| match entry.remove(&Value::from(key)) {
+            Some(Value::String(x)) => Ok(Some(x)),
+            None => Ok(None),
+            Some(_) => Err(invalid(&format!("`{}` is not a string", key))),
+        };
+        let (on_host, on_env) = (condition("host")?, condition("env")?);
+        if on_host.is_none() && on_env.is_none() {
+            return Err(invalid("an entry has no `host` or `env`"));
+        }
+
+        let host_matches = on_host.is_none_or(|x| {
+            host.as_deref().is_some_and(|host| {
+                let short = host.split('.').next().unwrap_or(host);
+                x.eq_ignore_ascii_case(host) || x.eq_ignore_ascii_case(short)
+            })
+        });
+        let env_matches = on_env.is_none_or(|x| match x.split_once('=') {
+            Some((name, value)) => std::env::var(name).is_ok_and(|x| x == value),
+            None => std::env::var_os(&x).is_some_and(|x| !x.is_empty()),
+        });
+        if host_matches && env_matches {
+            merge(config, Value::Mapping(entry));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|x| *x == 0).unwrap_or(buf.len());
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// Version of the config schema, in `version:` of a config.
+pub const VERSION: u64 = 2;
+
+/// Steps from each older version to the next, the first from version 1.
+const MIGRATIONS: [fn(&mut Mapping); 1] = [v1_to_v2];
+
+/// Bring `config` to the current `version`, a config without one is of
+/// version 1. Whether it changed.
+pub fn migrate(config: &mut Value) -> Result<bool, String> {
+    let config = match config {
+        Value::Mapping(x) => x,
+        _ => return Ok(false),
+    };
+    let key = Value::from("version");
+    let version = match config.get(&key) {
+        None => 1,
+        Some(x) => x
+            .as_u64()
+            .filter(|x| *x > 0)
+            .ok_or_else(|| "version: not a version number".to_owned())?,
+    };
+    if version > VERSION {
+        return Err(format!(
+            "version {} is newer than version {} of this cpx, update cpx",
+            version, VERSION
+        ));
+    }
+    if version == VERSION {
+        return Ok(false);
+    }
+
+    for step in &MIGRATIONS[version as usize - 1..] {
+        step(config);
+    }
+    // first in the file, where it is seen
+    let mut migrated = Mapping::new();
+    migrated.insert(key.clone(), Value::from(VERSION));
+    migrated.extend(
+        std::mem::take(config)
+            .into_iter()
+            .filter(|(k, _)| *k != key),
+    );
+    *config = migrated;
+    Ok(true)
+}
+
+/// Version 2 has `version` and no longer needs the empty `tag_list`,
+/// `file_list` and `script_list` version 1 had to have.
+fn v1_to_v2(config: &mut Mapping) {
+    for name in ["tag_list", "file_list", "script_list"] {
+        let key = Value::from(name);
+        let empty = match config.get(&key) {
+            Some(Value::Mapping(x)) => x.is_empty(),
+            Some(Value::Null) => true,
+            _ => false,
+        };
+        if empty {
+            config.remove(&key);
+        }
+    }
+}
+
+fn merge(base: &mut Value, over: Value) {
+    match (base, over) {
+        (Value::Mapping(base), Value::Mapping(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(x) => merge(x, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+/// Add `key: value` to the mapping `section` of the config file `path`,
+/// replacing the entry there with `replace`. The entry is written on one
+/// line below `section:` so the rest of the file stays as it is, a
+/// config it does not fit into is written anew, keeping the old one as
+/// .bak.
+pub fn save_entry(
+    path: &Path,
+    section: &str,
+    key: &str,
+    value: Value,
+    replace: bool,
+) -> io::Result<()> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let text = match std::fs::read_to_string(path) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    let mut config: Value = match text.trim().is_empty() {
+        true => Value::Null,
+        false => serde_yaml::from_str(&text).map_err(|e| invalid(e.to_string()))?,
+    };
+    let exists = config.get(section).and_then(|x| x.get(key)).is_some();
+    if exists && !replace {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{}.{} exists already", section, key),
+        ));
+    }
+
+    let line = format!("{}: {}", flow(&key.into()), flow(&value));
+    if config.is_null() {
+        config = Value::Mapping(Mapping::new());
+    }
+    let entries = match &mut config {
+        Value::Mapping(x) => x,
+        _ => return Err(invalid(format!("{} is not a mapping", path.display()))),
+    };
+    let section_key = Value::from(section);
+    if !entries.get(&section_key).is_some_and(Value::is_mapping) {
+        entries.insert(section_key.clone(), Value::Mapping(Mapping::new()));
+    }
+    if let Some(Value::Mapping(x)) = entries.get_mut(&section_key) {
+        // in its place, the edited text keeps it there
+        match x.get_mut(&Value::from(key)) {
+            Some(old) => *old = value,
+            None => {
+                x.insert(Value::from(key), value);
+            }
+        }
+    }
+
+    // the edited text must read as the config with the entry
+    let edited = put_line(&text, section, key, &line);
+    if serde_yaml::from_str::<Value>(&edited).ok().as_ref() == Some(&config) {
+        return crate::store::write(path, edited.as_bytes());
+    }
+
+    let mut text = serde_yaml::to_string(&config).map_err(|e| invalid(e.to_string()))?;
+    text.push('\n');
+    // the YAML is written anew, without the comments of the old one
+    if path.is_file() {
+        let backup = format!("{}.bak", path.display());
+        std::fs::copy(path, &backup)?;
+        log::warn!("Rewrote {}, the old config is {}", path.display(), backup);
+    }
+    crate::store::write(path, text.as_bytes())
+}
+
+/// `text` with the entry `key` of the block mapping `section` as `line`:
+/// in place of the one line of the entry there is, or else as the last
+/// entry indented like the others, or with the section added at the end
+/// when it has none.
+fn put_line(text: &str, section: &str, key: &str, line: &str) -> String {
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    let head = format!("{}:", section);
+    let at = lines.iter().position(|x| {
+        x.strip_prefix(&head)
+            .is_some_and(|rest| rest.trim().is_empty() || rest.trim_start().starts_with('#'))
+    });
+    let at = match at {
+        Some(x) => x,
+        None => {
+            lines.extend([head, format!("  {}", line)]);
+            return lines.join("\n") + "\n";
+        }
+    };
+
+    let indent_of = |x: &str| x.len() - x.trim_start().len();
+    let entries: Vec<_> = lines[at + 1..]
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| !x.trim().is_empty() && !x.trim_start().starts_with('#'))
+        .take_while(|(_, x)| indent_of(x) > 0)
+        .map(|(i, x)| (at + 1 + i, indent_of(x)))
+        .collect();
+    let indent = entries.first().map_or(2, |x| x.1);
+    let entry = format!("{}:", flow(&key.into()));
+    let own = entries
+        .iter()
+        .enumerate()
+        .find(|(_, (i, n))| *n == indent && lines[*i].trim_start().starts_with(&entry));
+    match own {
+        // an entry on more lines is left to the rewrite
+        Some((k, (i, _))) if entries.get(k + 1).is_none_or(|x| x.1 == indent) => {
+            lines[*i] = format!("{}{}", " ".repeat(indent), line)
+        }
+        _ => {
+            let end = entries.last().map_or(at, |x| x.0);
+            lines.insert(end + 1, format!("{}{}", " ".repeat(indent), line))
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// `value` on one line, in the flow style of the sample config.
+fn flow(value: &Value) -> String {
+    match value {
+        Value::Mapping(x) => {
+            let entries: Vec<_> = x
+                .iter()
+                .map(|(k, v)| format!("{}: {}", flow(k), flow(v)))
+                .collect();
+            format!("{{ {} }}", entries.join(", "))
+        }
+        Value::Sequence(x) => {
+            let items: Vec<_> = x.iter().map(flow).collect();
+            format!("[{}]", items.join(", "))
+        }
+        // plain when it cannot be taken for anything else
+        Value::String(x)
+            if !x.is_empty()
+                && !x.starts_with(['-', '.'])
+                && x.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-./".contains(c))
+                && serde_yaml::from_str::<Value>(x).ok().as_ref() == Some(value) =>
+        {
+            x.clone()
+        }
+        Value::String(x) => serde_json::to_string(x).unwrap(),
+        _ => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim_end()
+            .to_owned(),
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Lines of context around the changes of a diff.
+const CONTEXT: usize = 3;
+
+/// Diffs needing more cells than this are not shown.
+const MAX_CELLS: usize = 1 << 22;
+
+/// What to do with a destination file that exists and differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Choice {
+    Overwrite,
+    Skip,
+}
+
+/// The questions of --ask, one at a time, with an answer for all
+/// remembered.
+#[derive(Debug, Default)]
+pub struct Ask {
+    all: Mutex<Option<Choice>>,
+}
+
+impl Ask {
+    /// Ask about the destination `shown`, `files` are the local
+    /// destination and source to diff, if they can be. Without an answer
+    /// the file is left as it is.
+    pub fn resolve(&self, shown: &Path, files: Option<(&Path, &Path)>) -> Choice {
+        // held while asking, the other copies wait for the answer
+        let mut all = self.all.lock().unwrap();
+        if let Some(x) = *all {
+            return x;
+        }
+
+        let options = match files {
+            Some(_) => "[o]verwrite, [s]kip, [d]iff, [O]verwrite all, [S]kip all",
+            None => "[o]verwrite, [s]kip, [O]verwrite all, [S]kip all",
+        };
+        loop {
+            eprint!("{} exists and differs, {}? ", shown.display(), options);
+            let answer = match read_answer() {
+                Ok(Some(x)) => x,
+                _ => return Choice::Skip,
+            };
+            match (answer.trim(), files) {
+                ("o", _) => return Choice::Overwrite,
+                ("s", _) => return Choice::Skip,
+                ("O", _) => return *all.insert(Choice::Overwrite),
+                ("S", _) => return *all.insert(Choice::Skip),
+                ("d", Some((dst, src))) => match (std::fs::read(dst), std::fs::read(src)) {
+                    (Ok(old), Ok(new)) => eprint!("{}", diff(dst, &old, src, &new)),
+                    (Err(e), _) | (_, Err(e)) => eprintln!("Read for the diff failed, {}", e),
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A line from the terminal, or from stdin without one. `None` at the
+/// end of the input.
+fn read_answer() -> io::Result<Option<String>> {
+    io::stderr().flush()?;
+    let mut line = String::new();
+    let n = match std::fs::File::open("/dev/tty") {
+        Ok(tty) => io::BufReader::new(tty).read_line(&mut line)?,
+        Err(_) => io::stdin().lock().read_line(&mut line)?,
+    };
+    Ok(Some(line).filter(|_| n > 0))
+}
+
+/// Unified diff turning `old` into `new`, for text files.
+fn diff(old_path: &Path, old: &[u8], new_path: &Path, new: &[u8]) -> String {
+    if !crate::transform::is_text(old) || !crate::transform::is_text(new) {
+        return "Binary files differ\n".to_owned();
+    }
+    let (old, new) = (String::from_utf8_lossy(old), String::from_utf8_lossy(new));
+    let (a, b): (Vec<_>, Vec<_>) = (old.lines().collect(), new.lines().collect());
+    let ops = match edits(&a, &b) {
+        Some(x) => x,
+        None => return "Files too large to diff\n".to_owned(),
+    };
+
+    // lines of old and new before each edit
+    let mut pos = vec![(0, 0)];
+    for (op, _) in &ops {
+        let (x, y) = pos[pos.len() - 1];
+        pos.push(match op {
+            ' ' => (x + 1, y + 1),
+            '-' => (x + 1, y),
+            _ => (x, y + 1),
+        });
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_path.display(), new_path.display());
+    let mut i = 0;
+    while let Some(start) = (i..ops.len()).find(|&k| ops[k].0 != ' ') {
+        // changes closer than twice the context share a hunk
+        let mut end = start;
+        loop {
+            while end < ops.len() && ops[end].0 != ' ' {
+                end += 1;
+            }
+            let same = ops[end..].iter().take_while(|x| x.0 == ' ').count();
+            if end + same == ops.len() || same > 2 * CONTEXT {
+                break;
+            }
+            end += same;
+        }
+
+        let lo = start.saturating_sub(CONTEXT).max(i);
+        let hi = (end + CONTEXT).min(ops.len());
+        let range = |from: usize, to: usize| match to - from {
+            0 => format!("{},0", from),
+            n => format!("{},{}", from + 1, n),
+        };
+        out.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(pos[lo].0, pos[hi].0),
+            range(pos[lo].1, pos[hi].1)
+        ));
+        for (op, line) in &ops[lo..hi] {
+            out.push_str(&format!("{}{}\n", op, line));
+        }
+        i = hi;
+    }
+
+    out
+}
+
+/// The lines of `a` and `b` as kept, ` `, removed, `-`, and added, `+`,
+/// by a longest common subsequence.
+fn edits<'a>(a: &[&'a str], b: &[&'a str]) -> Option<Vec<(char, &'a str)>> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (x, y) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    if (x.len() + 1).saturating_mul(y.len() + 1) > MAX_CELLS {
+        return None;
+    }
+
+    // lcs[i][j], the common lines of x[i..] and y[j..]
+    let width = y.len() + 1;
+    let mut lcs = vec![0u32; (x.len() + 1) * width];
+    for i in (0..x.len()).rev() {
+        for j in (0..y.len()).rev() {
+            lcs[i * width + j] = match x[i] == y[j] {
+                true => lcs[(i + 1) * width + j + 1] + 1,
+                false => lcs[(i + 1) * width + j].max(lcs[i * width + j + 1]),
+            };
+        }
+    }
+
+    let mut ops: Vec<_> = a[..prefix].iter().map(|x| (' ', *x)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < x.len() || j < y.len() {
+        if i < x.len() && j < y.len() && x[i] == y[j] {
+            ops.push((' ', x[i]));
+            i += 1;
+            j += 1;
+        } else if j == y.len()
+            || (i < x.len() && lcs[(i + 1) * width + j] >= lcs[i * width + j + 1])
+        {
+            ops.push(('-', x[i]));
+            i += 1;
+        } else {
+            ops.push(('+', y[j]));
+            j += 1;
+        }
+    }
+    ops.extend(a[a.len() - suffix..].iter().map(|x| (' ', *x)));
+
+    Some(ops)
+}
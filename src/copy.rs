@@ -0,0 +1,992 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::{Mutex, OnceLock};
+
+use crate::checksum::Expected;
+#[cfg(feature = "remote")]
+use crate::hashcache::HashCache;
+use crate::profile::{FileProfile, Phase};
+#[cfg(feature = "remote")]
+use crate::remote::Remote;
+#[cfg(feature = "remote")]
+use crate::s3::Bucket;
+use crate::throttle::RateLimit;
+use crate::timeout;
+#[cfg(feature = "remote")]
+use crate::webdav::Dav;
+
+const BUFFER_SIZE: usize = 128 * 1024;
+const SMALL_FILE: u64 = 64 * 1024;
+const RESUMABLE_FILE: u64 = 1024 * 1024 * 1024;
+/// Ranges of a chunked copy, files of at least `CHUNK_WORKERS` of them
+/// are chunked in auto mode.
+pub const CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+const CHUNK_WORKERS: u64 = 4;
+/// The longest file name most filesystems take, in bytes.
+const NAME_MAX: usize = 255;
+
+/// Start of the names of temporary files, --temp-prefix.
+static TEMP_PREFIX: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Engine {
+    Auto,
+    Reflink,
+    CopyFileRange,
+    Chunked,
+    Small,
+    Buffered,
+}
+
+impl Engine {
+    pub const NAMES: [&'static str; 6] = [
+        "auto",
+        "reflink",
+        "copy_file_range",
+        "chunked",
+        "small",
+        "buffered",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Engine::Auto),
+            "reflink" => Some(Engine::Reflink),
+            "copy_file_range" => Some(Engine::CopyFileRange),
+            "chunked" => Some(Engine::Chunked),
+            "small" => Some(Engine::Small),
+            "buffered" => Some(Engine::Buffered),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Engine::Auto => "auto",
+            Engine::Reflink => "reflink",
+            Engine::CopyFileRange => "copy_file_range",
+            Engine::Chunked => "chunked",
+            Engine::Small => "small",
+            Engine::Buffered => "buffered",
+        }
+    }
+}
+
+/// What a (source device, destination device) pair turned out to support.
+#[derive(Debug, Clone, Copy, Default)]
+struct Support {
+    no_reflink: bool,
+    no_copy_file_range: bool,
+}
+
+/// Copies files with either a forced engine or one picked per file.
+///
+/// In auto mode reflink is tried first, then `copy_file_range`, then a
+/// buffered copy; whatever a device pair refuses is remembered so later
+/// files skip straight to the engine that works. Small files skip the
+/// reflink, `copy_file_range` copies them in one call on Linux.
+///
+/// With a rate limit every file goes through the buffered copy, the
+/// kernel side engines cannot be paced.
+///
+/// Atomic copies write a temporary file next to the destination and rename
+/// it into place, readers never see a half written file.
+pub struct Copier {
+    engine: Engine,
+    support: Mutex<HashMap<(u64, u64), Support>>,
+    limit: Option<RateLimit>,
+    atomic: bool,
+    sync: bool,
+    chunk_size: u64,
+}
+
+impl Copier {
+    pub fn new(
+        engine: Engine,
+        bwlimit: Option<u64>,
+        atomic: bool,
+        sync: bool,
+        chunk_size: u64,
+    ) -> Self {
+        Self {
+            engine,
+            support: Mutex::new(HashMap::new()),
+            limit: bwlimit.map(RateLimit::new),
+            atomic,
+            sync,
+            chunk_size,
+        }
+    }
+
+    /// Apply the source permissions and with `sync` flush the data to disk.
+    fn finish(
+        &self,
+        writer: &File,
+        meta: &std::fs::Metadata,
+        prof: &mut FileProfile,
+    ) -> io::Result<()> {
+        writer.set_permissions(meta.permissions())?;
+        if self.sync {
+            prof.measure(Phase::Fsync, || writer.sync_all())?;
+        }
+
+        Ok(())
+    }
+
+    /// With `sync`, flush the directory entry of `dst` too.
+    fn sync_parent(&self, dst: &Path, prof: &mut FileProfile) -> io::Result<()> {
+        #[cfg(unix)]
+        if let Some(parent) = dst.parent().filter(|_| self.sync) {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            prof.measure(Phase::Fsync, || File::open(parent)?.sync_all())?;
+        }
+
+        #[cfg(not(unix))]
+        let _ = (dst, prof);
+
+        Ok(())
+    }
+
+    /// Copy `src` to `dst` on a remote host, `stale` remote files are
+    /// removed first.
+    #[cfg(feature = "remote")]
+    pub fn upload(
+        &self,
+        src: &Path,
+        remote: &Remote,
+        dst: &Path,
+        stale: &[PathBuf],
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let reader = File::open(src)?;
+        let meta = prof.measure(Phase::Stat, || reader.metadata())?;
+
+        let tmp = temp_path(dst, std::process::id());
+        let tmp = Some(tmp.as_path()).filter(|_| self.atomic);
+        let child = remote.upload(dst, tmp, meta.len(), mode(&meta), self.sync, stale)?;
+
+        prof.bytes = self.feed(&reader, child, |x| remote.finish(x), prof)?;
+        Ok(prof.bytes)
+    }
+
+    /// Copy `src` to `dst` on a WebDAV server, `stale` files there are
+    /// removed first.
+    #[cfg(feature = "remote")]
+    pub fn upload_webdav(
+        &self,
+        src: &Path,
+        dav: &Dav,
+        dst: &Path,
+        stale: &[PathBuf],
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let reader = File::open(src)?;
+        let meta = prof.measure(Phase::Stat, || reader.metadata())?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|x| x.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|x| x.as_secs());
+
+        dav.delete(stale)?;
+        dav.create_parents(dst)?;
+        let tmp = temp_path(dst, std::process::id());
+        let target = if self.atomic { tmp.as_path() } else { dst };
+        let child = dav.put(target, meta.len(), mtime)?;
+
+        let result = self
+            .feed(&reader, child, |x| crate::http::finish(x).map(|_| ()), prof)
+            .and_then(|total| match self.atomic {
+                true => dav.rename(target, dst).map(|_| total),
+                false => Ok(total),
+            });
+        if result.is_err() && self.atomic {
+            let _ = dav.delete(std::slice::from_ref(&tmp));
+        }
+
+        prof.bytes = result?;
+        Ok(prof.bytes)
+    }
+
+    /// Upload `src` as the object `dst` of `bucket`.
+    #[cfg(feature = "remote")]
+    pub fn put(
+        &self,
+        src: &Path,
+        bucket: &Bucket,
+        dst: &Path,
+        hashes: &HashCache,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let reader = File::open(src)?;
+        let meta = prof.measure(Phase::Stat, || reader.metadata())?;
+        let hash = prof.measure(Phase::Read, || hashes.sha256(src))?;
+        if bucket.in_parts(meta.len()) {
+            let limit = self.limit.as_ref();
+            prof.measure(Phase::Write, || {
+                bucket.put_parts(src, dst, meta.len(), &hash, limit)
+            })?;
+            prof.advance(meta.len());
+            prof.bytes = meta.len();
+            return Ok(prof.bytes);
+        }
+        let child = bucket.put(dst, meta.len(), &hash)?;
+
+        prof.bytes = self.feed(&reader, child, |x| bucket.finish(x), prof)?;
+        Ok(prof.bytes)
+    }
+
+    /// Stream `reader` to the stdin of a transfer. A local error kills the
+    /// child, closing its input would pass the short file off as complete.
+    #[cfg(feature = "remote")]
+    fn feed(
+        &self,
+        reader: &File,
+        mut child: Child,
+        finish: impl FnOnce(Child) -> io::Result<()>,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let total = match child.stdin.take() {
+            Some(stdin) => buffered_copy(reader, stdin, self.limit.as_ref(), prof),
+            None => Ok(0),
+        };
+
+        match total {
+            // the child error explains a broken pipe better than the pipe does
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                let _ = child.kill();
+                let _ = child.wait();
+                Err(e)
+            }
+            total => {
+                finish(child)?;
+                total
+            }
+        }
+    }
+
+    /// Download the object `src` of `bucket` to `dst`.
+    #[cfg(feature = "remote")]
+    pub fn get(
+        &self,
+        bucket: &Bucket,
+        src: &Path,
+        dst: &Path,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let child = bucket.get(src)?;
+        let total = self.download(child, |x| bucket.finish(x).map(|_| true), dst, false, prof)?;
+        Ok(total.unwrap_or(0))
+    }
+
+    /// Download `url` to `dst`. With `changed_only` an existing `dst` is
+    /// only replaced if the server has a newer version, `None` if not.
+    pub fn fetch(
+        &self,
+        url: &str,
+        dst: &Path,
+        changed_only: bool,
+        prof: &mut FileProfile,
+    ) -> io::Result<Option<u64>> {
+        let newer_than = Some(dst).filter(|x| changed_only && x.exists());
+        let child = crate::http::get(url, newer_than)?;
+        self.download(child, crate::http::finish, dst, newer_than.is_some(), prof)
+    }
+
+    /// Write the stdout of a transfer to `dst`, `finish` tells whether it
+    /// sent the file or `dst` is to be kept. A conditional download goes
+    /// through a temporary file even when not atomic.
+    fn download(
+        &self,
+        mut child: Child,
+        finish: impl FnOnce(Child) -> io::Result<bool>,
+        dst: &Path,
+        conditional: bool,
+        prof: &mut FileProfile,
+    ) -> io::Result<Option<u64>> {
+        let tmp = temp_path(dst, std::process::id());
+        let target = if self.atomic || conditional {
+            tmp.as_path()
+        } else {
+            dst
+        };
+        if target != dst {
+            crate::cleanup::register(target);
+        }
+
+        let result = File::create(target).and_then(|writer| {
+            let total = match child.stdout.as_mut() {
+                Some(stdout) => buffered_copy(stdout, &writer, self.limit.as_ref(), prof),
+                None => Ok(0),
+            };
+            if total.is_err() {
+                let _ = child.kill();
+            }
+            let sent = finish(child);
+            let total = total?;
+            if !sent? {
+                return Ok(None);
+            }
+
+            if self.sync {
+                prof.measure(Phase::Fsync, || writer.sync_all())?;
+            }
+            Ok(Some(total))
+        });
+        let result = result.and_then(|total| match total {
+            Some(_) if target != dst => std::fs::rename(target, dst).map(|_| total),
+            Some(_) => Ok(total),
+            None => std::fs::remove_file(target).map(|_| None),
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(target);
+        }
+        crate::cleanup::release(target);
+
+        match result {
+            Ok(Some(total)) => {
+                self.sync_parent(dst, prof)?;
+                prof.bytes = total;
+                Ok(Some(total))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn copy(&self, src: &Path, dst: &Path, prof: &mut FileProfile) -> io::Result<u64> {
+        self.copy_pinned(src, dst, None, prof)
+    }
+
+    /// Copy `src` to `dst`, and with an `expected` hash only when what was
+    /// written into the temporary file has it. The bytes checked are the
+    /// bytes renamed into place, whatever the source does meanwhile.
+    pub fn copy_pinned(
+        &self,
+        src: &Path,
+        dst: &Path,
+        expected: Option<&Expected>,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let reader = File::open(src)?;
+        let meta = prof.measure(Phase::Stat, || reader.metadata())?;
+        let len = meta.len();
+
+        if len >= RESUMABLE_FILE && expected.is_none() {
+            return self.copy_resumable(&reader, &meta, dst, prof);
+        }
+
+        if !self.atomic && expected.is_none() {
+            let writer = File::create(dst)?;
+            let total = self.copy_with_engine(&reader, &writer, len, prof)?;
+            self.finish(&writer, &meta, prof)?;
+            self.sync_parent(dst, prof)?;
+            prof.bytes = total;
+
+            return Ok(total);
+        }
+
+        let tmp = temp_path(dst, std::process::id());
+        crate::cleanup::register(&tmp);
+        let result = File::create(&tmp).and_then(|writer| {
+            let total = self.copy_with_engine(&reader, &writer, len, prof)?;
+            self.finish(&writer, &meta, prof)?;
+            Ok(total)
+        });
+        let result = result.and_then(|total| match expected {
+            Some(x) => prof
+                .measure(Phase::Verify, || pinned(&tmp, x))
+                .map(|_| total),
+            None => Ok(total),
+        });
+        let result = result.and_then(|total| std::fs::rename(&tmp, dst).map(|_| total));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp);
+        }
+        crate::cleanup::release(&tmp);
+
+        let total = result?;
+        self.sync_parent(dst, prof)?;
+        prof.bytes = total;
+        Ok(total)
+    }
+
+    /// Copy through `<dst>.part`, renamed into place once complete. A part
+    /// left by an interrupted run is continued if its content is a prefix
+    /// of the source.
+    fn copy_resumable(
+        &self,
+        reader: &File,
+        meta: &std::fs::Metadata,
+        dst: &Path,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let part = part_path(dst);
+        let len = meta.len();
+
+        let done = match std::fs::metadata(&part) {
+            Ok(x) if x.len() > 0 && x.len() <= len => {
+                let prefix = x.len();
+                if is_prefix(reader, &File::open(&part)?, prefix, prof)? {
+                    prefix
+                } else {
+                    log::info!("Partial {} does not match, restarting", part.display());
+                    0
+                }
+            }
+            _ => 0,
+        };
+
+        let writer = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(done == 0)
+            .open(&part)?;
+
+        let total = if done == 0 && self.engine != Engine::Auto {
+            (&mut &*reader).rewind()?;
+            self.copy_with_engine(reader, &writer, len, prof)?
+        } else {
+            if done > 0 {
+                log::info!("Resume {} at {} of {} bytes", dst.display(), done, len);
+            }
+            done + self.copy_sequential(reader, &writer, done, len, prof)?
+        };
+
+        self.finish(&writer, meta, prof)?;
+        drop(writer);
+        std::fs::rename(&part, dst)?;
+        self.sync_parent(dst, prof)?;
+        prof.bytes = total;
+
+        Ok(total)
+    }
+
+    /// Copy `start..len` in order, so an interruption leaves a valid prefix.
+    fn copy_sequential(
+        &self,
+        mut reader: &File,
+        mut writer: &File,
+        start: u64,
+        len: u64,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        if self.limit.is_none() {
+            if start == 0 {
+                match prof.measure(Phase::Write, || reflink(reader, writer)) {
+                    Ok(n) => return Ok(n),
+                    Err(e) if unsupported(&e) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            reader.seek(SeekFrom::Start(start))?;
+            writer.seek(SeekFrom::Start(start))?;
+            match prof.measure(Phase::Write, || {
+                copy_file_range(reader, writer, len - start)
+            }) {
+                Ok(n) => return Ok(n),
+                Err(e) if unsupported(&e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        reader.seek(SeekFrom::Start(start))?;
+        writer.seek(SeekFrom::Start(start))?;
+        buffered_copy(reader, writer, self.limit.as_ref(), prof)
+    }
+
+    fn copy_with_engine(
+        &self,
+        reader: &File,
+        writer: &File,
+        len: u64,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let total = match self.engine {
+            _ if self.limit.is_some() => buffered_copy(reader, writer, self.limit.as_ref(), prof)?,
+            Engine::Auto => self.copy_auto(reader, writer, len, prof)?,
+            Engine::Reflink => prof.measure(Phase::Write, || reflink(reader, writer))?,
+            Engine::CopyFileRange => {
+                prof.measure(Phase::Write, || copy_file_range(reader, writer, len))?
+            }
+            Engine::Chunked => chunked_copy(reader, writer, len, self.chunk_size, prof)?,
+            Engine::Small => small_copy(reader, writer, prof)?,
+            Engine::Buffered => buffered_copy(reader, writer, None, prof)?,
+        };
+
+        Ok(total)
+    }
+
+    fn copy_auto(
+        &self,
+        reader: &File,
+        writer: &File,
+        len: u64,
+        prof: &mut FileProfile,
+    ) -> io::Result<u64> {
+        let key = device_pair(reader, writer);
+        let support = self
+            .support
+            .lock()
+            .unwrap()
+            .get(&key)
+            .copied()
+            .unwrap_or_default();
+
+        // one syscall with no copy through userspace, for the thousands
+        // of small files of a tree
+        if len <= SMALL_FILE {
+            if cfg!(target_os = "linux") && len > 0 && !support.no_copy_file_range {
+                match prof.measure(Phase::Write, || copy_file_range(reader, writer, len)) {
+                    Ok(n) => return Ok(n),
+                    Err(e) if unsupported(&e) => self.update(key, |x| x.no_copy_file_range = true),
+                    Err(e) => return Err(e),
+                }
+            }
+            return small_copy(reader, writer, prof);
+        }
+
+        if !support.no_reflink {
+            match prof.measure(Phase::Write, || reflink(reader, writer)) {
+                Ok(n) => return Ok(n),
+                Err(e) if unsupported(&e) => self.update(key, |x| x.no_reflink = true),
+                Err(e) => return Err(e),
+            }
+        }
+
+        if len >= CHUNK_WORKERS.saturating_mul(self.chunk_size) {
+            return chunked_copy(reader, writer, len, self.chunk_size, prof);
+        }
+
+        if !support.no_copy_file_range {
+            match prof.measure(Phase::Write, || copy_file_range(reader, writer, len)) {
+                Ok(n) => return Ok(n),
+                Err(e) if unsupported(&e) => self.update(key, |x| x.no_copy_file_range = true),
+                Err(e) => return Err(e),
+            }
+        }
+
+        buffered_copy(reader, writer, None, prof)
+    }
+
+    fn update<F: FnOnce(&mut Support)>(&self, key: (u64, u64), f: F) {
+        f(self.support.lock().unwrap().entry(key).or_default());
+    }
+}
+
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+/// Directories get a directory symlink, or a junction where creating
+/// symlinks needs a privilege or Developer Mode the user does not have.
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    use std::os::windows::fs::{symlink_dir, symlink_file};
+
+    // relative targets are relative to the directory of the link
+    let resolved = link.parent().unwrap_or(Path::new("")).join(target);
+    if !resolved.is_dir() {
+        return symlink_file(target, link);
+    }
+
+    const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+    match symlink_dir(target, link) {
+        Err(e) if e.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) => junction(&resolved, link),
+        x => x,
+    }
+}
+
+/// Junctions need no privilege, but an absolute target on a local drive.
+#[cfg(windows)]
+fn junction(target: &Path, link: &Path) -> io::Result<()> {
+    let target = crate::paths::simplify(&std::fs::canonicalize(target)?);
+    let out = std::process::Command::new("cmd")
+        .args(["/C", "mklink", "/J"])
+        .arg(link)
+        .arg(&target)
+        .output()?;
+    match out.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "mklink /J failed, {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ))),
+    }
+}
+
+/// Remove the file, symlink or junction at `path`, not what a link points
+/// to.
+pub fn remove_link(path: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileTypeExt;
+        // directory links are directories to Windows
+        if std::fs::symlink_metadata(path)?
+            .file_type()
+            .is_symlink_dir()
+        {
+            return std::fs::remove_dir(path);
+        }
+    }
+
+    std::fs::remove_file(path)
+}
+
+/// Permission bits to give a copy of a file with `meta`.
+#[cfg(all(unix, any(feature = "remote", feature = "archive")))]
+pub fn mode(meta: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o7777
+}
+
+#[cfg(all(not(unix), any(feature = "remote", feature = "archive")))]
+pub fn mode(_meta: &std::fs::Metadata) -> u32 {
+    0o644
+}
+
+/// Whether `a` and `b` are the same file, hard links of each other.
+#[cfg(unix)]
+pub fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn same_file(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+/// Whether `dst`, or the closest of its parents that exists, is on the
+/// filesystem of `src`.
+#[cfg(unix)]
+pub fn same_device(src: &Path, dst: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = match std::fs::metadata(src) {
+        Ok(x) => x.dev(),
+        Err(_) => return false,
+    };
+    dst.ancestors()
+        .find_map(|x| std::fs::metadata(x).ok())
+        .is_some_and(|x| x.dev() == dev)
+}
+
+#[cfg(not(unix))]
+pub fn same_device(_src: &Path, _dst: &Path) -> bool {
+    true
+}
+
+/// Name temporary files `{prefix}{name}` instead of `.{name}`, hidden.
+pub fn set_temp_prefix(prefix: &str) -> Result<(), String> {
+    if prefix.contains(std::path::is_separator) || prefix.len() > 64 {
+        return Err(format!("invalid temporary file prefix `{}`", prefix));
+    }
+    let _ = TEMP_PREFIX.set(prefix.to_owned());
+    Ok(())
+}
+
+/// Hidden temporary file in the destination directory, on the same
+/// filesystem so the final rename is atomic; named after the writing
+/// process so leftovers of a crashed run can be told apart.
+pub fn temp_path(dst: &Path, pid: u32) -> PathBuf {
+    let prefix = TEMP_PREFIX.get().map_or(".", String::as_str);
+    let name = dst.file_name().unwrap_or_default().to_string_lossy();
+    let suffix = format!(".{}.cpx-tmp", pid);
+    let mut tmp = format!("{}{}{}", prefix, name, suffix);
+    // too long a name is cut, its hash keeps the cut ones apart
+    if tmp.len() > NAME_MAX {
+        let hash = format!("~{:016x}", crate::state::fnv1a(name.as_bytes()));
+        let mut keep = NAME_MAX.saturating_sub(prefix.len() + hash.len() + suffix.len());
+        while !name.is_char_boundary(keep) {
+            keep -= 1;
+        }
+        tmp = format!("{}{}{}{}", prefix, &name[..keep], hash, suffix);
+    }
+    dst.with_file_name(tmp)
+}
+
+fn part_path(dst: &Path) -> PathBuf {
+    let mut name = dst.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    dst.with_file_name(name)
+}
+
+/// Whether the first `len` bytes of `a` and `b` are the same.
+fn is_prefix(a: &File, b: &File, len: u64, prof: &mut FileProfile) -> io::Result<bool> {
+    let (mut a, mut b) = (a.take(len), b.take(len));
+    let (mut buf_a, mut buf_b) = (vec![0; BUFFER_SIZE], vec![0; BUFFER_SIZE]);
+
+    loop {
+        let n = prof.measure(Phase::Verify, || a.read(&mut buf_a))?;
+        if n == 0 {
+            return Ok(true);
+        }
+        prof.measure(Phase::Verify, || b.read_exact(&mut buf_b[..n]))?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+fn unsupported(e: &io::Error) -> bool {
+    if e.kind() == io::ErrorKind::Unsupported {
+        return true;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        matches!(
+            e.raw_os_error(),
+            Some(libc::EOPNOTSUPP)
+                | Some(libc::EXDEV)
+                | Some(libc::EINVAL)
+                | Some(libc::ENOSYS)
+                | Some(libc::ENOTTY)
+                | Some(libc::EBADF)
+        )
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    false
+}
+
+#[cfg(unix)]
+fn device_pair(reader: &File, writer: &File) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = |f: &File| f.metadata().map(|x| x.dev()).unwrap_or_default();
+    (dev(reader), dev(writer))
+}
+
+#[cfg(not(unix))]
+fn device_pair(_reader: &File, _writer: &File) -> (u64, u64) {
+    (0, 0)
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(reader: &File, writer: &File) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    const FICLONE: libc::c_ulong = 0x4004_9409;
+
+    if unsafe { libc::ioctl(writer.as_raw_fd(), FICLONE as _, reader.as_raw_fd()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    reader.metadata().map(|x| x.len())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_reader: &File, _writer: &File) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflink is not supported",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn copy_file_range(reader: &File, writer: &File, len: u64) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut total = 0;
+    while total < len {
+        let chunk = (len - total).min(1 << 30) as usize;
+        let n = unsafe {
+            libc::syscall(
+                libc::SYS_copy_file_range,
+                reader.as_raw_fd(),
+                std::ptr::null_mut::<libc::loff_t>(),
+                writer.as_raw_fd(),
+                std::ptr::null_mut::<libc::loff_t>(),
+                chunk,
+                0u32,
+            )
+        };
+
+        match n {
+            n if n < 0 => return Err(io::Error::last_os_error()),
+            0 => break,
+            n => total += n as u64,
+        }
+        timeout::check()?;
+    }
+
+    Ok(total)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_file_range(_reader: &File, _writer: &File, _len: u64) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "copy_file_range is not supported",
+    ))
+}
+
+/// Whether the file at `path` has the `expected` hash, hashed anew.
+fn pinned(path: &Path, expected: &Expected) -> io::Result<()> {
+    let hex = expected.algorithm.file(path)?;
+    match hex == expected.hex {
+        true => Ok(()),
+        false => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{} of the source is {}, the config pins {}",
+                expected.algorithm.name(),
+                hex,
+                expected.hex
+            ),
+        )),
+    }
+}
+
+/// Tiny files are read and written in a single call each.
+fn small_copy(reader: &File, mut writer: &File, prof: &mut FileProfile) -> io::Result<u64> {
+    let mut buf = vec![];
+    prof.measure(Phase::Read, || timeout::read_to_end(reader, &mut buf))?;
+    prof.measure(Phase::Write, || {
+        timeout::write_all(|x| writer.write(x), &buf)
+    })?;
+
+    Ok(buf.len() as u64)
+}
+
+/// Copy through a userspace buffer, charging every syscall to its phase.
+fn buffered_copy<R: Read, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    limit: Option<&RateLimit>,
+    prof: &mut FileProfile,
+) -> io::Result<u64> {
+    let mut buf = vec![0; BUFFER_SIZE];
+    let mut total = 0;
+
+    loop {
+        let n = prof.measure(Phase::Read, || reader.read(&mut buf))?;
+        if n == 0 {
+            break;
+        }
+
+        prof.measure(Phase::Write, || {
+            timeout::write_all(|x| writer.write(x), &buf[..n])
+        })?;
+        total += n as u64;
+        prof.advance(n as u64);
+        timeout::check()?;
+
+        if let Some(limit) = limit {
+            limit.consume(n as u64);
+        }
+    }
+
+    Ok(total)
+}
+
+/// Split a huge file into ranges of `chunk` bytes, copied concurrently
+/// with positional IO by workers each taking the next range left.
+#[cfg(unix)]
+fn chunked_copy(
+    reader: &File,
+    writer: &File,
+    len: u64,
+    chunk: u64,
+    prof: &mut FileProfile,
+) -> io::Result<u64> {
+    use std::os::unix::fs::FileExt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    writer.set_len(len)?;
+    let chunk = chunk.max(1);
+    let ranges = len.div_ceil(chunk);
+    let next = AtomicU64::new(0);
+    let path = prof.path.clone();
+    let progress = prof.progress.clone();
+    // the chunks are interrupted with the file when it times out
+    let copying = timeout::current();
+
+    let results: Vec<io::Result<(u64, FileProfile)>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..CHUNK_WORKERS.min(ranges))
+            .map(|_| {
+                let (path, next) = (&path, &next);
+                let progress = progress.clone();
+                let copying = copying.clone();
+                scope.spawn(move || {
+                    let _entered = timeout::enter(copying);
+                    let mut part = FileProfile::new(path);
+                    part.progress = progress;
+                    let mut buf = vec![0; BUFFER_SIZE];
+                    let mut total = 0;
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= ranges {
+                            break;
+                        }
+                        let (mut offset, end) = (i * chunk, ((i + 1) * chunk).min(len));
+                        while offset < end {
+                            let want = ((end - offset) as usize).min(buf.len());
+                            let n = part.measure(Phase::Read, || {
+                                reader.read_at(&mut buf[..want], offset)
+                            })?;
+                            if n == 0 {
+                                break;
+                            }
+
+                            let mut at = offset;
+                            part.measure(Phase::Write, || {
+                                let write = |x: &[u8]| {
+                                    let written = writer.write_at(x, at)?;
+                                    at += written as u64;
+                                    Ok(written)
+                                };
+                                timeout::write_all(write, &buf[..n])
+                            })?;
+                            offset += n as u64;
+                            total += n as u64;
+                            part.advance(n as u64);
+                            timeout::check()?;
+                        }
+                    }
+
+                    Ok((total, part))
+                })
+            })
+            .collect();
+
+        handles.into_iter().map(|x| x.join().unwrap()).collect()
+    });
+
+    let mut total = 0;
+    for r in results {
+        let (n, part) = r?;
+        total += n;
+        prof.add(Phase::Read, part.get(Phase::Read));
+        prof.add(Phase::Write, part.get(Phase::Write));
+    }
+
+    Ok(total)
+}
+
+#[cfg(not(unix))]
+fn chunked_copy(
+    reader: &File,
+    writer: &File,
+    _len: u64,
+    _chunk: u64,
+    prof: &mut FileProfile,
+) -> io::Result<u64> {
+    buffered_copy(reader, writer, None, prof)
+}
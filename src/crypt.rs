@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tool {
+    Age,
+    Gpg,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Age => "age",
+            Tool::Gpg => "gpg",
+        }
+    }
+}
+
+/// Files of a path kept encrypted: encrypted when it is the destination
+/// and decrypted when it is the source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Encryption {
+    pub tool: Tool,
+    /// age public keys, or GPG key ids, fingerprints or emails.
+    pub recipients: Vec<String>,
+    /// age identity file to decrypt with, GPG asks its agent.
+    #[serde(default)]
+    pub identity: Option<PathBuf>,
+}
+
+impl Encryption {
+    pub fn encrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut cmd = Command::new(self.tool.name());
+        match self.tool {
+            Tool::Age => {
+                for x in &self.recipients {
+                    cmd.arg("-r").arg(x);
+                }
+            }
+            Tool::Gpg => {
+                cmd.args(["--batch", "--yes", "--trust-model", "always", "--encrypt"]);
+                for x in &self.recipients {
+                    cmd.arg("--recipient").arg(x);
+                }
+            }
+        }
+
+        pipe(cmd, self.tool.name(), data)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut cmd = Command::new(self.tool.name());
+        match self.tool {
+            Tool::Age => {
+                cmd.arg("-d");
+                if let Some(x) = &self.identity {
+                    cmd.arg("-i").arg(x);
+                }
+            }
+            Tool::Gpg => {
+                cmd.args(["--batch", "--quiet", "--decrypt"]);
+            }
+        }
+
+        pipe(cmd, self.tool.name(), data)
+    }
+}
+
+/// Run the tool `cmd` on `data`, what it prints is the result and its
+/// stderr the error message.
+pub fn pipe(mut cmd: Command, tool: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    log::debug!("Run {:?}", cmd);
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("{} failed, {}", tool, e)))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut out = vec![];
+    // written from another thread, the tool may not read all of its
+    // input before writing some output
+    std::thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(data));
+        let read = stdout.read_to_end(&mut out);
+        // a tool failing early closes stdin, its stderr says why
+        let _ = writer.join().unwrap();
+        read
+    })?;
+
+    let mut stderr = String::new();
+    if let Some(mut x) = child.stderr.take() {
+        x.read_to_string(&mut stderr)?;
+    }
+    if child.wait()?.success() {
+        Ok(out)
+    } else {
+        let e = format!("{} failed, {}", tool, stderr.trim());
+        Err(io::Error::other(e))
+    }
+}
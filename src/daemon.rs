@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use crate::state::{fnv1a, state_dir};
+
+/// What `cpx trigger` asks the daemon to copy.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Request {
+    pub tags: Vec<String>,
+}
+
+/// How the copy went, the daemon itself logs the files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Response {
+    pub counts: BTreeMap<String, usize>,
+    pub error: Option<String>,
+}
+
+/// Socket of the daemon serving `configs`, one per set of config files.
+pub fn socket_path(configs: &[String]) -> PathBuf {
+    let configs: Vec<_> = configs
+        .iter()
+        .map(|x| {
+            let path = std::fs::canonicalize(x)
+                .map(|x| crate::paths::simplify(&x))
+                .unwrap_or_else(|_| PathBuf::from(x));
+            path.to_string_lossy().into_owned()
+        })
+        .collect();
+    let key = fnv1a(configs.join(",").as_bytes());
+    state_dir().join(format!("daemon-{:016x}.sock", key))
+}
+
+/// Answer requests on `path` one at a time with `handle`, until killed.
+pub fn serve(path: &Path, mut handle: impl FnMut(Request) -> Response) -> io::Result<()> {
+    if UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("a daemon already listens on {}", path.display()),
+        ));
+    }
+    // left by a daemon that was killed
+    let _ = std::fs::remove_file(path);
+    std::fs::create_dir_all(state_dir())?;
+
+    let listener = UnixListener::bind(path)?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    log::info!("Listen on {}", path.display());
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(x) => x,
+            Err(e) => {
+                log::warn!("Accept failed, {}", e);
+                continue;
+            }
+        };
+
+        let mut line = String::new();
+        if let Err(e) = BufReader::new(&stream).read_line(&mut line) {
+            log::warn!("Read request failed, {}", e);
+            continue;
+        }
+        // a probe of whether the daemon runs
+        if line.is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => handle(request),
+            Err(e) => Response {
+                error: Some(format!("bad request, {}", e)),
+                ..Response::default()
+            },
+        };
+
+        let reply = serde_json::to_string(&response).unwrap();
+        if let Err(e) = writeln!(&stream, "{}", reply) {
+            log::warn!("Send response failed, {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Send `request` to the daemon on `path` and wait for its copy.
+pub fn trigger(path: &Path, request: &Request) -> io::Result<Response> {
+    let stream = UnixStream::connect(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("no daemon on {}, {}", path.display(), e)))?;
+    writeln!(&stream, "{}", serde_json::to_string(request)?)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
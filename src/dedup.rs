@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::hashcache::HashCache;
+
+/// What is done about a destination file already holding the content of
+/// its source, found by hash whatever the timestamps say.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dedup {
+    /// Leave the file as is.
+    Skip,
+    /// Skip as well, and hard link files whose content another
+    /// destination file already has instead of writing it again.
+    Link,
+}
+
+impl Dedup {
+    pub const NAMES: [&'static str; 2] = ["skip", "link"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(Dedup::Skip),
+            "link" => Some(Dedup::Link),
+            _ => None,
+        }
+    }
+}
+
+/// Destination files by the hash of their content.
+pub struct Index {
+    files: Mutex<HashMap<String, PathBuf>>,
+}
+
+impl Index {
+    /// Start with the files under `root` hashed in earlier runs.
+    pub fn new(root: &Path, hashes: &HashCache) -> Self {
+        let files = hashes
+            .entries_under(root)
+            .into_iter()
+            .map(|(path, hash)| (hash, path))
+            .collect();
+
+        Self {
+            files: Mutex::new(files),
+        }
+    }
+
+    pub fn insert(&self, hash: String, path: &Path) {
+        self.files.lock().unwrap().insert(hash, path.to_path_buf());
+    }
+
+    /// A file other than `except` with the content `hash`, checked again
+    /// as it may have changed since it was indexed.
+    pub fn find(&self, hash: &str, except: &Path, hashes: &HashCache) -> Option<PathBuf> {
+        let path = self.files.lock().unwrap().get(hash).cloned()?;
+        if path == except || hashes.hash(&path).ok()? != hash {
+            return None;
+        }
+
+        Some(path)
+    }
+}
+
+/// Replace `dst` with a hard link to `existing`. The link goes to a
+/// temporary name first, so `dst` is never missing.
+pub fn link(existing: &Path, dst: &Path) -> io::Result<()> {
+    let tmp = crate::copy::temp_path(dst, std::process::id());
+    crate::cleanup::register(&tmp);
+    let result = std::fs::hard_link(existing, &tmp).and_then(|_| std::fs::rename(&tmp, dst));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    crate::cleanup::release(&tmp);
+
+    result
+}
+
+/// Remove `dst` if it is linked elsewhere, writing it in place would
+/// change the other files too.
+pub fn unshare(dst: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    let shared = {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::symlink_metadata(dst).is_ok_and(|x| x.nlink() > 1)
+    };
+    // the link count is not at hand, the copy writes a new file anyway
+    #[cfg(not(unix))]
+    let shared = dst.is_file();
+
+    match shared {
+        true => std::fs::remove_file(dst),
+        false => Ok(()),
+    }
+}
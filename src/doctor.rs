@@ -0,0 +1,152 @@
+use std::fmt::Display;
+use std::io;
+use std::path::Path;
+
+/// Findings of `cpx doctor`, printed as they are made.
+#[derive(Debug, Default)]
+pub struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    pub fn ok(&mut self, what: impl Display) {
+        println!("{:<10} {}", "ok", what);
+    }
+
+    pub fn warn(&mut self, what: impl Display) {
+        self.warnings += 1;
+        println!("{:<10} {}", "warning", what);
+    }
+
+    pub fn error(&mut self, what: impl Display) {
+        self.errors += 1;
+        println!("{:<10} {}", "error", what);
+    }
+
+    /// `N errors, M warnings`, and whether there were errors.
+    pub fn finish(&self) -> bool {
+        eprintln!("{} errors, {} warnings", self.errors, self.warnings);
+        self.errors > 0
+    }
+}
+
+/// `path` or the nearest of its parents that exists, which a copy to it
+/// creates the rest in.
+pub fn existing(path: &Path) -> Option<&Path> {
+    path.ancestors()
+        .find(|x| !x.as_os_str().is_empty() && x.exists())
+        .or(path.is_relative().then(|| Path::new(".")))
+}
+
+/// Whether files can be created in the directory `dir`.
+#[cfg(unix)]
+pub fn writable(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn writable(dir: &Path) -> bool {
+    std::fs::metadata(dir).is_ok_and(|x| !x.permissions().readonly())
+}
+
+/// Why files cannot be written to `path`, with what would let them, none
+/// if they can. One not there yet is checked at the parent it is made in.
+pub fn unwritable(path: &Path) -> Option<String> {
+    let dir = existing(path)?;
+    if read_only(dir) {
+        return Some(match mount_point(dir) {
+            Some(x) => format!(
+                "it is on the read-only mount {}, `mount -o remount,rw {}` makes it writable",
+                x.display(),
+                x.display()
+            ),
+            None => "it is on a read-only filesystem".to_owned(),
+        });
+    }
+    if !writable(dir) {
+        return Some(format!(
+            "{} is not writable by this user, check its owner and mode",
+            dir.display()
+        ));
+    }
+    None
+}
+
+/// Whether the filesystem of `path` is mounted read-only.
+#[cfg(unix)]
+fn read_only(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = match std::ffi::CString::new(path.as_os_str().as_bytes()) {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    unsafe { libc::statvfs(path.as_ptr(), &mut stat) == 0 && stat.f_flag & libc::ST_RDONLY != 0 }
+}
+
+#[cfg(not(unix))]
+fn read_only(_path: &Path) -> bool {
+    false
+}
+
+/// The mount `path` is on, the longest mount point above it.
+#[cfg(target_os = "linux")]
+fn mount_point(path: &Path) -> Option<std::path::PathBuf> {
+    let path = std::fs::canonicalize(path).ok()?;
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|x| x.split(' ').nth(1))
+        // spaces and the like are octal escapes, `\040`
+        .map(|x| x.replace("\\040", " ").replace("\\011", "\t"))
+        .map(std::path::PathBuf::from)
+        .filter(|x| path.starts_with(x))
+        .max_by_key(|x| x.as_os_str().len())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_point(_path: &Path) -> Option<std::path::PathBuf> {
+    None
+}
+
+/// Bytes free for an unprivileged user on the filesystem of `path`.
+#[cfg(unix)]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space is only known on unix",
+    ))
+}
+
+/// The device `path` is on, none where it is not known.
+#[cfg(unix)]
+pub fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::metadata(path).ok().map(|x| x.dev())
+}
+
+#[cfg(not(unix))]
+pub fn device(_path: &Path) -> Option<u64> {
+    None
+}
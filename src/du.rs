@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::stats;
+
+/// Files and bytes of a set of paths, for `cpx du`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    pub files: usize,
+    pub bytes: u64,
+    /// Paths that do not exist, not counted.
+    pub missing: usize,
+}
+
+impl Usage {
+    /// The usage of `paths`, a file linked under several of them counted
+    /// once.
+    pub fn of<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut usage = Self::default();
+        let mut seen = HashSet::new();
+        for path in paths {
+            let meta = match std::fs::metadata(path) {
+                Ok(x) => x,
+                Err(_) => {
+                    usage.missing += 1;
+                    continue;
+                }
+            };
+            if inode(&meta).is_some_and(|x| !seen.insert(x)) {
+                continue;
+            }
+            usage.files += 1;
+            usage.bytes += meta.len();
+        }
+        usage
+    }
+
+    /// `12 files 3.4 MiB`, with the missing ones if any.
+    pub fn text(&self) -> String {
+        let noun = if self.files == 1 { "file" } else { "files" };
+        let mut text = format!(
+            "{} {} {}",
+            self.files,
+            noun,
+            stats::human(self.bytes as f64)
+        );
+        if self.missing > 0 {
+            text.push_str(&format!(", {} missing", self.missing));
+        }
+        text
+    }
+}
+
+#[cfg(unix)]
+fn inode(meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn inode(_meta: &std::fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+/// Line endings text files are converted to while copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Eol {
+    Lf,
+    Crlf,
+    /// Those of the platform cpx runs on.
+    Native,
+}
+
+impl Eol {
+    pub const NAMES: [&'static str; 3] = ["lf", "crlf", "native"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lf" => Some(Eol::Lf),
+            "crlf" => Some(Eol::Crlf),
+            "native" => Some(Eol::Native),
+            _ => None,
+        }
+    }
+
+    /// `data` with every `\n` and `\r\n` turned into this line ending.
+    pub fn convert(self, data: &[u8]) -> Vec<u8> {
+        let crlf = match self {
+            Eol::Lf => false,
+            Eol::Crlf => true,
+            Eol::Native => cfg!(windows),
+        };
+
+        let mut out = Vec::with_capacity(data.len() + data.len() / 32);
+        let mut rest = data;
+        while let Some(i) = rest.iter().position(|x| *x == b'\n') {
+            let line = rest[..i].strip_suffix(b"\r").unwrap_or(&rest[..i]);
+            out.extend_from_slice(line);
+            out.extend_from_slice(if crlf { b"\r\n" } else { b"\n" });
+            rest = &rest[i + 1..];
+        }
+        out.extend_from_slice(rest);
+
+        out
+    }
+}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::output::Record;
+
+/// Bytes progress of a file is reported at most this often.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// One line of the stream, `{"event": "file-done", ...}`.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+enum Event<'a> {
+    RunStart {
+        specs: Vec<String>,
+        dry_run: bool,
+    },
+    FileStart {
+        src: &'a Path,
+        dst: &'a Path,
+        size: Option<u64>,
+    },
+    BytesProgress {
+        dst: &'a Path,
+        bytes: u64,
+        size: Option<u64>,
+    },
+    FileDone {
+        #[serde(flatten)]
+        record: &'a Record<'a>,
+    },
+    RunDone {
+        counts: BTreeMap<String, usize>,
+        bytes: u64,
+        seconds: f64,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    run_id: &'a str,
+    #[serde(flatten)]
+    event: Event<'a>,
+}
+
+/// Newline-delimited JSON events of a run on a file descriptor, for
+/// editors and GUIs to show its progress, --progress-events.
+#[derive(Debug)]
+pub struct Events {
+    run_id: String,
+    out: Mutex<File>,
+}
+
+impl Events {
+    /// Events written to a copy of the descriptor `fd`.
+    #[cfg(unix)]
+    pub fn open(fd: i32, run_id: &str) -> io::Result<Self> {
+        use std::os::unix::io::FromRawFd;
+
+        let fd = unsafe { libc::dup(fd) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            run_id: run_id.to_owned(),
+            out: Mutex::new(unsafe { File::from_raw_fd(fd) }),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(_fd: i32, _run_id: &str) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "file descriptors are only known on unix",
+        ))
+    }
+
+    fn emit(&self, event: Event) {
+        let line = Line {
+            run_id: &self.run_id,
+            event,
+        };
+        let mut data = serde_json::to_vec(&line).unwrap();
+        data.push(b'\n');
+        // a frontend going away does not stop the copy
+        let _ = self.out.lock().unwrap().write_all(&data);
+    }
+
+    pub fn run_start(&self, specs: Vec<String>, dry_run: bool) {
+        self.emit(Event::RunStart { specs, dry_run });
+    }
+
+    /// Starting to write `dst`, the progress of its bytes goes to the
+    /// returned handle.
+    pub fn file_start(self: &Arc<Self>, src: &Path, dst: &Path) -> Arc<Progress> {
+        let size = std::fs::metadata(src).ok().map(|x| x.len());
+        self.emit(Event::FileStart { src, dst, size });
+        Arc::new(Progress {
+            events: self.clone(),
+            dst: dst.to_path_buf(),
+            size,
+            bytes: AtomicU64::new(0),
+            last: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub fn file_done(&self, record: &Record) {
+        self.emit(Event::FileDone { record });
+    }
+
+    pub fn run_done(&self, counts: BTreeMap<String, usize>, bytes: u64, elapsed: Duration) {
+        self.emit(Event::RunDone {
+            counts,
+            bytes,
+            seconds: elapsed.as_secs_f64(),
+        });
+    }
+}
+
+/// Bytes written of one file, shared by the threads writing it.
+#[derive(Debug)]
+pub struct Progress {
+    events: Arc<Events>,
+    dst: PathBuf,
+    size: Option<u64>,
+    bytes: AtomicU64,
+    last: Mutex<Instant>,
+}
+
+impl Progress {
+    /// `n` more bytes written.
+    pub fn advance(&self, n: u64) {
+        let bytes = self.bytes.fetch_add(n, Ordering::Relaxed) + n;
+        let mut last = self.last.lock().unwrap();
+        if last.elapsed() < INTERVAL {
+            return;
+        }
+        *last = Instant::now();
+        self.events.emit(Event::BytesProgress {
+            dst: &self.dst,
+            bytes,
+            size: self.size,
+        });
+    }
+}
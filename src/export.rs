@@ -0,0 +1,94 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::mode::Mode;
+
+/// Shell a run is exported for by `cpx export`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    /// A POSIX `sh` script.
+    Sh,
+    /// A Windows batch file.
+    Bat,
+}
+
+impl Shell {
+    pub const NAMES: [&'static str; 2] = ["sh", "bat"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sh" => Some(Shell::Sh),
+            "bat" => Some(Shell::Bat),
+            _ => None,
+        }
+    }
+
+    /// That of the platform cpx runs on.
+    pub fn native() -> Self {
+        if cfg!(windows) {
+            Shell::Bat
+        } else {
+            Shell::Sh
+        }
+    }
+
+    fn quote(self, path: &str) -> String {
+        match self {
+            Shell::Sh => format!("'{}'", path.replace('\'', r"'\''")),
+            Shell::Bat => format!("\"{}\"", path.replace('%', "%%")),
+        }
+    }
+}
+
+/// A copy of the run, `src` is a path or the URL of a download.
+pub struct Op {
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    pub url: bool,
+    pub mode: Option<Mode>,
+    pub post: Option<String>,
+}
+
+/// The script doing `ops` in order, stopping at the first that fails.
+pub fn script(shell: Shell, ops: &[Op]) -> String {
+    let q = |x: &Path| shell.quote(&x.to_string_lossy());
+    let mut out = String::new();
+    match shell {
+        Shell::Sh => out.push_str("#!/bin/sh\n# copies of a cpx run\nset -e\n\n"),
+        Shell::Bat => out.push_str("@echo off\nrem copies of a cpx run\n\n"),
+    }
+
+    let dirs: BTreeSet<_> = ops.iter().filter_map(|x| x.dst.parent()).collect();
+    for dir in dirs.iter().filter(|x| !x.as_os_str().is_empty()) {
+        let _ = match shell {
+            Shell::Sh => writeln!(out, "mkdir -p {}", q(dir)),
+            Shell::Bat => writeln!(out, "if not exist {0} mkdir {0} || exit /b 1", q(dir)),
+        };
+    }
+    if !dirs.is_empty() {
+        out.push('\n');
+    }
+
+    for op in ops {
+        let (src, dst) = (q(&op.src), q(&op.dst));
+        let _ = match (shell, op.url) {
+            (Shell::Sh, false) => writeln!(out, "cp -p {} {}", src, dst),
+            (Shell::Bat, false) => writeln!(out, "copy /Y {} {} >nul || exit /b 1", src, dst),
+            (Shell::Sh, true) => writeln!(out, "curl -fsSL -o {} {}", dst, src),
+            (Shell::Bat, true) => writeln!(out, "curl -fsSL -o {} {} || exit /b 1", dst, src),
+        };
+        // batch files have no permission bits to set
+        if let (Shell::Sh, Some(mode)) = (shell, op.mode) {
+            let _ = writeln!(out, "chmod {} {}", mode, dst);
+        }
+        if let Some(post) = &op.post {
+            let _ = match shell {
+                Shell::Sh => writeln!(out, "{} {}", post, dst),
+                Shell::Bat => writeln!(out, "{} {} || exit /b 1", post, dst),
+            };
+        }
+    }
+
+    out
+}
@@ -0,0 +1,143 @@
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Ad hoc narrowing of the resolved file set by size, age and extension.
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub newer_than: Option<SystemTime>,
+    /// Lowercase extensions without the leading dot.
+    pub ext: Vec<String>,
+}
+
+impl Filter {
+    /// Whether the source file at `path` passes. Files that can't be
+    /// inspected are kept, so the copy reports them.
+    pub fn matches(&self, path: &Path) -> bool {
+        if !self.ext.is_empty() {
+            let ext = path
+                .extension()
+                .map(|x| x.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !self.ext.contains(&ext) {
+                return false;
+            }
+        }
+
+        if self.min_size.is_none() && self.max_size.is_none() && self.newer_than.is_none() {
+            return true;
+        }
+        let meta = match std::fs::metadata(path) {
+            Ok(x) => x,
+            Err(_) => return true,
+        };
+
+        let len = meta.len();
+        if self.min_size.is_some_and(|x| len < x) || self.max_size.is_some_and(|x| len > x) {
+            return false;
+        }
+
+        match (self.newer_than, meta.modified()) {
+            (Some(since), Ok(mtime)) => mtime >= since,
+            _ => true,
+        }
+    }
+}
+
+/// Parse a size like `512`, `100K`, `10M` or `1.5GB`, units are powers of 1024.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let s = s.strip_suffix(['B', 'b']).unwrap_or(s);
+    let (number, unit) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&s[..i], c.to_ascii_uppercase()),
+        _ => (s, ' '),
+    };
+
+    let scale = match unit {
+        ' ' => 1u64,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        _ => return Err(format!("unknown unit in `{}`", s)),
+    };
+
+    match number.parse::<f64>() {
+        Ok(x) if x >= 0.0 => Ok((x * scale as f64) as u64),
+        _ => Err(format!("invalid size `{}`", s)),
+    }
+}
+
+/// Parse `--newer-than`, either an age like `90s`, `30m`, `12h`, `7d` or
+/// `2w` counted back from now, or a UTC timestamp `YYYY-MM-DD[ HH:MM[:SS]]`.
+pub fn parse_since(s: &str) -> Result<SystemTime, String> {
+    let s = s.trim();
+    let invalid = || format!("invalid duration or timestamp `{}`", s);
+
+    if s.contains('-') {
+        let (date, time) = match s.split_once([' ', 'T']) {
+            Some((d, t)) => (d, t),
+            None => (s, "00:00"),
+        };
+        let date: Vec<i64> = date
+            .split('-')
+            .map(|x| x.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        let time: Vec<u64> = time
+            .split(':')
+            .map(|x| x.parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+
+        let (y, m, d) = match date[..] {
+            [y, m @ 1..=12, d @ 1..=31] => (y, m, d),
+            _ => return Err(invalid()),
+        };
+        let secs = match time[..] {
+            [h @ 0..=23, min @ 0..=59] => h * 3600 + min * 60,
+            [h @ 0..=23, min @ 0..=59, sec @ 0..=60] => h * 3600 + min * 60 + sec,
+            _ => return Err(invalid()),
+        };
+        let days = u64::try_from(days_from_civil(y, m, d)).map_err(|_| invalid())?;
+
+        return Ok(UNIX_EPOCH + Duration::from_secs(days * 86400 + secs));
+    }
+
+    SystemTime::now()
+        .checked_sub(parse_age(s).map_err(|_| invalid())?)
+        .ok_or_else(invalid)
+}
+
+/// Parse an age like `90s`, `30m`, `12h`, `7d` or `2w`.
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let scale = match unit {
+        "s" | "" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(format!("invalid duration `{}`", s)),
+    };
+
+    match number.parse::<u64>() {
+        Ok(n) => Ok(Duration::from_secs(n * scale)),
+        Err(_) => Err(format!("invalid duration `{}`", s)),
+    }
+}
+
+/// Days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+pub fn days_from_civil(y: i64, m: i64, d: i64) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if m > 2 { m - 3 } else { m + 9 };
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
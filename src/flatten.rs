@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// What --flatten does with files of the same name from different
+/// directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Collision {
+    /// Copy nothing and report them.
+    #[default]
+    Error,
+    /// Number the later ones, `a-2.txt`.
+    Rename,
+    /// Copy the first one only.
+    Skip,
+}
+
+impl Collision {
+    pub const NAMES: [&'static str; 3] = ["error", "rename", "skip"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Collision::Error),
+            "rename" => Some(Collision::Rename),
+            "skip" => Some(Collision::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// The flattened destination of each of `files`, given as their relative
+/// path and their destination relative path before. Files skipped for a
+/// collision have none, earlier files keep their names.
+pub fn flatten(
+    files: &[(PathBuf, PathBuf)],
+    on: Collision,
+) -> Result<HashMap<PathBuf, PathBuf>, String> {
+    let mut taken: HashMap<OsString, &Path> = HashMap::new();
+    let mut names = HashMap::new();
+    for (key, dst) in files {
+        let name = dst.file_name().unwrap_or(dst.as_os_str()).to_owned();
+        let name = match (taken.get(&name), on) {
+            (None, _) => name,
+            (Some(first), Collision::Error) => {
+                return Err(format!(
+                    "{} and {} are both {}",
+                    first.display(),
+                    key.display(),
+                    Path::new(&name).display()
+                ))
+            }
+            (Some(first), Collision::Skip) => {
+                log::warn!(
+                    "Skip {}, its name is taken by {}",
+                    key.display(),
+                    first.display()
+                );
+                continue;
+            }
+            (Some(_), Collision::Rename) => (2..)
+                .map(|i| numbered(&name, i))
+                .find(|x| !taken.contains_key(x))
+                .unwrap(),
+        };
+        taken.insert(name.clone(), key);
+        names.insert(key.clone(), PathBuf::from(name));
+    }
+
+    Ok(names)
+}
+
+/// `name` with `-i` before its extensions, `a.tar.gz` as `a-2.tar.gz`.
+fn numbered(name: &OsString, i: usize) -> OsString {
+    let name = name.to_string_lossy();
+    let split = name
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '.')
+        .map_or(name.len(), |(x, _)| x);
+    format!("{}-{}{}", &name[..split], i, &name[split..]).into()
+}
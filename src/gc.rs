@@ -0,0 +1,158 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{checkpoint, filter, journal, snapshot};
+
+fn default_backups() -> String {
+    "30d".to_owned()
+}
+
+fn default_part_files() -> String {
+    "7d".to_owned()
+}
+
+/// An age like `30d`, checked when the config is read.
+fn age<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    filter::parse_age(&s).map_err(serde::de::Error::custom)?;
+    Ok(s)
+}
+
+/// How long leftovers are kept before `cpx gc` removes them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Retention {
+    /// Age of undo journals and their backups.
+    #[serde(default = "default_backups", deserialize_with = "age")]
+    pub backups: String,
+    /// Age of `.part` files an interrupted copy could still resume.
+    #[serde(default = "default_part_files", deserialize_with = "age")]
+    pub part_files: String,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            backups: default_backups(),
+            part_files: default_part_files(),
+        }
+    }
+}
+
+struct Gc {
+    dry_run: bool,
+    removed: usize,
+    failed: usize,
+}
+
+impl Gc {
+    fn remove(&mut self, what: &str, path: &Path, result: impl FnOnce() -> io::Result<()>) {
+        if self.dry_run {
+            println!("{:<10} {}", "remove", path.display());
+            self.removed += 1;
+            return;
+        }
+
+        match result() {
+            Ok(()) => {
+                log::info!("Remove {} {}", what, path.display());
+                self.removed += 1;
+            }
+            Err(e) => {
+                log::error!("Remove {} {} failed, {}", what, path.display(), e);
+                self.failed += 1;
+            }
+        }
+    }
+
+    /// Temporary files of dead processes and stale `.part` files below `dir`.
+    fn temp_files(&mut self, dir: &Path, part_before: SystemTime) -> io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let kind = entry.file_type()?;
+            if kind.is_dir() {
+                self.temp_files(&path, part_before)?;
+                continue;
+            }
+            if !kind.is_file() {
+                continue;
+            }
+
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let orphan = match name.strip_suffix(".cpx-tmp") {
+                Some(x) => x
+                    .rsplit('.')
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .is_some_and(|pid| !checkpoint::pid_alive(pid)),
+                None => name.ends_with(".part") && entry.metadata()?.modified()? < part_before,
+            };
+            if orphan {
+                self.remove("leftover", &path, || std::fs::remove_file(&path));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn before(age: &str) -> Result<SystemTime, String> {
+    let age = filter::parse_age(age)?;
+    Ok(SystemTime::now()
+        .checked_sub(age)
+        .unwrap_or(SystemTime::UNIX_EPOCH))
+}
+
+/// Remove what cpx left at the destination `dst`: temporary files, undo
+/// backups older than the retention and snapshots of `src` no running
+/// cpx uses. Returns whether everything could be removed.
+pub fn collect(dst: &Path, src: Option<&Path>, retention: &Retention, dry_run: bool) -> bool {
+    let (part_before, backups_before) =
+        match (before(&retention.part_files), before(&retention.backups)) {
+            (Ok(x), Ok(y)) => (x, y),
+            (Err(e), _) | (_, Err(e)) => {
+                log::error!("Invalid retention, {}", e);
+                return false;
+            }
+        };
+    let mut gc = Gc {
+        dry_run,
+        removed: 0,
+        failed: 0,
+    };
+
+    if let Err(e) = gc.temp_files(dst, part_before) {
+        log::error!("Scan {} failed, {}", dst.display(), e);
+        gc.failed += 1;
+    }
+
+    for (dir, journal) in journal::all().unwrap_or_default() {
+        let started = SystemTime::UNIX_EPOCH + Duration::from_secs(journal.started);
+        let ours = journal.entries.iter().any(|x| x.dst.starts_with(dst));
+        if ours && started < backups_before && !checkpoint::is_running(&journal.run_id) {
+            gc.remove("backups", &dir, || std::fs::remove_dir_all(&dir));
+        }
+    }
+
+    if let Some(src) = src {
+        match snapshot::leftovers(src) {
+            Ok(found) => {
+                for (kind, handle, run_id) in found {
+                    if !checkpoint::is_running(&run_id) {
+                        let path = PathBuf::from(&handle);
+                        gc.remove("snapshot", &path, || snapshot::destroy(kind, &handle));
+                    }
+                }
+            }
+            Err(e) => log::warn!("Look for snapshots of {} failed, {}", src.display(), e),
+        }
+    }
+
+    if !dry_run {
+        log::info!("Removed {}, {} failed", gc.removed, gc.failed);
+    }
+    gc.failed == 0
+}
@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::checksum::Algorithm;
+use crate::state::{fnv1a, state_dir};
+
+/// Files changed this shortly before they were hashed may change again
+/// within the same mtime, their hash is not kept.
+const RACY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    len: u64,
+    /// Modification time in nanoseconds since the epoch.
+    mtime: u128,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blake3: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    xxh3: Option<String>,
+}
+
+impl Entry {
+    fn sum(&self, algorithm: Algorithm) -> Option<&String> {
+        match algorithm {
+            Algorithm::Blake3 => self.blake3.as_ref(),
+            Algorithm::Sha256 => self.sha256.as_ref(),
+            Algorithm::Xxh3 => self.xxh3.as_ref(),
+        }
+    }
+
+    fn sum_mut(&mut self, algorithm: Algorithm) -> &mut Option<String> {
+        match algorithm {
+            Algorithm::Blake3 => &mut self.blake3,
+            Algorithm::Sha256 => &mut self.sha256,
+            Algorithm::Xxh3 => &mut self.xxh3,
+        }
+    }
+}
+
+/// Hashes of the files a destination was compared with, kept between
+/// runs so only files whose size or modification time changed are read
+/// again.
+pub struct HashCache {
+    /// `None` with --no-hash-cache, every hash is computed.
+    path: Option<PathBuf>,
+    /// What `hash` computes.
+    algorithm: Algorithm,
+    entries: Mutex<HashMap<PathBuf, Entry>>,
+    changed: Mutex<HashMap<PathBuf, Entry>>,
+}
+
+fn mtime(meta: &std::fs::Metadata) -> io::Result<u128> {
+    Ok(meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos())
+}
+
+fn read(path: &Path) -> HashMap<PathBuf, Entry> {
+    std::fs::read(path)
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default()
+}
+
+impl HashCache {
+    /// The cache of the destination `dst`, or none at all.
+    pub fn new(dst: Option<&Path>, algorithm: Algorithm) -> Self {
+        let path = dst.map(|x| {
+            let key = fnv1a(x.to_string_lossy().as_bytes());
+            state_dir()
+                .join("hashes")
+                .join(format!("{:016x}.json", key))
+        });
+        let entries = path.as_deref().map(read).unwrap_or_default();
+
+        Self {
+            path,
+            algorithm,
+            entries: Mutex::new(entries),
+            changed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Hash of the local file `path` by the algorithm of the run.
+    pub fn hash(&self, path: &Path) -> io::Result<String> {
+        self.sum(path, self.algorithm)
+    }
+
+    /// SHA-256 of the local file `path`, to compare with hosts and buckets.
+    #[cfg(feature = "remote")]
+    pub fn sha256(&self, path: &Path) -> io::Result<String> {
+        self.sum(path, Algorithm::Sha256)
+    }
+
+    /// Hash of the local file `path`, from the cache while its size and
+    /// modification time are the same.
+    pub fn sum(&self, path: &Path, algorithm: Algorithm) -> io::Result<String> {
+        if !self.enabled() {
+            return algorithm.file(path);
+        }
+
+        let meta = std::fs::metadata(path)?;
+        let (len, mtime) = (meta.len(), mtime(&meta)?);
+        let cached = self.entries.lock().unwrap().get(path).cloned();
+        let cached = cached.filter(|x| x.len == len && x.mtime == mtime);
+        if let Some(x) = cached.as_ref().and_then(|x| x.sum(algorithm)) {
+            return Ok(x.clone());
+        }
+
+        let sum = algorithm.file(path)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        if mtime + RACY.as_nanos() < now.as_nanos() {
+            // the hashes by other algorithms are still good
+            let mut entry = cached.unwrap_or(Entry {
+                len,
+                mtime,
+                sha256: None,
+                blake3: None,
+                xxh3: None,
+            });
+            *entry.sum_mut(algorithm) = Some(sum.clone());
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(path.to_path_buf(), entry.clone());
+            self.changed
+                .lock()
+                .unwrap()
+                .insert(path.to_path_buf(), entry);
+        }
+
+        Ok(sum)
+    }
+
+    /// Files under `root` with their hashes by the algorithm of the run,
+    /// as last seen.
+    pub fn entries_under(&self, root: &Path) -> Vec<(PathBuf, String)> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(path, _)| path.starts_with(root))
+            .filter_map(|(path, x)| Some((path.clone(), x.sum(self.algorithm)?.clone())))
+            .collect()
+    }
+
+    /// Write the hashes computed in this run over those on disk, which
+    /// another run may have added to meanwhile. Entries of files that are
+    /// gone are dropped.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let changed = std::mem::take(&mut *self.changed.lock().unwrap());
+        if changed.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = crate::store::lock()?;
+        let mut entries = read(path);
+        entries.extend(changed);
+        entries.retain(|x, _| x.exists());
+
+        let data = serde_json::to_vec(&entries)?;
+        crate::store::write(path, &data)
+    }
+}
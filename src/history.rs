@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::state::state_dir;
+
+/// One invocation of a copy, enough to show it and to run it again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub run_id: String,
+    pub started: u64,
+    pub cwd: PathBuf,
+    /// Command line arguments without the program name.
+    pub args: Vec<String>,
+    pub spec: Option<String>,
+    pub tags: Vec<String>,
+    pub files: Vec<String>,
+    pub dry_run: bool,
+    /// Number of records per status, e.g. `copied: 3`.
+    pub counts: BTreeMap<String, usize>,
+}
+
+fn history_path() -> PathBuf {
+    state_dir().join("history.jsonl")
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn append(entry: &Entry) -> io::Result<()> {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // one write of the whole line, under the lock so lines of processes
+    // ending together do not interleave
+    let line = format!("{}\n", serde_json::to_string(entry)?);
+    let _lock = crate::store::lock()?;
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    f.write_all(line.as_bytes())
+}
+
+/// All recorded invocations, oldest first; unreadable lines are skipped.
+pub fn load() -> io::Result<Vec<Entry>> {
+    let f = match File::open(history_path()) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    Ok(BufReader::new(f)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|x| serde_json::from_str(&x).ok())
+        .collect())
+}
+
+/// The `n`th invocation as numbered by `cpx history`, starting at 1.
+pub fn get(n: usize) -> io::Result<Entry> {
+    load()?
+        .into_iter()
+        .nth(n.wrapping_sub(1))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no history entry {}", n)))
+}
+
+/// Year, month and day of days since 1970-01-01, see
+/// http://howardhinnant.github.io/date_algorithms.html
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + i64::from(m <= 2);
+
+    (y, m, d)
+}
+
+/// Format a unix timestamp as UTC `YYYY-MM-DD HH:MM:SS`.
+fn format_time(secs: u64) -> String {
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+    let (y, m, d) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        y,
+        m,
+        d,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+pub fn print(entries: &[Entry]) {
+    for (i, entry) in entries.iter().enumerate() {
+        let counts: Vec<_> = entry
+            .counts
+            .iter()
+            .map(|(status, n)| format!("{} {}", status, n))
+            .collect();
+
+        println!(
+            "{:>4}  {}  {}{}  tags=[{}] files=[{}]  {}",
+            i + 1,
+            format_time(entry.started),
+            entry.spec.as_deref().unwrap_or("-"),
+            if entry.dry_run { " (dry run)" } else { "" },
+            entry.tags.join(","),
+            entry.files.join(","),
+            if counts.is_empty() {
+                "nothing to do".to_owned()
+            } else {
+                counts.join(", ")
+            }
+        );
+    }
+}
+
+/// Arguments of an interactive run with the picked selection in place of
+/// the picker, so rerunning it does not ask again.
+#[cfg(feature = "tui")]
+pub fn resolved_args(args: &[String], tags: &[String], files: &[String]) -> Vec<String> {
+    let mut resolved = vec![];
+    let mut in_values = false;
+
+    for arg in args {
+        if arg == "-i" || arg == "--interactive" {
+            continue;
+        }
+        if arg == "--tag" || arg == "--file" {
+            in_values = true;
+            continue;
+        }
+        // like clap, values of --tag/--file run until the next flag
+        if in_values && !arg.starts_with('-') {
+            continue;
+        }
+        in_values = false;
+        if arg.starts_with("--tag=") || arg.starts_with("--file=") {
+            continue;
+        }
+        resolved.push(arg.clone());
+    }
+
+    for t in tags {
+        resolved.extend(["--tag".to_owned(), t.clone()]);
+    }
+    for f in files {
+        resolved.extend(["--file".to_owned(), f.clone()]);
+    }
+
+    resolved
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Commands run before and after a copy run, like stopping a service and
+/// starting it again.
+///
+/// They see `CPX_SRC`, `CPX_DST` and `CPX_RUN_ID` in their environment,
+/// `post` commands also `CPX_FILES`, the destination files of the run one
+/// per line, and `CPX_FAILED`, the number of files that failed.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hooks {
+    /// Run in order before anything is copied, the first failing one
+    /// cancels the run.
+    #[serde(default)]
+    pub pre: Vec<String>,
+    /// Run in order after the copy, all of them even if the copy or one
+    /// of them failed.
+    #[serde(default)]
+    pub post: Vec<String>,
+}
+
+/// `cmd` run by the shell.
+pub fn shell(cmd: &str) -> Command {
+    #[cfg(unix)]
+    let mut command = Command::new("sh");
+    #[cfg(unix)]
+    command.arg("-c");
+    #[cfg(not(unix))]
+    let mut command = Command::new("cmd");
+    #[cfg(not(unix))]
+    command.arg("/C");
+    command.arg(cmd);
+    command
+}
+
+/// Run the hook `cmd` with `env` added to its environment. What it prints
+/// goes to stderr, stdout may carry the records.
+pub fn run(cmd: &str, env: &[(&str, String)]) -> io::Result<()> {
+    let mut command = shell(cmd);
+    command
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdout(io::stderr());
+
+    log::debug!("Run {:?}", command);
+    let status = command.status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(status.to_string())),
+    }
+}
+
+/// Run the `post` command `cmd` of a file on its copy `file`, given as the
+/// last argument and in `CPX_FILE`.
+pub fn run_on(cmd: &str, file: &Path, env: &[(String, String)]) -> io::Result<()> {
+    #[cfg(unix)]
+    let line = format!("{} \"$CPX_FILE\"", cmd);
+    #[cfg(not(unix))]
+    let line = format!("{} \"%CPX_FILE%\"", cmd);
+
+    let mut env: Vec<_> = env.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+    env.push(("CPX_FILE", file.to_string_lossy().into_owned()));
+    run(&line, &env)
+}
+
+/// Pipe `data`, the content of the source `file`, through the filter
+/// `cmd` with `env` added to its environment, what it prints is the
+/// content of the copy. `file` is in `CPX_FILE`.
+pub fn filter(
+    cmd: &str,
+    data: &[u8],
+    file: &Path,
+    env: &[(String, String)],
+) -> io::Result<Vec<u8>> {
+    let mut command = shell(cmd);
+    command
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .env("CPX_FILE", file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+
+    log::debug!("Run {:?}", command);
+    let mut child = command.spawn()?;
+    let mut stdin = child.stdin.take().unwrap();
+    // written while the output is read, a filter may stream
+    let out = std::thread::scope(|scope| {
+        scope.spawn(move || {
+            // a filter not reading all of its input is its own business
+            let _ = stdin.write_all(data);
+        });
+        child.wait_with_output()
+    })?;
+    match out.status.success() {
+        true => Ok(out.stdout),
+        false => Err(io::Error::other(out.status.to_string())),
+    }
+}
@@ -0,0 +1,89 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+
+/// The URL a source path of a `file_list` entry with `url` stands for.
+pub fn url(src: &Path) -> Option<&str> {
+    src.to_str()
+        .filter(|x| x.starts_with("https://") || x.starts_with("http://"))
+}
+
+/// Start downloading `url` to stdout through curl. With `newer_than` the
+/// server only sends the file if it changed after that file.
+pub fn get(url: &str, newer_than: Option<&Path>) -> io::Result<Child> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--proto", "=http,https", "--proto-redir", "=http,https"])
+        .args(["--write-out", "%{stderr}%{http_code}\n"]);
+    if let Some(x) = newer_than {
+        cmd.arg("--time-cond").arg(x);
+    }
+    cmd.arg("--").arg(url);
+
+    log::debug!("Run {:?}", cmd);
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+}
+
+/// Wait for a download, returns whether the server sent the file. Its
+/// stderr becomes the error message.
+pub fn finish(mut child: Child) -> io::Result<bool> {
+    let mut stderr = String::new();
+    if let Some(mut x) = child.stderr.take() {
+        x.read_to_string(&mut stderr)?;
+    }
+
+    // the last line is the status code from --write-out
+    let stderr = stderr.trim_end();
+    let (message, code) = stderr.rsplit_once('\n').unwrap_or(("", stderr));
+    if child.wait()?.success() {
+        Ok(code != "304")
+    } else {
+        Err(io::Error::other(format!("curl failed, {}", message.trim())))
+    }
+}
+
+/// Download `url` into memory.
+#[cfg(feature = "self-update")]
+pub fn fetch(url: &str) -> io::Result<Vec<u8>> {
+    let mut child = get(url, None)?;
+    let mut body = vec![];
+    if let Some(mut x) = child.stdout.take() {
+        x.read_to_end(&mut body)?;
+    }
+
+    finish(child)?;
+    Ok(body)
+}
+
+/// POST the JSON `body` to `url`.
+pub fn post_json(url: &str, body: &[u8]) -> io::Result<()> {
+    let mut cmd = Command::new("curl");
+    cmd.args(["--silent", "--show-error", "--fail", "--max-time", "30"])
+        .args(["--proto", "=http,https"])
+        .args(["--header", "Content-Type: application/json"])
+        .args(["--data-binary", "@-"])
+        .arg("--")
+        .arg(url);
+
+    log::debug!("Run {:?}", cmd);
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut x) = child.stdin.take() {
+        x.write_all(body)?;
+    }
+
+    let out = child.wait_with_output()?;
+    match out.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "curl failed, {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ))),
+    }
+}
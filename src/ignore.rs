@@ -0,0 +1,262 @@
+use std::path::{Component, Path};
+
+/// One gitignore style pattern.
+#[derive(Debug)]
+struct Rule {
+    /// Directory of the .gitignore the rule comes from, it only matches
+    /// below it.
+    base: Vec<String>,
+    segments: Vec<String>,
+    /// Matched against the whole relative path instead of any file name.
+    anchored: bool,
+    dir_only: bool,
+    negate: bool,
+}
+
+/// Patterns from `.cpxignore` and the config `exclude` section, in order,
+/// later patterns take precedence like in gitignore.
+#[derive(Debug, Default)]
+pub struct IgnoreList {
+    rules: Vec<Rule>,
+}
+
+impl IgnoreList {
+    /// The config patterns followed by `.cpxignore` in `root`, if any.
+    pub fn for_source(root: &Path, exclude: &[String]) -> Self {
+        let mut list = Self::default();
+        for x in exclude {
+            list.add(x);
+        }
+
+        let path = root.join(".cpxignore");
+        match std::fs::read_to_string(&path) {
+            Ok(text) => text.lines().for_each(|x| list.add(x)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Read {} failed, {}", path.display(), e),
+        }
+
+        list
+    }
+
+    pub fn add(&mut self, line: &str) {
+        self.add_below(&[], line);
+    }
+
+    /// The rules of the .gitignore files in `root` and its directories,
+    /// and of .git/info/exclude. Those of deeper directories take
+    /// precedence.
+    pub fn add_gitignore(&mut self, root: &Path) {
+        let path = root.join(".git/info/exclude");
+        if let Ok(text) = std::fs::read_to_string(path) {
+            text.lines().for_each(|x| self.add(x));
+        }
+
+        let mut dirs = vec![vec![]];
+        while let Some(base) = dirs.pop() {
+            let dir = base
+                .iter()
+                .fold(root.to_path_buf(), |p, x: &String| p.join(x));
+            let path = dir.join(".gitignore");
+            match std::fs::read_to_string(&path) {
+                Ok(text) => text.lines().for_each(|x| self.add_below(&base, x)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => log::warn!("Read {} failed, {}", path.display(), e),
+            }
+
+            let mut subdirs: Vec<_> = std::fs::read_dir(&dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|x| x.file_type().is_ok_and(|t| t.is_dir()))
+                .map(|x| x.file_name().to_string_lossy().into_owned())
+                .filter(|x| x != ".git")
+                .collect();
+            // ignored directories are not searched, like git does
+            subdirs.sort_unstable_by(|a, b| b.cmp(a));
+            for name in subdirs {
+                let mut sub = base.clone();
+                sub.push(name);
+                let parts: Vec<_> = sub.iter().map(|x| x.as_str()).collect();
+                if !(1..=parts.len()).any(|n| self.decide(&parts[..n], true)) {
+                    dirs.push(sub);
+                }
+            }
+        }
+    }
+
+    fn add_below(&mut self, base: &[String], line: &str) {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return;
+        }
+
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(x) => (true, x),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(x) => (true, x),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let segments = line
+            .trim_start_matches('/')
+            .split('/')
+            .map(str::to_owned)
+            .collect();
+
+        self.rules.push(Rule {
+            base: base.to_vec(),
+            segments,
+            anchored,
+            dir_only,
+            negate,
+        });
+    }
+
+    /// Whether the file at relative path `path` is excluded, either itself
+    /// or through one of its directories.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+
+        let parts: Vec<_> = path
+            .components()
+            .filter_map(|x| match x {
+                Component::Normal(x) => Some(x.to_string_lossy()),
+                _ => None,
+            })
+            .collect();
+        let parts: Vec<&str> = parts.iter().map(|x| x.as_ref()).collect();
+
+        (1..=parts.len()).any(|n| self.decide(&parts[..n], n < parts.len()))
+    }
+
+    /// Verdict of the last rule matching `parts`, gitignore semantics.
+    fn decide(&self, parts: &[&str], is_dir: bool) -> bool {
+        self.rules
+            .iter()
+            .rev()
+            .find(|r| (is_dir || !r.dir_only) && r.matches(parts))
+            .is_some_and(|r| !r.negate)
+    }
+}
+
+impl Rule {
+    fn matches(&self, parts: &[&str]) -> bool {
+        let below =
+            parts.len() > self.base.len() && self.base.iter().zip(parts).all(|(a, b)| a == b);
+        if !below {
+            return false;
+        }
+        let parts = &parts[self.base.len()..];
+        if self.anchored {
+            match_segments(&self.segments, parts)
+        } else {
+            parts
+                .last()
+                .is_some_and(|name| glob(self.segments[0].as_bytes(), name.as_bytes()))
+        }
+    }
+}
+
+/// Match path components, `**` standing for any number of them.
+fn match_segments(pattern: &[String], parts: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => parts.is_empty(),
+        Some((p, rest)) if p == "**" => {
+            (0..=parts.len()).any(|i| match_segments(rest, &parts[i..]))
+        }
+        Some((p, rest)) => match parts.split_first() {
+            Some((x, tail)) => glob(p.as_bytes(), x.as_bytes()) && match_segments(rest, tail),
+            None => false,
+        },
+    }
+}
+
+/// Match one file name against `*`, `?`, `[...]` and `\` escapes.
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|i| glob(rest, &name[i..])),
+        Some((b'?', rest)) => !name.is_empty() && glob(rest, &name[1..]),
+        Some((b'[', rest)) => match (class(rest, name.first()), name.split_first()) {
+            (Some((true, rest)), Some((_, tail))) => glob(rest, tail),
+            (Some(_), _) => false,
+            // unterminated class, a literal `[`
+            (None, _) => name.first() == Some(&b'[') && glob(rest, &name[1..]),
+        },
+        Some((b'\\', rest)) if !rest.is_empty() => {
+            name.first() == Some(&rest[0]) && glob(&rest[1..], &name[1..])
+        }
+        Some((c, rest)) => name.first() == Some(c) && glob(rest, &name[1..]),
+    }
+}
+
+/// Parse the class after `[`, returning whether `c` is in it and the
+/// pattern after the closing `]`.
+fn class<'a>(pattern: &'a [u8], c: Option<&u8>) -> Option<(bool, &'a [u8])> {
+    let (negate, pattern) = match pattern.first() {
+        Some(b'!') | Some(b'^') => (true, &pattern[1..]),
+        _ => (false, pattern),
+    };
+
+    let mut found = false;
+    let mut i = 0;
+    while i < pattern.len() {
+        if pattern[i] == b']' && i > 0 {
+            return Some((c.is_some() && found != negate, &pattern[i + 1..]));
+        }
+        let (lo, hi, len) = match pattern.get(i + 1..i + 3) {
+            Some([b'-', hi]) if *hi != b']' => (pattern[i], *hi, 3),
+            _ => (pattern[i], pattern[i], 1),
+        };
+        found |= c.is_some_and(|c| (lo..=hi).contains(c));
+        i += len;
+    }
+
+    None
+}
+
+/// The rules of an rsync filter file as gitignore style patterns, in
+/// the order of `add`. Only include and exclude rules are known.
+pub fn rsync_rules(text: &str) -> Result<Vec<String>, String> {
+    let mut rules = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let (rule, pattern) = line.split_once(' ').unwrap_or((line, ""));
+        let negate = match rule {
+            "-" | "exclude" => false,
+            "+" | "include" => true,
+            "!" | "clear" => {
+                rules.clear();
+                continue;
+            }
+            _ => return Err(format!("line {}: unsupported rule `{}`", n + 1, rule)),
+        };
+        if pattern.is_empty() {
+            return Err(format!("line {}: `{}` has no pattern", n + 1, rule));
+        }
+
+        // rsync patterns with a `/` inside match at the end of a path,
+        // gitignore ones from the root
+        let inner = pattern.trim_end_matches('/').contains('/');
+        let pattern = match (pattern.starts_with('/'), inner) {
+            (false, true) => format!("**/{}", pattern),
+            _ => pattern.to_owned(),
+        };
+        rules.push(match negate {
+            true => format!("!{}", pattern),
+            false if pattern.starts_with(['!', '#']) => format!("\\{}", pattern),
+            false => pattern,
+        });
+    }
+
+    // the first rsync rule matching wins, the last gitignore one
+    rules.reverse();
+    Ok(rules)
+}
@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::state::state_dir;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// The file did not exist before the run.
+    Created,
+    /// The file existed and its previous content is at `backup`.
+    Overwritten { backup: PathBuf },
+    /// The file was deleted and its content is at `backup`.
+    Deleted { backup: PathBuf },
+    /// The path was a symlink to `target`.
+    Symlink { target: PathBuf },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub dst: PathBuf,
+    pub action: Action,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct JournalFile {
+    pub run_id: String,
+    pub started: u64,
+    pub entries: Vec<Entry>,
+}
+
+fn journal_root() -> PathBuf {
+    state_dir().join("journal")
+}
+
+/// Records what a run changes at its destinations, so `cpx undo` can
+/// put the previous state back.
+pub struct Journal {
+    dir: PathBuf,
+    file: Mutex<JournalFile>,
+    seen: Mutex<HashSet<PathBuf>>,
+}
+
+impl Journal {
+    /// Journal of `run_id`, continuing the one a previous attempt of the
+    /// same run left behind.
+    pub fn new(run_id: &str) -> Self {
+        let dir = journal_root().join(run_id);
+        let file = File::open(dir.join("journal.yaml"))
+            .ok()
+            .and_then(|f| serde_yaml::from_reader::<_, JournalFile>(f).ok())
+            .unwrap_or_else(|| JournalFile {
+                run_id: run_id.to_owned(),
+                started: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|x| x.as_secs())
+                    .unwrap_or_default(),
+                entries: vec![],
+            });
+        let seen = file.entries.iter().map(|x| x.dst.clone()).collect();
+
+        Self {
+            dir,
+            file: Mutex::new(file),
+            seen: Mutex::new(seen),
+        }
+    }
+
+    fn backup(&self, dst: &Path) -> io::Result<PathBuf> {
+        let dir = self.dir.join("backup");
+        std::fs::create_dir_all(&dir)?;
+
+        let n = self.file.lock().unwrap().entries.len();
+        let backup = dir.join(format!(
+            "{}-{:016x}",
+            n,
+            crate::state::fnv1a(dst.to_string_lossy().as_bytes())
+        ));
+        std::fs::copy(dst, &backup)?;
+
+        Ok(backup)
+    }
+
+    fn push(&self, dst: &Path, action: Action) {
+        self.file.lock().unwrap().entries.push(Entry {
+            dst: dst.to_path_buf(),
+            action,
+        });
+    }
+
+    /// Must be called before `dst` is written; only the first write of a
+    /// file in a run is recorded, that is the state undo restores.
+    pub fn before_write(&self, dst: &Path) -> io::Result<()> {
+        if !self.seen.lock().unwrap().insert(dst.to_path_buf()) {
+            return Ok(());
+        }
+
+        match std::fs::symlink_metadata(dst) {
+            // backing up a link would copy whatever it points to
+            Ok(x) if x.file_type().is_symlink() => {
+                let target = std::fs::read_link(dst)?;
+                self.push(dst, Action::Symlink { target });
+            }
+            Ok(x) if x.is_file() => {
+                let backup = self.backup(dst)?;
+                self.push(dst, Action::Overwritten { backup });
+            }
+            _ => self.push(dst, Action::Created),
+        }
+
+        Ok(())
+    }
+
+    pub fn before_delete(&self, dst: &Path) -> io::Result<()> {
+        if !self.seen.lock().unwrap().insert(dst.to_path_buf()) {
+            return Ok(());
+        }
+
+        let backup = self.backup(dst)?;
+        self.push(dst, Action::Deleted { backup });
+
+        Ok(())
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let file = self.file.lock().unwrap();
+        if file.entries.is_empty() {
+            return Ok(());
+        }
+
+        let data = serde_yaml::to_vec(&*file).map_err(io::Error::other)?;
+        crate::store::write(&self.dir.join("journal.yaml"), &data)
+    }
+}
+
+fn read(dir: PathBuf) -> Option<(PathBuf, JournalFile)> {
+    let f = File::open(dir.join("journal.yaml")).ok()?;
+    let journal: JournalFile = serde_yaml::from_reader(f).ok()?;
+    Some((dir, journal))
+}
+
+/// Every readable journal with its directory.
+pub fn all() -> io::Result<Vec<(PathBuf, JournalFile)>> {
+    let dirs = match std::fs::read_dir(journal_root()) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+
+    Ok(dirs
+        .filter_map(|x| x.ok().and_then(|x| read(x.path())))
+        .collect())
+}
+
+/// Load the journal of `run_id`, or the most recent one.
+pub fn load(run_id: Option<&str>) -> io::Result<(PathBuf, JournalFile)> {
+    let journals = match run_id {
+        Some(id) => read(journal_root().join(id)).into_iter().collect(),
+        None => all()?,
+    };
+
+    journals
+        .into_iter()
+        .max_by_key(|(_, x)| x.started)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no journal found"))
+}
+
+/// Remove `path` if it is a symlink, so restoring content through it
+/// cannot write into whatever it points to.
+fn remove_link(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(x) if x.file_type().is_symlink() => crate::copy::remove_link(path),
+        _ => Ok(()),
+    }
+}
+
+/// Revert a run; the journal is removed once everything was restored.
+pub fn undo(dir: &Path, journal: &JournalFile, dry_run: bool) -> io::Result<()> {
+    let mut failed = false;
+
+    for entry in journal.entries.iter().rev() {
+        let dst = entry.dst.display();
+        let result = match &entry.action {
+            Action::Created => {
+                log::info!("Remove {}", dst);
+                if dry_run {
+                    println!("{:<10} {}", "remove", dst);
+                    Ok(())
+                } else {
+                    crate::copy::remove_link(&entry.dst).or_else(|e| match e.kind() {
+                        io::ErrorKind::NotFound => Ok(()),
+                        _ => Err(e),
+                    })
+                }
+            }
+            Action::Overwritten { backup } | Action::Deleted { backup } => {
+                log::info!("Restore {}", dst);
+                if dry_run {
+                    println!("{:<10} {}", "restore", dst);
+                    Ok(())
+                } else {
+                    remove_link(&entry.dst)
+                        .and_then(|_| std::fs::copy(backup, &entry.dst).map(|_| ()))
+                }
+            }
+            Action::Symlink { target } => {
+                log::info!("Restore link {} -> {}", dst, target.display());
+                if dry_run {
+                    println!("{:<10} {}", "restore", dst);
+                    Ok(())
+                } else {
+                    crate::copy::remove_link(&entry.dst)
+                        .or_else(|e| match e.kind() {
+                            io::ErrorKind::NotFound => Ok(()),
+                            _ => Err(e),
+                        })
+                        .and_then(|_| crate::copy::symlink(target, &entry.dst))
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            log::error!("Undo {} failed, {}", dst, e);
+            failed = true;
+        }
+    }
+
+    if failed {
+        return Err(io::Error::other("some files could not be restored"));
+    }
+
+    if !dry_run {
+        std::fs::remove_dir_all(dir)?;
+    }
+
+    Ok(())
+}
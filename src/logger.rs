@@ -0,0 +1,135 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Target of records for the log file only, the console shows them in
+/// its own way.
+pub const FILE_ONLY: &str = "cpx::file";
+
+static CONSOLE: OnceLock<(LevelFilter, bool)> = OnceLock::new();
+
+/// Logs to stderr at the level picked by `-v`/`-q`, and optionally to a
+/// file which always records at least `Info` for auditing.
+struct Logger {
+    console: LevelFilter,
+    color: bool,
+    file: Option<Mutex<File>>,
+    run_id: String,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= self.console && record.target() != FILE_ONLY {
+            let paint = |prefix: &str, code: &str| match self.color {
+                true => format!("\x1b[{}m{}\x1b[0m", code, prefix),
+                false => prefix.to_owned(),
+            };
+            match record.level() {
+                Level::Error => eprintln!("{} {}", paint("error:", "1;31"), record.args()),
+                Level::Warn => eprintln!("{} {}", paint("warning:", "1;33"), record.args()),
+                Level::Info => eprintln!("{}", record.args()),
+                Level::Debug | Level::Trace => {
+                    eprintln!("{} {}", paint("debug:", "2"), record.args())
+                }
+            }
+        }
+
+        if let Some(file) = &self.file {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let _ = writeln!(
+                file.lock().unwrap(),
+                "{}.{:03} {:<5} [{}] {}",
+                now.as_secs(),
+                now.subsec_millis(),
+                record.level(),
+                self.run_id,
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().flush();
+        }
+    }
+}
+
+impl Logger {
+    fn max_level(&self) -> LevelFilter {
+        match self.file {
+            Some(_) => self.console.max(LevelFilter::Info),
+            None => self.console,
+        }
+    }
+}
+
+pub fn level_for(verbose: u64, quiet: bool) -> LevelFilter {
+    match (quiet, verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Whether to color stderr, or `stdout`, for `--color`, `auto` honors
+/// `NO_COLOR`.
+pub fn use_color(when: &str, stdout: bool) -> bool {
+    match when {
+        "always" => true,
+        "never" => false,
+        _ => std::env::var_os("NO_COLOR").is_none() && is_tty(stdout),
+    }
+}
+
+/// Level and color of the console.
+pub fn console() -> (LevelFilter, bool) {
+    CONSOLE.get().copied().unwrap_or((LevelFilter::Warn, false))
+}
+
+#[cfg(unix)]
+fn is_tty(stdout: bool) -> bool {
+    let fd = match stdout {
+        true => libc::STDOUT_FILENO,
+        false => libc::STDERR_FILENO,
+    };
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+#[cfg(not(unix))]
+fn is_tty(_: bool) -> bool {
+    false
+}
+
+pub fn init(console: LevelFilter, color: bool, log_file: Option<&Path>, run_id: &str) {
+    let file = log_file.map(|x| {
+        Mutex::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(x)
+                .expect("Log file open failed!"),
+        )
+    });
+
+    let _ = CONSOLE.set((console, color));
+    let logger = Logger {
+        console,
+        color,
+        file,
+        run_id: run_id.to_owned(),
+    };
+    log::set_max_level(logger.max_level());
+    log::set_boxed_logger(Box::new(logger)).expect("Logger init failed!");
+}
@@ -1,234 +1,6156 @@
-use clap::{App, Arg};
+#[cfg(feature = "archive")]
+mod archive;
+mod backup;
+mod bench;
+mod blake3;
+mod breaker;
+mod case;
+mod checkpoint;
+mod checksum;
+mod cleanup;
+mod cli;
+mod compare;
+#[cfg(feature = "completions")]
+mod completions;
+mod compress;
+mod config;
+mod conflict;
+mod copy;
+mod crypt;
+#[cfg(all(unix, feature = "daemon"))]
+mod daemon;
+mod dedup;
+mod doctor;
+mod du;
+mod eol;
+mod events;
+mod export;
+mod filter;
+mod flatten;
+mod gc;
+mod hashcache;
+mod history;
+mod hooks;
+mod http;
+mod ignore;
+mod journal;
+mod logger;
+mod man;
+mod manifest;
+#[cfg(feature = "remote")]
+mod md5;
+mod mkdir;
+mod mode;
+mod normalize;
+mod notify;
+mod order;
+mod output;
+mod owner;
+mod paths;
+#[cfg(feature = "tui")]
+mod picker;
+mod prefetch;
+mod preset;
+mod priority;
+mod profile;
+mod regex;
+#[cfg(feature = "remote")]
+mod remote;
+mod report;
+mod retry;
+mod rotate;
+#[cfg(feature = "remote")]
+mod rsync;
+#[cfg(feature = "remote")]
+mod s3;
+mod scan;
+mod schedule;
+mod select;
+mod snapshot;
+mod spec;
+mod state;
+mod stats;
+mod store;
+mod suggest;
+mod template;
+mod throttle;
+mod timeout;
+mod transform;
+mod tree;
+#[cfg(feature = "self-update")]
+mod update;
+mod watch;
+#[cfg(feature = "remote")]
+mod webdav;
+mod xattr;
+mod xxh3;
+
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use backup::Backup;
+use breaker::CircuitBreaker;
+use case::CaseCollision;
+use checkpoint::{Checkpoint, CheckpointFile};
+use checksum::{Algorithm, Expected};
+use compare::{FileStatus, Strategy};
+use compress::{Compression, Decompress};
+use copy::{Copier, Engine};
+use dedup::Dedup;
+use eol::Eol;
+use filter::Filter;
+use flatten::Collision;
+use hashcache::HashCache;
+use hooks::Hooks;
+use ignore::IgnoreList;
+use journal::Journal;
+use mkdir::DirCreator;
+use mode::Mode;
+use normalize::NormalizePolicy;
+use notify::When;
+use order::Order;
+use output::{Output, OutputMode, Record, Status};
+use owner::Owner;
+use prefetch::Prefetch;
+use profile::{FileProfile, Phase, ProfileFormat, Profiler};
+#[cfg(feature = "remote")]
+use remote::Remote;
+use retry::OnChange;
+use rotate::Rotation;
+#[cfg(feature = "remote")]
+use rsync::{Rsync, Transport};
+#[cfg(feature = "remote")]
+use s3::Bucket;
+use snapshot::Snapshot;
+use state::{RunRecord, SourceStamps, Stamp, SyncState};
+use stats::Stats;
+use transform::Transformed;
+#[cfg(feature = "remote")]
+use webdav::Dav;
 
 struct Cpx {
     copy_config: CopyConfig,
     file_config: ConfigInfo,
+    dirs: DirCreator,
+    copier: Copier,
+    profiler: Option<Profiler>,
+    output: Output,
+    breaker: CircuitBreaker,
+    /// Asks what to do with each destination file that differs, --ask.
+    ask: Option<conflict::Ask>,
+    journal: Option<Journal>,
+    /// Destination host of the run, files are uploaded through ssh.
+    #[cfg(feature = "remote")]
+    remote: Option<Remote>,
+    /// Destination bucket of the run, files are uploaded as objects.
+    #[cfg(feature = "remote")]
+    bucket: Option<Bucket>,
+    /// Destination collection of the run, files are uploaded over WebDAV.
+    #[cfg(feature = "remote")]
+    webdav: Option<Dav>,
+    /// Source bucket of the run, objects are downloaded.
+    #[cfg(feature = "remote")]
+    source_bucket: Option<Bucket>,
+    checkpoint: Option<Checkpoint>,
+    /// How destination files are found identical, `--compare` over the
+    /// destination's `compare`.
+    strategy: Option<Strategy>,
+    /// Hashes of files compared in earlier runs to the same destination.
+    hashes: HashCache,
+    /// Destination files by content, with `--dedup link`.
+    index: Option<dedup::Index>,
+    /// Time spent in fsync over all files, for the run summary.
+    fsync: Mutex<Duration>,
+    /// Whether a pre or post hook failed.
+    hook_failed: AtomicBool,
+    /// Config entries of the selected files, by destination path.
+    file_info: Mutex<HashMap<PathBuf, FileInfo>>,
+    /// Environment of the filters and post commands of the selected files.
+    file_env: Mutex<Vec<(String, String)>>,
+    /// Owner of the copied files, `--chown` over the destination's `owner`.
+    owner: Option<Owner>,
+    /// Whether changing an owner was refused, it is not tried again.
+    chown_denied: AtomicBool,
+    /// Whether an extended attribute was refused, it is only logged once.
+    xattrs_refused: AtomicBool,
+    /// Files of the selection when resolved once for several specs.
+    selection: Option<HashSet<FileInfo>>,
+    /// Snapshots of the destinations the run copies into, --rotate.
+    rotations: Vec<Rotation>,
+    /// Destination names of the selected files with --flatten, by their
+    /// relative path.
+    flat: Mutex<HashMap<PathBuf, PathBuf>>,
 }
 
 impl Cpx {
-    fn new(copy_config: CopyConfig, file_config: ConfigInfo) -> Self {
+    fn new(
+        copy_config: CopyConfig,
+        mut file_config: ConfigInfo,
+        checkpoint: Option<Checkpoint>,
+    ) -> Self {
+        // every spec of the run, before any of them is copied
+        let specs = copy_config.specs.iter().flat_map(|(x, y)| [x, y]);
+        for name in copy_config.from.iter().chain(&copy_config.to).chain(specs) {
+            if let Err(e) = spec::resolve(&mut file_config.path_list, name) {
+                log::error!("Invalid spec, {}", e);
+                std::process::exit(1);
+            }
+            let info = file_config.path_list.get_mut(name).unwrap();
+            info.path = copy_config.vars.expand(&info.path).unwrap_or_else(|e| {
+                log::error!("Invalid path of {}, {}", name, e);
+                std::process::exit(1);
+            });
+        }
+        // before any worker is started, they inherit it
+        if copy_config.low_priority {
+            if let Err(e) = priority::lower() {
+                log::warn!("Lower the priority failed, {}", e);
+            }
+        }
+        let rotations = match copy_config.rotate {
+            true => Self::rotate(&copy_config, &mut file_config),
+            false => vec![],
+        };
+        let (src, dst) = match copy_config.reverse {
+            true => (&copy_config.to, &copy_config.from),
+            false => (&copy_config.from, &copy_config.to),
+        };
+        let src = src.as_ref().and_then(|x| file_config.path_list.get(x));
+        let dst = dst.as_ref().and_then(|x| file_config.path_list.get(x));
+        #[cfg(not(feature = "remote"))]
+        for x in src.iter().chain(&dst) {
+            if is_remote(&x.path) {
+                log::error!(
+                    "{} is a remote path, this cpx is built without the `remote` feature",
+                    x.path.display()
+                );
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "remote")]
+        let remote = dst.and_then(|x| Remote::parse(&x.path, x.ssh.as_ref()));
+        #[cfg(feature = "remote")]
+        let bucket = dst.and_then(|x| Bucket::parse(&x.path, x.s3.as_ref()));
+        #[cfg(feature = "remote")]
+        let source_bucket = src.and_then(|x| Bucket::parse(&x.path, x.s3.as_ref()));
+        #[cfg(feature = "remote")]
+        for x in bucket.iter().chain(&source_bucket) {
+            if let Err(e) = x.check() {
+                log::error!(
+                    "Invalid s3 settings of {}, {}",
+                    x.display(x.prefix()).display(),
+                    e
+                );
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "remote")]
+        let webdav = dst
+            .and_then(|x| Dav::parse(&x.path, x.webdav.as_ref()))
+            .map(|mut x| {
+                if let Err(e) = x.login() {
+                    log::error!("Read WebDAV credentials failed, {}", e);
+                    std::process::exit(1);
+                }
+                x
+            });
+        #[cfg(feature = "remote")]
+        let local = remote.is_none() && bucket.is_none() && webdav.is_none();
+        #[cfg(not(feature = "remote"))]
+        let local = true;
+        #[cfg(feature = "remote")]
+        let (from_bucket, dav) = (source_bucket.is_some(), webdav.is_some());
+        #[cfg(not(feature = "remote"))]
+        let (from_bucket, dav) = (false, false);
+        // the umask of the destination replaces that of the process
+        let umask = dst.and_then(|x| x.umask).map_or(0, Mode::bits);
+        let dir_mode = copy_config
+            .dir_mode
+            .or(dst.and_then(|x| x.dir_mode).map(Mode::bits))
+            .or(Some(0o777).filter(|_| umask != 0))
+            .map(|x| x & !umask);
+        // the paths of a `collect` or `merge` source are checked by its parts
+        let plain = src.filter(|x| x.collect.is_empty() && x.merge.is_empty());
+        if let (Some(src), Some(dst)) = (plain, dst) {
+            let overlap = local && !from_bucket && paths::overlap(&src.path, &dst.path);
+            if overlap && !copy_config.allow_overlap {
+                log::error!(
+                    "Destination {} overlaps source {}, --allow-overlap to copy anyway",
+                    dst.path.display(),
+                    src.path.display()
+                );
+                std::process::exit(1);
+            }
+        }
+        if !local && copy_config.journal && !copy_config.dry_run {
+            log::warn!("cpx undo does not cover remote destinations");
+        }
+
+        let algorithm = copy_config
+            .checksum
+            .or(file_config.checksum)
+            .unwrap_or_default();
+        let hashes = HashCache::new(
+            dst.map(|x| x.path.as_path())
+                .filter(|_| copy_config.hash_cache),
+            algorithm,
+        );
+        if copy_config.dedup.is_some() && (!local || from_bucket) {
+            log::error!("--dedup needs a local source and destination");
+            std::process::exit(1);
+        }
+        let index = dst
+            .filter(|_| copy_config.dedup == Some(Dedup::Link))
+            .map(|x| dedup::Index::new(&x.path, &hashes));
+
+        let owner = copy_config
+            .chown
+            .as_deref()
+            .or(dst.and_then(|x| x.owner.as_deref()))
+            .map(|x| {
+                Owner::parse(x).unwrap_or_else(|e| {
+                    log::error!("Invalid owner {}, {}", x, e);
+                    std::process::exit(1);
+                })
+            });
+
+        let strategy = copy_config.compare.or(dst.and_then(|x| x.compare));
+        let unsupported = match strategy {
+            Some(Strategy::Etag) if local && !from_bucket => {
+                Some("destinations other than buckets")
+            }
+            Some(Strategy::Hash) if dav => Some("WebDAV destinations"),
+            _ => None,
+        };
+        if let (Some(strategy), Some(what)) = (strategy, unsupported) {
+            log::error!(
+                "Comparing by {} does not work for {}",
+                strategy.name(),
+                what
+            );
+            std::process::exit(1);
+        }
+
         Self {
+            dirs: DirCreator::new(dir_mode),
+            copier: Copier::new(
+                copy_config.engine,
+                copy_config.bwlimit,
+                copy_config.atomic,
+                copy_config.sync,
+                copy_config.chunk_size.unwrap_or(copy::CHUNK_SIZE),
+            ),
+            profiler: copy_config
+                .profile
+                .as_ref()
+                .map(|(_, format)| Profiler::new(*format)),
+            output: Output::new(
+                copy_config.output,
+                &copy_config.run_id,
+                copy_config.format.clone(),
+                copy_config.color,
+                copy_config.events.clone(),
+                copy_config.report.is_some(),
+            ),
+            breaker: CircuitBreaker::new(copy_config.error_budget),
+            ask: copy_config.ask.then(conflict::Ask::default),
+            journal: if copy_config.dry_run || !copy_config.journal || !local {
+                None
+            } else {
+                Some(Journal::new(&copy_config.run_id))
+            },
+            #[cfg(feature = "remote")]
+            remote,
+            #[cfg(feature = "remote")]
+            bucket,
+            #[cfg(feature = "remote")]
+            webdav,
+            #[cfg(feature = "remote")]
+            source_bucket,
+            checkpoint,
+            strategy,
+            hashes,
+            index,
+            fsync: Mutex::new(Duration::default()),
+            hook_failed: AtomicBool::new(false),
+            file_info: Mutex::new(HashMap::new()),
+            file_env: Mutex::new(vec![]),
+            owner,
+            chown_denied: AtomicBool::new(false),
+            xattrs_refused: AtomicBool::new(false),
+            selection: None,
+            rotations,
+            flat: Mutex::new(HashMap::new()),
             copy_config,
             file_config,
         }
     }
 
-    fn execute<T: AsRef<str>>(&self, tags: Option<Vec<T>>, files: Option<Vec<T>>) {
-        let copy_files = self.file_config.calculate_file_list(&tags, &files);
+    /// Point each destination of the run at a new snapshot below it, for
+    /// --rotate, the parts of the run get the paths of the snapshots.
+    fn rotate(copy_config: &CopyConfig, file_config: &mut ConfigInfo) -> Vec<Rotation> {
+        let dsts = copy_config
+            .specs
+            .iter()
+            .map(|(from, to)| match copy_config.reverse {
+                true => from,
+                false => to,
+            });
+        let first = match copy_config.reverse {
+            true => &copy_config.from,
+            false => &copy_config.to,
+        };
+        let mut names: Vec<_> = first.iter().chain(dsts).collect();
+        names.sort_unstable();
+        names.dedup();
 
-        if !copy_files.is_empty() {
-            let from = self.src_path().expect("src path not found");
-            let to = self.dst_path().expect("dst path not found");
+        let mut rotations = vec![];
+        for name in names {
+            let info = file_config.path_list.get_mut(name).unwrap();
+            if is_remote(&info.path) || !info.collect.is_empty() || !info.merge.is_empty() {
+                log::error!("--rotate needs a local destination, {} is not", name);
+                std::process::exit(1);
+            }
+            let rotation = Rotation::new(&info.path, copy_config.vars.stamp(), info.keep)
+                .unwrap_or_else(|e| {
+                    log::error!("Rotate {} failed, {}", name, e);
+                    std::process::exit(1);
+                });
+            info.path = rotation.path();
+            rotations.push(rotation);
+        }
 
-            for f in copy_files {
-                let src = from.join(&f.relative_path);
-                let dst = to.join(f.relative_path);
+        rotations
+    }
 
-                self.execute_copy(src, dst);
+    /// Point `latest` at the snapshots of the run and remove those
+    /// beyond `keep`.
+    fn finish_rotations(&self) {
+        for rotation in &self.rotations {
+            match rotation.finish() {
+                Ok(removed) => {
+                    for x in removed {
+                        log::info!("Removed snapshot {}", x.display());
+                    }
+                }
+                Err(e) => log::error!("Rotate {} failed, {}", rotation.path().display(), e),
             }
         }
+    }
+
+    fn execute<T: AsRef<str> + Clone>(&self, tags: Option<Vec<T>>, files: Option<Vec<T>>) {
+        let _graceful = cleanup::graceful();
+        let started = Instant::now();
+        let tags = tags.map(|x| self.file_config.expand_tags(&x));
+        let files: Option<Vec<String>> =
+            files.map(|x| x.iter().map(|x| x.as_ref().to_owned()).collect());
+        if let Some(events) = &self.copy_config.events {
+            events.run_start(self.spec_names(), self.copy_config.dry_run);
+        }
+        self.check_writable(&tags);
+        let hooks = self.copy_config.hooks;
+        if hooks && !self.execute_hooks(&tags, None) {
+            self.summarize(started);
+            return;
+        }
+
+        let copied = match self.copy_config.specs.len() > 1 {
+            _ if self.tag_paths(&tags) => self.execute_tags(&tags, &files),
+            true => self.execute_specs(&tags, &files),
+            false => self.copy_selection(&tags, &files),
+        };
+        if !self.copy_config.dry_run && !self.failed() && !self.stopped() {
+            self.finish_rotations();
+        }
 
-        self.execute_copy_script(&tags);
+        if hooks {
+            self.execute_hooks(&tags, Some(&copied));
+        }
+        self.summarize(started);
     }
 
-    fn src_path(&self) -> Option<PathBuf> {
-        self.copy_config
-            .from
-            .as_ref()
-            .and_then(|x| self.file_config.path_list.get(x).map(|x| x.path.clone()))
+    /// Exit before anything is copied when local destinations of the run
+    /// cannot be written, listing them all rather than failing file by
+    /// file.
+    fn check_writable<T: AsRef<str>>(&self, tags: &Option<Vec<T>>) {
+        let c = &self.copy_config;
+        if c.dry_run {
+            return;
+        }
+        let dst = |from: &Option<String>, to: &Option<String>| match c.reverse {
+            true => from.clone(),
+            false => to.clone(),
+        };
+        let specs = c.specs.iter().map(|(from, to)| match c.reverse {
+            true => from.clone(),
+            false => to.clone(),
+        });
+        let tags = tags
+            .iter()
+            .flatten()
+            .filter_map(|t| self.file_config.tag_list.get(t.as_ref()))
+            .filter_map(|x| dst(&x.from, &x.to));
+        let mut names: Vec<_> = dst(&c.from, &c.to)
+            .into_iter()
+            .chain(specs)
+            .chain(tags)
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let mut paths = self.file_config.path_list.clone();
+        let mut unwritable = vec![];
+        for name in names {
+            // a spec or path that is not valid fails the copy later
+            if spec::resolve(&mut paths, &name).is_err() {
+                continue;
+            }
+            let info = &paths[&name];
+            if is_remote(&info.path) || !info.collect.is_empty() || !info.merge.is_empty() {
+                continue;
+            }
+            let path = match c.vars.expand(&info.path) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            if let Some(why) = doctor::unwritable(&path) {
+                unwritable.push((name, path, why));
+            }
+        }
+
+        if unwritable.is_empty() {
+            return;
+        }
+        for (name, path, why) in &unwritable {
+            log::error!(
+                "Destination {} {} is not writable, {}",
+                name,
+                path.display(),
+                why
+            );
+        }
+        match unwritable.len() {
+            1 => log::error!("Nothing copied, 1 destination is not writable"),
+            n => log::error!("Nothing copied, {} destinations are not writable", n),
+        }
+        std::process::exit(1);
     }
 
-    fn dst_path(&self) -> Option<PathBuf> {
-        self.copy_config
-            .to
-            .as_ref()
-            .and_then(|x| self.file_config.path_list.get(x).map(|x| x.path.clone()))
+    /// Every `from:to` of the run.
+    fn spec_names(&self) -> Vec<String> {
+        match self.copy_config.specs.is_empty() {
+            true => vec![format!(
+                "{}:{}",
+                self.copy_config.from.as_deref().unwrap_or_default(),
+                self.copy_config.to.as_deref().unwrap_or_default()
+            )],
+            false => self
+                .copy_config
+                .specs
+                .iter()
+                .map(|(a, b)| format!("{}:{}", a, b))
+                .collect(),
+        }
     }
 
-    fn execute_copy_script<T: AsRef<str>>(&self, tags: &Option<Vec<T>>) {
-        let scripts = self.file_config.calculate_script_list(tags);
+    /// Run the pre hooks, or with the files `copied` the post hooks, of
+    /// the config and of `tags`. Whether none of them failed.
+    fn execute_hooks<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        copied: Option<&BTreeSet<PathBuf>>,
+    ) -> bool {
+        let tag_hooks = tags
+            .iter()
+            .flatten()
+            .filter_map(|t| self.file_config.tag_list.get(t.as_ref()))
+            .map(|x| &x.hooks);
+        let commands: Vec<_> = std::iter::once(&self.file_config.hooks)
+            .chain(tag_hooks)
+            .flat_map(|x: &Hooks| match copied {
+                Some(_) => &x.post,
+                None => &x.pre,
+            })
+            .collect();
+        if commands.is_empty() {
+            return true;
+        }
 
-        for s in scripts {
-            self.execute_copy(s.from, s.to);
+        let phase = if copied.is_some() { "post" } else { "pre" };
+        let path = |x: Option<&PathInfo>| {
+            x.map(|x| x.path.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        let vars = self.env(tags);
+        let mut env: Vec<_> = vars.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+        env.extend([
+            ("CPX_SRC", path(self.src_info())),
+            ("CPX_DST", path(self.dst_info())),
+            ("CPX_RUN_ID", self.copy_config.run_id.clone()),
+        ]);
+        if let Some(copied) = copied {
+            let files: Vec<_> = copied.iter().map(|x| x.to_string_lossy()).collect();
+            let failed = self.output.failures().len();
+            env.push(("CPX_FILES", files.join("\n")));
+            env.push(("CPX_FAILED", failed.to_string()));
+        }
+
+        let mut ok = true;
+        for cmd in commands {
+            if self.copy_config.dry_run {
+                log::info!("Would run {} hook `{}`", phase, cmd);
+                continue;
+            }
+
+            log::info!("Run {} hook `{}`", phase, cmd);
+            if let Err(e) = hooks::run(cmd, &env) {
+                log::error!("Run {} hook `{}` failed, {}", phase, cmd, e);
+                self.hook_failed.store(true, Ordering::Relaxed);
+                ok = false;
+                // nothing is copied with the service still up, say
+                if copied.is_none() {
+                    log::error!("Cancelled the copy, a pre hook failed");
+                    break;
+                }
+            }
         }
+
+        ok
+    }
+
+    /// Variables of the commands run for `tags`: the `env` of the config,
+    /// of the tags in their order, then --env.
+    fn env<T: AsRef<str>>(&self, tags: &Option<Vec<T>>) -> Vec<(String, String)> {
+        let tag_env = tags
+            .iter()
+            .flatten()
+            .filter_map(|t| self.file_config.tag_list.get(t.as_ref()))
+            .flat_map(|x| &x.env);
+        let mut env: Vec<_> = self
+            .file_config
+            .env
+            .iter()
+            .chain(tag_env)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        env.extend(self.copy_config.env.iter().cloned());
+        env
     }
 
-    fn execute_copy<P: AsRef<Path>>(&self, src: P, dst: P) {
-        if self.copy_config.verbose > 0 || self.copy_config.dry_run {
-            println!(
-                "Copy:\n{}\nto:\n{}",
-                src.as_ref().display(),
-                dst.as_ref().display()
+    /// Copy the selection of `tags` and `files`, the destination files
+    /// written are returned.
+    fn execute_files<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> BTreeSet<PathBuf> {
+        self.save_progress();
+        *self.file_env.lock().unwrap() = self.env(tags);
+        let _lock = self.lock_destination();
+        let mut copy_files = self.selected_files(tags, files);
+        self.check_missing(&mut copy_files);
+        self.check_space(&copy_files);
+        // taken before the copies, a source changing during them is
+        // copied again next time
+        let stamps = self.stamps(&copy_files);
+        let mut record = RunRecord {
+            run_id: self.copy_config.run_id.clone(),
+            ..RunRecord::default()
+        };
+
+        let rsync = self.dst_info().is_some_and(|x| self.via_rsync(x));
+        let to = self.dst_info();
+        let chmod = self.copy_config.chmod.is_some()
+            || copy_files.iter().any(|f| f.mode.is_some())
+            || to.is_some_and(|x| {
+                x.file_mode.is_some() || x.dir_mode.is_some() || x.umask.is_some()
+            });
+        let post = copy_files.iter().any(|f| f.post.is_some());
+        if self.copy_config.backup.is_some() && (rsync || !self.is_local()) {
+            log::error!("--backup and --trash need a local destination not copied by rsync");
+            std::process::exit(1);
+        }
+        if (chmod || post || self.owner.is_some()) && (rsync || !self.is_local()) {
+            log::error!(
+                "`post` commands, modes and owners need a local destination not copied by rsync"
             );
+            std::process::exit(1);
+        }
+        let local = self.is_local() && !self.has_source_bucket();
+        if self.copy_config.xattrs && !rsync && !local {
+            log::error!("--xattrs needs local paths or the rsync transport");
+            std::process::exit(1);
+        }
+        if self.copy_config.xattrs && !rsync && !xattr::SUPPORTED {
+            log::error!("--xattrs is not supported on this platform");
+            std::process::exit(1);
+        }
+        #[cfg(feature = "remote")]
+        if !copy_files.is_empty() && rsync {
+            self.execute_rsync(&copy_files, &mut record);
+        }
+        if !copy_files.is_empty() && !rsync {
+            let from = self.src_info().expect("src path not found");
+            let to = self.dst_info().expect("dst path not found");
+            #[cfg(feature = "remote")]
+            if !self.is_local() || self.has_source_bucket() {
+                self.check_remote(from);
+            }
+            if copy_files.iter().any(|f| f.url.is_some()) {
+                self.check_urls(from);
+            }
+            let dst_root = self.dst_root(to);
+
+            // kept alive until the copies are done, dropping it removes it
+            let snapshot = self
+                .copy_config
+                .snapshot
+                .filter(|_| !self.copy_config.dry_run);
+            let snapshot = snapshot.map(|kind| {
+                Snapshot::create(kind, &from.path, &self.copy_config.run_id).unwrap_or_else(|e| {
+                    log::error!("Snapshot {} failed, {}", from.path.display(), e);
+                    std::process::exit(1);
+                })
+            });
+            let src_root = match &snapshot {
+                Some(snapshot) => snapshot.path(),
+                None => self.src_root(from),
+            };
+
+            let mut file_info = self.file_info.lock().unwrap();
+            let ops: Vec<_> = copy_files
+                .iter()
+                .map(|f| {
+                    let src = match &f.url {
+                        Some(url) => PathBuf::from(url),
+                        None => src_root.join(self.src_relative(from, f)),
+                    };
+                    let dst = dst_root.join(self.dst_relative(to, f));
+                    file_info.insert(dst.clone(), f.clone());
+                    (src, dst)
+                })
+                .collect();
+            drop(file_info);
+            #[cfg(feature = "archive")]
+            let archived = match archive::Format::detect(&from.path) {
+                Some(format) => Some(self.execute_extract(&from.path, format, &ops)),
+                None if !self.is_local() => None,
+                None => self.execute_archive(&to.path, &ops),
+            };
+            #[cfg(not(feature = "archive"))]
+            let archived: Option<Vec<PathBuf>> = None;
+
+            let keep: HashSet<_> = ops.iter().map(|(_, dst)| dst.clone()).collect();
+            match archived {
+                Some(files) => record.files.extend(files),
+                None => record.files.extend(self.execute_copy_list(dst_root, ops)),
+            }
+
+            // nothing is deleted after a failure stopped the copies
+            if self.copy_config.delete && !self.stopped() {
+                self.execute_delete(&keep);
+            }
+            if self.copy_config.prune_empty_dirs && !self.stopped() {
+                self.execute_prune(&keep);
+            }
+        }
+
+        if !self.stopped() {
+            self.execute_copy_script(tags, &mut record);
+        }
+
+        if let (Some(profiler), Some((path, _))) = (&self.profiler, &self.copy_config.profile) {
+            if let Err(e) = File::create(path).and_then(|f| profiler.write_to(f)) {
+                log::error!("Write profile {} failed, {}", path.display(), e);
+            }
         }
 
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.save() {
+                log::error!("Save journal failed, {}", e);
+            }
+        }
+        if let Err(e) = self.hashes.save() {
+            log::warn!("Save hash cache failed, {}", e);
+        }
+        if let Some(checkpoint) = &self.checkpoint {
+            if cleanup::interrupted().is_some() {
+                self.save_progress();
+                log::warn!("cpx resume continues the interrupted copy");
+            } else if let Err(e) = checkpoint.remove() {
+                log::warn!("Remove checkpoint failed, {}", e);
+            }
+        }
+
+        if !self.copy_config.dry_run && !self.failed() && !self.stopped() {
+            let mut last = SourceStamps::load(&self.copy_config.job);
+            last.files.extend(stamps);
+            if let Err(e) = last.save(&self.copy_config.job) {
+                log::warn!("Save source stamps failed, {}", e);
+            }
+        }
         if !self.copy_config.dry_run {
-            if let Err(e) = std::fs::copy(&src, &dst) {
-                eprintln!(
-                    "Copy:\n{}\nto:\n{}\nfailed, {:?}",
-                    src.as_ref().display(),
-                    dst.as_ref().display(),
-                    e
-                );
+            let previous = record.replace(&self.copy_config.job).unwrap_or_else(|e| {
+                log::warn!("Save run record failed, {}", e);
+                RunRecord::load(&self.copy_config.job)
+            });
+
+            if self.copy_config.summary_diff {
+                record.print_diff(&previous, self.copy_config.output == OutputMode::Json);
             }
         }
+
+        record.files
     }
-}
 
-struct CopyConfig {
-    pub from: Option<String>,
-    pub to: Option<String>,
-    pub dry_run: bool,
-    pub create_dir: bool,
-    pub verbose: u64,
-}
+    /// Log the counts, the failures and the statistics of the run.
+    fn summarize(&self, started: Instant) {
+        let counts: Vec<_> = self
+            .output
+            .counts()
+            .iter()
+            .map(|(status, n)| format!("{} {}", n, status))
+            .collect();
+        let elapsed = started.elapsed().as_secs_f64();
+        if cleanup::interrupted().is_some() {
+            log::warn!("Interrupted after {:.3}s: {}", elapsed, counts.join(", "));
+        } else if self.copy_config.sync {
+            log::info!(
+                "Done in {:.3}s, {:.3}s of it in fsync: {}",
+                elapsed,
+                self.fsync.lock().unwrap().as_secs_f64(),
+                counts.join(", ")
+            );
+        } else {
+            log::info!("Done in {:.3}s: {}", elapsed, counts.join(", "));
+        }
+        self.report_failures();
+        if let Some(events) = &self.copy_config.events {
+            events.run_done(self.output.counts(), self.output.bytes(), started.elapsed());
+        }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct PathInfo {
-    path: PathBuf,
-}
+        let stats = Stats::new(
+            &self.copy_config.run_id,
+            self.output.counts(),
+            self.output.bytes(),
+            started.elapsed(),
+        );
+        if self.copy_config.stats {
+            stats.print();
+        }
+        if let Some(path) = &self.copy_config.stats_json {
+            if let Err(e) = stats.write(path) {
+                log::error!("Write stats {} failed, {}", path.display(), e);
+            }
+        }
+        if let Some(path) = &self.copy_config.report {
+            self.write_report(path, &stats);
+        }
+        self.notify(&stats);
+    }
 
-#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
-struct TagInfo {
-    file_list: Option<Vec<String>>,
-    script_list: Option<Vec<String>>,
-}
+    /// The --report of the run, with the hashes of the local files written.
+    fn write_report(&self, path: &Path, stats: &Stats) {
+        let mut rows = self.output.rows();
+        if self.is_local() {
+            for r in &mut rows {
+                if matches!(r.status, Status::Copied | Status::Linked | Status::Changed) {
+                    r.hash = self.hashes.hash(&r.dst).ok();
+                }
+            }
+        }
+        let report = report::Report {
+            specs: &self.spec_names(),
+            stats,
+            algorithm: self.hashes.algorithm().name(),
+            dry_run: self.copy_config.dry_run,
+            rows: &rows,
+        };
+        match report.write(path) {
+            Ok(()) => log::info!("Wrote report {}", path.display()),
+            Err(e) => log::error!("Write report {} failed, {}", path.display(), e),
+        }
+    }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
-struct FileInfo {
-    relative_path: PathBuf,
-}
+    /// Tell the desktop and the webhook how the run went, for --notify and
+    /// --webhook. They failing does not fail the run.
+    fn notify(&self, stats: &Stats) {
+        let c = &self.copy_config;
+        let failed = self.failed();
+        if (!c.notify && c.webhook.is_none()) || (c.notify_on == When::Failure && !failed) {
+            return;
+        }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
-struct ScriptInfo {
-    from: PathBuf,
-    to: PathBuf,
-}
+        let failures = self.output.failures().into_iter();
+        let summary = notify::Summary {
+            specs: self.spec_names(),
+            status: match cleanup::interrupted() {
+                Some(_) => "interrupted",
+                None if failed => "failed",
+                None => "done",
+            },
+            failed,
+            stats,
+            failures: failures
+                .map(|(dst, error)| notify::Failure { dst, error })
+                .collect(),
+        };
+        if c.notify {
+            if let Err(e) = notify::desktop(&summary) {
+                log::warn!("Desktop notification failed, {}", e);
+            }
+        }
+        if let Some(url) = &c.webhook {
+            if let Err(e) = notify::webhook(url, &summary) {
+                log::warn!("Webhook {} failed, {}", url, e);
+            }
+        }
+    }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-struct ConfigInfo {
-    path_list: HashMap<String, PathInfo>,
-    tag_list: HashMap<String, TagInfo>,
-    file_list: HashMap<String, FileInfo>,
-    script_list: HashMap<String, ScriptInfo>,
-}
+    /// Keep other cpx runs from copying to the destination at the same
+    /// time, none for dry runs and --no-lock.
+    fn lock_destination(&self) -> Option<store::Lock> {
+        if self.copy_config.dry_run || !self.copy_config.lock {
+            return None;
+        }
+        let dst = &self.dst_info()?.path;
+        let dst = match self.is_local() {
+            true => std::fs::canonicalize(dst).unwrap_or_else(|_| dst.clone()),
+            false => dst.clone(),
+        };
 
-impl ConfigInfo {
-    fn calculate_script_list<T: AsRef<str>>(&self, tags: &Option<Vec<T>>) -> HashSet<ScriptInfo> {
-        let mut selected_scripts = HashSet::new();
+        let lock = store::lock_destination(&dst.to_string_lossy(), self.copy_config.wait);
+        Some(lock.unwrap_or_else(|e| {
+            match e.kind() {
+                std::io::ErrorKind::WouldBlock => log::error!("{}, --wait to wait for it", e),
+                _ => log::error!("Lock {} failed, {}", dst.display(), e),
+            }
+            std::process::exit(1);
+        }))
+    }
 
-        if let Some(x) = tags {
-            for t in x {
-                if let Some(item) = self.script_list.get(t.as_ref()) {
-                    selected_scripts.insert(item.clone());
+    /// Paths of `collect` of the source, none if it is a plain path.
+    fn collected(&self) -> Vec<String> {
+        self.src_info()
+            .map(|x| x.collect.clone())
+            .unwrap_or_default()
+    }
+
+    /// Paths of `merge` of the source, none if it is a plain path.
+    fn merged(&self) -> Vec<String> {
+        self.src_info().map(|x| x.merge.clone()).unwrap_or_default()
+    }
+
+    /// The runs copying from each of the paths `names` of a `merge` source
+    /// the files of the selection it has and no later path has. A file
+    /// none of them has is left to the first, which reports it missing.
+    fn merge_parts<T: AsRef<str>>(
+        &self,
+        names: &[String],
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> Vec<Cpx> {
+        let c = &self.copy_config;
+        let dst_name = match c.reverse {
+            true => &c.from,
+            false => &c.to,
+        };
+        #[cfg(feature = "archive")]
+        if c.archive.is_some()
+            || self
+                .dst_info()
+                .is_some_and(|x| archive::Format::detect(&x.path).is_some())
+        {
+            log::error!("A `merge` source cannot be copied into an archive");
+            std::process::exit(1);
+        }
+
+        let mut parts = vec![];
+        for name in names {
+            let mut copy_config = c.clone();
+            copy_config.reverse = false;
+            copy_config.rotate = false;
+            // each part lacks the files of the others, the merge deletes
+            copy_config.delete = false;
+            copy_config.prune_empty_dirs = false;
+            copy_config.from = Some(name.clone());
+            copy_config.to = dst_name.clone();
+            copy_config.job = format!("{} {}", c.job, name);
+
+            let mut part = Cpx::new(copy_config, self.file_config.clone(), None);
+            if !part.collected().is_empty() || !part.merged().is_empty() {
+                log::error!("`merge` path {} is a `collect` or `merge` path", name);
+                std::process::exit(1);
+            }
+            part.selection = self.selection.clone();
+            parts.push(part);
+        }
+
+        let mut taken = HashSet::new();
+        for (i, part) in parts.iter_mut().enumerate().rev() {
+            let selection: HashSet<_> = {
+                let from = part.src_info().expect("src path not found");
+                let to = part.dst_info().expect("dst path not found");
+                if part.has_source_bucket() || !from.path.is_dir() {
+                    log::error!(
+                        "`merge` paths are local directories, {} is not",
+                        from.path.display()
+                    );
+                    std::process::exit(1);
                 }
+                part.selected_files(tags, files)
+                    .into_iter()
+                    .filter(|f| {
+                        let has = i == 0
+                            || f.url.is_some()
+                            || from.path.join(part.src_relative(from, f)).exists();
+                        has && taken.insert(part.dst_relative(to, f))
+                    })
+                    .collect()
+            };
+            // the unlisted files of a mirror are in the selection already
+            part.copy_config.mirror = false;
+            part.selection = Some(selection);
+        }
+
+        parts
+    }
+
+    /// The run copying the selection from the path `name` of a `collect`
+    /// source into the subdirectory `name` of the destination.
+    fn collect_part(&self, name: &str) -> Cpx {
+        let dst_name = match self.copy_config.reverse {
+            true => &self.copy_config.from,
+            false => &self.copy_config.to,
+        };
+        let dst = self.dst_info().expect("dst path not found");
+        match self.file_config.path_list.get(name) {
+            Some(x) if x.collect.is_empty() => {}
+            Some(_) => {
+                log::error!("`collect` path {} collects itself", name);
+                std::process::exit(1);
+            }
+            None => {
+                log::error!("`collect` path {} not found", name);
+                std::process::exit(1);
             }
         }
 
-        selected_scripts
+        let key = format!("{}/{}", dst_name.as_deref().unwrap_or_default(), name);
+        let mut file_config = self.file_config.clone();
+        file_config.path_list.insert(
+            key.clone(),
+            PathInfo {
+                path: dst.path.join(name),
+                ..dst.clone()
+            },
+        );
+        let mut copy_config = self.copy_config.clone();
+        copy_config.reverse = false;
+        copy_config.rotate = false;
+        copy_config.from = Some(name.to_owned());
+        copy_config.to = Some(key);
+        copy_config.job = format!("{} {}", self.copy_config.job, name);
+
+        // a resumed collect copies again what it had copied
+        Cpx::new(copy_config, file_config, None)
     }
 
-    fn calculate_file_list<T: AsRef<str>>(
+    /// Copy the selection from the source, or from each of its paths for
+    /// a `collect` source.
+    fn copy_selection<T: AsRef<str> + Clone>(
         &self,
         tags: &Option<Vec<T>>,
         files: &Option<Vec<T>>,
-    ) -> HashSet<FileInfo> {
-        let mut selected_files: Vec<_> = vec![];
-        if let Some(x) = tags {
-            for t in x {
-                if let Some(mut item) = self
-                    .tag_list
-                    .get(t.as_ref())
-                    .and_then(|x| x.file_list.clone())
-                {
-                    selected_files.append(&mut item);
+    ) -> BTreeSet<PathBuf> {
+        if self.dst_info().is_some_and(|x| !x.merge.is_empty()) {
+            log::error!("A `merge` path is a source only");
+            std::process::exit(1);
+        }
+        let names = self.collected();
+        let merged = self.merged();
+        match (names.is_empty(), merged.is_empty()) {
+            (true, true) => self.execute_files(tags, files),
+            (true, false) => self.execute_merge(&merged, tags, files),
+            (false, _) => self.execute_collect(&names, tags.clone(), files.clone()),
+        }
+    }
+
+    /// Copy the selection for each spec of the run, one after the other,
+    /// reporting them as one run. The files are selected only once.
+    fn execute_specs<T: AsRef<str> + Clone>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> BTreeSet<PathBuf> {
+        let selection = self.file_config.calculate_file_list(tags, files);
+        let mut copied = BTreeSet::new();
+        for (from, to) in &self.copy_config.specs {
+            log::info!("Copy {}:{}", from, to);
+            let mut copy_config = self.copy_config.clone();
+            copy_config.from = Some(from.clone());
+            copy_config.to = Some(to.clone());
+            copy_config.specs = vec![];
+            copy_config.rotate = false;
+            copy_config.job = format!("{} {}:{}", self.copy_config.job, from, to);
+
+            // a resumed run copies the other specs again
+            let mut part = Cpx::new(copy_config, self.file_config.clone(), None);
+            part.selection = Some(selection.clone());
+            copied.extend(part.copy_selection(tags, files));
+
+            self.output.merge(&part.output);
+            *self.fsync.lock().unwrap() += *part.fsync.lock().unwrap();
+            if self.stopped() {
+                break;
+            }
+        }
+
+        copied
+    }
+
+    /// Whether any of `tags` has a `from` or `to` of its own.
+    fn tag_paths<T: AsRef<str>>(&self, tags: &Option<Vec<T>>) -> bool {
+        tags.iter()
+            .flatten()
+            .filter_map(|t| self.file_config.tag_list.get(t.as_ref()))
+            .any(|x| x.from.is_some() || x.to.is_some())
+    }
+
+    /// Copy the files of the tags with a `from` or `to` of their own
+    /// between those paths and the others by the spec, one pair of paths
+    /// after the other and reported as one run.
+    fn execute_tags<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> BTreeSet<PathBuf> {
+        let c = &self.copy_config;
+        if c.specs.len() > 1 {
+            log::error!("Tags with a `from` or `to` of their own need one spec at most");
+            std::process::exit(1);
+        }
+        let spec = (c.from.clone(), c.to.clone());
+        let mut parts: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+        for t in tags.iter().flatten().map(|x| x.as_ref()) {
+            let tag = &self.file_config.tag_list[t];
+            match (
+                tag.from.clone().or(spec.0.clone()),
+                tag.to.clone().or(spec.1.clone()),
+            ) {
+                (Some(from), Some(to)) => parts.entry((from, to)).or_default().push(t.to_owned()),
+                (from, _) => {
+                    let missing = if from.is_none() { "from" } else { "to" };
+                    log::error!("Tag {} has no `{}` of its own and no spec", t, missing);
+                    std::process::exit(1);
                 }
             }
         }
+        let files: Option<Vec<String>> = files
+            .as_ref()
+            .map(|x| x.iter().map(|x| x.as_ref().to_owned()).collect());
+        if let (Some(from), Some(to)) = spec.clone() {
+            if files.is_some() {
+                parts.entry((from, to)).or_default();
+            }
+        } else if files.is_some() {
+            log::error!("--file needs a spec");
+            std::process::exit(1);
+        }
 
-        if let Some(x) = files {
-            for f in x {
-                selected_files.push(f.as_ref().to_string());
+        let mut copied = BTreeSet::new();
+        for ((from, to), tags) in parts {
+            log::info!("Copy {}:{} for {}", from, to, tags.join(", "));
+            let files = files
+                .clone()
+                .filter(|_| (Some(&from), Some(&to)) == (spec.0.as_ref(), spec.1.as_ref()));
+            let mut copy_config = c.clone();
+            copy_config.from = Some(from.clone());
+            copy_config.to = Some(to.clone());
+            copy_config.specs = vec![];
+            copy_config.rotate = false;
+            copy_config.job = format!("{} {}:{}", c.job, from, to);
+
+            let tags = Some(tags);
+            let mut part = Cpx::new(copy_config, self.file_config.clone(), None);
+            part.selection = Some(self.file_config.calculate_file_list(&tags, &files));
+            copied.extend(part.copy_selection(&tags, &files));
+
+            self.output.merge(&part.output);
+            *self.fsync.lock().unwrap() += *part.fsync.lock().unwrap();
+            if self.stopped() {
+                break;
             }
         }
 
-        selected_files
-            .iter()
-            .map(|x| {
-                self.file_list
-                    .get(x)
-                    .expect(&format!("file {} not found in config", x))
-                    .clone()
-            })
-            .collect()
+        copied
     }
-}
 
-fn main() {
-    let default_config = dirs::home_dir()
-        .and_then(|x| x.join("cpx.yaml").to_str().map(|x| x.to_owned()))
-        .unwrap_or("cpx.yaml".to_owned());
-    let m = App::new("Help you copy files")
-        .version("0.1")
-        .author("sbw <sbw@sbw.so>")
-        .about("Help you copy files!")
-        .arg(
-            Arg::with_name("spec")
-                .help("specific source path and destination path")
-                .takes_value(true)
-                .index(1),
-        )
-        .arg(
-            Arg::with_name("files")
-                .help("copy files")
-                .long("file")
-                .takes_value(true)
-                .required_unless("tags")
-                .multiple(true),
-        )
-        .arg(
-            Arg::with_name("tags")
-                .long("tag")
-                .takes_value(true)
-                .required_unless("files")
-                .multiple(true),
-        )
-        .arg(Arg::with_name("verbose").short("v"))
-        .arg(
-            Arg::with_name("config")
-                .short("c")
-                .takes_value(true)
-                .default_value(&default_config),
-        )
-        .arg(Arg::with_name("dry-run").long("dry-run").help("Dry run"))
-        .get_matches();
-
-    let tags: Option<Vec<_>> = m.values_of("tags").map(|x| x.collect());
-    let files: Option<Vec<_>> = m.values_of("files").map(|x| x.collect());
-    let f = File::open(m.value_of("config").unwrap()).expect("File read failed!");
-    let config: ConfigInfo = serde_yaml::from_reader(f).expect("File parse failed!");
+    /// Copy the selection from each path of a `collect` source, one after
+    /// the other, reporting them as one run.
+    fn execute_collect<T: AsRef<str> + Clone>(
+        &self,
+        names: &[String],
+        tags: Option<Vec<T>>,
+        files: Option<Vec<T>>,
+    ) -> BTreeSet<PathBuf> {
+        let mut copied = BTreeSet::new();
+        for name in names {
+            log::info!("Collect from {}", name);
+            let part = self.collect_part(name);
+            copied.extend(part.execute_files(&tags, &files));
 
-    let mut cpx_config = CopyConfig {
-        from: None,
-        to: None,
-        dry_run: m.is_present("dry-run"),
-        create_dir: true,
-        verbose: m.occurrences_of("verbose"),
-    };
+            self.output.merge(&part.output);
+            *self.fsync.lock().unwrap() += *part.fsync.lock().unwrap();
+            if self.stopped() {
+                break;
+            }
+        }
+
+        copied
+    }
+
+    /// Copy each file of the selection from the last path of a `merge`
+    /// source that has it, reporting them as one run.
+    fn execute_merge<T: AsRef<str>>(
+        &self,
+        names: &[String],
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> BTreeSet<PathBuf> {
+        let mut copied = BTreeSet::new();
+        let mut keep = HashSet::new();
+        for part in self.merge_parts(names, tags, files) {
+            log::info!("Merge from {}", part.copy_config.from.as_deref().unwrap());
+            copied.extend(part.execute_files(tags, files));
+            let to = part.dst_info().expect("dst path not found");
+            let selection = part.selection.iter().flatten();
+            keep.extend(selection.map(|f| to.path.join(part.dst_relative(to, f))));
+
+            self.output.merge(&part.output);
+            *self.fsync.lock().unwrap() += *part.fsync.lock().unwrap();
+            if self.stopped() {
+                return copied;
+            }
+        }
 
-    if let Some(spec) = m.value_of("spec").map(|x| x.split(':').collect::<Vec<_>>()) {
-        if spec.len() == 2 {
-            cpx_config.from = Some(spec[0].to_owned());
-            cpx_config.to = Some(spec[1].to_owned());
+        if self.copy_config.delete && self.is_local() {
+            self.execute_delete(&keep);
         }
+        if self.copy_config.prune_empty_dirs && self.is_local() {
+            self.execute_prune(&keep);
+        }
+        copied
+    }
+
+    /// The config entry of the selected file copied to `dst`.
+    fn file_info(&self, dst: &Path) -> Option<FileInfo> {
+        self.file_info.lock().unwrap().get(dst).cloned()
+    }
+
+    /// Mode of the copy at `dst`, from its file, its tag, --chmod or the
+    /// `file_mode` of the destination.
+    fn mode(&self, dst: &Path) -> Option<Mode> {
+        self.file_info(dst)
+            .and_then(|x| x.mode)
+            .or(self.copy_config.chmod)
+            .or(self.dst_info().and_then(|x| x.file_mode))
+    }
+
+    /// Bits cleared from the modes of the copies, the `umask` of the
+    /// destination.
+    fn umask(&self) -> Option<Mode> {
+        self.dst_info().and_then(|x| x.umask)
     }
 
-    let cpx = Cpx::new(cpx_config, config);
-    cpx.execute(tags, files);
+    /// Give the local file `dst` its owner, the extended attributes of
+    /// `src` with --xattrs and its mode, those it has. Without the
+    /// privileges for them, owners and attributes are left as they are.
+    fn apply_attributes(&self, src: &Path, dst: &Path) -> std::io::Result<()> {
+        let owner = self
+            .owner
+            .filter(|_| !self.chown_denied.load(Ordering::Relaxed));
+        if let Some(owner) = owner {
+            match owner.apply(dst) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                    if !self.chown_denied.swap(true, Ordering::Relaxed) {
+                        log::warn!(
+                            "Keep the owners of destination files, changing them needs root"
+                        );
+                    }
+                }
+                Err(e) => {
+                    return Err(std::io::Error::new(
+                        e.kind(),
+                        format!("chown failed, {}", e),
+                    ))
+                }
+            }
+        }
+
+        // after chown, which drops file capabilities
+        let xattrs = self.copy_config.xattrs && http::url(src).is_none();
+        if xattrs && !self.has_source_bucket() {
+            let refused = xattr::copy(src, dst).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("copy extended attributes failed, {}", e))
+            })?;
+            if !refused.is_empty() && !self.xattrs_refused.swap(true, Ordering::Relaxed) {
+                let names: Vec<_> = refused.iter().map(|x| x.to_string_lossy()).collect();
+                log::warn!(
+                    "Skip extended attributes {} of {} and the like, the destination refused them",
+                    names.join(", "),
+                    dst.display()
+                );
+            }
+        }
+
+        let mode = match (self.mode(dst), self.umask()) {
+            (mode, None) => mode,
+            (Some(mode), Some(umask)) => Some(mode.without(umask)),
+            (None, Some(umask)) => Some(Mode::of(dst)?.without(umask)),
+        };
+        // after chown, which clears setuid bits, and after the ACLs
+        match mode {
+            Some(mode) => mode.apply(dst).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("chmod {} failed, {}", mode, e))
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether a file or a hook failed, or the run timed out.
+    fn failed(&self) -> bool {
+        self.hook_failed.load(Ordering::Relaxed)
+            || timeout::run_timed_out()
+            || self
+                .output
+                .has_any(&[Status::Failed, Status::CircuitOpen, Status::Conflict])
+    }
+
+    /// Whether a failure stopped the run, with `--fail-fast`, or its
+    /// --run-timeout.
+    fn stopped(&self) -> bool {
+        (self.copy_config.fail_fast && self.output.has_any(&[Status::Failed]))
+            || cleanup::interrupted().is_some()
+            || timeout::run_timed_out()
+    }
+
+    /// List every failed file again, their errors are long scrolled away
+    /// in a big run.
+    fn report_failures(&self) {
+        if timeout::run_timed_out() {
+            log::error!("Stopped at the --run-timeout, the remaining files were not copied");
+        }
+        let failures = self.output.failures();
+        if failures.is_empty() {
+            return;
+        }
+
+        if self.stopped() && !timeout::run_timed_out() {
+            log::error!("Stopped at the first failure, the remaining files were not copied");
+        }
+        match failures.len() {
+            1 => log::error!("1 file failed:"),
+            n => log::error!("{} files failed:", n),
+        }
+        for (dst, e) in failures {
+            log::error!("  {}: {}", dst.display(), e);
+        }
+    }
+
+    /// The files of `tags` and `files` that are sampled, not ignored and
+    /// pass the filters.
+    fn selected_files<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> HashSet<FileInfo> {
+        let mut copy_files = match &self.selection {
+            Some(x) => x.clone(),
+            None => self.file_config.calculate_file_list(tags, files),
+        };
+        if self.copy_config.mirror {
+            self.add_unlisted(&mut copy_files);
+        }
+        if let Some(percent) = self.copy_config.sample {
+            let seed = self.copy_config.seed;
+            copy_files.retain(|f| sampled(&f.relative_path, seed, percent));
+        }
+        if !copy_files.is_empty() {
+            let from = self.src_info().expect("src path not found");
+            if let Some(rev) = &self.copy_config.since {
+                if self.has_source_bucket() {
+                    log::error!("--since needs a local source in a git repository");
+                    std::process::exit(1);
+                }
+                let changed = git_changed(&from.path, rev);
+                copy_files
+                    .retain(|f| f.url.is_none() && changed.contains(&self.src_relative(from, f)));
+            }
+            if self.copy_config.since_last_run {
+                if self.has_source_bucket() {
+                    log::error!("--since-last-run needs a local source");
+                    std::process::exit(1);
+                }
+                let stamps = SourceStamps::load(&self.copy_config.job);
+                copy_files.retain(|f| {
+                    let src = from.path.join(self.src_relative(from, f));
+                    f.url.is_some() || stamps.changed(&src)
+                });
+            }
+            let ignore = self.ignore_list(from);
+            copy_files.retain(|f| {
+                if f.url.is_some() && self.copy_config.reverse {
+                    log::warn!(
+                        "Skip {}, downloads cannot be reversed",
+                        f.relative_path.display()
+                    );
+                    return false;
+                }
+                let relative = self.src_relative(from, f);
+                if ignore.is_ignored(&relative) {
+                    log::debug!("Ignore {}", relative.display());
+                    return false;
+                }
+                let src = from.path.join(relative);
+                if self.copy_config.skip_sources.contains(&src) {
+                    log::debug!("Skip {}, unchanged since copied", src.display());
+                    return false;
+                }
+                self.copy_config.filter.matches(&src)
+            });
+        }
+
+        let copy_files = match self.copy_config.flatten {
+            Some(on) => self.flatten(copy_files, on),
+            None => copy_files,
+        };
+        self.check_case(&copy_files);
+        copy_files
+    }
+
+    /// Add every other file of the source to `copy_files`, for `cpx
+    /// mirror`. Those of the config keep their options.
+    fn add_unlisted(&self, copy_files: &mut HashSet<FileInfo>) {
+        let from = self.src_info().expect("src path not found");
+        if self.has_source_bucket() || http::url(&from.path).is_some() {
+            log::error!("cpx mirror needs a local source");
+            std::process::exit(1);
+        }
+        let found = scan::all(&from.path, &self.ignore_list(from)).unwrap_or_else(|e| {
+            log::error!("Read {} failed, {}", from.path.display(), e);
+            std::process::exit(1);
+        });
+        let listed: HashSet<_> = copy_files
+            .iter()
+            .map(|f| self.src_relative(from, f))
+            .collect();
+        let configured: HashMap<_, _> = self
+            .file_config
+            .file_list
+            .values()
+            .filter(|f| f.url.is_none())
+            .map(|f| (self.src_relative(from, f), f))
+            .collect();
+        copy_files.extend(found.into_iter().filter(|x| !listed.contains(x)).map(|x| {
+            match configured.get(&x) {
+                Some(f) => (*f).clone(),
+                None => FileInfo::at(x),
+            }
+        }));
+    }
+
+    /// Report the destinations of `copy_files` that differ only by case
+    /// when the destination ignores case, one would overwrite the other.
+    fn check_case(&self, copy_files: &HashSet<FileInfo>) {
+        let to = match self.dst_info() {
+            Some(x) if self.is_local() && copy_files.len() > 1 => x,
+            _ => return,
+        };
+        let mut dsts: Vec<_> = copy_files
+            .iter()
+            .map(|f| self.dst_relative(to, f))
+            .collect();
+        dsts.sort_unstable();
+        let collisions = case::collisions(&dsts);
+        if collisions.is_empty() || !case::insensitive(&to.path) {
+            return;
+        }
+
+        let level = match self.copy_config.case_collision {
+            CaseCollision::Error => log::Level::Error,
+            CaseCollision::Warn => log::Level::Warn,
+        };
+        for (a, b) in &collisions {
+            log::log!(
+                level,
+                "{} and {} differ only by case, {} takes them for one file",
+                a.display(),
+                b.display(),
+                to.path.display()
+            );
+        }
+        if level == log::Level::Error {
+            log::error!("Copy failed, --on-case-collision warn copies them anyway");
+            std::process::exit(1);
+        }
+    }
+
+    /// Give the destinations of `copy_files` the root of the destination
+    /// for --flatten, the files skipped for a collision are left out.
+    fn flatten(&self, copy_files: HashSet<FileInfo>, on: Collision) -> HashSet<FileInfo> {
+        let to = match self.dst_info() {
+            Some(x) => x,
+            None => return copy_files,
+        };
+        self.flat.lock().unwrap().clear();
+        let mut files: Vec<_> = copy_files
+            .iter()
+            .map(|f| {
+                let dst = self.dst_relative(to, f);
+                (f.relative_path.clone(), dst)
+            })
+            .collect();
+        // files nearer the root keep their names
+        files.sort_unstable_by_key(|(x, _)| (x.components().count(), x.clone()));
+
+        let names = flatten::flatten(&files, on).unwrap_or_else(|e| {
+            log::error!(
+                "Flatten failed, {}, --on-collision rename or skip copies them",
+                e
+            );
+            std::process::exit(1);
+        });
+        let copy_files = copy_files
+            .into_iter()
+            .filter(|f| names.contains_key(&f.relative_path))
+            .collect();
+        *self.flat.lock().unwrap() = names;
+        copy_files
+    }
+
+    /// Print the files and bytes of each of `tags`, of `files` and of all
+    /// of them, at the source and the local destination, for `cpx du`.
+    fn du<T: AsRef<str>>(&self, tags: &Option<Vec<T>>, files: &Option<Vec<T>>) {
+        if !self.collected().is_empty()
+            || !self.merged().is_empty()
+            || self.copy_config.specs.len() > 1
+        {
+            log::error!("cpx du measures single specs of plain paths only");
+            std::process::exit(1);
+        }
+        if self.has_source_bucket() {
+            log::error!("cpx du needs a local source");
+            std::process::exit(1);
+        }
+        let from = self.src_info().expect("src path not found");
+        let to = self.dst_info().expect("dst path not found");
+
+        let mut rows = vec![];
+        for t in tags.iter().flatten() {
+            let t = t.as_ref();
+            rows.push((t.to_owned(), self.selected_files(&Some(vec![t]), &None)));
+        }
+        if files.is_some() {
+            rows.push(("files".to_owned(), self.selected_files(&None, files)));
+        }
+        if rows.len() != 1 {
+            rows.push(("total".to_owned(), self.selected_files(tags, files)));
+        }
+
+        for (name, copy_files) in rows {
+            // downloads are measured where they are written only
+            let src: Vec<_> = copy_files
+                .iter()
+                .filter(|f| f.url.is_none())
+                .map(|f| from.path.join(self.src_relative(from, f)))
+                .collect();
+            let src = du::Usage::of(src.iter().map(PathBuf::as_path));
+            let dst = match self.is_local() {
+                true => {
+                    let dst: Vec<_> = copy_files
+                        .iter()
+                        .map(|f| to.path.join(self.dst_relative(to, f)))
+                        .collect();
+                    du::Usage::of(dst.iter().map(PathBuf::as_path)).text()
+                }
+                false => "not measured, remote".to_owned(),
+            };
+            println!("{:<10} src {}, dst {}", name, src.text(), dst);
+        }
+    }
+
+    /// Check that the copy of `tags` and `files` can be made, for `cpx
+    /// doctor`: its sources exist and the destination can be written and
+    /// has room for them.
+    fn doctor<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+        report: &mut doctor::Report,
+    ) {
+        let spec = self.spec_names().join(" ");
+        if !self.collected().is_empty()
+            || !self.merged().is_empty()
+            || self.copy_config.specs.len() > 1
+        {
+            report.warn(format!(
+                "{} is not checked, only single specs of plain paths are",
+                spec
+            ));
+            return;
+        }
+        let from = self.src_info().expect("src path not found");
+        let to = self.dst_info().expect("dst path not found");
+        let mut copy_files: Vec<_> = self.selected_files(tags, files).into_iter().collect();
+        copy_files.sort_unstable_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        report.ok(format!("{} selects {} files", spec, copy_files.len()));
+        if !self.is_local() || self.has_source_bucket() {
+            report.ok(format!("{} has remote paths, they are not checked", spec));
+            return;
+        }
+
+        for f in copy_files.iter().filter(|f| f.url.is_none()) {
+            let src = from.path.join(self.src_relative(from, f));
+            if let Err(e) = std::fs::metadata(&src) {
+                report.error(format!("source {} cannot be read, {}", src.display(), e));
+            }
+        }
+        let needed = self.space_needed(copy_files.iter());
+
+        let shown = to.path.display();
+        let dir = match doctor::existing(&to.path) {
+            Some(x) => x,
+            None => return report.error(format!("destination {} has no parent there", shown)),
+        };
+        if !doctor::writable(dir) {
+            report.error(format!(
+                "destination {} cannot be made in {}",
+                shown,
+                dir.display()
+            ));
+        }
+        let needed_text = stats::human(needed as f64);
+        match doctor::free_space(dir) {
+            Ok(free) if free < needed => report.error(format!(
+                "destination {} needs {} more, {} is free",
+                shown,
+                needed_text,
+                stats::human(free as f64)
+            )),
+            Ok(free) => report.ok(format!(
+                "destination {} needs {} more, {} is free",
+                shown,
+                needed_text,
+                stats::human(free as f64)
+            )),
+            Err(e) => report.warn(format!("free space of {} is not known, {}", shown, e)),
+        }
+        if copy_files.iter().any(|f| f.url.is_some()) {
+            report.ok("downloads are not counted, their size is only known once copied");
+        }
+
+        let devices = (doctor::device(&from.path), doctor::device(dir));
+        if let (Some(a), Some(b)) = devices {
+            if a != b {
+                report.warn(format!(
+                    "{} and {} are on different devices, files are copied in full without reflinks",
+                    from.path.display(),
+                    shown
+                ));
+            }
+        }
+    }
+
+    /// Bytes the destination grows by when `copy_files` are copied to it,
+    /// the files it has are overwritten. Downloads are not known yet.
+    fn space_needed<'a, I: Iterator<Item = &'a FileInfo>>(&self, copy_files: I) -> u64 {
+        let (from, to) = match (self.src_info(), self.dst_info()) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return 0,
+        };
+        let len = |x: &Path| std::fs::metadata(x).map(|x| x.len()).unwrap_or(0);
+        copy_files
+            .filter(|f| f.url.is_none())
+            .map(|f| {
+                let src = from.path.join(self.src_relative(from, f));
+                let dst = to.path.join(self.dst_relative(to, f));
+                len(&src).saturating_sub(len(&dst))
+            })
+            .sum()
+    }
+
+    /// Fail before anything is copied when the destination has no room
+    /// for `copy_files`, or with --no-space-check warn.
+    fn check_space(&self, copy_files: &HashSet<FileInfo>) {
+        let to = match self.dst_info() {
+            Some(x) if self.is_local() && !self.copy_config.dry_run && !copy_files.is_empty() => x,
+            _ => return,
+        };
+        let free = match doctor::existing(&to.path).map(doctor::free_space) {
+            Some(Ok(x)) => x,
+            _ => return,
+        };
+        let needed = self.space_needed(copy_files.iter());
+        if needed <= free {
+            return;
+        }
+
+        let (needed, free) = (stats::human(needed as f64), stats::human(free as f64));
+        if self.copy_config.no_space_check {
+            log::warn!(
+                "{} needs {} more, {} is free",
+                to.path.display(),
+                needed,
+                free
+            );
+            return;
+        }
+        log::error!(
+            "{} needs {} more, {} is free, --no-space-check copies anyway",
+            to.path.display(),
+            needed,
+            free
+        );
+        std::process::exit(1);
+    }
+
+    /// Fail before anything is copied when sources of `copy_files` do not
+    /// exist, with --ignore-missing leave them out and report them.
+    fn check_missing(&self, copy_files: &mut HashSet<FileInfo>) {
+        let (from, to) = match (self.src_info(), self.dst_info()) {
+            (Some(from), Some(to)) if !self.has_source_bucket() => (from, to),
+            _ => return,
+        };
+        // the sources are inside the archive
+        #[cfg(feature = "archive")]
+        if archive::Format::detect(&from.path).is_some() {
+            return;
+        }
+
+        let mut missing: Vec<_> = copy_files
+            .iter()
+            .filter(|f| f.url.is_none())
+            .map(|f| {
+                let src = from.path.join(self.src_relative(from, f));
+                (src, f.clone())
+            })
+            .filter(|(src, _)| std::fs::symlink_metadata(src).is_err())
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        missing.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        if !self.copy_config.ignore_missing {
+            log::error!(
+                "{} selected source files are missing, --ignore-missing copies the others:",
+                missing.len()
+            );
+            for (src, _) in &missing {
+                log::error!("  {}", src.display());
+            }
+            std::process::exit(1);
+        }
+        for (src, f) in missing {
+            let dst = to.path.join(self.dst_relative(to, &f));
+            self.output
+                .record(&Record::new(Some(&src), &dst, Status::Missing));
+            copy_files.remove(&f);
+        }
+    }
+
+    /// Stamps of the local sources of `copy_files`.
+    fn stamps(&self, copy_files: &HashSet<FileInfo>) -> Vec<(PathBuf, Stamp)> {
+        let from = match self.src_info() {
+            Some(x) if !self.has_source_bucket() && !self.copy_config.dry_run => x,
+            _ => return vec![],
+        };
+        copy_files
+            .iter()
+            .filter(|f| f.url.is_none())
+            .filter_map(|f| {
+                let src = from.path.join(self.src_relative(from, f));
+                Stamp::of(&src).map(|x| (src, x))
+            })
+            .collect()
+    }
+
+    /// Exclusions of the source `from`: `exclude`, .cpxignore, with
+    /// --exclude-gitignore the .gitignore files and last --filter-from.
+    fn ignore_list(&self, from: &PathInfo) -> IgnoreList {
+        let mut list = IgnoreList::for_source(&from.path, &self.file_config.exclude);
+        if self.copy_config.exclude_gitignore {
+            list.add_gitignore(&from.path);
+        }
+        for x in &self.copy_config.exclude {
+            list.add(x);
+        }
+        list
+    }
+
+    /// Local source files of the selection, including scripts.
+    fn sources<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> Vec<PathBuf> {
+        let names = self.collected();
+        if !names.is_empty() {
+            let mut sources: Vec<_> = names
+                .iter()
+                .flat_map(|x| self.collect_part(x).sources(tags, files))
+                .collect();
+            sources.sort_unstable();
+            sources.dedup();
+            return sources;
+        }
+        let names = self.merged();
+        if !names.is_empty() {
+            let parts = self.merge_parts(&names, tags, files);
+            return parts.iter().flat_map(|x| x.sources(tags, files)).collect();
+        }
+
+        let mut sources = vec![];
+        let copy_files = self.selected_files(tags, files);
+        if let Some(from) = self.src_info().filter(|_| !copy_files.is_empty()) {
+            if is_remote(&from.path) {
+                log::error!("Watching sources needs a local source");
+                std::process::exit(1);
+            }
+            sources.extend(
+                copy_files
+                    .iter()
+                    .filter(|f| f.url.is_none())
+                    .map(|f| from.path.join(self.src_relative(from, f))),
+            );
+        }
+        for s in self.file_config.calculate_script_list(tags) {
+            sources.push(if self.copy_config.reverse {
+                s.to
+            } else {
+                s.from
+            });
+        }
+
+        sources.sort_unstable();
+        sources
+    }
+
+    /// Copy the selection again whenever one of its source files changes,
+    /// once they are quiet for `debounce`.
+    fn watch<T: AsRef<str> + Clone>(
+        &self,
+        tags: Option<Vec<T>>,
+        files: Option<Vec<T>>,
+        debounce: Duration,
+    ) {
+        let sources = self.sources(&tags, &files);
+        let mut watcher = watch::Watcher::new(&sources).unwrap_or_else(|e| {
+            log::error!("Watch failed, {}", e);
+            std::process::exit(1);
+        });
+        log::info!("Watch {} files", sources.len());
+
+        loop {
+            match watcher.wait(debounce) {
+                Ok(changed) => {
+                    for x in &changed {
+                        log::info!("Changed {}", x.display());
+                    }
+                }
+                Err(e) => {
+                    log::error!("Watch failed, {}", e);
+                    std::process::exit(1);
+                }
+            }
+            self.output.reset();
+            self.hook_failed.store(false, Ordering::Relaxed);
+            self.execute(tags.clone(), files.clone());
+            if cleanup::interrupted().is_some() {
+                return;
+            }
+        }
+    }
+
+    /// Print how the file `key` would be resolved and copied, without
+    /// copying it.
+    fn explain(&self, key: &str) {
+        let f = self.file_config.file_list.get(key).unwrap_or_else(|| {
+            let hint = suggest::hint(key, self.file_config.file_list.keys());
+            log::error!("No file {} in config{}", key, hint);
+            std::process::exit(1);
+        });
+        let from = self.src_info().expect("src path not found");
+        let to = self.dst_info().expect("dst path not found");
+        let line = |label: &str, value: &dyn std::fmt::Display| println!("{:<10} {}", label, value);
+
+        let mut tags = vec![];
+        for name in self
+            .file_config
+            .tag_list
+            .keys()
+            .collect::<std::collections::BTreeSet<_>>()
+        {
+            let tag = &self.file_config.tag_list[name];
+            if tag.file_list.iter().flatten().any(|x| x == key) {
+                tags.push(name.clone());
+            }
+            for g in tag.files_from_group.iter().flatten() {
+                if self
+                    .file_config
+                    .group_list
+                    .get(g)
+                    .is_some_and(|x| x.contains(&key.to_owned()))
+                {
+                    tags.push(format!("{} (group {})", name, g));
+                }
+            }
+        }
+        line("file", &key);
+        line(
+            "tags",
+            &if tags.is_empty() {
+                "none, --file only".to_owned()
+            } else {
+                tags.join(", ")
+            },
+        );
+
+        let src_relative = self.src_relative(from, f);
+        let dst_relative = self.dst_relative(to, f);
+        let src = match &f.url {
+            Some(url) => PathBuf::from(url),
+            None => self.src_root(from).join(&src_relative),
+        };
+        let dst = self.dst_root(to).join(&dst_relative);
+        let shown = self.dst_shown(&dst);
+        let src_shown = match &f.url {
+            Some(_) => src.clone(),
+            None => self.src_shown(&src),
+        };
+        line("src", &src_shown.display());
+        line("dst", &shown.display());
+        if dst_relative != f.relative_path {
+            line(
+                "normalize",
+                &format!(
+                    "{} -> {}",
+                    f.relative_path.display(),
+                    dst_relative.display()
+                ),
+            );
+        }
+
+        #[cfg(feature = "remote")]
+        let backend = match (
+            &self.remote,
+            &self.bucket,
+            &self.webdav,
+            &self.source_bucket,
+        ) {
+            _ if f.url.is_some() => "download with curl",
+            (Some(x), ..) if x.is_container() => "upload with docker exec",
+            (Some(_), ..) => "upload over ssh",
+            (_, Some(_), ..) => "upload to s3",
+            (_, _, Some(_), _) => "upload over WebDAV",
+            (.., Some(_)) => "download from s3",
+            _ => "local copy",
+        };
+        #[cfg(not(feature = "remote"))]
+        let backend = match f.url {
+            Some(_) => "download with curl",
+            None => "local copy",
+        };
+        let rsync = self.via_rsync(to);
+        line("transport", &if rsync { "rsync" } else { backend });
+        if self.copy_config.link {
+            line("write", &"symlink to the source");
+        } else if self.copy_config.hardlink && self.is_local() {
+            line(
+                "write",
+                &"hard link to the source, a copy across filesystems",
+            );
+        } else if self.is_local() && !rsync {
+            line(
+                "write",
+                &match self.copy_config.atomic {
+                    true => "through a temporary file, renamed into place",
+                    false => "in place",
+                },
+            );
+        }
+
+        let ignore = self.ignore_list(from);
+        let mut skipped = vec![];
+        if ignore.is_ignored(&src_relative) {
+            skipped
+                .push("excluded by `exclude`, .cpxignore, .gitignore or --filter-from".to_owned());
+        }
+        if let Some(percent) = self.copy_config.sample {
+            if !sampled(&f.relative_path, self.copy_config.seed, percent) {
+                skipped.push(format!("not in the {}% sample", percent));
+            }
+        }
+        if let Some(rev) = &self.copy_config.since {
+            if f.url.is_some() || !git_changed(&from.path, rev).contains(&src_relative) {
+                skipped.push(format!("unchanged since {}", rev));
+            }
+        }
+        if f.url.is_none() && !self.has_source_bucket() && !self.copy_config.filter.matches(&src) {
+            skipped
+                .push("filtered out by --min-size, --max-size, --newer-than or --ext".to_owned());
+        }
+        if f.url.is_some() && self.copy_config.reverse {
+            skipped.push("downloads cannot be reversed".to_owned());
+        }
+        if !skipped.is_empty() {
+            line("skipped", &skipped.join(", "));
+            return;
+        }
+
+        let status = match self.compare(&src, &dst) {
+            Ok(x) => Status::planned(x, self.copy_config.changed_only)
+                .name()
+                .to_owned(),
+            Err(e) => format!("unknown, {}", e),
+        };
+        line("status", &status);
+    }
+
+    /// The copies of the run as a script of `shell`, for each spec and
+    /// each path of a `collect` source.
+    fn export<T: AsRef<str> + Clone>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+        shell: export::Shell,
+    ) {
+        let mut ops = vec![];
+        match self.copy_config.specs.len() > 1 {
+            true => {
+                let selection = self.file_config.calculate_file_list(tags, files);
+                for (from, to) in &self.copy_config.specs {
+                    let mut copy_config = self.copy_config.clone();
+                    copy_config.from = Some(from.clone());
+                    copy_config.to = Some(to.clone());
+                    copy_config.specs = vec![];
+                    let mut part = Cpx::new(copy_config, self.file_config.clone(), None);
+                    part.selection = Some(selection.clone());
+                    part.export_selection(tags, files, &mut ops);
+                }
+            }
+            false => self.export_selection(tags, files, &mut ops),
+        }
+        print!("{}", export::script(shell, &ops));
+    }
+
+    fn export_selection<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+        ops: &mut Vec<export::Op>,
+    ) {
+        let names = self.collected();
+        if !names.is_empty() {
+            for name in &names {
+                self.collect_part(name).export_files(tags, files, ops);
+            }
+            return;
+        }
+        let names = self.merged();
+        if !names.is_empty() {
+            for part in self.merge_parts(&names, tags, files) {
+                part.export_files(tags, files, ops);
+            }
+            return;
+        }
+        self.export_files(tags, files, ops);
+    }
+
+    fn export_files<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+        ops: &mut Vec<export::Op>,
+    ) {
+        let from = self.src_info().expect("src path not found");
+        let to = self.dst_info().expect("dst path not found");
+        let copy_files = self.selected_files(tags, files);
+
+        let c = &self.copy_config;
+        let mut unsupported = vec![];
+        if !self.is_local() || self.has_source_bucket() {
+            unsupported.push("remote hosts, buckets and WebDAV");
+        }
+        #[cfg(feature = "archive")]
+        if c.archive.is_some()
+            || archive::Format::detect(&from.path).is_some()
+            || archive::Format::detect(&to.path).is_some()
+        {
+            unsupported.push("archives");
+        }
+        if c.eol.is_some() || copy_files.iter().any(|f| f.transformed()) {
+            unsupported.push("templates, filters, compression and --eol");
+        }
+        if copy_files.iter().any(|f| f.hash.is_some()) {
+            unsupported.push("pinned hashes");
+        }
+        if from.encryption.is_some() || to.encryption.is_some() {
+            unsupported.push("`encryption`");
+        }
+        if c.link || c.hardlink || c.delete || c.snapshot.is_some() {
+            unsupported.push("--link, --hardlink, --delete and --snapshot");
+        }
+        if !unsupported.is_empty() {
+            log::error!("cpx export does not support {}", unsupported.join(", "));
+            std::process::exit(1);
+        }
+        if to.umask.is_some() || to.dir_mode.is_some() {
+            log::warn!("The exported script ignores the `umask` and `dir_mode` of the destination");
+        }
+        if self.owner.is_some() {
+            log::warn!("The exported script keeps the owners of the copies");
+        }
+
+        let mut files: Vec<_> = copy_files.into_iter().collect();
+        files.sort_unstable_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        for f in files {
+            let src = match &f.url {
+                Some(url) => PathBuf::from(url),
+                None => from.path.join(self.src_relative(from, &f)),
+            };
+            ops.push(export::Op {
+                src,
+                dst: to.path.join(self.dst_relative(to, &f)),
+                url: f.url.is_some(),
+                mode: f.mode.or(c.chmod).or(to.file_mode),
+                post: f.post,
+            });
+        }
+
+        for s in self.file_config.calculate_script_list(tags) {
+            let (src, dst) = match c.reverse {
+                true => (s.to, s.from),
+                false => (s.from, s.to),
+            };
+            ops.push(export::Op {
+                src,
+                dst,
+                url: false,
+                mode: None,
+                post: None,
+            });
+        }
+    }
+
+    /// Time copies of the selection into a scratch directory of the
+    /// destination by each engine and at each of `levels` parallel copies,
+    /// for `cpx bench`.
+    fn bench<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+        levels: &[usize],
+    ) {
+        let c = &self.copy_config;
+        if c.specs.len() > 1 || !self.collected().is_empty() || !self.merged().is_empty() {
+            log::error!("cpx bench needs a single spec without `collect` or `merge`");
+            std::process::exit(1);
+        }
+        if !self.is_local() || self.has_source_bucket() {
+            log::error!("cpx bench needs a local source and destination");
+            std::process::exit(1);
+        }
+        let from = self.src_info().expect("src path not found");
+        let to = self.dst_info().expect("dst path not found");
+        let copy_files: Vec<_> = self
+            .selected_files(tags, files)
+            .into_iter()
+            .filter(|f| f.url.is_none())
+            .map(|f| {
+                let src = from.path.join(self.src_relative(from, &f));
+                (src, self.dst_relative(to, &f))
+            })
+            .filter(|(src, _)| src.is_file())
+            .collect();
+        if copy_files.is_empty() {
+            log::error!("No source files of the selection to copy");
+            std::process::exit(1);
+        }
+        let bytes = bench::warm(&copy_files).unwrap_or_else(|e| {
+            log::error!("Read the sources failed, {}", e);
+            std::process::exit(1);
+        });
+
+        let scratch = to.path.join(format!(".cpx-bench-{}", std::process::id()));
+        println!(
+            "{} files, {}, copied into {}",
+            copy_files.len(),
+            stats::human(bytes as f64),
+            scratch.display()
+        );
+        println!(
+            "{:<16} {:>4} {:>10} {:>14}",
+            "engine", "jobs", "seconds", "throughput"
+        );
+        let chunk_size = c.chunk_size.unwrap_or(copy::CHUNK_SIZE);
+        for trial in bench::trials(levels) {
+            let copier = Copier::new(trial.engine, None, c.atomic, c.sync, chunk_size);
+            let result = match bench::run(&copier, &copy_files, &scratch, trial.jobs) {
+                Ok(x) => {
+                    let rate = bytes as f64 / x.as_secs_f64().max(f64::EPSILON);
+                    format!(
+                        "{:>10.3} {:>14}",
+                        x.as_secs_f64(),
+                        format!("{}/s", stats::human(rate))
+                    )
+                }
+                Err(e) => {
+                    let _ = std::fs::remove_dir_all(&scratch);
+                    format!("failed, {}", e)
+                }
+            };
+            println!("{:<16} {:>4} {}", trial.engine.name(), trial.jobs, result);
+        }
+    }
+
+    /// Copy each file of the selection that changed on one side since the
+    /// last sync to the other side, `cpx sync`. Files changed on both
+    /// sides are conflicts and left as they are.
+    fn sync<T: AsRef<str>>(&self, tags: &Option<Vec<T>>, files: &Option<Vec<T>>) {
+        let started = Instant::now();
+        let c = &self.copy_config;
+        if c.specs.len() > 1 || !self.collected().is_empty() || !self.merged().is_empty() {
+            log::error!("cpx sync needs a single spec without `collect` or `merge`");
+            std::process::exit(1);
+        }
+        let part = |reverse| {
+            let mut copy_config = c.clone();
+            copy_config.reverse = reverse;
+            copy_config.specs = vec![];
+            Cpx::new(copy_config, self.file_config.clone(), None)
+        };
+        let (there, back) = (part(false), part(true));
+        let from = there.src_info().expect("src path not found");
+        let to = there.dst_info().expect("dst path not found");
+
+        let mut unsupported = vec![];
+        let local = |x: &Cpx| x.is_local() && !x.has_source_bucket();
+        if !local(&there) || !local(&back) {
+            unsupported.push("remote hosts, buckets and WebDAV");
+        }
+        #[cfg(feature = "archive")]
+        if c.archive.is_some()
+            || archive::Format::detect(&from.path).is_some()
+            || archive::Format::detect(&to.path).is_some()
+        {
+            unsupported.push("archives");
+        }
+        if from.encryption.is_some() || to.encryption.is_some() {
+            unsupported.push("`encryption`");
+        }
+        if c.delete || c.prune_empty_dirs || c.rotate || c.snapshot.is_some() {
+            unsupported.push("--delete, --prune-empty-dirs, --rotate and --snapshot");
+        }
+        if c.flatten.is_some() || c.normalize.is_some() {
+            unsupported.push("--flatten and --normalize");
+        }
+        if c.since.is_some() || c.since_last_run {
+            unsupported.push("--since and --since-last-run");
+        }
+        if !unsupported.is_empty() {
+            log::error!("cpx sync does not support {}", unsupported.join(", "));
+            std::process::exit(1);
+        }
+
+        let mut copy_files: Vec<_> = there
+            .selected_files(tags, files)
+            .into_iter()
+            .filter(|f| {
+                if f.url.is_some() || f.transformed() {
+                    log::warn!(
+                        "Skip {}, downloads and transformed files are copied one way only",
+                        f.relative_path.display()
+                    );
+                }
+                f.url.is_none() && !f.transformed()
+            })
+            .collect();
+        copy_files.sort_unstable_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+        let mut state = SyncState::load(&c.job);
+        let (mut forward, mut backward) = (HashSet::new(), HashSet::new());
+        let mut synced = vec![];
+        for f in copy_files {
+            let a = from.path.join(there.src_relative(from, &f));
+            let b = to.path.join(there.dst_relative(to, &f));
+            let (stamp_a, stamp_b) = (Stamp::of(&a), Stamp::of(&b));
+            let last = state.files.get(&f.relative_path);
+            let changed_a = last.is_none_or(|x| Some(x.0) != stamp_a);
+            let changed_b = last.is_none_or(|x| Some(x.1) != stamp_b);
+
+            // a file removed on one side is copied back from the other
+            let newer_a = match (stamp_a, stamp_b) {
+                (None, None) => {
+                    log::warn!("Skip {}, missing on both sides", f.relative_path.display());
+                    continue;
+                }
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                _ if !changed_a && !changed_b => continue,
+                _ if !changed_b => true,
+                _ if !changed_a => false,
+                (Some(x), Some(y)) => match compare::compare(&a, &b) {
+                    Ok(FileStatus::Identical) => {
+                        state.files.insert(f.relative_path.clone(), (x, y));
+                        continue;
+                    }
+                    // first synced, nothing says which side changed
+                    Ok(_) if last.is_none() && x.mtime != y.mtime => x.mtime > y.mtime,
+                    Ok(_) => {
+                        self.output
+                            .record(&Record::new(Some(&a), &b, Status::Conflict));
+                        continue;
+                    }
+                    Err(e) => {
+                        let mut r = Record::new(Some(&a), &b, Status::Failed);
+                        r.error = Some(format!("compare failed, {}", e));
+                        self.output.record(&r);
+                        continue;
+                    }
+                },
+            };
+            synced.push((f.relative_path.clone(), a, b));
+            match newer_a {
+                true => forward.insert(f),
+                false => backward.insert(f),
+            };
+        }
+
+        for (mut part, selection) in [(there, forward), (back, backward)] {
+            if selection.is_empty() {
+                continue;
+            }
+            part.selection = Some(selection);
+            part.execute_files(&None::<Vec<String>>, &None);
+            self.output.merge(&part.output);
+        }
+
+        if !c.dry_run {
+            // a file failed to copy is still changed for the next sync
+            let failed: HashSet<_> = self.output.failures().into_iter().map(|x| x.0).collect();
+            for (relative, a, b) in synced {
+                if failed.contains(&a) || failed.contains(&b) {
+                    continue;
+                }
+                if let (Some(x), Some(y)) = (Stamp::of(&a), Stamp::of(&b)) {
+                    state.files.insert(relative, (x, y));
+                }
+            }
+            if let Err(e) = state.save(&c.job) {
+                log::warn!("Save sync state failed, {}", e);
+            }
+        }
+        self.summarize(started);
+    }
+
+    /// Hand the file set to a single rsync run, which brings delta
+    /// transfers and partial files for remote hosts.
+    #[cfg(feature = "remote")]
+    fn execute_rsync(&self, copy_files: &HashSet<FileInfo>, record: &mut RunRecord) {
+        let from = self.src_info().expect("src path not found");
+        let to = self.dst_info().expect("dst path not found");
+
+        let c = &self.copy_config;
+        let mut unsupported = vec![];
+        if self.bucket.is_some() || self.webdav.is_some() || self.has_source_bucket() {
+            unsupported.push("buckets and WebDAV");
+        }
+        if self.remote.as_ref().is_some_and(|x| x.is_container()) {
+            unsupported.push("containers");
+        }
+        if c.snapshot.is_some() {
+            unsupported.push("--snapshot");
+        }
+        if c.link || c.hardlink || c.delete || c.prune_empty_dirs {
+            unsupported.push("--link, --hardlink, --delete and --prune-empty-dirs");
+        }
+        #[cfg(feature = "archive")]
+        if c.archive.is_some() || archive::Format::detect(&from.path).is_some() {
+            unsupported.push("archives");
+        }
+        if copy_files.iter().any(|f| f.url.is_some()) {
+            unsupported.push("files with a url");
+        }
+        if c.eol.is_some() || copy_files.iter().any(|f| f.transformed()) {
+            unsupported.push("templates, filters, compression and --eol");
+        }
+        if copy_files.iter().any(|f| f.hash.is_some()) {
+            unsupported.push("pinned hashes");
+        }
+        if from.encryption.is_some() || to.encryption.is_some() {
+            unsupported.push("`encryption`");
+        }
+
+        let mut files = vec![];
+        for f in copy_files {
+            let relative = self.src_relative(from, f);
+            if relative != self.dst_relative(to, f) {
+                unsupported.push("renames by --normalize and --flatten");
+                break;
+            }
+            files.push(relative);
+        }
+        files.sort_unstable();
+        if !unsupported.is_empty() {
+            unsupported.dedup();
+            log::error!(
+                "The rsync transport does not support {}",
+                unsupported.join(", ")
+            );
+            std::process::exit(1);
+        }
+        if self.journal.is_some() {
+            log::warn!("cpx undo does not cover rsync transfers");
+        }
+
+        let rsync = Rsync {
+            src: &from.path,
+            dst: &to.path,
+            ssh: [to, from]
+                .iter()
+                .find(|x| Remote::parse(&x.path, None).is_some())
+                .map(|x| x.ssh.clone().unwrap_or_default()),
+            dry_run: c.dry_run,
+            bwlimit: c.bwlimit,
+            compare: self.strategy,
+            xattrs: c.xattrs,
+        };
+        let mut transferred = HashSet::new();
+        let result = rsync.run(&files, |x| {
+            let (src, dst) = (from.path.join(&x.relative), to.path.join(&x.relative));
+            let status = match (c.dry_run, x.new) {
+                (true, true) => Status::New,
+                (true, false) => Status::Overwrite,
+                (false, _) => Status::Copied,
+            };
+            self.output.record(&Record {
+                bytes: Some(x.bytes).filter(|_| !c.dry_run),
+                ..Record::new(Some(&src), &dst, status)
+            });
+            transferred.insert(x.relative);
+        });
+        if let Err(e) = result {
+            log::error!(
+                "Copy {} -> {} failed, {}",
+                from.path.display(),
+                to.path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+
+        for relative in files {
+            let (src, dst) = (from.path.join(&relative), to.path.join(&relative));
+            if !transferred.contains(&relative) {
+                let status = match c.dry_run {
+                    true => Status::Identical,
+                    false => Status::Skipped,
+                };
+                self.output.record(&Record::new(Some(&src), &dst, status));
+            }
+            record.files.insert(dst);
+        }
+    }
+
+    /// Persist the journal and then the checkpoint, so a checkpoint never
+    /// lists files whose undo information is lost.
+    fn save_progress(&self) {
+        let checkpoint = match &self.checkpoint {
+            Some(x) => x,
+            None => return,
+        };
+
+        if let Some(journal) = &self.journal {
+            if let Err(e) = journal.save() {
+                log::warn!("Save journal failed, {}", e);
+            }
+        }
+        if let Err(e) = checkpoint.save() {
+            log::warn!("Save checkpoint failed, {}", e);
+        }
+    }
+
+    fn progress(&self, dst: &Path) {
+        if self.checkpoint.as_ref().is_some_and(|x| x.done(dst)) {
+            self.save_progress();
+        }
+    }
+
+    fn path_info(&self, name: &Option<String>) -> Option<&PathInfo> {
+        name.as_ref()
+            .and_then(|x| self.file_config.path_list.get(x))
+    }
+
+    fn src_info(&self) -> Option<&PathInfo> {
+        if self.copy_config.reverse {
+            self.path_info(&self.copy_config.to)
+        } else {
+            self.path_info(&self.copy_config.from)
+        }
+    }
+
+    fn dst_info(&self) -> Option<&PathInfo> {
+        if self.copy_config.reverse {
+            self.path_info(&self.copy_config.from)
+        } else {
+            self.path_info(&self.copy_config.to)
+        }
+    }
+
+    /// Relative path of a file under `info`, normalized by the path's own
+    /// policy or, on the destination side, by `--normalize`.
+    /// Path of the source of `f` relative to `from`, with the extension it
+    /// is found with when decompressed.
+    fn src_relative(&self, from: &PathInfo, f: &FileInfo) -> PathBuf {
+        let relative = self.relative_for(from, &f.relative_path, false);
+        let local = !self.has_source_bucket() && f.url.is_none();
+        match f.decompress {
+            Some(Decompress::Auto) if local => {
+                Compression::find(&from.path, &relative).unwrap_or(relative)
+            }
+            _ => relative,
+        }
+    }
+
+    /// Path of the copy of `f` relative to the destination `to`, without
+    /// the extension of a decompressed source and with that of its
+    /// compression.
+    fn dst_relative(&self, to: &PathInfo, f: &FileInfo) -> PathBuf {
+        let mut relative = self.relative_for(to, &f.relative_path, true);
+        if f.decompress.is_some() {
+            relative = Compression::strip(&relative);
+        }
+        match f.compress {
+            Some(x) => x.path(&relative),
+            None => relative,
+        }
+    }
+
+    fn relative_for(&self, info: &PathInfo, relative_path: &Path, is_dst: bool) -> PathBuf {
+        let flatten = is_dst && self.copy_config.flatten.is_some();
+        if let Some(x) = self
+            .flat
+            .lock()
+            .unwrap()
+            .get(relative_path)
+            .filter(|_| flatten)
+        {
+            return x.clone();
+        }
+
+        let cli = self.copy_config.normalize.as_ref().filter(|_| is_dst);
+        let relative_path = paths::relative(relative_path);
+        let relative_path = match cli.or(info.normalize.as_ref()) {
+            Some(policy) => policy.apply(&relative_path),
+            None => relative_path,
+        };
+        match relative_path.file_name() {
+            Some(x) if flatten => PathBuf::from(x),
+            _ => relative_path,
+        }
+    }
+
+    fn execute_copy_script<T: AsRef<str>>(&self, tags: &Option<Vec<T>>, record: &mut RunRecord) {
+        let scripts = self.file_config.calculate_script_list(tags);
+
+        for s in scripts {
+            if self.stopped() {
+                break;
+            }
+            let (src, dst) = if self.copy_config.reverse {
+                (s.to, s.from)
+            } else {
+                (s.from, s.to)
+            };
+
+            let root = dst.parent().unwrap_or(&dst).to_path_buf();
+            if self.execute_copy(&src, &dst, &root) {
+                record.files.insert(dst);
+            }
+        }
+    }
+
+    fn execute_copy_list(&self, root: &Path, mut ops: Vec<(PathBuf, PathBuf)>) -> Vec<PathBuf> {
+        self.copy_config.order.sort(&mut ops);
+        let jobs = self.copy_config.jobs.max(1).min(ops.len().max(1));
+        let next = AtomicUsize::new(0);
+        let copied = Mutex::new(vec![]);
+        let prefetch = Some(self.copy_config.prefetch)
+            .filter(|x| *x > 0 && !self.copy_config.dry_run)
+            .map(Prefetch::new);
+        let watchdog = timeout::Watchdog::new(self.copy_config.timeout, self.copy_config.deadline);
+
+        std::thread::scope(|scope| {
+            if let Some(prefetch) = &prefetch {
+                let ops = &ops;
+                scope.spawn(move || prefetch.run(ops));
+            }
+            if let Some(watchdog) = &watchdog {
+                scope.spawn(move || watchdog.run());
+            }
+            let workers: Vec<_> = (0..jobs)
+                .map(|_| {
+                    scope.spawn(|| loop {
+                        if self.stopped() {
+                            break;
+                        }
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if let Some(prefetch) = &prefetch {
+                            prefetch.advance(i);
+                        }
+                        let (src, dst) = match ops.get(i) {
+                            Some(x) => x,
+                            None => break,
+                        };
+                        if watchdog.as_ref().is_some_and(|x| x.past_deadline()) {
+                            break;
+                        }
+
+                        let _copying = watchdog.as_ref().map(|x| x.start(dst));
+                        if self.execute_copy(src, dst, root) {
+                            copied.lock().unwrap().push(dst.clone());
+                        }
+                    })
+                })
+                .collect();
+            let joined: Vec<_> = workers.into_iter().map(|x| x.join()).collect();
+            if let Some(watchdog) = &watchdog {
+                watchdog.stop();
+            }
+            for x in joined {
+                if let Err(e) = x {
+                    std::panic::resume_unwind(e);
+                }
+            }
+        });
+
+        copied.into_inner().unwrap()
+    }
+
+    /// Write `ops` into the archive at `root` if the destination is one,
+    /// `None` when it is a plain directory.
+    #[cfg(feature = "archive")]
+    fn execute_archive(&self, root: &Path, ops: &[(PathBuf, PathBuf)]) -> Option<Vec<PathBuf>> {
+        let format = self
+            .copy_config
+            .archive
+            .or_else(|| archive::Format::detect(root))?;
+        let mut ops = ops.to_vec();
+        ops.sort_unstable();
+
+        if self.copy_config.delete {
+            log::warn!("--delete has no effect on archive destinations");
+        }
+
+        if self.copy_config.dry_run {
+            for (src, dst) in &ops {
+                self.output
+                    .record(&Record::new(Some(src), dst, Status::New));
+            }
+            return Some(vec![]);
+        }
+
+        let writer = self
+            .prepare_write(root)
+            .and_then(|_| archive::ArchiveWriter::create(root, format));
+        let mut writer = match writer {
+            Ok(x) => x,
+            Err(e) => {
+                log::error!("Create archive {} failed, {}", root.display(), e);
+                return Some(vec![]);
+            }
+        };
+
+        let mut stored = vec![];
+        for (src, dst) in &ops {
+            if self.stopped() {
+                break;
+            }
+            let mut r = Record::new(Some(src), dst, Status::Copied);
+            match writer.append(src, dst.strip_prefix(root).unwrap_or(dst)) {
+                Ok(bytes) => {
+                    r.bytes = Some(bytes);
+                    stored.push(dst.clone());
+                }
+                Err(e) => {
+                    r.status = Status::Failed;
+                    r.error = Some(e.to_string());
+                }
+            }
+            self.output.record(&r);
+        }
+
+        if let Err(e) = writer.finish() {
+            log::error!("Write archive {} failed, {}", root.display(), e);
+            stored.clear();
+        }
+
+        Some(stored)
+    }
+
+    /// Copy `ops` out of the archive at `root`, reading only the selected entries.
+    #[cfg(feature = "archive")]
+    fn execute_extract(
+        &self,
+        root: &Path,
+        format: archive::Format,
+        ops: &[(PathBuf, PathBuf)],
+    ) -> Vec<PathBuf> {
+        if self.copy_config.dry_run {
+            for (src, dst) in ops {
+                let status = if dst.exists() {
+                    Status::Overwrite
+                } else {
+                    Status::New
+                };
+                self.output.record(&Record::new(Some(src), dst, status));
+            }
+            return vec![];
+        }
+
+        let names: Vec<_> = ops
+            .iter()
+            .map(|(src, _)| src.strip_prefix(root).unwrap_or(src).to_path_buf())
+            .collect();
+        let results = archive::extract(root, format, &names, |i, reader| {
+            let dst = &ops[i].1;
+            self.prepare_write(dst)?;
+            std::io::copy(reader, &mut File::create(dst)?)
+        });
+        let results = match results {
+            Ok(x) => x,
+            Err(e) => {
+                log::error!("Read archive {} failed, {}", root.display(), e);
+                return vec![];
+            }
+        };
+
+        let mut copied = vec![];
+        for ((src, dst), result) in ops.iter().zip(results) {
+            let mut r = Record::new(Some(src), dst, Status::Copied);
+            match result {
+                Some(Ok(bytes)) => {
+                    r.bytes = Some(bytes);
+                    copied.push(dst.clone());
+                }
+                Some(Err(e)) => {
+                    r.status = Status::Failed;
+                    r.error = Some(e.to_string());
+                }
+                None => {
+                    r.status = Status::Failed;
+                    r.error = Some("not found in archive".to_owned());
+                }
+            }
+            self.output.record(&r);
+        }
+
+        copied
+    }
+
+    /// The files of the destination that are not kept: those next to kept
+    /// files, or with `cpx mirror` all of the destination but excluded ones.
+    fn extraneous(&self, keep: &HashSet<PathBuf>) -> Vec<PathBuf> {
+        let mut found = vec![];
+        if self.copy_config.mirror {
+            let (from, to) = (self.src_info().unwrap(), self.dst_info().unwrap());
+            match scan::all(&to.path, &self.ignore_list(from)) {
+                Ok(files) => found.extend(files.into_iter().map(|x| to.path.join(x))),
+                Err(e) => log::error!("Read {} failed, {}", to.path.display(), e),
+            }
+        } else {
+            for dir in managed_dirs(keep) {
+                let entries = match std::fs::read_dir(dir) {
+                    Ok(x) => x,
+                    Err(_) => continue,
+                };
+                let files = entries
+                    .filter_map(|x| x.ok())
+                    .filter(|x| x.file_type().map(|x| x.is_file()).unwrap_or(false));
+                found.extend(files.map(|x| x.path()));
+            }
+        }
+
+        found.retain(|x| !keep.contains(x));
+        found
+    }
+
+    fn execute_delete(&self, keep: &HashSet<PathBuf>) {
+        for path in self.extraneous(keep) {
+            if let Some(backup) = self.copy_config.backup.as_ref() {
+                if backup.is_backup(&path) {
+                    continue;
+                }
+            }
+
+            if self.copy_config.dry_run {
+                self.output
+                    .record(&Record::new(None, &path, Status::Delete));
+                continue;
+            }
+
+            let mut r = Record::new(None, &path, Status::Deleted);
+            let journaled = match &self.journal {
+                Some(journal) => journal.before_delete(&path),
+                None => Ok(()),
+            };
+            let removed = journaled.and_then(|_| match &self.copy_config.backup {
+                Some(backup) => backup.keep(&path).map(|_| ()),
+                None => std::fs::remove_file(&path),
+            });
+            if let Err(e) = removed {
+                r.status = Status::Failed;
+                r.error = Some(e.to_string());
+            }
+            self.output.record(&r);
+        }
+    }
+
+    /// Remove empty directories below the managed destination directories,
+    /// or the whole destination with `cpx mirror`.
+    fn execute_prune(&self, keep: &HashSet<PathBuf>) {
+        let dirs = match self.copy_config.mirror {
+            true => vec![self.dst_info().unwrap().path.as_path()],
+            false => managed_dirs(keep),
+        };
+        for dir in dirs {
+            let entries = match std::fs::read_dir(dir) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+
+            for entry in entries.filter_map(|x| x.ok()) {
+                if entry.file_type().map(|x| x.is_dir()).unwrap_or(false) {
+                    self.prune_dir(&entry.path());
+                }
+            }
+        }
+    }
+
+    /// Prune `dir` bottom up, returns whether it is (or in a dry run would be) gone.
+    fn prune_dir(&self, dir: &Path) -> bool {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+
+        let mut empty = true;
+        for entry in entries.filter_map(|x| x.ok()) {
+            let is_dir = entry.file_type().map(|x| x.is_dir()).unwrap_or(false);
+            if !is_dir || !self.prune_dir(&entry.path()) {
+                empty = false;
+            }
+        }
+        if !empty {
+            return false;
+        }
+
+        if self.copy_config.dry_run {
+            self.output.record(&Record::new(None, dir, Status::Delete));
+            return true;
+        }
+
+        let mut r = Record::new(None, dir, Status::Deleted);
+        if let Err(e) = std::fs::remove_dir(dir) {
+            r.status = Status::Failed;
+            r.error = Some(e.to_string());
+        }
+        self.output.record(&r);
+
+        r.status == Status::Deleted
+    }
+
+    /// Make `dst` a symlink to `src`. Existing links are replaced, but a
+    /// real file in the way is left alone and reported.
+    fn execute_link(&self, src: &Path, dst: &Path) -> bool {
+        let target = match std::fs::canonicalize(src) {
+            Ok(x) => paths::simplify(&x),
+            Err(e) => {
+                self.output.record(&Record {
+                    error: Some(e.to_string()),
+                    ..Record::new(Some(src), dst, Status::Failed)
+                });
+                return false;
+            }
+        };
+
+        let (status, error) = match std::fs::symlink_metadata(dst) {
+            Ok(x) if x.file_type().is_symlink() => match std::fs::read_link(dst) {
+                Ok(current) if paths::simplify(&current) == target => (Status::Skipped, None),
+                _ => (Status::Overwrite, None),
+            },
+            Ok(_) => (
+                Status::Failed,
+                Some("destination exists and is not a symlink".to_owned()),
+            ),
+            Err(_) => (Status::New, None),
+        };
+
+        let status = match status {
+            Status::Skipped if self.copy_config.dry_run => Status::Identical,
+            Status::Failed | Status::Skipped => status,
+            _ if self.copy_config.dry_run => status,
+            _ => {
+                let linked = self.prepare_write(dst).and_then(|_| {
+                    if status == Status::Overwrite {
+                        copy::remove_link(dst)?;
+                    }
+                    copy::symlink(&target, dst)
+                });
+                if let Err(e) = linked {
+                    self.output.record(&Record {
+                        error: Some(e.to_string()),
+                        ..Record::new(Some(&target), dst, Status::Failed)
+                    });
+                    return false;
+                }
+                Status::Linked
+            }
+        };
+
+        self.output.record(&Record {
+            error,
+            ..Record::new(Some(&target), dst, status)
+        });
+
+        status != Status::Failed
+    }
+
+    /// The content of the local file `src` as `dst` is to get it,
+    /// decrypted, decompressed, through its filter, with placeholders
+    /// filled and line endings converted, compressed and encrypted again,
+    /// `None` if it is copied as is.
+    fn transform(&self, src: &Path, dst: &Path) -> std::io::Result<Option<Transformed>> {
+        let f = self.file_info(dst);
+        let template = f.as_ref().is_some_and(|x| x.template);
+        let filter = f.as_ref().and_then(|x| x.filter.as_ref());
+        let filter = filter.map(|x| (x, &self.file_config.filters[x]));
+        let eol = f.as_ref().and_then(|x| x.eol).or(self.copy_config.eol);
+        let decrypt = self.src_info().and_then(|x| x.encryption.as_ref());
+        let encrypt = self.dst_info().and_then(|x| x.encryption.as_ref());
+        let compress = f.as_ref().and_then(|x| x.compress);
+        let decompress = f
+            .as_ref()
+            .and_then(|x| x.decompress)
+            .and_then(|_| Compression::detect(src));
+        let local = !self.has_source_bucket() && http::url(src).is_none();
+        let text = template || eol.is_some();
+        let plain = !text && filter.is_none() && compress.is_none() && decompress.is_none();
+        if !local || (plain && decrypt.is_none() && encrypt.is_none()) {
+            return Ok(None);
+        }
+
+        let mut data = std::fs::read(src)?;
+        if let Some(x) = decrypt {
+            data = x.decrypt(&data)?;
+        }
+        if let Some(x) = decompress {
+            data = x.decompress(&data)?;
+        }
+        if let Some((name, cmd)) = filter {
+            let env = self.file_env.lock().unwrap().clone();
+            data = hooks::filter(cmd, &data, src, &env).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("filter {} failed, {}", name, e))
+            })?;
+        }
+        if text && !transform::is_text(&data) {
+            log::debug!("Copy {} as is, not a text file", src.display());
+        } else {
+            if template {
+                data = template::render(&data, &self.file_config.vars)?;
+            }
+            if let Some(eol) = eol {
+                data = eol.convert(&data);
+            }
+        }
+        if let Some(x) = compress {
+            data = x.compress(&data)?;
+        }
+        if let Some(x) = encrypt {
+            data = x.encrypt(&data)?;
+        }
+
+        Transformed::write(src, &data).map(Some)
+    }
+
+    /// Hard link `dst` to `src`, `None` when they are on different
+    /// filesystems and `src` is to be copied instead.
+    fn execute_hardlink(&self, src: &Path, dst: &Path) -> Option<bool> {
+        let status = match std::fs::symlink_metadata(dst) {
+            Ok(_) if copy::same_file(src, dst) => Status::Skipped,
+            Ok(x) if x.is_file() => Status::Overwrite,
+            Ok(_) => {
+                self.output.record(&Record {
+                    error: Some("destination exists and is not a file".to_owned()),
+                    ..Record::new(Some(src), dst, Status::Failed)
+                });
+                return Some(false);
+            }
+            Err(_) => Status::New,
+        };
+
+        let status = match status {
+            Status::Skipped if self.copy_config.dry_run => Status::Identical,
+            Status::Skipped => status,
+            _ if self.copy_config.dry_run => match copy::same_device(src, dst) {
+                true => status,
+                false => return None,
+            },
+            _ => {
+                let linked = self.prepare_write(dst).and_then(|_| dedup::link(src, dst));
+                match linked {
+                    Ok(()) => Status::Linked,
+                    Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+                        log::debug!(
+                            "Copy {}, not on the filesystem of its source",
+                            dst.display()
+                        );
+                        return None;
+                    }
+                    Err(e) => {
+                        self.output.record(&Record {
+                            error: Some(e.to_string()),
+                            ..Record::new(Some(src), dst, Status::Failed)
+                        });
+                        return Some(false);
+                    }
+                }
+            }
+        };
+
+        self.output.record(&Record::new(Some(src), dst, status));
+        Some(true)
+    }
+
+    /// Create the parent of `dst` and journal it, right before it is written.
+    fn prepare_write(&self, dst: &Path) -> std::io::Result<()> {
+        if self.copy_config.create_dir {
+            if let Some(parent) = dst.parent() {
+                if let Err(e) = self.dirs.create_all(parent) {
+                    log::error!("Create directory {} failed, {}", parent.display(), e);
+                }
+            }
+        }
+
+        if let Some(journal) = &self.journal {
+            journal.before_write(dst).map_err(|e| {
+                std::io::Error::new(e.kind(), format!("backup for undo failed, {}", e))
+            })?;
+        }
+        match &self.copy_config.backup {
+            Some(backup) if std::fs::symlink_metadata(dst).is_ok_and(|x| x.is_file()) => {
+                let kept = backup
+                    .keep(dst)
+                    .map_err(|e| std::io::Error::new(e.kind(), format!("backup failed, {}", e)))?;
+                log::debug!("Keep {} as {}", dst.display(), kept.display());
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the source `src` of `dst` has the `hash` of its file, if it
+    /// has one, before anything of it is copied. A local copy checks the
+    /// bytes it wrote again before they replace the destination.
+    fn check_hash(&self, src: &Path, dst: &Path) -> Result<(), String> {
+        let expected = match self.file_info(dst).and_then(|x| x.hash) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        if self.has_source_bucket() {
+            return Err("a pinned hash needs a local source".to_owned());
+        }
+        // not the cached hash, a file changed and touched back has it
+        let hex = expected
+            .algorithm
+            .file(src)
+            .map_err(|e| format!("hash the source failed, {}", e))?;
+        match hex == expected.hex {
+            true => Ok(()),
+            false => Err(format!(
+                "{} of the source is {}, the config pins {}",
+                expected.algorithm.name(),
+                hex,
+                expected.hex
+            )),
+        }
+    }
+
+    fn execute_copy<P: AsRef<Path>>(&self, src: P, dst: P, root: &Path) -> bool {
+        let (src, dst) = (src.as_ref(), dst.as_ref());
+        let shown = self.dst_shown(dst);
+        let shown = shown.as_path();
+        let src_shown = self.src_shown(src);
+        let src_shown = src_shown.as_path();
+
+        if self.breaker.is_open(root) {
+            self.output
+                .record(&Record::new(Some(src_shown), shown, Status::CircuitOpen));
+            return false;
+        }
+
+        let mut stale = vec![];
+        if let Some(checkpoint) = &self.checkpoint {
+            if checkpoint.is_done(dst) {
+                log::debug!(
+                    "Skip {}, done before the run was interrupted",
+                    shown.display()
+                );
+                self.output
+                    .record(&Record::new(Some(src_shown), shown, Status::Skipped));
+                return true;
+            }
+            stale = checkpoint.orphans(dst);
+            if self.is_local() {
+                checkpoint::remove_orphans(&stale);
+            }
+        }
+
+        if let Err(e) = self.check_hash(src, dst) {
+            self.output.record(&Record {
+                error: Some(e),
+                ..Record::new(Some(src_shown), shown, Status::Failed)
+            });
+            return false;
+        }
+
+        if self.copy_config.link {
+            let linked = self.execute_link(src, dst);
+            if linked {
+                self.progress(dst);
+            }
+            return linked;
+        }
+
+        let transformed = match self.transform(src, dst) {
+            Ok(x) => x,
+            Err(e) => {
+                self.output.record(&Record {
+                    error: Some(e.to_string()),
+                    ..Record::new(Some(src_shown), shown, Status::Failed)
+                });
+                return false;
+            }
+        };
+        // the transformed file is what is compared and copied, attributes
+        // come from the original
+        let original = src;
+        let src = transformed.as_ref().map_or(src, |x| x.path());
+
+        // a mode or owner given to a hard link would change its source too
+        let plain = transformed.is_none()
+            && http::url(src).is_none()
+            && self.mode(dst).is_none()
+            && self.umask().is_none()
+            && self.owner.is_none();
+        if self.copy_config.hardlink && self.is_local() && plain {
+            if let Some(linked) = self.execute_hardlink(src, dst) {
+                if linked {
+                    self.progress(dst);
+                }
+                return linked;
+            }
+        }
+
+        if self.copy_config.dry_run {
+            let r = match self.compare(src, dst) {
+                Ok(status) => Record::new(
+                    Some(src_shown),
+                    shown,
+                    Status::planned(status, self.copy_config.changed_only),
+                ),
+                Err(e) => Record {
+                    error: Some(e.to_string()),
+                    ..Record::new(Some(src_shown), shown, Status::Failed)
+                },
+            };
+            self.output.record(&r);
+
+            return true;
+        }
+
+        if self.copy_config.changed_only {
+            if let Ok(FileStatus::Identical) = self.compare(src, dst) {
+                // the mode or owner may be new in the config
+                let r = match self.apply_attributes(original, dst) {
+                    Ok(()) => Record::new(Some(src_shown), shown, Status::Skipped),
+                    Err(e) => Record {
+                        error: Some(e.to_string()),
+                        ..Record::new(Some(src_shown), shown, Status::Failed)
+                    },
+                };
+                let skipped = r.status == Status::Skipped;
+                self.output.record(&r);
+                return skipped;
+            }
+        }
+
+        if let Some(ask) = &self.ask {
+            if let Ok(FileStatus::Differs) = self.compare(src, dst) {
+                let local =
+                    self.is_local() && !self.has_source_bucket() && http::url(src).is_none();
+                let choice = ask.resolve(shown, Some((dst, src)).filter(|_| local));
+                if choice == conflict::Choice::Skip {
+                    self.output
+                        .record(&Record::new(Some(src_shown), shown, Status::Skipped));
+                    return true;
+                }
+            }
+        }
+
+        // hash of the source, to find its content at the destination
+        let hash = match self.copy_config.dedup {
+            Some(_) if http::url(src).is_none() => match self.hashes.hash(src) {
+                Ok(x) => Some(x),
+                Err(e) => {
+                    self.output.record(&Record {
+                        error: Some(e.to_string()),
+                        ..Record::new(Some(src_shown), dst, Status::Failed)
+                    });
+                    return false;
+                }
+            },
+            _ => None,
+        };
+        if let Some(hash) = &hash {
+            let len = |x: &Path| std::fs::metadata(x).ok().map(|x| x.len());
+            let same = dst.is_file()
+                && len(src) == len(dst)
+                && self.hashes.hash(dst).ok().as_ref() == Some(hash);
+            if same {
+                log::debug!("Skip {}, same content", dst.display());
+                if let Some(index) = &self.index {
+                    index.insert(hash.clone(), dst);
+                }
+                self.output
+                    .record(&Record::new(Some(src_shown), shown, Status::Skipped));
+                return true;
+            }
+        }
+
+        if self.is_local() {
+            if let Err(e) = self.prepare_write(dst) {
+                self.output.record(&Record {
+                    error: Some(e.to_string()),
+                    ..Record::new(Some(src_shown), dst, Status::Failed)
+                });
+                return false;
+            }
+        }
+
+        if let (Some(index), Some(hash)) = (&self.index, &hash) {
+            let linked = index
+                .find(hash, dst, &self.hashes)
+                .map(|existing| dedup::link(&existing, dst).map(|_| existing));
+            let unshared = match linked {
+                Some(Ok(existing)) => {
+                    self.output
+                        .record(&Record::new(Some(&existing), dst, Status::Linked));
+                    return true;
+                }
+                Some(Err(e)) => Err(e),
+                None => dedup::unshare(dst),
+            };
+            if let Err(e) = unshared {
+                self.output.record(&Record {
+                    error: Some(e.to_string()),
+                    ..Record::new(Some(src_shown), dst, Status::Failed)
+                });
+                return false;
+            }
+        }
+
+        let start = Instant::now();
+        let mut prof = FileProfile::new(src);
+        prof.progress = self
+            .copy_config
+            .events
+            .as_ref()
+            .map(|x| x.file_start(src_shown, shown));
+        let on_change = self
+            .copy_config
+            .on_source_change
+            .filter(|_| !self.has_source_bucket() && http::url(original).is_none());
+        // a transformed copy has another hash than its source
+        let pinned = self
+            .file_info(dst)
+            .and_then(|x| x.hash)
+            .filter(|_| transformed.is_none());
+        let mut attempts = 0;
+        let (result, changed) = loop {
+            attempts += 1;
+            let before = on_change.and_then(|_| Stamp::of(original));
+            let result = match http::url(src) {
+                Some(url) => {
+                    let changed_only = self.copy_config.changed_only;
+                    self.copier.fetch(url, dst, changed_only, &mut prof)
+                }
+                #[cfg(feature = "remote")]
+                None if !self.is_local() || self.has_source_bucket() => {
+                    self.transfer(src, dst, &stale, &mut prof).map(Some)
+                }
+                None => self
+                    .copier
+                    .copy_pinned(src, dst, pinned.as_ref(), &mut prof)
+                    .map(Some),
+            };
+            let result = result.map_err(timeout::explain);
+
+            let changed = before.is_some() && result.is_ok() && Stamp::of(original) != before;
+            match &result {
+                Ok(_) if changed && on_change == Some(OnChange::Retry) => {
+                    if attempts > self.copy_config.retries.max(1) {
+                        break (result, true);
+                    }
+                    let delay = retry::backoff(attempts);
+                    log::warn!(
+                        "{} changed while it was copied, copying it again in {:.1}s",
+                        original.display(),
+                        delay.as_secs_f64()
+                    );
+                    std::thread::sleep(delay);
+                }
+                Err(e)
+                    if attempts <= self.copy_config.retries
+                        && retry::transient(e)
+                        && !timeout::expired() =>
+                {
+                    let delay = retry::backoff(attempts);
+                    log::warn!(
+                        "Copy {} failed, {}, retrying in {:.1}s",
+                        shown.display(),
+                        e,
+                        delay.as_secs_f64()
+                    );
+                    std::thread::sleep(delay);
+                }
+                _ => break (result, changed),
+            }
+        };
+        let result = match result {
+            Ok(Some(bytes)) => self.apply_attributes(original, dst).map(|_| Some(bytes)),
+            result => result,
+        };
+        let post = self.file_info(dst).and_then(|x| x.post);
+        let result = match (result, post) {
+            (Ok(Some(bytes)), Some(cmd)) => {
+                let env = self.file_env.lock().unwrap().clone();
+                hooks::run_on(&cmd, dst, &env)
+                    .map(|_| Some(bytes))
+                    .map_err(|e| {
+                        std::io::Error::other(format!("post command `{}` failed, {}", cmd, e))
+                    })
+            }
+            (result, _) => result,
+        };
+        prof.total = start.elapsed();
+        let seconds = prof.total.as_secs_f64();
+        *self.fsync.lock().unwrap() += prof.get(Phase::Fsync);
+        prof.progress = None;
+        if let Some(profiler) = &self.profiler {
+            profiler.push(prof);
+        }
+
+        let mut r = Record::new(Some(src_shown), shown, Status::Copied);
+        r.attempts = Some(attempts).filter(|x| *x > 1);
+        r.seconds = Some(seconds);
+        match &result {
+            Ok(Some(bytes)) => {
+                r.bytes = Some(*bytes);
+                if changed {
+                    r.status = Status::Changed;
+                }
+                if let (Some(index), Some(hash)) = (&self.index, hash) {
+                    index.insert(hash, dst);
+                }
+            }
+            Ok(None) => r.status = Status::Skipped,
+            Err(e) => {
+                r.status = Status::Failed;
+                r.error = Some(e.to_string());
+            }
+        }
+        self.output.record(&r);
+
+        if result.is_err() && self.breaker.record_failure(root) {
+            log::warn!(
+                "Error budget for {} exhausted, skipping its remaining files",
+                root.display()
+            );
+        }
+        if result.is_ok() {
+            self.progress(dst);
+        }
+
+        result.is_ok()
+    }
+
+    /// What copying `src` would do to `dst`, on the remote side if any.
+    /// Downloads are only known to be unchanged once the server says so.
+    fn compare(&self, src: &Path, dst: &Path) -> std::io::Result<FileStatus> {
+        if http::url(src).is_some() {
+            return match dst.exists() {
+                true => Ok(FileStatus::Differs),
+                false => Ok(FileStatus::New),
+            };
+        }
+        #[cfg(feature = "remote")]
+        if !self.is_local() || self.has_source_bucket() {
+            return self.compare_remote(src, dst);
+        }
+        compare::compare_with(src, dst, self.strategy, &self.hashes)
+    }
+
+    /// `compare` of a remote destination or a bucket source.
+    #[cfg(feature = "remote")]
+    fn compare_remote(&self, src: &Path, dst: &Path) -> std::io::Result<FileStatus> {
+        match (&self.source_bucket, &self.bucket, &self.remote) {
+            (Some(bucket), ..) => bucket.status_get(src, dst, self.strategy, &self.hashes),
+            (_, Some(bucket), _) => bucket.status_put(src, dst, self.strategy, &self.hashes),
+            _ if self.webdav.is_some() => {
+                let status = self.webdav.as_ref().unwrap().status(src, dst)?;
+                match (self.strategy, status) {
+                    (Some(Strategy::Always), FileStatus::Identical) => Ok(FileStatus::Differs),
+                    _ => Ok(status),
+                }
+            }
+            (.., Some(remote)) => remote.status(src, dst, self.strategy, &self.hashes),
+            _ => unreachable!("no remote side"),
+        }
+    }
+
+    /// Upload `src` to the remote destination `dst`, or download the
+    /// object `src` of the source bucket.
+    #[cfg(feature = "remote")]
+    fn transfer(
+        &self,
+        src: &Path,
+        dst: &Path,
+        stale: &[PathBuf],
+        prof: &mut FileProfile,
+    ) -> std::io::Result<u64> {
+        match (
+            &self.source_bucket,
+            &self.bucket,
+            &self.webdav,
+            &self.remote,
+        ) {
+            (Some(bucket), ..) => self.copier.get(bucket, src, dst, prof),
+            (_, Some(bucket), ..) => self.copier.put(src, bucket, dst, &self.hashes, prof),
+            (_, _, Some(dav), _) => self.copier.upload_webdav(src, dav, dst, stale, prof),
+            (.., Some(remote)) => self.copier.upload(src, remote, dst, stale, prof),
+            _ => unreachable!("no remote side"),
+        }
+    }
+
+    /// Files with a `url` are downloaded into a local destination only.
+    fn check_urls(&self, from: &PathInfo) {
+        let c = &self.copy_config;
+        let mut unsupported = vec![];
+        if !self.is_local() {
+            unsupported.push("remote destinations");
+        }
+        if self.has_source_bucket() {
+            unsupported.push("bucket sources");
+        }
+        if c.link {
+            unsupported.push("--link");
+        }
+        if self.dst_info().is_some_and(|x| x.encryption.is_some()) {
+            unsupported.push("encrypted destinations");
+        }
+        #[cfg(feature = "archive")]
+        if c.archive.is_some() || archive::Format::detect(&from.path).is_some() {
+            unsupported.push("archives");
+        }
+        #[cfg(not(feature = "archive"))]
+        let _ = from;
+
+        if !unsupported.is_empty() {
+            log::error!("Files with a url do not support {}", unsupported.join(", "));
+            std::process::exit(1);
+        }
+    }
+
+    /// Whether the destination is a local directory.
+    fn is_local(&self) -> bool {
+        #[cfg(feature = "remote")]
+        {
+            self.remote.is_none() && self.bucket.is_none() && self.webdav.is_none()
+        }
+        #[cfg(not(feature = "remote"))]
+        {
+            true
+        }
+    }
+
+    /// Whether the source is a bucket, its objects are downloaded.
+    fn has_source_bucket(&self) -> bool {
+        #[cfg(feature = "remote")]
+        {
+            self.source_bucket.is_some()
+        }
+        #[cfg(not(feature = "remote"))]
+        {
+            false
+        }
+    }
+
+    /// Whether the files go to `to` in a single rsync run.
+    fn via_rsync(&self, to: &PathInfo) -> bool {
+        #[cfg(feature = "remote")]
+        {
+            self.copy_config.via.or(to.transport) == Some(Transport::Rsync)
+        }
+        #[cfg(not(feature = "remote"))]
+        {
+            let _ = to;
+            false
+        }
+    }
+
+    /// Root the source files are below, the key prefix of a bucket.
+    fn src_root<'a>(&'a self, from: &'a PathInfo) -> &'a Path {
+        #[cfg(feature = "remote")]
+        if let Some(bucket) = &self.source_bucket {
+            return bucket.prefix();
+        }
+        from.path.as_path()
+    }
+
+    /// Root the destination files are below, on the remote side if any.
+    fn dst_root<'a>(&'a self, to: &'a PathInfo) -> &'a Path {
+        #[cfg(feature = "remote")]
+        match (&self.remote, &self.bucket, &self.webdav) {
+            (Some(remote), ..) => return remote.path(),
+            (_, Some(bucket), _) => return bucket.prefix(),
+            (.., Some(dav)) => return dav.path(),
+            _ => {}
+        }
+        to.path.as_path()
+    }
+
+    /// The source file `src` as shown in logs and output.
+    fn src_shown(&self, src: &Path) -> PathBuf {
+        #[cfg(feature = "remote")]
+        if let Some(bucket) = &self.source_bucket {
+            return bucket.display(src);
+        }
+        src.to_path_buf()
+    }
+
+    /// The destination file `dst` as shown in logs and output.
+    fn dst_shown(&self, dst: &Path) -> PathBuf {
+        #[cfg(feature = "remote")]
+        match (&self.remote, &self.bucket, &self.webdav) {
+            (Some(remote), ..) => return remote.display(dst),
+            (_, Some(bucket), _) => return bucket.display(dst),
+            (.., Some(dav)) => return dav.display(dst),
+            _ => {}
+        }
+        dst.to_path_buf()
+    }
+
+    /// Remote destinations take uploads only and bucket sources downloads
+    /// only, refuse what needs local access.
+    #[cfg(feature = "remote")]
+    fn check_remote(&self, from: &PathInfo) {
+        let c = &self.copy_config;
+        let mut unsupported = vec![];
+        if self.has_source_bucket() {
+            if !self.is_local() {
+                unsupported.push("remote destinations");
+            }
+            if c.snapshot.is_some() {
+                unsupported.push("--snapshot");
+            }
+            if c.link || c.hardlink {
+                unsupported.push("--link and --hardlink");
+            }
+            if from.encryption.is_some() {
+                unsupported.push("`encryption`");
+            }
+            #[cfg(feature = "archive")]
+            if c.archive.is_some() || archive::Format::detect(&from.path).is_some() {
+                unsupported.push("archives");
+            }
+            if !unsupported.is_empty() {
+                log::error!("Bucket sources do not support {}", unsupported.join(", "));
+                std::process::exit(1);
+            }
+            return;
+        }
+
+        if c.delete {
+            unsupported.push("--delete");
+        }
+        if c.prune_empty_dirs {
+            unsupported.push("--prune-empty-dirs");
+        }
+        if c.link || c.hardlink {
+            unsupported.push("--link and --hardlink");
+        }
+        #[cfg(feature = "archive")]
+        if c.archive.is_some() || archive::Format::detect(&from.path).is_some() {
+            unsupported.push("archives");
+        }
+        if Remote::parse(&from.path, None).is_some() || Dav::parse(&from.path, None).is_some() {
+            unsupported.push("remote sources");
+        }
+
+        if !unsupported.is_empty() {
+            log::error!(
+                "Remote destinations do not support {}",
+                unsupported.join(", ")
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CopyConfig {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Every `from:to` of the run, the first one also in `from` and `to`.
+    pub specs: Vec<(String, String)>,
+    pub dry_run: bool,
+    pub changed_only: bool,
+    pub create_dir: bool,
+    pub dir_mode: Option<u32>,
+    pub jobs: usize,
+    /// Order the files are copied in.
+    pub order: Order,
+    /// What to do with destinations differing only by case.
+    pub case_collision: CaseCollision,
+    /// Copy every file of the source, `cpx mirror`.
+    pub mirror: bool,
+    /// Copy with lowered CPU and IO priority.
+    pub low_priority: bool,
+    pub prefetch: usize,
+    pub error_budget: Option<usize>,
+    /// Stop at the first failed file instead of copying the rest.
+    pub fail_fast: bool,
+    /// Time a file may take to copy, --timeout.
+    pub timeout: Option<Duration>,
+    /// When the run stops, from its --run-timeout.
+    pub deadline: Option<Instant>,
+    /// Times a copy failing with a transient error is tried again.
+    pub retries: u32,
+    /// Check the sources did not change while copied.
+    pub on_source_change: Option<OnChange>,
+    /// Keep destination files before overwriting or deleting them.
+    pub backup: Option<Backup>,
+    pub sample: Option<f64>,
+    pub seed: u64,
+    pub filter: Filter,
+    pub engine: Engine,
+    #[cfg(feature = "remote")]
+    pub via: Option<Transport>,
+    pub compare: Option<Strategy>,
+    /// Keep the hashes of compared files for the next run.
+    pub hash_cache: bool,
+    /// Hash of local files, over the `checksum` of the config.
+    pub checksum: Option<Algorithm>,
+    pub dedup: Option<Dedup>,
+    pub eol: Option<Eol>,
+    /// Mode of every copied file without a `mode` in the config.
+    pub chmod: Option<Mode>,
+    /// `user:group` of every copied file, over the destination's `owner`.
+    pub chown: Option<String>,
+    /// Copy extended attributes, ACLs and resource forks along.
+    pub xattrs: bool,
+    /// Copy even if the destination is the source or inside it.
+    pub allow_overlap: bool,
+    /// Ask before overwriting a destination file that differs.
+    pub ask: bool,
+    /// Color the status of the lines on stdout.
+    pub color: bool,
+    /// JSON lines of the progress of the run, --progress-events.
+    pub events: Option<std::sync::Arc<events::Events>>,
+    /// Patterns of --filter-from, taking precedence over `exclude`.
+    pub exclude: Vec<String>,
+    pub exclude_gitignore: bool,
+    /// Copy only files git sees changed since this revision.
+    pub since: Option<String>,
+    /// Copy only files changed since the last successful run of the job.
+    pub since_last_run: bool,
+    /// Lock the destination against other runs, and wait for them.
+    pub lock: bool,
+    pub wait: bool,
+    pub bwlimit: Option<u64>,
+    /// Size of the ranges huge files are copied in concurrently.
+    pub chunk_size: Option<u64>,
+    pub atomic: bool,
+    pub sync: bool,
+    #[cfg(feature = "archive")]
+    pub archive: Option<archive::Format>,
+    pub reverse: bool,
+    pub snapshot: Option<snapshot::Kind>,
+    /// Copy into a new snapshot directory of the destination.
+    pub rotate: bool,
+    /// Leave out selected sources that do not exist instead of failing.
+    pub ignore_missing: bool,
+    /// Only warn when the destination has no room for the copy.
+    pub no_space_check: bool,
+    /// Show a desktop notification when the run is done.
+    pub notify: bool,
+    /// URL the summary of the run is posted to.
+    pub webhook: Option<String>,
+    pub notify_on: When,
+    /// Copy every file into the destination root, with what to do when
+    /// names collide.
+    pub flatten: Option<Collision>,
+    pub delete: bool,
+    pub link: bool,
+    /// Hard link destination files to their sources where possible.
+    pub hardlink: bool,
+    pub prune_empty_dirs: bool,
+    pub journal: bool,
+    pub normalize: Option<NormalizePolicy>,
+    pub summary_diff: bool,
+    pub profile: Option<(PathBuf, ProfileFormat)>,
+    pub stats: bool,
+    pub stats_json: Option<PathBuf>,
+    /// Markdown or HTML report of the run written to it.
+    pub report: Option<PathBuf>,
+    /// Run the `hooks` of the config and the tags.
+    pub hooks: bool,
+    /// Variables of the commands of the run over the `env` of the config
+    /// and the tags, --env.
+    pub env: Vec<(String, String)>,
+    pub output: OutputMode,
+    pub format: Option<String>,
+    pub job: String,
+    pub run_id: String,
+    /// The `{date}` and others in the paths of the run.
+    pub vars: spec::Vars,
+    /// Sources known to be copied already, left out of the selection.
+    pub skip_sources: HashSet<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathInfo {
+    /// None for a `collect` or `merge` path.
+    #[serde(default)]
+    path: PathBuf,
+    #[serde(default)]
+    normalize: Option<NormalizePolicy>,
+    /// Connection settings when `path` is `[user@]host:/dir`.
+    #[cfg(feature = "remote")]
+    #[serde(default)]
+    ssh: Option<remote::SshOptions>,
+    /// Endpoint and upload settings when `path` is `s3://bucket/prefix`.
+    #[cfg(feature = "remote")]
+    #[serde(default)]
+    s3: Option<s3::S3Options>,
+    /// Credentials when `path` is `davs://host/path` or `dav://`.
+    #[cfg(feature = "remote")]
+    #[serde(default)]
+    webdav: Option<webdav::WebDavOptions>,
+    /// Transport used when this path is the destination.
+    #[cfg(feature = "remote")]
+    #[serde(default)]
+    transport: Option<Transport>,
+    /// How files are found identical when this path is the destination.
+    #[serde(default)]
+    compare: Option<Strategy>,
+    /// `user:group` of the files copied to this path.
+    #[serde(default)]
+    owner: Option<String>,
+    /// Mode of the files copied to this path without one of their own.
+    #[serde(default)]
+    file_mode: Option<Mode>,
+    /// Mode of the directories created in this path, --dir-mode
+    /// overrides it.
+    #[serde(default)]
+    dir_mode: Option<Mode>,
+    /// Bits cleared from the modes of everything copied to this path.
+    #[serde(default)]
+    umask: Option<Mode>,
+    /// Files are encrypted when copied to this path and decrypted when
+    /// copied from it.
+    #[serde(default)]
+    encryption: Option<crypt::Encryption>,
+    /// Paths whose files are all copied into subdirectories named after
+    /// them when this path is the source.
+    #[serde(default)]
+    collect: Vec<String>,
+    /// Paths whose files are copied as one when this path is the source,
+    /// those of the later paths replace those of the earlier ones.
+    #[serde(default)]
+    merge: Vec<String>,
+    /// Snapshots --rotate keeps of this path as the destination, all
+    /// without it.
+    #[serde(default)]
+    keep: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TagInfo {
+    file_list: Option<Vec<String>>,
+    /// Tags selected with this one, saved by `cpx tag save`.
+    #[serde(default)]
+    tag_list: Option<Vec<String>>,
+    /// Groups from `group_list` whose files belong to this tag too.
+    files_from_group: Option<Vec<String>>,
+    /// Shell command printing more relative paths of this tag, one per line.
+    #[serde(default)]
+    files_from_cmd: Option<String>,
+    script_list: Option<Vec<String>>,
+    /// Commands run around copies of this tag, after those of the config.
+    #[serde(default)]
+    hooks: Hooks,
+    /// Mode of the files of this tag without one of their own.
+    #[serde(default)]
+    mode: Option<Mode>,
+    /// Filter of the files of this tag without one of their own.
+    #[serde(default)]
+    filter: Option<String>,
+    /// Compression of the files of this tag without one of their own.
+    #[serde(default)]
+    compress: Option<Compression>,
+    /// Expand the compressed sources of this tag without a `decompress`
+    /// of their own.
+    #[serde(default)]
+    decompress: Option<Decompress>,
+    /// Path the files of this tag are copied from, over that of the spec.
+    #[serde(default)]
+    from: Option<String>,
+    /// Path the files of this tag are copied to, over that of the spec.
+    #[serde(default)]
+    to: Option<String>,
+    /// Variables of the commands run for this tag, over those of the
+    /// config.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileInfo {
+    relative_path: PathBuf,
+    /// Downloaded from this HTTP(S) URL instead of copied from the source.
+    #[serde(default)]
+    url: Option<String>,
+    /// Shell command run on the destination file each time it is written.
+    #[serde(default)]
+    post: Option<String>,
+    /// Replace `{{NAME}}` in the file with the value of NAME from the
+    /// environment or `vars`.
+    #[serde(default)]
+    template: bool,
+    /// Line endings of the copy, over --eol.
+    #[serde(default)]
+    eol: Option<Eol>,
+    /// Permission bits of the copy, over those of the source and --chmod.
+    #[serde(default)]
+    mode: Option<Mode>,
+    /// Name of the `filters` command the content is piped through.
+    #[serde(default)]
+    filter: Option<String>,
+    /// Compression of the copy, which gets the extension of the format.
+    #[serde(default)]
+    compress: Option<Compression>,
+    /// Expand the source if compressed, the copy loses the extension.
+    #[serde(default)]
+    decompress: Option<Decompress>,
+    /// Hash the source must have to be copied, `sha256:<hex>`.
+    #[serde(default)]
+    hash: Option<Expected>,
+}
+
+impl FileInfo {
+    /// A file at `relative_path` with nothing else set.
+    fn at(relative_path: PathBuf) -> Self {
+        Self {
+            relative_path,
+            url: None,
+            post: None,
+            template: false,
+            eol: None,
+            mode: None,
+            filter: None,
+            compress: None,
+            decompress: None,
+            hash: None,
+        }
+    }
+
+    /// Whether the content of the copy is not that of the source.
+    fn transformed(&self) -> bool {
+        self.template
+            || self.eol.is_some()
+            || self.filter.is_some()
+            || self.compress.is_some()
+            || self.decompress.is_some()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScriptInfo {
+    from: PathBuf,
+    to: PathBuf,
+    /// Scripts copied before this one, and with it.
+    #[serde(default)]
+    depends_on: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigInfo {
+    /// Version of the schema, configs of older ones are migrated as read.
+    #[serde(default)]
+    version: Option<u64>,
+    path_list: HashMap<String, PathInfo>,
+    #[serde(default)]
+    tag_list: HashMap<String, TagInfo>,
+    #[serde(default)]
+    file_list: HashMap<String, FileInfo>,
+    #[serde(default)]
+    script_list: HashMap<String, ScriptInfo>,
+    /// Named lists of file keys, shared by several tags.
+    #[serde(default)]
+    group_list: HashMap<String, Vec<String>>,
+    /// gitignore style patterns of files never to copy, see `.cpxignore`.
+    #[serde(default)]
+    exclude: Vec<String>,
+    /// gitignore style patterns of source files that belong in
+    /// `file_list`, `cpx scan` lists those missing from it.
+    #[serde(default)]
+    discover: Vec<String>,
+    /// Commands run before and after every copy.
+    #[serde(default)]
+    hooks: Hooks,
+    /// Values of the placeholders in `template` files.
+    #[serde(default)]
+    vars: HashMap<String, String>,
+    /// Variables in the environment of the hooks, filters and post
+    /// commands.
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    /// Commands by name, turning the content of a file on stdin into that
+    /// of its copy on stdout.
+    #[serde(default)]
+    filters: HashMap<String, String>,
+    /// Hash of local files, BLAKE3 by default.
+    #[serde(default)]
+    checksum: Option<Algorithm>,
+    #[serde(default)]
+    retention: gc::Retention,
+    /// Copies `cpx schedule run` starts periodically.
+    #[serde(default)]
+    schedule: Vec<schedule::Entry>,
+    /// Copies `cpx run` starts by name.
+    #[serde(default)]
+    profiles: HashMap<String, preset::Preset>,
+    /// Release endpoint and keys of `cpx self-update`.
+    #[cfg(feature = "self-update")]
+    #[serde(default)]
+    update: Option<update::UpdateConfig>,
+}
+
+impl ConfigInfo {
+    #[cfg(any(feature = "tui", feature = "completions"))]
+    fn keys(&self, kind: &str) -> Vec<&String> {
+        let mut keys: Vec<_> = match kind {
+            "tags" => self.tag_list.keys().collect(),
+            "files" => self.file_list.keys().collect(),
+            "profiles" => self.profiles.keys().collect(),
+            _ => self.path_list.keys().collect(),
+        };
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Keys of the files selected by the tag expression `text`, sorted.
+    fn select(&self, text: &str) -> Result<Vec<String>, String> {
+        let tag_files = |name: &str| {
+            self.tag_list.get(name)?;
+            let mut keys = HashSet::new();
+            for tag in self
+                .expand_tags(&[name])
+                .iter()
+                .filter_map(|x| self.tag_list.get(x))
+            {
+                let groups = tag.files_from_group.iter().flatten().flat_map(|g| {
+                    self.group_list
+                        .get(g)
+                        .unwrap_or_else(|| panic!("group {} not found in config", g))
+                });
+                keys.extend(tag.file_list.iter().flatten().chain(groups).cloned());
+            }
+            Some(keys)
+        };
+        let all = self.file_list.keys().cloned().collect();
+
+        let mut keys: Vec<_> = select::Expr::parse(text)?
+            .eval(&tag_files, &all)?
+            .into_iter()
+            .collect();
+        keys.sort_unstable();
+        Ok(keys)
+    }
+
+    /// `tags` with the tags they select too, each once and after the one
+    /// that selects it.
+    fn expand_tags<T: AsRef<str>>(&self, tags: &[T]) -> Vec<String> {
+        let mut expanded: Vec<String> = vec![];
+        let mut stack: Vec<String> = tags.iter().rev().map(|x| x.as_ref().to_owned()).collect();
+        while let Some(t) = stack.pop() {
+            if expanded.contains(&t) {
+                continue;
+            }
+            if let Some(tag) = self.tag_list.get(&t) {
+                stack.extend(tag.tag_list.iter().flatten().rev().cloned());
+            }
+            expanded.push(t);
+        }
+        expanded
+    }
+
+    /// Every tag, file and group of the selection missing from the
+    /// config, each with the closest key as a suggestion.
+    fn unknown_names<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> Vec<String> {
+        let mut unknown = vec![];
+        let file = |key: &str, of: String| {
+            let hint = || suggest::hint(key, self.file_list.keys());
+            (!self.file_list.contains_key(key))
+                .then(|| format!("No file {}{} in config{}", key, of, hint()))
+        };
+        let tag = |t: &str, of: String| {
+            let hint = || suggest::hint(t, self.tag_list.keys());
+            (!self.tag_list.contains_key(t))
+                .then(|| format!("No tag {}{} in config{}", t, of, hint()))
+        };
+        for t in tags.iter().flatten() {
+            unknown.extend(tag(t.as_ref(), String::new()));
+        }
+        let expanded = tags.as_ref().map(|x| self.expand_tags(x));
+        for t in expanded.iter().flatten() {
+            let tag_info = match self.tag_list.get(t) {
+                Some(x) => x,
+                None => continue,
+            };
+            for x in tag_info.tag_list.iter().flatten() {
+                unknown.extend(tag(x, format!(" of tag {}", t)));
+            }
+            let tag = tag_info;
+            for key in tag.file_list.iter().flatten() {
+                unknown.extend(file(key, format!(" of tag {}", t)));
+            }
+            for g in tag.files_from_group.iter().flatten() {
+                match self.group_list.get(g) {
+                    Some(keys) => {
+                        let of = format!(" of group {}", g);
+                        unknown.extend(keys.iter().filter_map(|key| file(key, of.clone())));
+                    }
+                    None => {
+                        let hint = suggest::hint(g, self.group_list.keys());
+                        unknown.push(format!("No group {} of tag {} in config{}", g, t, hint));
+                    }
+                }
+            }
+        }
+        for key in files.iter().flatten() {
+            unknown.extend(file(key.as_ref(), String::new()));
+        }
+
+        unknown.dedup();
+        unknown
+    }
+
+    /// Exit listing the names of the selection the config does not have.
+    fn check_selection<T: AsRef<str>>(&self, tags: &Option<Vec<T>>, files: &Option<Vec<T>>) {
+        let unknown = self.unknown_names(tags, files);
+        for x in &unknown {
+            log::error!("{}", x);
+        }
+        if !unknown.is_empty() {
+            std::process::exit(1);
+        }
+    }
+
+    /// The scripts of `tags`, those of their `script_list` and that of
+    /// their name, with what they depend on, each after its dependencies.
+    fn calculate_script_list<T: AsRef<str>>(&self, tags: &Option<Vec<T>>) -> Vec<ScriptInfo> {
+        let mut names = vec![];
+        for t in tags.iter().flatten().map(|x| x.as_ref()) {
+            let listed = self.tag_list.get(t).and_then(|x| x.script_list.as_ref());
+            names.extend(listed.into_iter().flatten().map(String::as_str));
+            if self.script_list.contains_key(t) {
+                names.push(t);
+            }
+        }
+
+        // checked when the config was loaded
+        let order = self
+            .script_order(names)
+            .expect("invalid script dependencies");
+        order
+            .into_iter()
+            .filter_map(|x| self.script_list.get(x).cloned())
+            .collect()
+    }
+
+    /// The scripts `names` and their dependencies, each after those it
+    /// depends on. Unknown names are left out, a dependency on an unknown
+    /// script or a cycle is an error.
+    fn script_order<'a>(
+        &'a self,
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<&'a str>, String> {
+        fn visit<'a>(
+            config: &'a ConfigInfo,
+            name: &'a str,
+            path: &mut Vec<&'a str>,
+            order: &mut Vec<&'a str>,
+        ) -> Result<(), String> {
+            if order.contains(&name) {
+                return Ok(());
+            }
+            if let Some(i) = path.iter().position(|x| *x == name) {
+                let mut cycle = path[i..].to_vec();
+                cycle.push(name);
+                return Err(format!(
+                    "scripts {} depend on each other",
+                    cycle.join(" -> ")
+                ));
+            }
+            let script = match config.script_list.get(name) {
+                Some(x) => x,
+                None => return Ok(()),
+            };
+            path.push(name);
+            for x in &script.depends_on {
+                if !config.script_list.contains_key(x) {
+                    let hint = suggest::hint(x, config.script_list.keys());
+                    return Err(format!("no script {} of script {}{}", x, name, hint));
+                }
+                visit(config, x, path, order)?;
+            }
+            path.pop();
+            order.push(name);
+            Ok(())
+        }
+
+        let mut order = vec![];
+        for name in names {
+            visit(self, name, &mut vec![], &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn calculate_file_list<T: AsRef<str>>(
+        &self,
+        tags: &Option<Vec<T>>,
+        files: &Option<Vec<T>>,
+    ) -> HashSet<FileInfo> {
+        let mut selected_files: Vec<_> = vec![];
+        let mut generated = vec![];
+        // the mode and filter of the first selected tag with one
+        let mut tag_modes = HashMap::new();
+        let mut tag_filters = HashMap::new();
+        let mut tag_compress = HashMap::new();
+        let mut tag_decompress = HashMap::new();
+        if let Some(x) = tags {
+            for t in x {
+                let tag = match self.tag_list.get(t.as_ref()) {
+                    Some(x) => x,
+                    None => continue,
+                };
+
+                let start = selected_files.len();
+                if let Some(mut item) = tag.file_list.clone() {
+                    selected_files.append(&mut item);
+                }
+
+                for g in tag.files_from_group.iter().flatten() {
+                    let mut group = self
+                        .group_list
+                        .get(g)
+                        .unwrap_or_else(|| panic!("group {} not found in config", g))
+                        .clone();
+                    selected_files.append(&mut group);
+                }
+
+                let files_start = generated.len();
+                if let Some(cmd) = &tag.files_from_cmd {
+                    generated.extend(files_from_cmd(cmd));
+                }
+
+                if let Some(mode) = tag.mode {
+                    for key in &selected_files[start..] {
+                        tag_modes.entry(key.clone()).or_insert(mode);
+                    }
+                    for f in &mut generated[files_start..] {
+                        f.mode = Some(mode);
+                    }
+                }
+                if let Some(filter) = &tag.filter {
+                    for key in &selected_files[start..] {
+                        tag_filters.entry(key.clone()).or_insert(filter);
+                    }
+                    for f in &mut generated[files_start..] {
+                        f.filter = Some(filter.clone());
+                    }
+                }
+                if let Some(compress) = tag.compress {
+                    for key in &selected_files[start..] {
+                        tag_compress.entry(key.clone()).or_insert(compress);
+                    }
+                    for f in &mut generated[files_start..] {
+                        f.compress = Some(compress);
+                    }
+                }
+                if let Some(decompress) = tag.decompress {
+                    for key in &selected_files[start..] {
+                        tag_decompress.entry(key.clone()).or_insert(decompress);
+                    }
+                    for f in &mut generated[files_start..] {
+                        f.decompress = Some(decompress);
+                    }
+                }
+            }
+        }
+
+        if let Some(x) = files {
+            for f in x {
+                selected_files.push(f.as_ref().to_string());
+            }
+        }
+
+        selected_files
+            .iter()
+            .map(|x| {
+                let mut f = self
+                    .file_list
+                    .get(x)
+                    .unwrap_or_else(|| panic!("file {} not found in config", x))
+                    .clone();
+                f.mode = f.mode.or_else(|| tag_modes.get(x).copied());
+                f.filter = f
+                    .filter
+                    .or_else(|| tag_filters.get(x).map(|x| x.to_string()));
+                f.compress = f.compress.or_else(|| tag_compress.get(x).copied());
+                f.decompress = f.decompress.or_else(|| tag_decompress.get(x).copied());
+                // a restore does not compress again what it expands
+                if f.decompress.is_some() {
+                    f.compress = None;
+                }
+                f
+            })
+            .chain(generated)
+            .collect()
+    }
+}
+
+/// Files printed by the shell command `cmd`, relative to the source.
+fn files_from_cmd(cmd: &str) -> Vec<FileInfo> {
+    let mut command = hooks::shell(cmd);
+    command.stderr(std::process::Stdio::inherit());
+
+    log::debug!("Run {:?}", command);
+    let out = match command.output() {
+        Ok(x) if x.status.success() => x.stdout,
+        Ok(x) => {
+            log::error!("Command `{}` for files failed, {}", cmd, x.status);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            log::error!("Command `{}` for files failed, {}", cmd, e);
+            std::process::exit(1);
+        }
+    };
+
+    String::from_utf8_lossy(&out)
+        .lines()
+        .filter_map(below_source)
+        .map(FileInfo::at)
+        .collect()
+}
+
+/// Paths below `root` that differ from `rev` of its git repository.
+fn git_changed(root: &Path, rev: &str) -> HashSet<PathBuf> {
+    let mut command = std::process::Command::new("git");
+    command.arg("-C").arg(root);
+    command.args(["diff", "--name-only", "--relative", "-z", rev, "--"]);
+
+    log::debug!("Run {:?}", command);
+    let out = match command.output() {
+        Ok(x) if x.status.success() => x.stdout,
+        Ok(x) => {
+            log::error!(
+                "git diff {} in {} failed, {}",
+                rev,
+                root.display(),
+                String::from_utf8_lossy(&x.stderr).trim()
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            log::error!("git diff {} in {} failed, {}", rev, root.display(), e);
+            std::process::exit(1);
+        }
+    };
+
+    out.split(|x| *x == 0)
+        .filter(|x| !x.is_empty())
+        .map(|x| PathBuf::from(String::from_utf8_lossy(x).into_owned()))
+        .collect()
+}
+
+/// The relative path `name`, or none with a warning if it is not below
+/// the source. The input decides what is read, it is kept inside.
+fn below_source(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name.trim());
+    let path = path.strip_prefix(".").unwrap_or(path);
+    if path.as_os_str().is_empty() {
+        return None;
+    }
+    if !path
+        .components()
+        .all(|x| matches!(x, std::path::Component::Normal(_)))
+    {
+        log::warn!(
+            "Skip {}, not a relative path below the source",
+            path.display()
+        );
+        return None;
+    }
+
+    Some(path.to_path_buf())
+}
+
+/// Run the interactive picker, preselecting what was given on the command line.
+#[cfg(feature = "tui")]
+fn pick_selection(
+    config: &ConfigInfo,
+    tags: &Option<Vec<String>>,
+    files: &Option<Vec<String>>,
+) -> Option<(Vec<String>, Vec<String>)> {
+    let given = |x: &Option<Vec<String>>, key: &str| x.iter().flatten().any(|x| x == key);
+    let items = config
+        .keys("tags")
+        .into_iter()
+        .map(|x| picker::Item {
+            kind: picker::Kind::Tag,
+            selected: given(tags, x),
+            name: x.clone(),
+        })
+        .chain(config.keys("files").into_iter().map(|x| picker::Item {
+            kind: picker::Kind::File,
+            selected: given(files, x),
+            name: x.clone(),
+        }))
+        .collect();
+
+    let picked = picker::pick(items).expect("Interactive picker failed!")?;
+    let names = |kind| {
+        picked
+            .iter()
+            .filter(|x| x.selected && x.kind == kind)
+            .map(|x| x.name.clone())
+            .collect()
+    };
+
+    Some((names(picker::Kind::Tag), names(picker::Kind::File)))
+}
+
+/// Whether `path` is in the `percent` sample for `seed`. Each path is
+/// decided on its own, so a path stays in or out as the selection grows.
+fn sampled(path: &Path, seed: u64, percent: f64) -> bool {
+    let hash = state::fnv1a(format!("{}|{}", seed, path.display()));
+    ((hash % 10_000) as f64) < percent * 100.0
+}
+
+/// Destination directories cpx manages, the parents of the files it copies.
+fn managed_dirs(keep: &HashSet<PathBuf>) -> Vec<&Path> {
+    let mut dirs: Vec<_> = keep.iter().filter_map(|x| x.parent()).collect();
+    dirs.sort_unstable();
+    dirs.dedup();
+    dirs
+}
+
+/// Boolean environment variable, set unless empty, `0` or `false`.
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|x| !matches!(x.to_lowercase().as_str(), "" | "0" | "false" | "no"))
+        .unwrap_or(false)
+}
+
+/// Whether `path` is on a host, in a container, a bucket or a WebDAV
+/// collection rather than local.
+fn is_remote(path: &Path) -> bool {
+    #[cfg(feature = "remote")]
+    {
+        Remote::parse(path, None).is_some()
+            || Bucket::parse(path, None).is_some()
+            || Dav::parse(path, None).is_some()
+    }
+    // by the look of it, to refuse instead of copying to a local `host:dir`
+    #[cfg(not(feature = "remote"))]
+    {
+        let host = path.to_str().and_then(|x| x.split_once(':')).map(|x| x.0);
+        http::url(path).is_none() && host.is_some_and(|x| x.len() >= 2 && !x.contains(['/', '\\']))
+    }
+}
+
+fn load_config(paths: &[String]) -> ConfigInfo {
+    let config: ConfigInfo = config::load(paths).unwrap_or_else(|e| {
+        log::error!("Invalid config, {}", e);
+        std::process::exit(1);
+    });
+
+    let mut keys: Vec<_> = config.file_list.keys().collect();
+    keys.sort_unstable();
+    for key in keys {
+        let f = &config.file_list[key];
+        if f.url.is_some() && f.hash.is_some() {
+            log::error!(
+                "Invalid config, file {} has a url and a hash, hashes pin copied sources",
+                key
+            );
+            std::process::exit(1);
+        }
+    }
+    let files = config.file_list.iter().map(|(k, v)| ("file", k, &v.filter));
+    let tags = config.tag_list.iter().map(|(k, v)| ("tag", k, &v.filter));
+    let mut unknown = false;
+    for (kind, key, filter) in files.chain(tags) {
+        if let Some(x) = filter.as_ref().filter(|x| !config.filters.contains_key(*x)) {
+            let hint = suggest::hint(x, config.filters.keys());
+            log::error!("No filter {} of {} {} in filters{}", x, kind, key, hint);
+            unknown = true;
+        }
+    }
+    if unknown {
+        std::process::exit(1);
+    }
+    let mut scripts: Vec<_> = config.script_list.keys().map(String::as_str).collect();
+    scripts.sort_unstable();
+    if let Err(e) = config.script_order(scripts) {
+        log::error!("Invalid config, {}", e);
+        std::process::exit(1);
+    }
+
+    config
+}
+
+/// Tags and file keys chosen by --tag, --file, --tag-file, --files-from,
+/// --select and --match.
+fn selection(
+    m: &clap::ArgMatches,
+    config: &mut ConfigInfo,
+) -> (Option<Vec<String>>, Option<Vec<String>>) {
+    let mut tags: Option<Vec<String>> = m.values_of("tags").map(|x| x.map(String::from).collect());
+    let mut files: Option<Vec<String>> =
+        m.values_of("files").map(|x| x.map(String::from).collect());
+    for path in m.values_of("tag-file").into_iter().flatten() {
+        let (t, f) = read_selection(path, config);
+        tags.get_or_insert_with(Vec::new).extend(t);
+        files.get_or_insert_with(Vec::new).extend(f);
+    }
+    if let Some(path) = m.value_of("files-from") {
+        let keys = read_files_from(path, config);
+        files.get_or_insert_with(Vec::new).extend(keys);
+    }
+    if let Some(text) = m.value_of("select") {
+        let keys = config.select(text).unwrap_or_else(|e| {
+            log::error!("Invalid selection, {}", e);
+            std::process::exit(1);
+        });
+        if keys.is_empty() {
+            log::warn!("No files match `{}`", text);
+        }
+        files.get_or_insert_with(Vec::new).extend(keys);
+    }
+    for pattern in m.values_of("match").into_iter().flatten() {
+        let re = regex::Regex::new(pattern).unwrap_or_else(|e| {
+            log::error!("Invalid pattern {}, {}", pattern, e);
+            std::process::exit(1);
+        });
+        let mut keys: Vec<_> = config
+            .file_list
+            .iter()
+            .filter(|(_, f)| re.is_match(&f.relative_path.to_string_lossy()))
+            .map(|(key, _)| key.clone())
+            .collect();
+        if keys.is_empty() {
+            log::warn!("No files match `{}`", pattern);
+        }
+        keys.sort_unstable();
+        files.get_or_insert_with(Vec::new).extend(keys);
+    }
+
+    (tags, files)
+}
+
+/// Options of a copy from the command line.
+fn copy_config(
+    m: &clap::ArgMatches,
+    config_paths: &[String],
+    tags: &Option<Vec<String>>,
+    files: &Option<Vec<String>>,
+    run_id: String,
+    watching: bool,
+) -> CopyConfig {
+    let mut cpx_config = CopyConfig {
+        from: None,
+        to: None,
+        specs: vec![],
+        dry_run: m.is_present("dry-run") || m.is_present("check") || env_flag("CPX_DRY_RUN"),
+        // a watch copies what changed, not everything on every save, a
+        // mirror what differs, and --check asks whether anything differs
+        changed_only: m.is_present("changed-only")
+            || watching
+            || m.is_present("mirror")
+            || m.is_present("check"),
+        mirror: m.is_present("mirror"),
+        create_dir: true,
+        engine: Engine::from_name(m.value_of("engine").unwrap()).unwrap(),
+        #[cfg(feature = "remote")]
+        via: m.value_of("via").map(|x| Transport::from_name(x).unwrap()),
+        hash_cache: !m.is_present("no-hash-cache"),
+        checksum: m
+            .value_of("checksum")
+            .map(|x| Algorithm::from_name(x).unwrap()),
+        dedup: m.value_of("dedup").map(|x| Dedup::from_name(x).unwrap()),
+        eol: m.value_of("eol").map(|x| Eol::from_name(x).unwrap()),
+        chmod: m
+            .value_of("chmod")
+            .map(|x| Mode::parse(x).expect("invalid mode")),
+        chown: m.value_of("chown").map(|x| x.to_owned()),
+        xattrs: m.is_present("xattrs"),
+        allow_overlap: m.is_present("allow-overlap"),
+        ask: m.is_present("ask"),
+        color: logger::use_color(m.value_of("color").unwrap_or("auto"), true),
+        events: m.value_of("progress-events").map(|x| {
+            let events = x
+                .parse()
+                .map_err(|_| format!("{} is not a file descriptor", x))
+                .and_then(|fd| events::Events::open(fd, &run_id).map_err(|e| e.to_string()));
+            std::sync::Arc::new(events.unwrap_or_else(|e| {
+                log::error!("Invalid --progress-events, {}", e);
+                std::process::exit(1);
+            }))
+        }),
+        exclude: m
+            .values_of("filter-from")
+            .into_iter()
+            .flatten()
+            .flat_map(|path| {
+                let rules = std::fs::read_to_string(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|x| ignore::rsync_rules(&x));
+                rules.unwrap_or_else(|e| {
+                    log::error!("Invalid filter file {}, {}", path, e);
+                    std::process::exit(1);
+                })
+            })
+            .collect(),
+        exclude_gitignore: m.is_present("exclude-gitignore"),
+        since_last_run: m.is_present("since-last-run"),
+        since: m.value_of("since").map(|x| x.to_owned()),
+        lock: !m.is_present("no-lock"),
+        wait: m.is_present("wait"),
+        compare: m
+            .value_of("compare")
+            .map(|x| Strategy::from_name(x).unwrap()),
+        atomic: !m.is_present("no-atomic"),
+        sync: m.is_present("sync"),
+        bwlimit: m
+            .value_of("bwlimit")
+            .map(|x| throttle::RateLimit::parse(x).expect("invalid bandwidth limit")),
+        chunk_size: m.value_of("chunk-size").map(|x| {
+            filter::parse_size(x)
+                .ok()
+                .filter(|x| *x > 0)
+                .expect("invalid chunk size")
+        }),
+        #[cfg(feature = "archive")]
+        archive: m
+            .value_of("archive")
+            .map(|x| archive::Format::from_name(x).unwrap()),
+        dir_mode: m
+            .value_of("dir-mode")
+            .map(|x| u32::from_str_radix(x, 8).expect("invalid dir mode")),
+        jobs: m
+            .value_of("jobs")
+            .unwrap()
+            .parse()
+            .expect("invalid number of jobs"),
+        low_priority: m.is_present("low-priority"),
+        case_collision: m
+            .value_of("on-case-collision")
+            .map(|x| CaseCollision::from_name(x).unwrap())
+            .unwrap_or_default(),
+        order: m
+            .value_of("order")
+            .map(|x| Order::from_name(x).unwrap())
+            .unwrap_or_default(),
+        prefetch: m.value_of("prefetch").map_or(0, |x| {
+            x.parse().expect("invalid number of files to prefetch")
+        }),
+        error_budget: m
+            .value_of("error-budget")
+            .map(|x| x.parse().expect("invalid error budget")),
+        fail_fast: m.is_present("fail-fast"),
+        retries: m
+            .value_of("retries")
+            .map_or(0, |x| x.parse().expect("invalid number of retries")),
+        timeout: m
+            .value_of("timeout")
+            .map(|x| Duration::from_secs_f64(x.parse().expect("invalid --timeout"))),
+        deadline: m.value_of("run-timeout").map(|x| {
+            Instant::now() + Duration::from_secs_f64(x.parse().expect("invalid --run-timeout"))
+        }),
+        on_source_change: m
+            .value_of("on-source-change")
+            .map(|x| OnChange::from_name(x).unwrap()),
+        backup: match m.value_of("backup") {
+            _ if m.is_present("trash") => Some(Backup::Trash),
+            Some(x) => Some(Backup::Suffix(x.to_owned())),
+            None if m.is_present("backup") => Some(Backup::Suffix(backup::SUFFIX.to_owned())),
+            None => None,
+        },
+        sample: m.value_of("sample").map(|x| {
+            x.trim_end_matches('%')
+                .parse()
+                .ok()
+                .filter(|x| (0.0..=100.0).contains(x))
+                .expect("invalid sample percentage")
+        }),
+        seed: m
+            .value_of("seed")
+            .unwrap()
+            .parse()
+            .expect("invalid sample seed"),
+        filter: Filter {
+            min_size: m
+                .value_of("min-size")
+                .map(|x| filter::parse_size(x).expect("invalid minimum size")),
+            max_size: m
+                .value_of("max-size")
+                .map(|x| filter::parse_size(x).expect("invalid maximum size")),
+            newer_than: m
+                .value_of("newer-than")
+                .map(|x| filter::parse_since(x).expect("invalid --newer-than")),
+            ext: m
+                .values_of("ext")
+                .into_iter()
+                .flatten()
+                .flat_map(|x| x.split(','))
+                .map(|x| x.trim_start_matches('.').to_lowercase())
+                .collect(),
+        },
+        reverse: m.is_present("reverse"),
+        snapshot: m
+            .value_of("snapshot")
+            .map(|x| snapshot::Kind::from_name(x).unwrap()),
+        rotate: m.is_present("rotate"),
+        // nor a watch, when a source is deleted
+        ignore_missing: m.is_present("ignore-missing") || watching,
+        no_space_check: m.is_present("no-space-check"),
+        notify: m.is_present("notify"),
+        webhook: m.value_of("webhook").map(String::from),
+        notify_on: When::from_name(m.value_of("notify-on").unwrap()).unwrap(),
+        flatten: m.is_present("flatten").then(|| {
+            m.value_of("on-collision")
+                .map(|x| Collision::from_name(x).unwrap())
+                .unwrap_or_default()
+        }),
+        delete: m.is_present("delete"),
+        link: m.is_present("link"),
+        hardlink: m.is_present("hardlink"),
+        prune_empty_dirs: m.is_present("prune-empty-dirs"),
+        journal: !m.is_present("no-journal"),
+        normalize: m
+            .value_of("normalize")
+            .map(|x| NormalizePolicy::parse(x).expect("invalid normalization policy")),
+        summary_diff: m.is_present("summary-diff"),
+        stats: m.is_present("stats"),
+        stats_json: m.value_of("stats-json").map(PathBuf::from),
+        report: m.value_of("report").map(PathBuf::from),
+        hooks: !m.is_present("no-hooks"),
+        env: m
+            .values_of("env")
+            .into_iter()
+            .flatten()
+            .map(|x| match x.split_once('=') {
+                Some((k, v)) if !k.is_empty() => (k.to_owned(), v.to_owned()),
+                _ => {
+                    log::error!("Invalid --env {}, expected KEY=VAL", x);
+                    std::process::exit(1);
+                }
+            })
+            .collect(),
+        profile: m.value_of("profile").map(|x| {
+            let format = match m.value_of("profile-format") {
+                Some("folded") => ProfileFormat::Folded,
+                _ => ProfileFormat::Csv,
+            };
+            (PathBuf::from(x), format)
+        }),
+        output: match m.value_of("output") {
+            _ if m.is_present("print0") => OutputMode::Print0,
+            Some("json") => OutputMode::Json,
+            _ => OutputMode::Text,
+        },
+        format: m.value_of("format").map(String::from),
+        job: job_key(
+            config_paths,
+            spec_arg(m).as_deref(),
+            tags,
+            files,
+            m.is_present("reverse"),
+        ),
+        run_id,
+        vars: spec::Vars::new(tags),
+        skip_sources: HashSet::new(),
+    };
+
+    for spec in m.values_of("spec").into_iter().flatten() {
+        let spec = spec::parse(spec).unwrap_or_else(|e| {
+            log::error!("Invalid spec, {}", e);
+            std::process::exit(1);
+        });
+        cpx_config.specs.push(spec);
+    }
+    if let Some((from, to)) = cpx_config.specs.first() {
+        cpx_config.from = Some(from.clone());
+        cpx_config.to = Some(to.clone());
+    }
+
+    cpx_config
+}
+
+/// Exit code of a --check run, like `terraform plan -detailed-exitcode`:
+/// 1 when it failed, 2 when a file would be copied or deleted and 0 when
+/// the destination is up to date.
+fn check_code(cpx: &Cpx) -> i32 {
+    if cpx.failed() {
+        1
+    } else if cpx
+        .output
+        .has_any(&[Status::New, Status::Overwrite, Status::Delete])
+    {
+        2
+    } else {
+        0
+    }
+}
+
+/// The specs of the command line as one string, several are separated
+/// by spaces.
+fn spec_arg(m: &clap::ArgMatches) -> Option<String> {
+    m.values_of("spec").map(|x| x.collect::<Vec<_>>().join(" "))
+}
+
+/// Sources of the daemon with how often each changed and at which of
+/// those counts it was last copied.
+#[cfg(all(unix, feature = "daemon"))]
+#[derive(Default)]
+struct DaemonSources {
+    /// Bumped when the config is reloaded, retiring the old watcher.
+    generation: u64,
+    changes: HashMap<PathBuf, u64>,
+    synced: HashMap<PathBuf, u64>,
+}
+
+/// Watch `sources` in the background, counting their changes.
+#[cfg(all(unix, feature = "daemon"))]
+fn spawn_source_watcher(state: &std::sync::Arc<Mutex<DaemonSources>>, sources: Vec<PathBuf>) {
+    let state = state.clone();
+    let generation = state.lock().unwrap().generation;
+    let mut watcher = match watch::Watcher::new(&sources) {
+        Ok(x) => x,
+        Err(e) => {
+            log::error!("Watch failed, {}", e);
+            std::process::exit(1);
+        }
+    };
+    log::info!("Watch {} files", sources.len());
+
+    std::thread::spawn(move || loop {
+        let changed = match watcher.wait(Duration::ZERO) {
+            Ok(x) => x,
+            Err(e) => {
+                log::error!("Watch failed, {}", e);
+                std::process::exit(1);
+            }
+        };
+        let mut state = state.lock().unwrap();
+        if state.generation != generation {
+            break;
+        }
+        for x in changed {
+            log::debug!("Changed {}", x.display());
+            *state.changes.entry(x).or_insert(0) += 1;
+        }
+    });
+}
+
+/// `cpx daemon`: keep the config parsed and the sources watched, and copy
+/// the tags `cpx trigger` asks for, skipping sources that did not change
+/// since they were copied.
+#[cfg(all(unix, feature = "daemon"))]
+fn run_daemon(default_config: &str, config_paths: &[String], args: Vec<String>) {
+    let parse = |tags: &[String]| {
+        let argv = std::iter::once("cpx".to_owned())
+            .chain(args.iter().cloned())
+            .chain(tags.iter().flat_map(|x| ["--tag".to_owned(), x.clone()]));
+        cli::app(default_config).get_matches_from_safe(argv)
+    };
+    let mtimes = || {
+        config_paths
+            .iter()
+            .map(|x| std::fs::metadata(x).and_then(|x| x.modified()).ok())
+            .collect::<Vec<_>>()
+    };
+    let load = || (load_config(config_paths), mtimes());
+    let all_sources = |config: &ConfigInfo| {
+        let mut tags: Vec<_> = config.tag_list.keys().cloned().collect();
+        tags.sort_unstable();
+        let m = parse(&tags).unwrap_or_else(|e| e.exit());
+        let copy_config = copy_config(
+            &m,
+            config_paths,
+            &Some(tags.clone()),
+            &None,
+            String::new(),
+            false,
+        );
+        if copy_config.specs.len() > 1 {
+            log::error!("cpx daemon copies a single spec");
+            std::process::exit(1);
+        }
+        Cpx::new(copy_config, config.clone(), None).sources(&Some(tags), &None::<Vec<String>>)
+    };
+
+    let (mut config, mut modified) = load();
+    let state = std::sync::Arc::new(Mutex::new(DaemonSources::default()));
+    spawn_source_watcher(&state, all_sources(&config));
+
+    let socket = daemon::socket_path(config_paths);
+    let result = daemon::serve(&socket, |request| {
+        let fail = |e: String| daemon::Response {
+            error: Some(e),
+            ..daemon::Response::default()
+        };
+
+        if mtimes() != modified {
+            let reloaded = std::panic::catch_unwind(load);
+            match reloaded {
+                Ok(x) => (config, modified) = x,
+                Err(_) => return fail("the config does not parse".to_owned()),
+            }
+            log::info!("Reload {}", config_paths.join(", "));
+            {
+                let mut state = state.lock().unwrap();
+                state.generation += 1;
+                state.changes.clear();
+                state.synced.clear();
+            }
+            spawn_source_watcher(&state, all_sources(&config));
+        }
+
+        let unknown = config.unknown_names(&Some(request.tags.clone()), &None);
+        if !unknown.is_empty() {
+            return fail(unknown.join("; "));
+        }
+        let m = match parse(&request.tags) {
+            Ok(x) => x,
+            Err(e) => return fail(e.message),
+        };
+        let tags = Some(request.tags);
+
+        let mut copy_config =
+            copy_config(&m, config_paths, &tags, &None, state::new_run_id(), false);
+        // deleting a source does not stop the daemon
+        copy_config.ignore_missing = true;
+        let before = {
+            let state = state.lock().unwrap();
+            copy_config.skip_sources = state
+                .synced
+                .iter()
+                .filter(|(path, n)| state.changes.get(*path).copied().unwrap_or(0) == **n)
+                .map(|(path, _)| path.clone())
+                .collect();
+            state.changes.clone()
+        };
+        let dry_run = copy_config.dry_run;
+
+        let copied = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let cpx = Cpx::new(copy_config, config.clone(), None);
+            let sources = cpx.sources(&tags, &None::<Vec<String>>);
+            cpx.execute(tags.clone(), None);
+            let failed = cpx.failed();
+            (sources, failed, cpx.output.counts())
+        }));
+        // a stopped daemon finishes the copy it was doing, no more
+        cleanup::exit_if_interrupted();
+        let (sources, failed, counts) = match copied {
+            Ok(x) => x,
+            Err(_) => return fail("the copy panicked, see the daemon log".to_owned()),
+        };
+
+        // a source changed during the copy stays out of date
+        if !failed && !dry_run {
+            let mut state = state.lock().unwrap();
+            for x in sources {
+                let n = before.get(&x).copied().unwrap_or(0);
+                state.synced.insert(x, n);
+            }
+        }
+
+        daemon::Response {
+            counts,
+            error: None,
+        }
+    });
+
+    if let Err(e) = result {
+        log::error!("Daemon failed, {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// `cpx trigger`: have the daemon copy `tags`, exiting like a copy would.
+#[cfg(all(unix, feature = "daemon"))]
+fn run_trigger(config_paths: &[String], tags: Vec<String>) {
+    let socket = daemon::socket_path(config_paths);
+    let response = daemon::trigger(&socket, &daemon::Request { tags }).unwrap_or_else(|e| {
+        log::error!("Trigger failed, {}", e);
+        std::process::exit(1);
+    });
+    if let Some(e) = response.error {
+        log::error!("Trigger failed, {}", e);
+        std::process::exit(1);
+    }
+
+    for (status, n) in &response.counts {
+        println!("{:<10} {}", status, n);
+    }
+    let failed = ["failed", "circuit_open"];
+    if failed.iter().any(|x| response.counts.contains_key(*x)) {
+        std::process::exit(1);
+    }
+}
+
+/// Read a selection file: one tag or file key per line, `tag <name>` or
+/// `file <key>` to say which, and `#` starting a comment. A bare name is
+/// a tag if `config` has one of that name, a file key otherwise.
+fn read_selection(path: &str, config: &ConfigInfo) -> (Vec<String>, Vec<String>) {
+    let content = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        log::error!("Read tag file {} failed, {}", path, e);
+        std::process::exit(1);
+    });
+    let (mut tags, mut files) = (vec![], vec![]);
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        match line.split_once(char::is_whitespace) {
+            _ if line.is_empty() => {}
+            None if !config.tag_list.contains_key(line) && config.file_list.contains_key(line) => {
+                files.push(line.to_owned())
+            }
+            None => tags.push(line.to_owned()),
+            Some(("tag", x)) => tags.push(x.trim().to_owned()),
+            Some(("file", x)) => files.push(x.trim().to_owned()),
+            Some(_) => {
+                log::error!(
+                    "{}:{}: invalid line `{}`, expected a name, `tag <name>` or `file <key>`",
+                    path,
+                    i + 1,
+                    line
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    (tags, files)
+}
+
+/// `cpx tag save`: add the tag of the files and tags given to the last
+/// config, or the user config with --user.
+fn save_tag(m: &clap::ArgMatches) {
+    let name = m.value_of("name").unwrap();
+    let list = |arg| -> Vec<String> {
+        m.values_of(arg)
+            .into_iter()
+            .flatten()
+            .map(String::from)
+            .collect()
+    };
+    let (tags, files) = (list("tags"), list("files"));
+    if tags.iter().any(|x| x == name) {
+        log::error!("Tag {} cannot select itself", name);
+        std::process::exit(1);
+    }
+    let paths = config::paths(m);
+    let config = load_config(&paths);
+    config.check_selection(&Some(tags.clone()), &Some(files.clone()));
+
+    let path = match m.is_present("user") {
+        true => config::user_path(),
+        false => paths.last().map(PathBuf::from),
+    };
+    let path = match path {
+        Some(x) if x.is_file() || m.is_present("user") => x,
+        x => {
+            let shown = x.map(|x| x.display().to_string()).unwrap_or_default();
+            log::error!(
+                "{} is not a file to write, --user saves to the user config",
+                shown
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let mut tag = serde_yaml::Mapping::new();
+    let seq = |x: Vec<String>| serde_yaml::Value::Sequence(x.into_iter().map(Into::into).collect());
+    if !tags.is_empty() {
+        tag.insert("tag_list".into(), seq(tags));
+    }
+    if !files.is_empty() {
+        tag.insert("file_list".into(), seq(files));
+    }
+    let tag = serde_yaml::Value::Mapping(tag);
+    match config::save_entry(&path, "tag_list", name, tag, m.is_present("force")) {
+        Ok(()) => log::info!("Saved tag {} to {}", name, path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            log::error!("Save tag {} failed, {}, --force replaces it", name, e);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            log::error!("Save tag {} to {} failed, {}", name, path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `cpx config migrate`: print the config at `path` in the current
+/// schema, or with `write` replace it, keeping the old one as .bak.
+fn migrate_config(path: &str, write: bool) -> std::io::Result<()> {
+    let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, e);
+    let mut value: serde_yaml::Value =
+        serde_yaml::from_str(&config::text(path)?).map_err(|e| invalid(e.to_string()))?;
+    if !config::migrate(&mut value).map_err(invalid)? {
+        log::info!("{} is at version {} already", path, config::VERSION);
+        return Ok(());
+    }
+
+    let mut text = serde_yaml::to_string(&value).map_err(|e| invalid(e.to_string()))?;
+    text.push('\n');
+    if !write {
+        print!("{}", text);
+        return Ok(());
+    }
+    if !Path::new(path).is_file() {
+        return Err(invalid(format!("{} is not a file to write", path)));
+    }
+    // the YAML is written anew, without the comments of the old one
+    let backup = format!("{}.bak", path);
+    std::fs::copy(path, &backup)?;
+    store::write(Path::new(path), text.as_bytes())?;
+    log::info!(
+        "Migrated {} to version {}, the old config is {}",
+        path,
+        config::VERSION,
+        backup
+    );
+    Ok(())
+}
+
+/// Keys of the files named in `path`, or stdin for `-`, separated by
+/// newlines or NULs. A name is a key of `file_list` or a relative path,
+/// paths not in `file_list` are added to it.
+fn read_files_from(path: &str, config: &mut ConfigInfo) -> Vec<String> {
+    let content = match path {
+        "-" => {
+            let mut x = vec![];
+            std::io::Read::read_to_end(&mut std::io::stdin(), &mut x).map(|_| x)
+        }
+        _ => std::fs::read(path),
+    };
+    let content = content.unwrap_or_else(|e| {
+        log::error!("Read files from {} failed, {}", path, e);
+        std::process::exit(1);
+    });
+    let content = String::from_utf8_lossy(&content);
+    let names: Vec<_> = match content.contains('\0') {
+        true => content.split('\0').collect(),
+        false => content.lines().collect(),
+    };
+
+    let mut keys_by_path: HashMap<_, _> = config
+        .file_list
+        .iter()
+        .map(|(key, f)| (f.relative_path.clone(), key.clone()))
+        .collect();
+    let mut keys = vec![];
+    for name in names {
+        if config.file_list.contains_key(name) {
+            keys.push(name.to_owned());
+            continue;
+        }
+        let path = match below_source(name) {
+            Some(x) => x,
+            None => continue,
+        };
+        let key = keys_by_path.entry(path.clone()).or_insert_with(|| {
+            let key = path.to_string_lossy().into_owned();
+            config.file_list.insert(key.clone(), FileInfo::at(path));
+            key
+        });
+        keys.push(key.clone());
+    }
+
+    keys
+}
+
+/// Check the names the entries of the config refer to, for `cpx doctor`.
+fn doctor_config(config: &ConfigInfo, paths: &[String], report: &mut doctor::Report) {
+    match paths.is_empty() {
+        true => report.warn("no config"),
+        false => report.ok(format!("config {}", paths.join(", "))),
+    }
+
+    let mut tags: Vec<_> = config.tag_list.keys().cloned().collect();
+    tags.sort_unstable();
+    let mut unknown = config.unknown_names(&Some(tags.clone()), &None);
+    let mut groups: Vec<_> = config.group_list.iter().collect();
+    groups.sort_unstable();
+    for (group, keys) in groups {
+        for key in keys.iter().filter(|x| !config.file_list.contains_key(*x)) {
+            let hint = suggest::hint(key, config.file_list.keys());
+            unknown.push(format!(
+                "No file {} of group {} in config{}",
+                key, group, hint
+            ));
+        }
+    }
+    for tag in &tags {
+        let scripts = config.tag_list[tag].script_list.iter().flatten();
+        for name in scripts.filter(|x| !config.script_list.contains_key(*x)) {
+            let hint = suggest::hint(name, config.script_list.keys());
+            unknown.push(format!(
+                "No script {} of tag {} in config{}",
+                name, tag, hint
+            ));
+        }
+    }
+    for tag in &tags {
+        let info = &config.tag_list[tag];
+        for name in info.from.iter().chain(&info.to) {
+            if let Err(e) = spec::resolve(&mut config.path_list.clone(), name) {
+                unknown.push(format!("Invalid path {} of tag {}, {}", name, tag, e));
+            }
+        }
+    }
+    unknown.dedup();
+    for x in unknown {
+        report.error(x);
+    }
+}
+
+/// Report the entries of the config nothing uses and the tags selecting
+/// nothing, for `cpx lint`. Returns whether there were any.
+fn lint_config(config: &ConfigInfo, report: &mut doctor::Report) -> bool {
+    // the path names of a spec or of a side of one, `base+prod/www`
+    fn path_names(spec: &str) -> impl Iterator<Item = &str> {
+        spec.split(':')
+            .flat_map(|x| x.split('+'))
+            .map(|x| x.split('/').next().unwrap())
+    }
+    fn sorted<V>(entries: &HashMap<String, V>) -> Vec<&String> {
+        let mut keys: Vec<_> = entries.keys().collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    let mut files: HashSet<&str> = HashSet::new();
+    let mut groups: HashSet<&str> = HashSet::new();
+    let mut scripts: Vec<&str> = vec![];
+    let mut paths: HashSet<&str> = HashSet::new();
+    for (name, tag) in &config.tag_list {
+        // a tag runs the script of its name too
+        if config.script_list.contains_key(name) {
+            scripts.push(name);
+        }
+        files.extend(tag.file_list.iter().flatten().map(String::as_str));
+        groups.extend(tag.files_from_group.iter().flatten().map(String::as_str));
+        scripts.extend(tag.script_list.iter().flatten().map(String::as_str));
+        paths.extend(tag.from.iter().chain(&tag.to).flat_map(|x| path_names(x)));
+    }
+    for group in &groups {
+        files.extend(
+            config
+                .group_list
+                .get(*group)
+                .into_iter()
+                .flatten()
+                .map(String::as_str),
+        );
+    }
+    for profile in config.profiles.values() {
+        files.extend(profile.files.iter().map(String::as_str));
+        paths.extend(path_names(&profile.from).chain(path_names(&profile.to)));
+    }
+    for entry in &config.schedule {
+        files.extend(entry.files.iter().map(String::as_str));
+        paths.extend(path_names(&entry.spec));
+    }
+    for info in config.path_list.values() {
+        paths.extend(
+            info.collect
+                .iter()
+                .chain(&info.merge)
+                .flat_map(|x| path_names(x)),
+        );
+    }
+    // a script a used one depends on is used too
+    let mut used_scripts = HashSet::new();
+    while let Some(name) = scripts.pop() {
+        if used_scripts.insert(name) {
+            let depends = config.script_list.get(name).map(|x| &x.depends_on);
+            scripts.extend(depends.into_iter().flatten().map(String::as_str));
+        }
+    }
+
+    let mut found = vec![];
+    for key in sorted(&config.file_list) {
+        if !files.contains(key.as_str()) {
+            found.push(format!(
+                "file {} is in no tag, group of a tag, profile or schedule",
+                key
+            ));
+        }
+    }
+    for key in sorted(&config.group_list) {
+        if !groups.contains(key.as_str()) {
+            found.push(format!("group {} is in no tag", key));
+        }
+    }
+    for key in sorted(&config.script_list) {
+        if !used_scripts.contains(key.as_str()) {
+            found.push(format!(
+                "script {} is in no tag nor named like one, nor a dependency of one",
+                key
+            ));
+        }
+    }
+    for key in sorted(&config.tag_list) {
+        let expanded = config.expand_tags(&[key]);
+        let empty = expanded.iter().all(|name| {
+            let group = |g: &String| config.group_list.get(g).is_some_and(|x| !x.is_empty());
+            !config.script_list.contains_key(name)
+                && config.tag_list.get(name).is_none_or(|x| {
+                    x.file_list.iter().flatten().next().is_none()
+                        && !x.files_from_group.iter().flatten().any(group)
+                        && x.files_from_cmd.is_none()
+                        && x.script_list.iter().flatten().next().is_none()
+                })
+        });
+        if empty {
+            found.push(format!("tag {} selects no files or scripts", key));
+        }
+    }
+    for key in sorted(&config.path_list) {
+        if !paths.contains(key.as_str()) {
+            found.push(format!(
+                "path {} is in no tag, profile, schedule, `collect` or `merge`, only specs can use it",
+                key
+            ));
+        }
+    }
+
+    if found.is_empty() {
+        report.ok("every entry of the config is used");
+    }
+    for x in &found {
+        report.warn(x);
+    }
+    !found.is_empty()
+}
+
+/// Check that the local paths of `path_list` are writable directories,
+/// for `cpx doctor`. One not there yet is made by a copy to it.
+fn doctor_paths(config: &ConfigInfo, report: &mut doctor::Report) {
+    let vars = spec::Vars::new(&None);
+    let mut names: Vec<_> = config.path_list.keys().collect();
+    names.sort_unstable();
+    for name in names {
+        let info = &config.path_list[name];
+        if !info.collect.is_empty() {
+            match info
+                .collect
+                .iter()
+                .find(|x| !config.path_list.contains_key(*x))
+            {
+                Some(x) => report.error(format!("path {} collects {}, not in path_list", name, x)),
+                None => report.ok(format!(
+                    "path {} collects {}",
+                    name,
+                    info.collect.join(", ")
+                )),
+            }
+            continue;
+        }
+        if !info.merge.is_empty() {
+            match info
+                .merge
+                .iter()
+                .find(|x| !config.path_list.contains_key(*x))
+            {
+                Some(x) => report.error(format!("path {} merges {}, not in path_list", name, x)),
+                None => report.ok(format!("path {} merges {}", name, info.merge.join(", "))),
+            }
+            continue;
+        }
+
+        let shown = info.path.display();
+        let remote = is_remote(&info.path);
+        let path = match vars.expand(&info.path) {
+            _ if remote => {
+                report.ok(format!(
+                    "path {} {}, remote paths are not checked",
+                    name, shown
+                ));
+                continue;
+            }
+            Ok(x) => x,
+            Err(_) => {
+                report.ok(format!("path {} {}, filled in by each copy", name, shown));
+                continue;
+            }
+        };
+        let shown = path.display();
+        match std::fs::metadata(&path) {
+            Ok(x) if !x.is_dir() => {
+                report.error(format!("path {} {} is not a directory", name, shown))
+            }
+            Ok(_) => match doctor::unwritable(&path) {
+                Some(why) => {
+                    report.warn(format!("path {} {} is not writable, {}", name, shown, why))
+                }
+                None => report.ok(format!("path {} {}", name, shown)),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                report.warn(format!("path {} {} does not exist", name, shown))
+            }
+            Err(e) => report.warn(format!("path {} {} cannot be read, {}", name, shown, e)),
+        }
+    }
+}
+
+/// Print `file_list` entries for the files below `root` matching the
+/// `discover` patterns that it lacks, and warn of the entries missing
+/// there, for `cpx scan`. Whether the config was up to date.
+fn scan_config(config: &ConfigInfo, root: &Path) -> bool {
+    if config.discover.is_empty() {
+        log::warn!("No `discover` patterns in the config, nothing to scan for");
+    }
+    let mut patterns = IgnoreList::default();
+    config.discover.iter().for_each(|x| patterns.add(x));
+    let excluded = IgnoreList::for_source(root, &config.exclude);
+    let listed: HashSet<_> = config
+        .file_list
+        .values()
+        .filter(|x| x.url.is_none())
+        .map(|x| paths::relative(&x.relative_path))
+        .collect();
+
+    let found = scan::unlisted(root, &patterns, &excluded, &listed).unwrap_or_else(|e| {
+        log::error!("Scan {} failed, {}", root.display(), e);
+        std::process::exit(1);
+    });
+    let mut taken: HashSet<_> = config.file_list.keys().cloned().collect();
+    if !found.is_empty() {
+        println!("file_list:");
+    }
+    for relative in &found {
+        let key = scan::key(relative, &taken);
+        let shown = relative.to_string_lossy().replace('\\', "/");
+        println!("  {}: {{ relative_path: {:?} }}", key, shown);
+        taken.insert(key);
+    }
+
+    let mut missing: Vec<_> = config
+        .file_list
+        .iter()
+        .filter(|(_, f)| f.url.is_none())
+        .filter(|(_, f)| {
+            std::fs::symlink_metadata(root.join(paths::relative(&f.relative_path))).is_err()
+        })
+        .collect();
+    missing.sort_unstable_by_key(|(key, _)| *key);
+    for (key, f) in &missing {
+        log::warn!(
+            "File {} is not in {}, {}",
+            key,
+            root.display(),
+            f.relative_path.display()
+        );
+    }
+
+    log::info!(
+        "{} files to add to file_list, {} entries not on disk",
+        found.len(),
+        missing.len()
+    );
+    found.is_empty() && missing.is_empty()
+}
+
+/// The tree of `tag` for `cpx tree`: its groups, files, `files_from_cmd`
+/// and scripts, those not in the config or, files under `src`, not on
+/// disk marked.
+fn tag_tree(config: &ConfigInfo, name: &str, src: Option<&Path>) -> tree::Node {
+    let file = |key: &str| {
+        let f = match config.file_list.get(key) {
+            Some(x) => x,
+            None => return tree::Node::new(format!("{} (unknown)", key)),
+        };
+        let missing = match (&f.url, src) {
+            (None, Some(src)) => {
+                std::fs::symlink_metadata(src.join(paths::relative(&f.relative_path))).is_err()
+            }
+            _ => false,
+        };
+        let label = match &f.url {
+            Some(url) => format!("{} {}", key, url),
+            None => format!("{} {}", key, f.relative_path.display()),
+        };
+        tree::Node::new(label + if missing { " (missing)" } else { "" })
+    };
+
+    let mut node = tree::Node::new(format!("tag {}", name));
+    let tag = match config.tag_list.get(name) {
+        Some(x) => x,
+        None => {
+            node.label.push_str(" (unknown)");
+            return node;
+        }
+    };
+    for t in tag.tag_list.iter().flatten() {
+        node.children.push(tree::Node::new(format!("tag {}", t)));
+    }
+    for g in tag.files_from_group.iter().flatten() {
+        let mut group = tree::Node::new(format!("group {}", g));
+        match config.group_list.get(g) {
+            Some(keys) => group.children.extend(keys.iter().map(|x| file(x))),
+            None => group.label.push_str(" (unknown)"),
+        }
+        node.children.push(group);
+    }
+    node.children
+        .extend(tag.file_list.iter().flatten().map(|x| file(x)));
+    if let Some(cmd) = &tag.files_from_cmd {
+        node.children
+            .push(tree::Node::new(format!("files_from_cmd `{}`", cmd)));
+    }
+    // the script of the tag's own name is copied with it too
+    let mut scripts: Vec<_> = tag
+        .script_list
+        .iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    if config.script_list.contains_key(name) && !scripts.contains(&name) {
+        scripts.push(name);
+    }
+    node.children
+        .extend(scripts.into_iter().map(|x| script_tree(config, x)));
+
+    node
+}
+
+/// The script `name` for `cpx tree`, with the scripts it depends on
+/// below it.
+fn script_tree(config: &ConfigInfo, name: &str) -> tree::Node {
+    let script = match config.script_list.get(name) {
+        Some(x) => x,
+        None => return tree::Node::new(format!("script {} (unknown)", name)),
+    };
+    let mut label = format!(
+        "script {} {} -> {}",
+        name,
+        script.from.display(),
+        script.to.display()
+    );
+    if !script.from.exists() {
+        label.push_str(" (missing)");
+    }
+    let mut node = tree::Node::new(label);
+    // there are no cycles, the config is checked for them when loaded
+    node.children
+        .extend(script.depends_on.iter().map(|x| script_tree(config, x)));
+    node
+}
+
+/// Paths of a `manifest`/`verify`/`gc` spec, either `dst` or `src:dst`.
+fn spec_paths(config: &mut ConfigInfo, m: &clap::ArgMatches) -> (Option<PathInfo>, PathInfo) {
+    let resolved = spec::parse_dst(m.value_of("spec").unwrap()).and_then(|(from, to)| {
+        let vars = spec::Vars::new(&None);
+        let mut resolve = |name: String| {
+            spec::resolve(&mut config.path_list, &name)?;
+            let mut info = config.path_list[&name].clone();
+            info.path = vars.expand(&info.path)?;
+            Ok::<_, String>(info)
+        };
+        Ok((from.map(&mut resolve).transpose()?, resolve(to)?))
+    });
+    resolved.unwrap_or_else(|e| {
+        log::error!("Invalid spec, {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Hash algorithm of a `manifest`, from --checksum or the config.
+fn checksum(config: &ConfigInfo, m: &clap::ArgMatches) -> Algorithm {
+    m.value_of("checksum")
+        .map(|x| Algorithm::from_name(x).unwrap())
+        .or(config.checksum)
+        .unwrap_or_default()
+}
+
+/// The manifest file, by default `cpx.<algorithm>` in the destination.
+/// To `verify`, one by another algorithm is taken if there is none.
+fn manifest_path(
+    to: &PathInfo,
+    m: &clap::ArgMatches,
+    algorithm: Algorithm,
+    verify: bool,
+) -> PathBuf {
+    if let Some(x) = m.value_of("manifest") {
+        return PathBuf::from(x);
+    }
+
+    let path = |name: &str| to.path.join(format!("cpx.{}", name));
+    std::iter::once(algorithm.name())
+        .chain(Algorithm::NAMES)
+        .map(path)
+        .find(|x| verify && x.exists())
+        .unwrap_or_else(|| path(algorithm.name()))
+}
+
+fn job_key(
+    configs: &[String],
+    spec: Option<&str>,
+    tags: &Option<Vec<String>>,
+    files: &Option<Vec<String>>,
+    reverse: bool,
+) -> String {
+    let sorted = |x: &Option<Vec<String>>| {
+        let mut x = x.clone().unwrap_or_default();
+        x.sort_unstable();
+        x.join(",")
+    };
+    let configs: Vec<_> = configs
+        .iter()
+        .map(|x| {
+            let path = std::fs::canonicalize(x)
+                .map(|x| paths::simplify(&x))
+                .unwrap_or_else(|_| PathBuf::from(x));
+            path.display().to_string()
+        })
+        .collect();
+
+    format!(
+        "{}|{}|{}|{}|{}",
+        configs.join(","),
+        spec.unwrap_or_default(),
+        sorted(tags),
+        sorted(files),
+        reverse
+    )
+}
+
+fn main() {
+    let default_config = config::default_path();
+    let m = cli::app(&default_config).get_matches();
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // a watch runs the copy as it would run without `watch`
+    let watching = m.subcommand_name() == Some("watch");
+    let m = match watching {
+        true => {
+            if let Some(i) = args.iter().position(|x| x == "watch") {
+                args.remove(i);
+            }
+            cli::app(&default_config)
+                .get_matches_from(std::iter::once("cpx".to_owned()).chain(args.clone()))
+        }
+        false => m,
+    };
+
+    // a resumed run keeps its id, so its logs and journal cover all attempts
+    let mut resumed = match m.subcommand() {
+        ("resume", Some(sub)) => Some(checkpoint::load(sub.value_of("run-id"))),
+        _ => None,
+    };
+    if let Some(Ok(x)) = &resumed {
+        std::env::set_var("CPX_RUN_ID", &x.run_id);
+    }
+    let run_id = state::run_id();
+    // global flags are propagated down, so the subcommand sees all of them
+    let g = m.subcommand().1.unwrap_or(&m);
+    logger::init(
+        logger::level_for(g.occurrences_of("verbose"), g.is_present("quiet")),
+        logger::use_color(g.value_of("color").unwrap(), false),
+        g.value_of("log-file").map(Path::new),
+        &run_id,
+    );
+    log::debug!("Run {}", run_id);
+    cleanup::install();
+    if let Some(x) = g.value_of("temp-prefix") {
+        if let Err(e) = copy::set_temp_prefix(x) {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    let m = match m.subcommand() {
+        ("man", Some(_)) => {
+            man::write(&mut std::io::stdout(), &default_config).expect("Write man page failed!");
+            return;
+        }
+        #[cfg(feature = "completions")]
+        ("completions", Some(sub)) => {
+            completions::generate(sub.value_of("shell").unwrap(), &default_config);
+            return;
+        }
+        #[cfg(feature = "completions")]
+        ("complete", Some(sub)) => {
+            // completion must stay silent when the config is missing or broken
+            if let Some(config) = config::read(&config::paths(sub))
+                .ok()
+                .and_then(|x| serde_yaml::from_value::<ConfigInfo>(x).ok())
+            {
+                for key in config.keys(sub.value_of("kind").unwrap()) {
+                    println!("{}", key);
+                }
+            }
+            return;
+        }
+        ("undo", Some(sub)) => {
+            // another undo must not pick the same journal
+            let result = store::lock().and_then(|_lock| {
+                let (dir, journal) = journal::load(sub.value_of("run-id"))?;
+                log::info!("Undo run {}", journal.run_id);
+                journal::undo(&dir, &journal, sub.is_present("dry-run"))
+            });
+            if let Err(e) = result {
+                log::error!("Undo failed, {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        ("manifest", Some(sub)) => {
+            let mut config = load_config(&config::paths(sub));
+            let (_, to) = spec_paths(&mut config, sub);
+            let tags = sub.values_of("tags").map(|x| x.collect::<Vec<_>>());
+            let files = sub.values_of("files").map(|x| x.collect::<Vec<_>>());
+            config.check_selection(&tags, &files);
+
+            let mut relative: Vec<_> = config
+                .calculate_file_list(&tags, &files)
+                .into_iter()
+                .map(|f| match &to.normalize {
+                    Some(policy) => policy.apply(&f.relative_path),
+                    None => f.relative_path,
+                })
+                .collect();
+            relative.sort_unstable();
+
+            let algorithm = checksum(&config, sub);
+            let path = manifest_path(&to, sub, algorithm, false);
+            let key = sub.value_of("path-key");
+            if let Err(e) = manifest::write(&path, &to.path, &relative, key, algorithm) {
+                log::error!("Write manifest {} failed, {}", path.display(), e);
+                std::process::exit(1);
+            }
+            log::info!("Wrote manifest {}", path.display());
+            return;
+        }
+        ("verify", Some(sub)) => {
+            let mut config = load_config(&config::paths(sub));
+            let (_, to) = spec_paths(&mut config, sub);
+            let path = manifest_path(&to, sub, checksum(&config, sub), true);
+
+            match manifest::verify(&path, &to.path, sub.value_of("path-key")) {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(e) => {
+                    log::error!("Verify against {} failed, {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        ("gc", Some(sub)) => {
+            let mut config = load_config(&config::paths(sub));
+            let (from, to) = spec_paths(&mut config, sub);
+            if is_remote(&to.path) {
+                log::error!("cpx gc needs a local destination");
+                std::process::exit(1);
+            }
+
+            let from = from.as_ref().map(|x| x.path.as_path());
+            if !gc::collect(&to.path, from, &config.retention, sub.is_present("dry-run")) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        #[cfg(feature = "self-update")]
+        ("self-update", Some(sub)) => {
+            let config = load_config(&config::paths(sub));
+            let update = config.update.unwrap_or_else(|| {
+                log::error!("No `update` section in the config");
+                std::process::exit(1);
+            });
+            if let Err(e) = update::run(&update, sub.is_present("check"), sub.is_present("force")) {
+                log::error!("Update failed, {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        #[cfg(all(unix, feature = "daemon"))]
+        ("daemon", Some(sub)) => {
+            let args = sub
+                .values_of("args")
+                .into_iter()
+                .flatten()
+                .map(String::from);
+            run_daemon(&default_config, &config::paths(sub), args.collect());
+            return;
+        }
+        #[cfg(all(unix, feature = "daemon"))]
+        ("trigger", Some(sub)) => {
+            let tags = sub.values_of("tags").unwrap().map(String::from);
+            run_trigger(&config::paths(sub), tags.collect());
+            return;
+        }
+        ("schedule", Some(sub)) => {
+            let sub = sub.subcommand_matches("run").unwrap();
+            let paths = config::paths(sub);
+            let config = load_config(&paths);
+            if let Err(e) = schedule::run(&paths, &config.schedule) {
+                log::error!("Schedule failed, {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        ("explain", Some(sub)) => {
+            // the copy of only this file, with its options
+            let key = sub.value_of("file").unwrap();
+            let paths = config::paths(sub);
+            let mut argv = vec!["cpx"];
+            argv.extend(paths.iter().flat_map(|x| ["-c", x.as_str()]));
+            argv.extend(sub.values_of("args").unwrap());
+            argv.extend(["--file", key]);
+            let m = cli::app(&default_config).get_matches_from(argv);
+            let files = Some(vec![key.to_owned()]);
+            let mut copy_config = copy_config(&m, &paths, &None, &files, String::new(), false);
+            copy_config.dry_run = true;
+            Cpx::new(copy_config, load_config(&paths), None).explain(key);
+            return;
+        }
+        ("doctor", Some(sub)) => {
+            let paths = config::paths(sub);
+            let mut config = load_config(&paths);
+            let mut report = doctor::Report::default();
+            doctor_config(&config, &paths, &mut report);
+            doctor_paths(&config, &mut report);
+
+            if let Some(args) = sub.values_of("args") {
+                let mut argv = vec!["cpx"];
+                argv.extend(paths.iter().flat_map(|x| ["-c", x.as_str()]));
+                argv.extend(args);
+                let m = cli::app(&default_config).get_matches_from(argv);
+                let (tags, files) = selection(&m, &mut config);
+                config.check_selection(&tags, &files);
+                let mut copy_config = copy_config(&m, &paths, &tags, &files, String::new(), false);
+                copy_config.dry_run = true;
+                Cpx::new(copy_config, config, None).doctor(&tags, &files, &mut report);
+            }
+            if report.finish() {
+                std::process::exit(1);
+            }
+            return;
+        }
+        ("lint", Some(sub)) => {
+            let paths = config::paths(sub);
+            let config = load_config(&paths);
+            let mut report = doctor::Report::default();
+            let found = lint_config(&config, &mut report);
+            // what nothing uses is a warning, only --strict fails on it
+            if report.finish() || (found && sub.is_present("strict")) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        ("du", Some(sub)) => {
+            let paths = config::paths(sub);
+            let mut argv = vec!["cpx"];
+            argv.extend(paths.iter().flat_map(|x| ["-c", x.as_str()]));
+            argv.extend(sub.values_of("args").unwrap());
+            let m = cli::app(&default_config).get_matches_from(argv);
+            let mut config = load_config(&paths);
+            let (tags, files) = selection(&m, &mut config);
+            config.check_selection(&tags, &files);
+            let mut copy_config = copy_config(&m, &paths, &tags, &files, String::new(), false);
+            copy_config.dry_run = true;
+            Cpx::new(copy_config, config, None).du(&tags, &files);
+            return;
+        }
+        ("tree", Some(sub)) => {
+            let paths = config::paths(sub);
+            let mut config = load_config(&paths);
+            let tags = sub.values_of("tags").map(|x| x.collect::<Vec<_>>());
+            let src = sub.value_of("src").map(|name| {
+                let vars = spec::Vars::new(
+                    &tags
+                        .as_ref()
+                        .map(|x| x.iter().map(|x| x.to_string()).collect()),
+                );
+                let resolved = spec::resolve(&mut config.path_list, name)
+                    .and_then(|_| vars.expand(&config.path_list[name].path));
+                resolved.unwrap_or_else(|e| {
+                    log::error!("Invalid path {}, {}", name, e);
+                    std::process::exit(1);
+                })
+            });
+            let mut names: Vec<_> = match &tags {
+                Some(x) => x.clone(),
+                None => config.tag_list.keys().map(String::as_str).collect(),
+            };
+            if tags.is_none() {
+                names.sort_unstable();
+            }
+            for name in names {
+                print!("{}", tag_tree(&config, name, src.as_deref()).render());
+            }
+            return;
+        }
+        ("scan", Some(sub)) => {
+            let mut config = load_config(&config::paths(sub));
+            let name = sub.value_of("path").unwrap();
+            let root = spec::resolve(&mut config.path_list, name)
+                .and_then(|_| spec::Vars::new(&None).expand(&config.path_list[name].path))
+                .unwrap_or_else(|e| {
+                    log::error!("Invalid path {}, {}", name, e);
+                    std::process::exit(1);
+                });
+            if !scan_config(&config, &root) && sub.is_present("check") {
+                std::process::exit(1);
+            }
+            return;
+        }
+        ("export", Some(sub)) => {
+            // the top level --format is a template, here it names the shell
+            let mut args: Vec<_> = sub.values_of("args").unwrap().collect();
+            let mut format = sub.value_of("format");
+            if let Some(i) = args.iter().position(|x| *x == "--format") {
+                format = args.get(i + 1).copied();
+                args.drain(i..(i + 2).min(args.len()));
+            }
+            let shell = match format {
+                Some(x) => export::Shell::from_name(x).unwrap_or_else(|| {
+                    log::error!("Invalid format {}, sh or bat", x);
+                    std::process::exit(1);
+                }),
+                None => export::Shell::native(),
+            };
+
+            let paths = config::paths(sub);
+            let mut argv = vec!["cpx"];
+            argv.extend(paths.iter().flat_map(|x| ["-c", x.as_str()]));
+            argv.extend(args);
+            let m = cli::app(&default_config).get_matches_from(argv);
+            let mut config = load_config(&paths);
+            let (tags, files) = selection(&m, &mut config);
+            config.check_selection(&tags, &files);
+            let mut copy_config = copy_config(&m, &paths, &tags, &files, String::new(), false);
+            if copy_config.rotate {
+                log::error!("cpx export does not support --rotate");
+                std::process::exit(1);
+            }
+            copy_config.dry_run = true;
+            Cpx::new(copy_config, config, None).export(&tags, &files, shell);
+            return;
+        }
+        ("bench", Some(sub)) => {
+            let mut args: Vec<_> = sub.values_of("args").unwrap().collect();
+            let mut levels = sub.value_of("levels");
+            if let Some(i) = args.iter().position(|x| *x == "--levels") {
+                levels = args.get(i + 1).copied();
+                args.drain(i..(i + 2).min(args.len()));
+            }
+            let levels = match levels {
+                Some(x) => x
+                    .split(',')
+                    .map(|x| x.trim().parse().ok().filter(|x| *x > 0))
+                    .collect::<Option<Vec<usize>>>()
+                    .unwrap_or_else(|| {
+                        log::error!("Invalid levels {}, numbers like 1,4,16", x);
+                        std::process::exit(1);
+                    }),
+                None => bench::LEVELS.to_vec(),
+            };
+
+            let paths = config::paths(sub);
+            let mut argv = vec!["cpx"];
+            argv.extend(paths.iter().flat_map(|x| ["-c", x.as_str()]));
+            argv.extend(args);
+            let m = cli::app(&default_config).get_matches_from(argv);
+            let mut config = load_config(&paths);
+            let (tags, files) = selection(&m, &mut config);
+            config.check_selection(&tags, &files);
+            let mut copy_config = copy_config(&m, &paths, &tags, &files, String::new(), false);
+            copy_config.rotate = false;
+            Cpx::new(copy_config, config, None).bench(&tags, &files, &levels);
+            return;
+        }
+        ("sync", Some(sub)) => {
+            let paths = config::paths(sub);
+            let mut argv = vec!["cpx"];
+            argv.extend(paths.iter().flat_map(|x| ["-c", x.as_str()]));
+            argv.extend(sub.values_of("args").unwrap());
+            let m = cli::app(&default_config).get_matches_from(argv);
+            let mut config = load_config(&paths);
+            let (tags, files) = selection(&m, &mut config);
+            config.check_selection(&tags, &files);
+            let copy_config = copy_config(&m, &paths, &tags, &files, String::new(), false);
+            let cpx = Cpx::new(copy_config, config, None);
+            cpx.sync(&tags, &files);
+            let code = match m.is_present("check") {
+                true => check_code(&cpx),
+                false => cpx.failed() as i32,
+            };
+            if code != 0 {
+                drop(cpx);
+                std::process::exit(code);
+            }
+            return;
+        }
+        ("config", Some(sub)) => {
+            let sub = sub.subcommand_matches("migrate").unwrap();
+            for path in config::paths(sub) {
+                if let Err(e) = migrate_config(&path, sub.is_present("write")) {
+                    log::error!("Migrate {} failed, {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        ("tag", Some(sub)) => {
+            save_tag(sub.subcommand_matches("save").unwrap());
+            return;
+        }
+        ("history", Some(_)) => {
+            match history::load() {
+                Ok(entries) => history::print(&entries),
+                Err(e) => {
+                    log::error!("Read history failed, {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        ("mirror", Some(_)) => {
+            // the copy as it would run with every file listed
+            if let Some(i) = args.iter().position(|x| x == "mirror") {
+                args.remove(i);
+            }
+            args.push("--mirror".to_owned());
+            cli::app(&default_config)
+                .get_matches_from(std::iter::once("cpx".to_owned()).chain(args.clone()))
+        }
+        ("rerun", Some(sub)) => {
+            let n = sub
+                .value_of("n")
+                .unwrap()
+                .parse()
+                .expect("invalid history number");
+            let entry = history::get(n)
+                .and_then(|x| std::env::set_current_dir(&x.cwd).map(|_| x))
+                .unwrap_or_else(|e| {
+                    log::error!("Rerun failed, {}", e);
+                    std::process::exit(1);
+                });
+
+            log::info!("Rerun cpx {}", entry.args.join(" "));
+            args = entry.args;
+            cli::app(&default_config)
+                .get_matches_from(std::iter::once("cpx".to_owned()).chain(args.clone()))
+        }
+        ("run", Some(sub)) => {
+            let name = sub.value_of("profile").unwrap();
+            let paths = config::paths(sub);
+            let preset = load_config(&paths).profiles.remove(name);
+            let preset = preset.unwrap_or_else(|| {
+                log::error!("No profile named {}", name);
+                std::process::exit(1);
+            });
+            let preset = preset.args().unwrap_or_else(|e| {
+                log::error!("Invalid profile {}, {}", name, e);
+                std::process::exit(1);
+            });
+
+            // the configs are found again when none was given
+            args = vec![];
+            if sub.occurrences_of("config") > 0 {
+                args.extend(paths.iter().flat_map(|x| ["-c".to_owned(), x.clone()]));
+            }
+            args.extend(preset);
+            args.extend(
+                sub.values_of("args")
+                    .into_iter()
+                    .flatten()
+                    .map(String::from),
+            );
+            log::info!("Run cpx {}", args.join(" "));
+            cli::app(&default_config)
+                .get_matches_from(std::iter::once("cpx".to_owned()).chain(args.clone()))
+        }
+        ("resume", Some(_)) => {
+            let file = resumed
+                .take()
+                .unwrap()
+                .and_then(|x| std::env::set_current_dir(&x.cwd).map(|_| x))
+                .unwrap_or_else(|e| {
+                    log::error!("Resume failed, {}", e);
+                    std::process::exit(1);
+                });
+
+            log::info!(
+                "Resume run {}, {} files done before",
+                file.run_id,
+                file.done.len()
+            );
+            args = file.args.clone();
+            resumed = Some(Ok(file));
+            cli::app(&default_config)
+                .get_matches_from(std::iter::once("cpx".to_owned()).chain(args.clone()))
+        }
+        _ => m,
+    };
+    let config_paths = config::paths(&m);
+
+    let mut config = load_config(&config_paths);
+    let (tags, files) = selection(&m, &mut config);
+
+    #[cfg(feature = "tui")]
+    let (tags, files) = if m.is_present("interactive") {
+        match pick_selection(&config, &tags, &files) {
+            Some((t, f)) => {
+                args = history::resolved_args(&args, &t, &f);
+                (Some(t), Some(f))
+            }
+            None => return,
+        }
+    } else {
+        (tags, files)
+    };
+
+    config.check_selection(&tags, &files);
+    let cpx_config = copy_config(&m, &config_paths, &tags, &files, run_id, watching);
+    if watching && cpx_config.specs.len() > 1 {
+        log::error!("cpx watch copies a single spec");
+        std::process::exit(1);
+    }
+
+    let entry = history::Entry {
+        run_id: cpx_config.run_id.clone(),
+        started: history::now(),
+        cwd: std::env::current_dir().unwrap_or_default(),
+        args,
+        spec: spec_arg(&m),
+        tags: tags.clone().unwrap_or_default(),
+        files: files.clone().unwrap_or_default(),
+        dry_run: cpx_config.dry_run,
+        counts: Default::default(),
+    };
+
+    // a watch has no end to resume from, and its rounds copy files again
+    let checkpoint = if cpx_config.dry_run || watching {
+        None
+    } else {
+        let file = match resumed {
+            Some(Ok(x)) => x,
+            _ => CheckpointFile {
+                run_id: entry.run_id.clone(),
+                started: entry.started,
+                cwd: entry.cwd.clone(),
+                args: entry.args.clone(),
+                ..CheckpointFile::default()
+            },
+        };
+        Some(Checkpoint::new(file))
+    };
+
+    let cpx = Cpx::new(cpx_config, config, checkpoint);
+    cpx.execute(tags.clone(), files.clone());
+
+    let entry = history::Entry {
+        counts: cpx.output.counts(),
+        ..entry
+    };
+    if let Err(e) = history::append(&entry) {
+        log::warn!("Save history failed, {}", e);
+    }
+
+    if let Some(sig) = cleanup::interrupted() {
+        drop(cpx);
+        std::process::exit(128 + sig);
+    }
+    if m.is_present("check") {
+        let code = check_code(&cpx);
+        // exit skips destructors, the WebDAV login among them
+        drop(cpx);
+        std::process::exit(code);
+    }
+    if !watching && cpx.failed() {
+        drop(cpx);
+        std::process::exit(1);
+    }
+
+    if watching {
+        let debounce = m
+            .value_of("debounce")
+            .unwrap()
+            .parse()
+            .expect("invalid debounce");
+        cpx.watch(tags, files, Duration::from_millis(debounce));
+    }
 
     // let mut config = ConfigInfo {
     //     path_list: HashMap::new(),
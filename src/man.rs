@@ -0,0 +1,74 @@
+use std::io::{self, Write};
+
+use crate::cli;
+
+/// Text safe to put on a roff line.
+fn escape(line: &str) -> String {
+    let line = line.replace('\\', "\\e").replace('-', "\\-");
+    if line.starts_with(['.', '\'']) {
+        format!("\\&{}", line)
+    } else {
+        line
+    }
+}
+
+/// Write the man page, built from the same help text `--help` prints so
+/// the two never drift apart.
+pub fn write<W: Write>(out: &mut W, default_config: &str) -> io::Result<()> {
+    let mut help = vec![];
+    cli::app(default_config)
+        .bin_name("cpx")
+        .write_long_help(&mut help)
+        .map_err(|e| io::Error::other(e.message))?;
+    let help = String::from_utf8_lossy(&help);
+
+    writeln!(out, ".TH CPX 1 \"\" \"cpx {}\"", env!("CARGO_PKG_VERSION"))?;
+    writeln!(out, ".SH NAME\ncpx \\- help you copy files")?;
+
+    // clap starts with name, version and about, sections follow from USAGE
+    let mut open = false;
+    for line in help.lines().skip_while(|x| !x.starts_with("USAGE:")) {
+        match line.strip_suffix(':') {
+            Some(section) if !line.starts_with(' ') => {
+                if open {
+                    writeln!(out, ".fi")?;
+                }
+                let section = if section == "USAGE" {
+                    "SYNOPSIS"
+                } else {
+                    section
+                };
+                writeln!(out, ".SH {}\n.nf", section)?;
+                open = true;
+            }
+            _ => writeln!(
+                out,
+                "{}",
+                escape(line.strip_prefix("    ").unwrap_or(line).trim_end())
+            )?,
+        }
+    }
+    if open {
+        writeln!(out, ".fi")?;
+    }
+
+    writeln!(
+        out,
+        ".SH ENVIRONMENT\n\
+         .TP\n\\fBCPX_CONFIG\\fR, \\fBCPX_JOBS\\fR, \\fBCPX_DRY_RUN\\fR, \\fBCPX_COLOR\\fR\n\
+         Defaults for \\-\\-config, \\-\\-jobs, \\-\\-dry\\-run and \\-\\-color.\n\
+         .TP\n\\fBCPX_RUN_ID\\fR\n\
+         Run id to use instead of a random one, also set for scripts.\n\
+         .TP\n\\fBCPX_PATH_KEY\\fR\n\
+         Key for \\-\\-path\\-key of manifest and verify.\n\
+         .TP\n\\fBCPX_WEBDAV_USER\\fR, \\fBCPX_WEBDAV_PASSWORD\\fR\n\
+         Credentials of WebDAV paths.\n\
+         .SH FILES\n\
+         .TP\ncpx.yaml, .cpx.yaml\n\
+         Project config, looked for in the current directory and above.\n\
+         .TP\n$XDG_CONFIG_HOME/cpx/config.yaml, ~/cpx.yaml\n\
+         User config, under a project config.\n\
+         .TP\n~/.local/share/cpx\n\
+         Run history, undo journals and incremental state."
+    )
+}
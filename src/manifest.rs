@@ -0,0 +1,177 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+
+use crate::checksum::Algorithm;
+
+/// Header line of a manifest whose paths are keyed hashes.
+const HASHED_PATHS: &str = "# cpx: paths are hmac-sha256";
+/// Header line naming the algorithm of the hashes, SHA-256 without it.
+const ALGORITHM: &str = "# cpx: hashes are ";
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// HMAC-SHA256 of the relative `path` under `key`, `/` separated on every
+/// platform. Without the key names cannot be guessed from the hashes.
+pub fn path_hash(key: &str, path: &Path) -> String {
+    let path: Vec<_> = path
+        .components()
+        .filter_map(|x| match x {
+            Component::Normal(x) => Some(x.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key.as_bytes()));
+    } else {
+        block[..key.len()].copy_from_slice(key.as_bytes());
+    }
+    let pad = |x: u8| block.iter().map(|b| b ^ x).collect::<Vec<_>>();
+
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(path.join("/").as_bytes())
+        .finalize();
+    hex(&Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize())
+}
+
+/// Write a manifest of `files`, relative to `root`, in the format of
+/// `sha256sum` so one by SHA-256 can also be checked with `sha256sum -c`.
+/// With `path_key` the paths are recorded as `path_hash`es instead,
+/// proving the content without revealing the layout.
+pub fn write(
+    manifest: &Path,
+    root: &Path,
+    files: &[PathBuf],
+    path_key: Option<&str>,
+    algorithm: Algorithm,
+) -> io::Result<()> {
+    let mut lines = vec![];
+    for f in files {
+        let hash = algorithm
+            .file(&root.join(f))
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", f.display(), e)))?;
+        match path_key {
+            Some(key) => lines.push(format!("{}  {}\n", hash, path_hash(key, f))),
+            None => lines.push(format!("{}  {}\n", hash, f.display())),
+        }
+    }
+
+    let mut out = String::new();
+    if algorithm != Algorithm::Sha256 {
+        out.push_str(ALGORITHM);
+        out.push_str(algorithm.name());
+        out.push('\n');
+    }
+    if path_key.is_some() {
+        // in path order the lines would still hint at the layout
+        let path = |x: &String| x.split_once("  ").map(|(_, x)| x.to_owned());
+        lines.sort_unstable_by_key(path);
+        out.push_str(HASHED_PATHS);
+        out.push('\n');
+    }
+    out.extend(lines);
+
+    File::create(manifest)?.write_all(out.as_bytes())
+}
+
+/// Relative paths of all files below `root` by their `path_hash`.
+fn hashed_files(
+    key: &str,
+    root: &Path,
+    dir: &Path,
+    found: &mut HashMap<String, PathBuf>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            hashed_files(key, root, &path, found)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            found.insert(path_hash(key, relative), relative.to_path_buf());
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-check the files listed in `manifest` under `root`, printing every
+/// file that does not match. Returns whether all of them matched. Hashed
+/// paths are resolved with `path_key`, a missing file is then shown by
+/// its hash.
+pub fn verify(manifest: &Path, root: &Path, path_key: Option<&str>) -> io::Result<bool> {
+    let mut ok = true;
+    let mut hashed: Option<HashMap<String, PathBuf>> = None;
+    let mut algorithm = Algorithm::Sha256;
+
+    for line in BufReader::new(File::open(manifest)?).lines() {
+        let line = line?;
+        if let Some(name) = line.strip_prefix(ALGORITHM) {
+            algorithm = Algorithm::from_name(name).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown hash algorithm `{}`", name),
+                )
+            })?;
+            continue;
+        }
+        if line == HASHED_PATHS {
+            let key = path_key.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the manifest has hashed paths, a path key is needed",
+                )
+            })?;
+            let mut found = HashMap::new();
+            hashed_files(key, root, root, &mut found)?;
+            hashed = Some(found);
+            continue;
+        }
+
+        let (expected, file) = match line.split_once("  ") {
+            Some(x) => x,
+            None if line.trim().is_empty() => continue,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("malformed manifest line `{}`", line),
+                ))
+            }
+        };
+
+        let path = match hashed.as_ref().map(|x| x.get(file)) {
+            Some(Some(relative)) => root.join(relative),
+            Some(None) => {
+                println!("{:<10} {}", "missing", file);
+                ok = false;
+                continue;
+            }
+            None => root.join(file),
+        };
+        let status = match algorithm.file(&path) {
+            Ok(actual) if actual == expected => {
+                log::info!("Verified {}", path.display());
+                continue;
+            }
+            Ok(_) => "modified",
+            Err(e) if e.kind() == io::ErrorKind::NotFound => "missing",
+            Err(e) => {
+                log::error!("Read {} failed, {}", path.display(), e);
+                "failed"
+            }
+        };
+
+        println!("{:<10} {}", status, path.display());
+        ok = false;
+    }
+
+    Ok(ok)
+}
@@ -0,0 +1,117 @@
+use std::convert::TryInto;
+
+const S: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// MD5, only to compare S3 ETags with local files, never for integrity.
+pub struct Md5 {
+    state: [u32; 4],
+    buf: Vec<u8>,
+    len: u64,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Md5 {
+    pub fn new() -> Self {
+        Self {
+            state: [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476],
+            buf: Vec::with_capacity(64),
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        if !self.buf.is_empty() {
+            let n = (64 - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.buf.len() < 64 {
+                return;
+            }
+            let block: [u8; 64] = self.buf[..].try_into().unwrap();
+            self.block(&block);
+            self.buf.clear();
+        }
+
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.block(block.try_into().unwrap());
+        }
+        self.buf.extend_from_slice(blocks.remainder());
+    }
+
+    pub fn finish(mut self) -> [u8; 16] {
+        let bits = self.len.wrapping_mul(8);
+        let mut pad = vec![0x80u8];
+        pad.resize((119 - self.len % 64) as usize % 64 + 1, 0);
+        pad.extend_from_slice(&bits.to_le_bytes());
+        self.update(&pad);
+
+        let mut out = [0; 16];
+        for (i, x) in self.state.iter().enumerate() {
+            out[i * 4..i * 4 + 4].copy_from_slice(&x.to_le_bytes());
+        }
+        out
+    }
+
+    fn block(&mut self, block: &[u8; 64]) {
+        let m: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|x| u32::from_le_bytes(x.try_into().unwrap()))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+
+        for (i, s) in S.iter().enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            // floor(abs(sin(i + 1)) * 2^32)
+            let k = ((i as f64 + 1.0).sin().abs() * 4_294_967_296.0) as u32;
+            let f = f.wrapping_add(a).wrapping_add(k).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(*s));
+        }
+
+        for (x, y) in self.state.iter_mut().zip([a, b, c, d]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::hex;
+
+    fn md5(data: &[u8]) -> String {
+        let mut hasher = Md5::new();
+        hasher.update(data);
+        hex(&hasher.finish())
+    }
+
+    #[test]
+    fn known_answers() {
+        assert_eq!(md5(b""), "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(md5(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+        assert_eq!(
+            md5(
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+            ),
+            "57edf4a22be3c955ac49da2e2107b67a"
+        );
+    }
+}
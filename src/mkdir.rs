@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Creates destination directories, shared between copy workers.
+///
+/// Every directory is created at most once per run, and the configured mode
+/// is only applied to directories created by this run.
+pub struct DirCreator {
+    known: Mutex<HashSet<PathBuf>>,
+    mode: Option<u32>,
+}
+
+impl DirCreator {
+    pub fn new(mode: Option<u32>) -> Self {
+        Self {
+            known: Mutex::new(HashSet::new()),
+            mode,
+        }
+    }
+
+    pub fn create_all(&self, path: &Path) -> io::Result<()> {
+        let mut known = self.known.lock().unwrap();
+        self.create_locked(&mut known, path)
+    }
+
+    fn create_locked(&self, known: &mut HashSet<PathBuf>, path: &Path) -> io::Result<()> {
+        if path.as_os_str().is_empty() || known.contains(path) {
+            return Ok(());
+        }
+
+        if path.is_dir() {
+            known.insert(path.to_path_buf());
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            self.create_locked(known, parent)?;
+        }
+
+        match std::fs::create_dir(path) {
+            Ok(()) => self.apply_mode(path)?,
+            // another process won the race, leave its directory alone
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && path.is_dir() => {}
+            Err(e) => return Err(e),
+        }
+
+        known.insert(path.to_path_buf());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn apply_mode(&self, path: &Path) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        match self.mode {
+            Some(mode) => std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn apply_mode(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Permission bits given to a destination file, written in octal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Mode(u32);
+
+impl Mode {
+    /// `0755`, `755` or `0o755`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_prefix("0o").unwrap_or(s);
+        u32::from_str_radix(s, 8)
+            .ok()
+            .filter(|x| *x <= 0o7777)
+            .map(Mode)
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// This mode with the bits of `umask` cleared.
+    pub fn without(self, umask: Mode) -> Self {
+        Mode(self.0 & !umask.0)
+    }
+
+    /// The permission bits of `path`.
+    #[cfg(unix)]
+    pub fn of(path: &Path) -> io::Result<Self> {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(Mode(std::fs::metadata(path)?.permissions().mode() & 0o7777))
+    }
+
+    #[cfg(not(unix))]
+    pub fn of(_path: &Path) -> io::Result<Self> {
+        Ok(Mode(0o666))
+    }
+
+    #[cfg(unix)]
+    pub fn apply(self, path: &Path) -> io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(self.0))
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl Serialize for Mode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Mode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ModeVisitor)
+    }
+}
+
+struct ModeVisitor;
+
+impl<'de> Visitor<'de> for ModeVisitor {
+    type Value = Mode;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an octal mode like 0644")
+    }
+
+    // YAML reads an unquoted 0644 as the number 644, its digits are octal
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Mode, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Mode, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Mode, E> {
+        Mode::parse(v).ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+/// Rewrites destination keys so they match what a downstream store accepts.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NormalizePolicy {
+    #[serde(default)]
+    pub lowercase: bool,
+    /// Replacement for whitespace in each path component.
+    #[serde(default)]
+    pub spaces: Option<String>,
+    /// Replace everything but `[A-Za-z0-9._-]` with `_`.
+    #[serde(default)]
+    pub safe: bool,
+}
+
+impl NormalizePolicy {
+    /// Parse a comma separated policy like `lowercase,spaces=_,safe`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut policy = Self::default();
+
+        for item in s.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            match item.split_once('=') {
+                None if item == "lowercase" => policy.lowercase = true,
+                None if item == "safe" => policy.safe = true,
+                None if item == "spaces" => policy.spaces = Some("_".to_owned()),
+                Some(("spaces", x)) => policy.spaces = Some(x.to_owned()),
+                _ => return Err(format!("unknown normalization `{}`", item)),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    pub fn apply<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        path.as_ref()
+            .components()
+            .map(|c| match c {
+                Component::Normal(x) => self.apply_component(&x.to_string_lossy()).into(),
+                other => other.as_os_str().to_owned(),
+            })
+            .collect()
+    }
+
+    fn apply_component(&self, s: &str) -> String {
+        let mut s = if self.lowercase {
+            s.to_lowercase()
+        } else {
+            s.to_owned()
+        };
+
+        if let Some(r) = &self.spaces {
+            s = s
+                .split(char::is_whitespace)
+                .collect::<Vec<_>>()
+                .join(r.as_str());
+        }
+
+        if self.safe {
+            s = s
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+        }
+
+        s
+    }
+}
@@ -0,0 +1,103 @@
+use serde::Serialize;
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::stats::Stats;
+
+/// Which runs --notify and --webhook report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    Always,
+    Failure,
+}
+
+impl When {
+    pub const NAMES: [&'static str; 2] = ["always", "failure"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "always" => Some(When::Always),
+            "failure" => Some(When::Failure),
+            _ => None,
+        }
+    }
+}
+
+/// The end of a run, as a notification gets it.
+#[derive(Debug, Serialize)]
+pub struct Summary<'a> {
+    pub specs: Vec<String>,
+    /// `done` or `failed`.
+    pub status: &'static str,
+    #[serde(skip)]
+    pub failed: bool,
+    #[serde(flatten)]
+    pub stats: &'a Stats,
+    pub failures: Vec<Failure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub dst: PathBuf,
+    pub error: String,
+}
+
+impl Summary<'_> {
+    fn title(&self) -> String {
+        format!("cpx {} {}", self.specs.join(" "), self.status)
+    }
+
+    fn body(&self) -> String {
+        format!(
+            "{} copied, {} skipped, {} failed in {:.1}s",
+            self.stats.copied, self.stats.skipped, self.stats.failed, self.stats.elapsed
+        )
+    }
+}
+
+/// Show a desktop notification of the run, through notify-send on Linux
+/// and the BSDs, osascript on macOS.
+pub fn desktop(summary: &Summary) -> io::Result<()> {
+    let (title, body) = (summary.title(), summary.body());
+    let mut cmd = if cfg!(target_os = "macos") {
+        let quote = |x: &str| format!("\"{}\"", x.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut cmd = Command::new("osascript");
+        cmd.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            quote(&body),
+            quote(&title)
+        ));
+        cmd
+    } else if cfg!(unix) {
+        let mut cmd = Command::new("notify-send");
+        cmd.args(["--app-name", "cpx"]);
+        if summary.failed {
+            cmd.args(["--urgency", "critical"]);
+        }
+        cmd.arg("--").arg(title).arg(body);
+        cmd
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "desktop notifications are not supported on this platform",
+        ));
+    };
+
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let out = cmd
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}, {}", program, e)))?;
+    match out.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(
+            String::from_utf8_lossy(&out.stderr).trim().to_owned(),
+        )),
+    }
+}
+
+/// POST the summary as JSON to `url`.
+pub fn webhook(url: &str, summary: &Summary) -> io::Result<()> {
+    crate::http::post_json(url, &serde_json::to_vec(summary)?)
+}
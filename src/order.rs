@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+/// Order the workers take the files of a run in, --order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    /// Largest sources first, so a huge file is not left to one worker at
+    /// the end while the others are idle.
+    #[default]
+    Size,
+    /// By destination path.
+    Path,
+}
+
+impl Order {
+    pub const NAMES: [&'static str; 2] = ["size", "path"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "size" => Some(Order::Size),
+            "path" => Some(Order::Path),
+            _ => None,
+        }
+    }
+
+    /// Sort `ops`, source and destination of each file. Sources whose size
+    /// is not known, like URLs, go last by size.
+    pub fn sort(self, ops: &mut [(PathBuf, PathBuf)]) {
+        match self {
+            Order::Size => ops.sort_by_cached_key(|(src, dst)| {
+                let size = std::fs::metadata(src).map_or(0, |x| x.len());
+                (std::cmp::Reverse(size), dst.clone())
+            }),
+            Order::Path => ops.sort_unstable_by(|a, b| a.1.cmp(&b.1)),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::compare::FileStatus;
+use crate::events::Events;
+use crate::logger;
+use crate::report::Row;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    Text,
+    Json,
+    /// Destination paths of the written files, each followed by a NUL.
+    Print0,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    // planned, reported by dry runs
+    New,
+    Overwrite,
+    Identical,
+    Skip,
+    Delete,
+    // performed
+    Copied,
+    Linked,
+    Skipped,
+    Deleted,
+    Failed,
+    CircuitOpen,
+    /// Not copied, the source does not exist, --ignore-missing.
+    Missing,
+    /// Copied, but the source changed meanwhile, --on-source-change flag.
+    Changed,
+    /// Not copied, both sides changed since the last `cpx sync`.
+    Conflict,
+}
+
+impl Status {
+    /// What a copy would do to a file of `status`, identical files being
+    /// written again unless `changed_only` skips them.
+    pub fn planned(status: FileStatus, changed_only: bool) -> Self {
+        match status {
+            FileStatus::New => Status::New,
+            FileStatus::Identical if changed_only => Status::Skip,
+            FileStatus::Differs | FileStatus::Identical => Status::Overwrite,
+        }
+    }
+
+    pub fn is_planned(self) -> bool {
+        matches!(
+            self,
+            Status::New | Status::Overwrite | Status::Identical | Status::Skip | Status::Delete
+        )
+    }
+
+    /// Color of the status in text lines, green for what is written,
+    /// yellow for what is left and red for what is removed or failed.
+    fn color(self) -> &'static str {
+        match self {
+            Status::New | Status::Overwrite | Status::Copied | Status::Linked => "32",
+            Status::Identical
+            | Status::Skip
+            | Status::Skipped
+            | Status::Missing
+            | Status::Changed => "33",
+            Status::Delete
+            | Status::Deleted
+            | Status::Failed
+            | Status::CircuitOpen
+            | Status::Conflict => "31",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::New => "new",
+            Status::Overwrite => "overwrite",
+            Status::Identical => "identical",
+            Status::Skip => "skip",
+            Status::Delete => "delete",
+            Status::Copied => "copied",
+            Status::Linked => "linked",
+            Status::Skipped => "skipped",
+            Status::Deleted => "deleted",
+            Status::Failed => "failed",
+            Status::CircuitOpen => "circuit_open",
+            Status::Missing => "missing",
+            Status::Changed => "changed",
+            Status::Conflict => "conflict",
+        }
+    }
+}
+
+/// One planned or performed operation on a destination file.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    pub src: Option<&'a Path>,
+    pub dst: &'a Path,
+    pub status: Status,
+    pub bytes: Option<u64>,
+    pub error: Option<String>,
+    /// Tries it took when the first one failed.
+    pub attempts: Option<u32>,
+    /// Seconds it took to copy.
+    pub seconds: Option<f64>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(src: Option<&'a Path>, dst: &'a Path, status: Status) -> Self {
+        Self {
+            src,
+            dst,
+            status,
+            bytes: None,
+            error: None,
+            attempts: None,
+            seconds: None,
+        }
+    }
+}
+
+pub struct Output {
+    pub mode: OutputMode,
+    pub run_id: String,
+    /// Line template for text output, an empty one suppresses the lines.
+    template: Option<String>,
+    counts: Mutex<BTreeMap<String, usize>>,
+    /// Destination and error of each failed file, for the report at the end.
+    failures: Mutex<Vec<(PathBuf, String)>>,
+    /// Bytes written by the copied files.
+    bytes: AtomicU64,
+    /// Color the status of the lines on stdout.
+    color: bool,
+    /// Paths below it are shown relative to it.
+    cwd: PathBuf,
+    /// Gets a `file-done` event for each record.
+    events: Option<Arc<Events>>,
+    /// Each record, kept for --report.
+    rows: Option<Mutex<Vec<Row>>>,
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    run_id: &'a str,
+    #[serde(flatten)]
+    record: &'a Record<'a>,
+}
+
+impl Output {
+    pub fn new(
+        mode: OutputMode,
+        run_id: &str,
+        template: Option<String>,
+        color: bool,
+        events: Option<Arc<Events>>,
+        keep_rows: bool,
+    ) -> Self {
+        Self {
+            mode,
+            run_id: run_id.to_owned(),
+            template,
+            counts: Mutex::new(BTreeMap::new()),
+            failures: Mutex::new(vec![]),
+            bytes: AtomicU64::new(0),
+            color,
+            cwd: std::env::current_dir().unwrap_or_default(),
+            events,
+            rows: keep_rows.then(|| Mutex::new(vec![])),
+        }
+    }
+
+    /// Number of records seen per status name.
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        self.counts.lock().unwrap().clone()
+    }
+
+    /// Start counting anew, for the next round of a watch.
+    pub fn reset(&self) {
+        self.counts.lock().unwrap().clear();
+        self.failures.lock().unwrap().clear();
+        if let Some(rows) = &self.rows {
+            rows.lock().unwrap().clear();
+        }
+        self.bytes.store(0, Ordering::Relaxed);
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Count the records of `other` as well, a part of the same run.
+    pub fn merge(&self, other: &Output) {
+        let mut counts = self.counts.lock().unwrap();
+        for (status, n) in other.counts() {
+            *counts.entry(status).or_insert(0) += n;
+        }
+        self.failures.lock().unwrap().extend(other.failures());
+        if let Some(rows) = &self.rows {
+            rows.lock().unwrap().extend(other.rows());
+        }
+        self.bytes.fetch_add(other.bytes(), Ordering::Relaxed);
+    }
+
+    /// The failed files in the order they failed.
+    pub fn failures(&self) -> Vec<(PathBuf, String)> {
+        self.failures.lock().unwrap().clone()
+    }
+
+    /// The records in the order they were made, none unless kept.
+    pub fn rows(&self) -> Vec<Row> {
+        self.rows
+            .as_ref()
+            .map(|x| x.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether any file was recorded with one of `statuses`.
+    pub fn has_any(&self, statuses: &[Status]) -> bool {
+        let counts = self.counts.lock().unwrap();
+        statuses.iter().any(|x| counts.contains_key(x.name()))
+    }
+
+    pub fn record(&self, r: &Record) {
+        self.log(r);
+        if let Some(events) = &self.events {
+            events.file_done(r);
+        }
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry(r.status.name().to_owned())
+            .or_insert(0) += 1;
+        if let (Status::Copied | Status::Changed, Some(bytes)) = (r.status, r.bytes) {
+            self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        }
+        if r.status == Status::Failed {
+            let error = r.error.clone().unwrap_or_default();
+            self.failures
+                .lock()
+                .unwrap()
+                .push((r.dst.to_path_buf(), error));
+        }
+        if let Some(rows) = &self.rows {
+            rows.lock().unwrap().push(Row {
+                status: r.status,
+                src: r.src.map(Path::to_path_buf),
+                dst: r.dst.to_path_buf(),
+                bytes: r.bytes,
+                hash: None,
+                seconds: r.seconds,
+                error: r.error.clone(),
+            });
+        }
+        if r.attempts.is_some() {
+            *self
+                .counts
+                .lock()
+                .unwrap()
+                .entry("retried".to_owned())
+                .or_insert(0) += 1;
+        }
+
+        match self.mode {
+            OutputMode::Json => {
+                let line = JsonLine {
+                    run_id: &self.run_id,
+                    record: r,
+                };
+                println!("{}", serde_json::to_string(&line).unwrap())
+            }
+            OutputMode::Print0 => {
+                let written = matches!(
+                    r.status,
+                    Status::New
+                        | Status::Overwrite
+                        | Status::Copied
+                        | Status::Linked
+                        | Status::Changed
+                );
+                if written {
+                    print0(r.dst);
+                }
+            }
+            OutputMode::Text if self.template.is_some() => {
+                let line = self.render(self.template.as_deref().unwrap_or_default(), r);
+                if !line.is_empty() {
+                    println!("{}", line);
+                }
+            }
+            OutputMode::Text if r.status.is_planned() => {
+                println!("{}", self.line(r, self.color, true))
+            }
+            OutputMode::Text => {}
+        }
+    }
+
+    /// `status     src -> dst`, the status aligned and maybe colored and
+    /// the paths maybe relative to the current directory.
+    fn line(&self, r: &Record, color: bool, relative: bool) -> String {
+        let shown = |x: &Path| match x.strip_prefix(&self.cwd) {
+            Ok(x) if relative && !x.as_os_str().is_empty() => x.display().to_string(),
+            _ => x.display().to_string(),
+        };
+        let status = match color {
+            true => format!(
+                "\x1b[{}m{}\x1b[0m{:pad$}",
+                r.status.color(),
+                r.status.name(),
+                "",
+                pad = 10usize.saturating_sub(r.status.name().len())
+            ),
+            false => format!("{:<10}", r.status.name()),
+        };
+        match r.src {
+            Some(src) => format!("{} {} -> {}", status, shown(src), shown(r.dst)),
+            None => format!("{} {}", status, shown(r.dst)),
+        }
+    }
+
+    /// Fill `{status}`, `{src}`, `{dst}`, `{bytes}`, `{error}`, `{attempts}`
+    /// and `{run_id}` in `template`, other text is kept as is.
+    fn render(&self, template: &str, r: &Record) -> String {
+        let mut out = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let end = match rest.find('}') {
+                Some(x) => x,
+                None => break,
+            };
+            let value = match &rest[1..end] {
+                "status" => r.status.name().to_owned(),
+                "src" => r.src.map(|x| x.display().to_string()).unwrap_or_default(),
+                "dst" => r.dst.display().to_string(),
+                "bytes" => r.bytes.map(|x| x.to_string()).unwrap_or_default(),
+                "error" => r.error.clone().unwrap_or_default(),
+                "attempts" => r.attempts.unwrap_or(1).to_string(),
+                "run_id" => self.run_id.clone(),
+                _ => rest[..=end].to_owned(),
+            };
+            out.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+        out.push_str(rest);
+
+        out
+    }
+
+    fn log(&self, r: &Record) {
+        let dst = r.dst.display();
+        let error = r.error.as_deref().unwrap_or_default();
+
+        match (r.status, r.src) {
+            (Status::Failed, Some(src)) => {
+                log::error!("Copy {} -> {} failed, {}", src.display(), dst, error)
+            }
+            (Status::Failed, None) => log::error!("Delete {} failed, {}", dst, error),
+            (Status::Copied | Status::Linked | Status::Skipped | Status::Deleted, _) => {
+                let (level, color) = logger::console();
+                if level >= log::LevelFilter::Info {
+                    eprintln!("{}", self.line(r, color, true));
+                }
+                log::info!(target: logger::FILE_ONLY, "{}", self.line(r, false, false));
+            }
+            (Status::CircuitOpen, _) => log::warn!("Skip {}, circuit open", dst),
+            (Status::Missing, Some(src)) => {
+                log::warn!("Skip {}, the source is missing", src.display())
+            }
+            (Status::Changed, Some(src)) => log::warn!(
+                "Copied {}, but {} changed while it was copied",
+                dst,
+                src.display()
+            ),
+            (Status::Conflict, Some(src)) => log::error!(
+                "Skip {} and {}, both changed since the last sync",
+                src.display(),
+                dst
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Write `path` and a NUL to stdout, as is on unix where paths are bytes.
+fn print0(path: &Path) {
+    #[cfg(unix)]
+    let bytes = std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()).to_vec();
+    #[cfg(not(unix))]
+    let bytes = path.to_string_lossy().into_owned().into_bytes();
+
+    let mut out = std::io::stdout().lock();
+    let _ = out.write_all(&bytes).and_then(|_| out.write_all(b"\0"));
+}
@@ -0,0 +1,97 @@
+use std::io;
+use std::path::Path;
+
+/// User and group given to destination files, from `user:group`, `user`
+/// or `:group` with names or numeric ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Owner {
+    uid: Option<u32>,
+    gid: Option<u32>,
+}
+
+impl Owner {
+    pub fn parse(spec: &str) -> io::Result<Self> {
+        let (user, group) = match spec.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (spec, None),
+        };
+        let uid = match user {
+            "" => None,
+            _ => Some(lookup(user, Kind::User)?),
+        };
+        let gid = match group {
+            Some("") | None => None,
+            Some(group) => Some(lookup(group, Kind::Group)?),
+        };
+        if uid.is_none() && gid.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("no user or group in `{}`", spec),
+            ));
+        }
+
+        Ok(Self { uid, gid })
+    }
+
+    /// Change the owner of `path`, of a symlink itself and not its target.
+    #[cfg(unix)]
+    pub fn apply(self, path: &Path) -> io::Result<()> {
+        std::os::unix::fs::lchown(path, self.uid, self.gid)
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    User,
+    Group,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::User => "user",
+            Kind::Group => "group",
+        }
+    }
+}
+
+fn lookup(name: &str, kind: Kind) -> io::Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+
+    lookup_name(name, kind).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no {} named {}", kind.name(), name),
+        )
+    })
+}
+
+#[cfg(unix)]
+fn lookup_name(name: &str, kind: Kind) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // only called while a run is set up, never by the copy workers
+    unsafe {
+        match kind {
+            Kind::User => {
+                let x = libc::getpwnam(name.as_ptr());
+                (!x.is_null()).then(|| (*x).pw_uid)
+            }
+            Kind::Group => {
+                let x = libc::getgrnam(name.as_ptr());
+                (!x.is_null()).then(|| (*x).gr_gid)
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lookup_name(_name: &str, _kind: Kind) -> Option<u32> {
+    None
+}
@@ -0,0 +1,125 @@
+use std::path::{Component, Path, PathBuf};
+
+/// `path` with its root and any Windows drive or share dropped, so joining
+/// it to a destination cannot leave the destination, in particular for a
+/// `C:\dir` or `\dir` on Windows. Separators are those of the platform.
+pub fn relative(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|x| {
+            !matches!(
+                x,
+                Component::Prefix(_) | Component::RootDir | Component::CurDir
+            )
+        })
+        .collect()
+}
+
+/// `path` without the `\\?\` that `canonicalize` puts in front of it on
+/// Windows, where it is not needed. Other programs, and the targets of
+/// symlinks, do not all understand it. Paths too long to go without it
+/// keep it, std adds it itself to the long paths cpx opens.
+pub fn simplify(path: &Path) -> PathBuf {
+    match path.to_str().and_then(simplify_str) {
+        Some(x) if cfg!(windows) => PathBuf::from(x),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Whether `a` and `b` are the same directory or one is inside the
+/// other, with symlinks resolved. Paths that do not exist yet are
+/// resolved from their nearest existing parent.
+pub fn overlap(a: &Path, b: &Path) -> bool {
+    let (a, b) = (resolve(a), resolve(b));
+    a.starts_with(&b) || b.starts_with(&a)
+}
+
+fn resolve(path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    for dir in path.ancestors() {
+        if let Ok(x) = dir.canonicalize() {
+            // the ancestor is a prefix of the path
+            return x.join(path.strip_prefix(dir).unwrap());
+        }
+    }
+    path
+}
+
+/// Longest path Windows takes without `\\?\`.
+const MAX_PATH: usize = 260;
+
+fn simplify_str(path: &str) -> Option<String> {
+    let (rest, unc) = match path.strip_prefix(r"\\?\") {
+        Some(x) => match x.strip_prefix(r"UNC\") {
+            Some(x) => (x, true),
+            None => (x, false),
+        },
+        None => return None,
+    };
+
+    // a verbatim path is taken as is, one with `.`, `..`, `/` or names
+    // Windows reserves means something else without the prefix
+    let reserved = |name: &str| {
+        let stem = name
+            .split('.')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let stem = stem.trim_end_matches(' ');
+        matches!(stem, "CON" | "PRN" | "AUX" | "NUL")
+            || ((stem.starts_with("COM") || stem.starts_with("LPT"))
+                && stem.len() == 4
+                && stem.as_bytes()[3].is_ascii_digit())
+    };
+    let mut names = rest.split('\\').skip(if unc { 0 } else { 1 });
+    let plain = !rest.contains('/')
+        && rest.split('\\').all(|x| x != "." && x != "..")
+        && names.all(|x| !reserved(x) && !x.ends_with(['.', ' ']));
+    let drive =
+        rest.len() >= 2 && rest.as_bytes()[0].is_ascii_alphabetic() && rest.as_bytes()[1] == b':';
+
+    let simple = match unc {
+        true => format!(r"\\{}", rest),
+        false if drive => rest.to_owned(),
+        false => return None,
+    };
+    Some(simple).filter(|x| plain && x.len() < MAX_PATH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_drops_roots() {
+        assert_eq!(relative(Path::new("a/b")), Path::new("a").join("b"));
+        assert_eq!(relative(Path::new("/etc/a")), Path::new("etc").join("a"));
+        assert_eq!(relative(Path::new("./a")), Path::new("a"));
+        assert_eq!(relative(Path::new("a/../b")), Path::new("a/../b"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn relative_drops_drives() {
+        assert_eq!(relative(Path::new(r"C:\a\b")), Path::new(r"a\b"));
+        assert_eq!(relative(Path::new(r"\a")), Path::new("a"));
+        assert_eq!(relative(Path::new("a/b")), Path::new(r"a\b"));
+        assert_eq!(relative(Path::new(r"\\server\share\a")), Path::new("a"));
+    }
+
+    #[test]
+    fn simplify_strips_verbatim() {
+        let s = |x: &str| simplify_str(x);
+        assert_eq!(s(r"\\?\C:\build\out").as_deref(), Some(r"C:\build\out"));
+        assert_eq!(s(r"\\?\UNC\srv\share\a").as_deref(), Some(r"\\srv\share\a"));
+        assert_eq!(s(r"C:\build"), None);
+        assert_eq!(s(r"\\?\Volume{1234}\a"), None);
+        assert_eq!(s(r"\\?\C:\a\..\b"), None);
+        assert_eq!(s(r"\\?\C:\dir\nul.txt"), None);
+        assert_eq!(s(r"\\?\C:\dir\COM1"), None);
+        assert_eq!(s(r"\\?\C:\dir\trailing."), None);
+        assert_eq!(s(r"\\?\C:\dir\com10").as_deref(), Some(r"C:\dir\com10"));
+
+        let long = format!(r"\\?\C:\{}", "a".repeat(MAX_PATH));
+        assert_eq!(s(&long), None);
+    }
+}
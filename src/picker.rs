@@ -0,0 +1,254 @@
+use std::io::{self, BufRead, Read, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Tag,
+    File,
+}
+
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub kind: Kind,
+    pub name: String,
+    pub selected: bool,
+}
+
+impl Item {
+    fn label(&self) -> String {
+        match self.kind {
+            Kind::Tag => format!("tag  {}", self.name),
+            Kind::File => format!("file {}", self.name),
+        }
+    }
+}
+
+/// Score `candidate` against a fuzzy `query`: every query character has to
+/// appear in order, consecutive matches score higher. `None` if no match.
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last: Option<usize> = None;
+
+    for q in query.to_lowercase().chars() {
+        let found = candidate[pos..].iter().position(|c| *c == q)? + pos;
+        score += match last {
+            Some(l) if l + 1 == found => 5,
+            _ => 1,
+        };
+        last = Some(found);
+        pos = found + 1;
+    }
+
+    Some(score * 100 - candidate.len() as i64)
+}
+
+fn filter(items: &[Item], query: &str) -> Vec<usize> {
+    let mut matched: Vec<_> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(i, x)| fuzzy_score(query, &x.name).map(|s| (s, i)))
+        .collect();
+    matched.sort_by_key(|(s, i)| (-s, *i));
+    matched.into_iter().map(|(_, i)| i).collect()
+}
+
+/// Let the user pick items; returns `None` when cancelled.
+pub fn pick(items: Vec<Item>) -> io::Result<Option<Vec<Item>>> {
+    #[cfg(unix)]
+    {
+        if let Ok(tty) = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+        {
+            return tui::run(tty, items);
+        }
+    }
+
+    pick_by_number(items)
+}
+
+/// Fallback without a terminal: print a numbered list and read a line.
+fn pick_by_number(mut items: Vec<Item>) -> io::Result<Option<Vec<Item>>> {
+    for (i, item) in items.iter().enumerate() {
+        eprintln!("{:>3}) {}", i + 1, item.label());
+    }
+    eprint!("Select (e.g. 1 3 5): ");
+    io::stderr().flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    for n in line.split(|c: char| c == ',' || c.is_whitespace()) {
+        if let Some(item) = n
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| items.get_mut(n.wrapping_sub(1)))
+        {
+            item.selected = true;
+        }
+    }
+
+    Ok(Some(items))
+}
+
+#[cfg(unix)]
+mod tui {
+    use super::*;
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    const VISIBLE: usize = 15;
+
+    struct RawMode {
+        fd: i32,
+        saved: libc::termios,
+    }
+
+    impl RawMode {
+        fn enable(tty: &File) -> io::Result<Self> {
+            let fd = tty.as_raw_fd();
+            let mut saved = unsafe { std::mem::zeroed::<libc::termios>() };
+            if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self { fd, saved })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved) };
+        }
+    }
+
+    enum Key {
+        Char(char),
+        Up,
+        Down,
+        Toggle,
+        Backspace,
+        Enter,
+        Cancel,
+        Other,
+    }
+
+    fn read_key(tty: &mut File) -> io::Result<Key> {
+        let mut b = [0u8; 1];
+        tty.read_exact(&mut b)?;
+
+        Ok(match b[0] {
+            b'\r' | b'\n' => Key::Enter,
+            b' ' | b'\t' => Key::Toggle,
+            0x7f | 0x08 => Key::Backspace,
+            0x03 => Key::Cancel,
+            0x1b => {
+                // a lone escape cancels, escape sequences are arrow keys
+                let mut seq = [0u8; 2];
+                let mut fds = libc::pollfd {
+                    fd: tty.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                if unsafe { libc::poll(&mut fds, 1, 30) } <= 0 {
+                    return Ok(Key::Cancel);
+                }
+                tty.read_exact(&mut seq)?;
+                match seq {
+                    [b'[', b'A'] => Key::Up,
+                    [b'[', b'B'] => Key::Down,
+                    _ => Key::Other,
+                }
+            }
+            c if c.is_ascii_graphic() => Key::Char(c as char),
+            _ => Key::Other,
+        })
+    }
+
+    fn render(
+        tty: &mut File,
+        items: &[Item],
+        shown: &[usize],
+        query: &str,
+        cursor: usize,
+        lines: &mut usize,
+    ) -> io::Result<()> {
+        let mut out = String::new();
+        // move back to where the previous frame started
+        if *lines > 0 {
+            out.push_str(&format!("\r\x1b[{}A", *lines));
+        }
+        out.push_str("\r\x1b[J");
+        out.push_str(&format!(
+            "> {}\r\n  (type to filter, space toggles, enter runs, esc cancels)\r\n",
+            query
+        ));
+
+        let start = cursor.saturating_sub(VISIBLE - 1);
+        for (row, &i) in shown.iter().enumerate().skip(start).take(VISIBLE) {
+            let item = &items[i];
+            out.push_str(&format!(
+                "{}{} {}\x1b[0m\r\n",
+                if row == cursor { "\x1b[7m" } else { "" },
+                if item.selected { "[x]" } else { "[ ]" },
+                item.label()
+            ));
+        }
+        *lines = 2 + shown.len().saturating_sub(start).min(VISIBLE);
+
+        tty.write_all(out.as_bytes())?;
+        tty.flush()
+    }
+
+    pub fn run(mut tty: File, mut items: Vec<Item>) -> io::Result<Option<Vec<Item>>> {
+        let _raw = RawMode::enable(&tty)?;
+        let mut query = String::new();
+        let mut cursor = 0;
+        let mut lines = 0;
+
+        loop {
+            let shown = filter(&items, &query);
+            cursor = cursor.min(shown.len().saturating_sub(1));
+            render(&mut tty, &items, &shown, &query, cursor, &mut lines)?;
+
+            match read_key(&mut tty)? {
+                Key::Char(c) => {
+                    query.push(c);
+                    cursor = 0;
+                }
+                Key::Backspace => {
+                    query.pop();
+                    cursor = 0;
+                }
+                Key::Up => cursor = cursor.saturating_sub(1),
+                Key::Down => cursor += 1,
+                Key::Toggle => {
+                    if let Some(&i) = shown.get(cursor) {
+                        items[i].selected = !items[i].selected;
+                    }
+                }
+                Key::Enter => {
+                    tty.write_all(b"\r\n")?;
+                    return Ok(Some(items));
+                }
+                Key::Cancel => {
+                    tty.write_all(b"\r\n")?;
+                    return Ok(None);
+                }
+                Key::Other => {}
+            }
+        }
+    }
+}
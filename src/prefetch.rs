@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+
+/// Most of a file to warm, so a huge file does not evict the ones before.
+const MAX_WARM: u64 = 256 * 1024 * 1024;
+
+/// Warms the page cache for the source files the workers come to next, so
+/// reading them overlaps with writing the current ones.
+pub struct Prefetch {
+    depth: usize,
+    /// Number of files the workers took so far.
+    taken: Mutex<usize>,
+    wake: Condvar,
+}
+
+impl Prefetch {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            taken: Mutex::new(0),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// A worker took the file at index `i`.
+    pub fn advance(&self, i: usize) {
+        let mut taken = self.taken.lock().unwrap();
+        *taken = (*taken).max(i + 1);
+        self.wake.notify_one();
+    }
+
+    /// Warm the sources of `ops` in order, at most `depth` files ahead of
+    /// the workers.
+    pub fn run(&self, ops: &[(PathBuf, PathBuf)]) {
+        for (i, (src, _)) in ops.iter().enumerate() {
+            let mut taken = self.taken.lock().unwrap();
+            while i >= *taken + self.depth {
+                taken = self.wake.wait(taken).unwrap();
+            }
+            // already being copied, too late to help
+            if i < *taken {
+                continue;
+            }
+            drop(taken);
+
+            if let Err(e) = warm(src) {
+                log::debug!("Prefetch {} failed, {}", src.display(), e);
+            }
+        }
+    }
+}
+
+/// Ask the kernel to read `path` ahead, it does so in the background.
+#[cfg(target_os = "linux")]
+fn warm(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let f = File::open(path)?;
+    let len = f.metadata()?.len().min(MAX_WARM);
+    let ret = unsafe {
+        libc::posix_fadvise(
+            f.as_raw_fd(),
+            0,
+            len as libc::off_t,
+            libc::POSIX_FADV_WILLNEED,
+        )
+    };
+
+    match ret {
+        0 => Ok(()),
+        e => Err(std::io::Error::from_raw_os_error(e)),
+    }
+}
+
+/// Without readahead hints read the file, the page cache keeps it.
+#[cfg(not(target_os = "linux"))]
+fn warm(path: &Path) -> std::io::Result<()> {
+    use std::io::Read;
+
+    let f = File::open(path)?;
+    std::io::copy(&mut f.take(MAX_WARM), &mut std::io::sink())?;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::BTreeMap;
+
+/// A copy of the `profiles` section, started by `cpx run <name>`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    /// Source and destination entries of `path_list`.
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// Any other long option by name, `changed_only: true` is
+    /// --changed-only and `jobs: 4` is --jobs 4.
+    #[serde(flatten)]
+    pub options: BTreeMap<String, Value>,
+}
+
+impl Preset {
+    /// The command line of the copy, without the program name.
+    pub fn args(&self) -> Result<Vec<String>, String> {
+        let mut args = vec![format!("{}:{}", self.from, self.to)];
+        for x in &self.tags {
+            args.extend(["--tag".to_owned(), x.clone()]);
+        }
+        for x in &self.files {
+            args.extend(["--file".to_owned(), x.clone()]);
+        }
+
+        for (name, value) in &self.options {
+            let flag = format!("--{}", name.replace('_', "-"));
+            let values = match value {
+                Value::Sequence(x) => x.iter().collect(),
+                x => vec![x],
+            };
+            for value in values {
+                match value {
+                    Value::Bool(true) => args.push(flag.clone()),
+                    Value::Bool(false) | Value::Null => {}
+                    // joined, for options like --backup whose value is optional
+                    Value::String(x) => args.push(format!("{}={}", flag, x)),
+                    Value::Number(x) => args.push(format!("{}={}", flag, x)),
+                    _ => return Err(format!("`{}` is not a flag or a value", name)),
+                }
+            }
+        }
+
+        Ok(args)
+    }
+}
@@ -0,0 +1,75 @@
+use std::io;
+
+/// Nice value of a --low-priority process.
+#[cfg(unix)]
+const NICE: libc::c_int = 10;
+
+/// Lower the CPU and IO priority of this thread and the threads and
+/// processes it starts from now on, for --low-priority. Priorities already
+/// lower are kept.
+#[cfg(unix)]
+pub fn lower() -> io::Result<()> {
+    // -1 is a valid priority too, errno tells them apart
+    unsafe { *errno() = 0 };
+    let current = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+    if current == -1 && io::Error::last_os_error().raw_os_error() != Some(0) {
+        return Err(io::Error::last_os_error());
+    }
+    if current < NICE && unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, NICE) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    lower_io()
+}
+
+#[cfg(not(unix))]
+pub fn lower() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "priorities are only changed on unix",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+fn errno() -> *mut libc::c_int {
+    unsafe { libc::__errno_location() }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+fn errno() -> *mut libc::c_int {
+    unsafe { libc::__error() }
+}
+
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd"
+    ))
+))]
+fn errno() -> *mut libc::c_int {
+    unsafe { libc::__errno() }
+}
+
+/// The idle IO class, like `ionice -c 3`: disk time only when no one
+/// else wants it.
+#[cfg(target_os = "linux")]
+fn lower_io() -> io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+
+    let prio = IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT;
+    match unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Elsewhere IO follows the CPU priority, as far as the kernel does so.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn lower_io() -> io::Result<()> {
+    Ok(())
+}
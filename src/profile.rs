@@ -0,0 +1,155 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::events::Progress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Stat,
+    Read,
+    Write,
+    Fsync,
+    Verify,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 5] = [
+        Phase::Stat,
+        Phase::Read,
+        Phase::Write,
+        Phase::Fsync,
+        Phase::Verify,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Stat => "stat",
+            Phase::Read => "read",
+            Phase::Write => "write",
+            Phase::Fsync => "fsync",
+            Phase::Verify => "verify",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Csv,
+    Folded,
+}
+
+/// Time spent copying a single file, split by phase.
+#[derive(Debug, Clone)]
+pub struct FileProfile {
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub total: Duration,
+    phases: [Duration; 5],
+    /// Where the bytes written are reported, with --progress-events.
+    pub progress: Option<Arc<Progress>>,
+}
+
+impl FileProfile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            bytes: 0,
+            total: Duration::default(),
+            phases: Default::default(),
+            progress: None,
+        }
+    }
+
+    pub fn add(&mut self, phase: Phase, d: Duration) {
+        self.phases[phase as usize] += d;
+    }
+
+    /// Run `f` and charge its duration to `phase`.
+    pub fn measure<T, F: FnOnce() -> T>(&mut self, phase: Phase, f: F) -> T {
+        let start = Instant::now();
+        let r = f();
+        self.add(phase, start.elapsed());
+        r
+    }
+
+    /// `n` more bytes written.
+    pub fn advance(&self, n: u64) {
+        if let Some(x) = &self.progress {
+            x.advance(n);
+        }
+    }
+
+    pub fn get(&self, phase: Phase) -> Duration {
+        self.phases[phase as usize]
+    }
+}
+
+pub struct Profiler {
+    format: ProfileFormat,
+    files: Mutex<Vec<FileProfile>>,
+}
+
+impl Profiler {
+    pub fn new(format: ProfileFormat) -> Self {
+        Self {
+            format,
+            files: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn push(&self, profile: FileProfile) {
+        self.files.lock().unwrap().push(profile);
+    }
+
+    pub fn write_to<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap().clone();
+        files.sort_by_key(|x| std::cmp::Reverse(x.total));
+
+        match self.format {
+            ProfileFormat::Csv => {
+                write!(w, "file,bytes,total_us")?;
+                for p in Phase::ALL.iter() {
+                    write!(w, ",{}_us", p.name())?;
+                }
+                writeln!(w)?;
+
+                for f in files {
+                    write!(
+                        w,
+                        "\"{}\",{},{}",
+                        f.path.display().to_string().replace('"', "\"\""),
+                        f.bytes,
+                        f.total.as_micros()
+                    )?;
+                    for p in Phase::ALL.iter() {
+                        write!(w, ",{}", f.get(*p).as_micros())?;
+                    }
+                    writeln!(w)?;
+                }
+            }
+            // one "stack count" line per phase, consumable by flamegraph.pl / inferno
+            ProfileFormat::Folded => {
+                for f in files {
+                    let name = f.path.display().to_string().replace(';', "_");
+                    let mut accounted = Duration::default();
+                    for p in Phase::ALL.iter() {
+                        let d = f.get(*p);
+                        accounted += d;
+                        if d.as_micros() > 0 {
+                            writeln!(w, "cpx;{};{} {}", name, p.name(), d.as_micros())?;
+                        }
+                    }
+
+                    let other = f.total.checked_sub(accounted).unwrap_or_default();
+                    if other.as_micros() > 0 {
+                        writeln!(w, "cpx;{};other {}", name, other.as_micros())?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,415 @@
+/// A small regular expression, searched for anywhere in a string.
+///
+/// It knows literals, `.`, classes like `[a-z_]` and `[^/]`, `\d`, `\w`,
+/// `\s`, anchors `^` and `$`, groups with `|` and the greedy `*`, `+`,
+/// `?` and `{n,m}`. The pattern is compiled to a program whose threads
+/// all advance a character at a time, in time linear in the text.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+    /// Continue at both.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+/// Instructions a pattern may compile to, counts like `{1000}` repeat
+/// their node that often.
+const MAX_PROGRAM: usize = 100_000;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+        };
+        let alts = parser.alts()?;
+        if parser.chars.get(parser.pos).is_some() {
+            return Err(format!("unmatched `)` in `{}`", pattern));
+        }
+
+        let mut program = vec![];
+        compile_alts(&alts, &mut program).map_err(|e| format!("`{}` {}", pattern, e))?;
+        program.push(Inst::Match);
+        Ok(Self { program })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<_> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+
+        for pos in 0..=text.len() {
+            // a match starts anywhere
+            if self.add(&mut current, 0, pos, text.len()) {
+                return true;
+            }
+            let c = match text.get(pos) {
+                Some(x) => *x,
+                None => break,
+            };
+
+            next.clear();
+            for &pc in &current.list {
+                let step = match &self.program[pc] {
+                    Inst::Char(x) => *x == c,
+                    Inst::Any => true,
+                    Inst::Class(ranges, negated) => {
+                        ranges.iter().any(|(a, b)| (*a..=*b).contains(&c)) != *negated
+                    }
+                    _ => false,
+                };
+                if step && self.add(&mut next, pc + 1, pos + 1, text.len()) {
+                    return true;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+        false
+    }
+
+    /// Add the thread at `pc` to `threads` at `pos`, following jumps and
+    /// assertions to the instructions taking a character. Whether one of
+    /// them reached the match.
+    fn add(&self, threads: &mut Threads, pc: usize, pos: usize, len: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            // once per position, which also ends loops of empty matches
+            if threads.seen[pc] == threads.generation {
+                continue;
+            }
+            threads.seen[pc] = threads.generation;
+
+            match &self.program[pc] {
+                Inst::Match => return true,
+                Inst::Jump(x) => stack.push(*x),
+                Inst::Split(a, b) => stack.extend([*b, *a]),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == len => stack.push(pc + 1),
+                Inst::Start | Inst::End => {}
+                _ => threads.list.push(pc),
+            }
+        }
+        false
+    }
+}
+
+/// The threads at a position of the text, each at an instruction
+/// taking a character.
+struct Threads {
+    list: Vec<usize>,
+    /// The generation an instruction was last added in.
+    seen: Vec<usize>,
+    generation: usize,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self {
+            list: vec![],
+            seen: vec![0; len],
+            generation: 1,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.generation += 1;
+    }
+}
+
+/// Append `alts` to `program`, split to each of them which then jump to
+/// the end. Errors once the program grows past `MAX_PROGRAM`.
+fn compile_alts(alts: &[Vec<Node>], program: &mut Vec<Inst>) -> Result<(), String> {
+    let mut jumps = vec![];
+    for (i, seq) in alts.iter().enumerate() {
+        let split = program.len();
+        let last = i + 1 == alts.len();
+        if !last {
+            program.push(Inst::Split(split + 1, 0));
+        }
+        for node in seq {
+            compile(node, program)?;
+        }
+        if !last {
+            jumps.push(program.len());
+            program.push(Inst::Jump(0));
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+    }
+    let end = program.len();
+    for x in jumps {
+        program[x] = Inst::Jump(end);
+    }
+    Ok(())
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), String> {
+    if program.len() > MAX_PROGRAM {
+        return Err("repeats too much".to_owned());
+    }
+    match node {
+        Node::Char(x) => program.push(Inst::Char(*x)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(ranges, negated) => program.push(Inst::Class(ranges.clone(), *negated)),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::Group(alts) => compile_alts(alts, program)?,
+        Node::Repeat(node, min, max) => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            match max {
+                // the node, then back to choose again
+                None => {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(split));
+                    program[split] = Inst::Split(split + 1, program.len());
+                }
+                // each optional one skips to the end
+                Some(max) => {
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program)?;
+                    }
+                    let end = program.len();
+                    for x in splits {
+                        program[x] = Inst::Split(x + 1, end);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied();
+        self.pos += 1;
+        c
+    }
+
+    fn alts(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut alts = vec![self.seq()?];
+        while self.chars.get(self.pos) == Some(&'|') {
+            self.pos += 1;
+            alts.push(self.seq()?);
+        }
+        Ok(alts)
+    }
+
+    fn seq(&mut self) -> Result<Vec<Node>, String> {
+        let mut seq = vec![];
+        while let Some(&c) = self.chars.get(self.pos) {
+            if c == '|' || c == ')' {
+                break;
+            }
+            self.pos += 1;
+            let node = match c {
+                '.' => Node::Any,
+                '^' => Node::Start,
+                '$' => Node::End,
+                '(' => {
+                    let alts = self.alts()?;
+                    if self.next() != Some(')') {
+                        return Err("unclosed `(`".to_owned());
+                    }
+                    Node::Group(alts)
+                }
+                '[' => self.class()?,
+                '\\' => self.escape()?,
+                '*' | '+' | '?' | '{' => return Err(format!("nothing to repeat before `{}`", c)),
+                c => Node::Char(c),
+            };
+            let node = self.repeat(node)?;
+            seq.push(node);
+        }
+        Ok(seq)
+    }
+
+    fn repeat(&mut self, node: Node) -> Result<Node, String> {
+        let (min, max) = match self.chars.get(self.pos) {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+                let rest: String = self.chars[self.pos..].iter().collect();
+                let end = rest.find('}').ok_or("unclosed `{`")?;
+                let (min, max) = match rest[..end].split_once(',') {
+                    Some((min, "")) => (min, None),
+                    Some((min, max)) => (min, Some(max)),
+                    None => (&rest[..end], Some(&rest[..end])),
+                };
+                let count = |x: &str| {
+                    x.trim()
+                        .parse::<usize>()
+                        .map_err(|_| format!("invalid count `{{{}}}`", &rest[..end]))
+                };
+                let (min, max) = (count(min)?, max.map(count).transpose()?);
+                if max.is_some_and(|x| x < min) {
+                    return Err(format!("invalid count `{{{}}}`", &rest[..end]));
+                }
+                self.pos += rest[..end].chars().count();
+                (min, max)
+            }
+            _ => return Ok(node),
+        };
+        self.pos += 1;
+        if matches!(node, Node::Start | Node::End) {
+            return Err("nothing to repeat before a quantifier".to_owned());
+        }
+        Ok(Node::Repeat(Box::new(node), min, max))
+    }
+
+    fn escape(&mut self) -> Result<Node, String> {
+        let c = self.next().ok_or("trailing `\\`")?;
+        Ok(match c {
+            'd' | 'w' | 's' | 'D' | 'W' | 'S' => {
+                Node::Class(class_of(c.to_ascii_lowercase()), c.is_ascii_uppercase())
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.chars.get(self.pos) == Some(&'^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            let c = match self.next() {
+                Some(']') if !first => break,
+                Some('\\') => match self.next() {
+                    Some(x @ ('d' | 'w' | 's')) => {
+                        ranges.extend(class_of(x));
+                        first = false;
+                        continue;
+                    }
+                    Some(x) => x,
+                    None => return Err("unclosed `[`".to_owned()),
+                },
+                Some(x) => x,
+                None => return Err("unclosed `[`".to_owned()),
+            };
+            first = false;
+
+            let range = self.chars.get(self.pos) == Some(&'-')
+                && self.chars.get(self.pos + 1).is_some_and(|x| *x != ']');
+            if range {
+                let end = self.chars[self.pos + 1];
+                self.pos += 2;
+                if end < c {
+                    return Err(format!("invalid range `{}-{}`", c, end));
+                }
+                ranges.push((c, end));
+            } else {
+                ranges.push((c, c));
+            }
+        }
+
+        Ok(Node::Class(ranges, negated))
+    }
+}
+
+fn class_of(c: char) -> Vec<(char, char)> {
+    match c {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        _ => vec![(' ', ' '), ('\t', '\r')],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(text)
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[]a]", "]"));
+        assert!(matches("[]a]", "a"));
+        assert!(!matches("[]a]", "b"));
+        assert!(matches("^[^/]+$", "name.rs"));
+        assert!(!matches("^[^/]+$", "src/name.rs"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches(r"^\d\w\s$", "1_ "));
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("[a").is_err());
+    }
+
+    #[test]
+    fn counts() {
+        assert!(matches("^a{2}$", "aa"));
+        assert!(!matches("^a{2}$", "aaa"));
+        assert!(matches("^a{1,3}$", "aaa"));
+        assert!(!matches("^a{1,3}$", "aaaa"));
+        assert!(matches("^a{2,}$", "aaaaa"));
+        assert!(!matches("^a{2,}$", "a"));
+        assert!(matches("^(ab){0,1}c$", "c"));
+        assert!(Regex::new("a{3,1}").is_err());
+        assert!(Regex::new("a{x}").is_err());
+        assert!(Regex::new("a{2").is_err());
+        assert!(Regex::new("{2}").is_err());
+        assert!(Regex::new("(a{1000}){1000}").is_err());
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(matches(r"\.rs$", "src/main.rs"));
+        assert!(!matches(r"\.rs$", "src/main.rs.bak"));
+        assert!(matches("^src/", "src/main.rs"));
+        assert!(!matches("^src/", "lib/src/main.rs"));
+        assert!(matches("main", "src/main.rs"));
+        assert!(matches("^$", ""));
+        assert!(matches("^(a|b)$|^c", "cd"));
+        assert!(!matches("a^b", "ab"));
+        assert!(Regex::new("^*").is_err());
+        assert!(Regex::new("(a").is_err());
+        assert!(Regex::new("a)").is_err());
+    }
+
+    #[test]
+    fn pathological() {
+        let path = format!("src/{}", "a".repeat(2000));
+        assert!(!matches(r"([a-z]+)*\.rs$", &path));
+        assert!(!matches("(.*)*x", &"a".repeat(2000)));
+        assert!(matches("(a*)*b", &format!("{}b", "a".repeat(2000))));
+    }
+}
@@ -0,0 +1,282 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+use crate::compare::{FileStatus, Strategy};
+use crate::hashcache::HashCache;
+use crate::state::state_dir;
+
+/// Connection settings of a remote path, the defaults of ssh are tuned
+/// for fast links.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SshOptions {
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
+    /// Seconds to wait for the connection to be established.
+    #[serde(default)]
+    pub connect_timeout: Option<u64>,
+    /// Seconds of silence after which the server is probed.
+    #[serde(default)]
+    pub keepalive_interval: Option<u64>,
+    /// Unanswered probes after which the connection counts as dead.
+    #[serde(default)]
+    pub keepalive_count: Option<u32>,
+}
+
+/// A destination directory on another host, written through ssh, or in
+/// a running container, written through `docker exec`.
+#[derive(Debug)]
+pub struct Remote {
+    /// `user@host` or `host` as given to ssh, or the container.
+    host: String,
+    path: PathBuf,
+    options: SshOptions,
+    container: bool,
+}
+
+/// Start of the paths in containers, `docker://container:/path`.
+const DOCKER: &str = "docker://";
+
+/// Quote `s` for the remote POSIX shell.
+pub fn quote<T: AsRef<str>>(s: T) -> String {
+    format!("'{}'", s.as_ref().replace('\'', r"'\''"))
+}
+
+fn quote_path(path: &Path) -> String {
+    quote(path.to_string_lossy())
+}
+
+/// Options of ssh for `options`. Connections are shared between the
+/// files of a run, each file only opens a new session.
+pub fn ssh_args(options: &SshOptions) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-o".into(),
+        "BatchMode=yes".into(),
+        "-o".into(),
+        "ControlMaster=auto".into(),
+        "-o".into(),
+        format!("ControlPath={}", state_dir().join("ssh-%C").display()),
+        "-o".into(),
+        "ControlPersist=60".into(),
+    ];
+
+    if let Some(port) = options.port {
+        args.extend(["-p".into(), port.to_string()]);
+    }
+    if let Some(identity) = &options.identity_file {
+        args.extend(["-i".into(), identity.display().to_string()]);
+    }
+    if let Some(x) = options.connect_timeout {
+        args.extend(["-o".into(), format!("ConnectTimeout={}", x)]);
+    }
+    if let Some(x) = options.keepalive_interval {
+        args.extend(["-o".into(), format!("ServerAliveInterval={}", x)]);
+    }
+    if let Some(x) = options.keepalive_count {
+        args.extend(["-o".into(), format!("ServerAliveCountMax={}", x)]);
+    }
+
+    args
+}
+
+impl Remote {
+    /// Split `user@host:/path`, a single letter before the colon is a
+    /// Windows drive and `scheme://` is a url, neither is a host.
+    /// `docker://container:/path` is a path in a container.
+    pub fn parse(path: &Path, options: Option<&SshOptions>) -> Option<Self> {
+        let s = path.to_str()?;
+        if let Some(rest) = s.strip_prefix(DOCKER) {
+            let (container, path) = rest.split_once(':')?;
+            if container.is_empty() || container.starts_with('-') {
+                return None;
+            }
+            return Some(Self {
+                host: container.to_owned(),
+                path: PathBuf::from(if path.is_empty() { "/" } else { path }),
+                options: SshOptions::default(),
+                container: true,
+            });
+        }
+        let (host, rest) = s.split_once(':')?;
+        if host.len() < 2 || host.contains(['/', '\\']) || rest.starts_with("//") {
+            return None;
+        }
+
+        Some(Self {
+            host: host.to_owned(),
+            path: PathBuf::from(if rest.is_empty() { "." } else { rest }),
+            options: options.cloned().unwrap_or_default(),
+            container: false,
+        })
+    }
+
+    /// Whether the destination is in a container.
+    pub fn is_container(&self) -> bool {
+        self.container
+    }
+
+    /// Destination root on the remote host.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `path` on the remote host as shown in logs and output.
+    pub fn display(&self, path: &Path) -> PathBuf {
+        match self.container {
+            true => PathBuf::from(format!("{}{}:{}", DOCKER, self.host, path.display())),
+            false => PathBuf::from(format!("{}:{}", self.host, path.display())),
+        }
+    }
+
+    /// `ssh HOST` or `docker exec CONTAINER`, as named in errors.
+    fn shown(&self) -> String {
+        match self.container {
+            true => format!("docker exec {}", self.host),
+            false => format!("ssh {}", self.host),
+        }
+    }
+
+    /// ssh running `script` on the host, or docker in the container.
+    fn command(&self, script: &str) -> Command {
+        let cmd = match self.container {
+            true => {
+                let mut cmd = Command::new("docker");
+                cmd.args(["exec", "-i"])
+                    .arg(&self.host)
+                    .args(["sh", "-c", script]);
+                cmd
+            }
+            false => {
+                let mut cmd = Command::new("ssh");
+                cmd.args(ssh_args(&self.options))
+                    .arg("--")
+                    .arg(&self.host)
+                    .arg(script);
+                cmd
+            }
+        };
+        log::debug!("Run {:?}", cmd);
+        cmd
+    }
+
+    fn run(&self, script: &str) -> io::Result<String> {
+        std::fs::create_dir_all(state_dir())?;
+        let out = self.command(script).stdin(Stdio::null()).output()?;
+        if !out.status.success() {
+            return Err(io::Error::other(format!(
+                "{} failed, {}",
+                self.shown(),
+                String::from_utf8_lossy(&out.stderr).trim()
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+    }
+
+    /// Compare what a copy of `src` would do to the remote `dst`, by
+    /// checksum so only the hash crosses the network, unless `strategy`
+    /// says otherwise.
+    pub fn status(
+        &self,
+        src: &Path,
+        dst: &Path,
+        strategy: Option<Strategy>,
+        hashes: &HashCache,
+    ) -> io::Result<FileStatus> {
+        let dst = quote_path(dst);
+        let check = match strategy {
+            Some(Strategy::SizeMtime) => "stat -c '%s %Y' --",
+            Some(Strategy::Always) => "echo",
+            _ => "sha256sum --",
+        };
+        let out = self.run(&format!("if [ -e {0} ]; then {1} {0}; fi", dst, check))?;
+        let mut fields = out.split_whitespace();
+        let first = match fields.next() {
+            Some(x) => x,
+            None => return Ok(FileStatus::New),
+        };
+
+        let identical = match strategy {
+            Some(Strategy::SizeMtime) => {
+                let meta = std::fs::metadata(src)?;
+                let modified = meta
+                    .modified()?
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                let mtime = fields.next().and_then(|x| x.parse::<u64>().ok());
+                first.parse().ok() == Some(meta.len()) && mtime.is_some_and(|x| x >= modified)
+            }
+            Some(Strategy::Always) => false,
+            _ => first == hashes.sha256(src)?,
+        };
+        match identical {
+            true => Ok(FileStatus::Identical),
+            false => Ok(FileStatus::Differs),
+        }
+    }
+
+    /// Start writing `len` bytes from stdin to `dst` with permissions
+    /// `mode`. Atomic writes go to `tmp` first, `stale` files are removed
+    /// beforehand.
+    pub fn upload(
+        &self,
+        dst: &Path,
+        tmp: Option<&Path>,
+        len: u64,
+        mode: u32,
+        sync: bool,
+        stale: &[PathBuf],
+    ) -> io::Result<Child> {
+        let target = quote_path(tmp.unwrap_or(dst));
+        let mut steps = vec![];
+
+        if !stale.is_empty() {
+            let stale: Vec<_> = stale.iter().map(|x| quote_path(x)).collect();
+            steps.push(format!("rm -f -- {}", stale.join(" ")));
+        }
+        if let Some(parent) = dst.parent().filter(|x| !x.as_os_str().is_empty()) {
+            steps.push(format!("mkdir -p -- {}", quote_path(parent)));
+        }
+        steps.push(format!("cat > {}", target));
+        // a dropped connection ends the input early, cat cannot tell
+        steps.push(format!("test \"$(wc -c < {})\" -eq {}", target, len));
+        steps.push(format!("chmod {:o} -- {}", mode, target));
+        if sync {
+            steps.push(format!("sync -- {}", target));
+        }
+        if tmp.is_some() {
+            steps.push(format!("mv -f -- {} {}", target, quote_path(dst)));
+        }
+
+        std::fs::create_dir_all(state_dir())?;
+        self.command(&steps.join(" && "))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+
+    /// Wait for an upload, its stderr becomes the error message.
+    pub fn finish(&self, mut child: Child) -> io::Result<()> {
+        drop(child.stdin.take());
+        let mut stderr = String::new();
+        if let Some(mut x) = child.stderr.take() {
+            x.read_to_string(&mut stderr)?;
+        }
+
+        if child.wait()?.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "{} failed, {}",
+                self.shown(),
+                stderr.trim()
+            )))
+        }
+    }
+}
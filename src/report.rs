@@ -0,0 +1,189 @@
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::output::Status;
+use crate::stats::{self, Stats};
+
+/// How a --report is written, by the extension of its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    /// HTML for `.html` and `.htm` files, Markdown for the others.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(x) if x.eq_ignore_ascii_case("html") || x.eq_ignore_ascii_case("htm") => {
+                ReportFormat::Html
+            }
+            _ => ReportFormat::Markdown,
+        }
+    }
+}
+
+/// One file of the report, as it was recorded.
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub status: Status,
+    pub src: Option<PathBuf>,
+    pub dst: PathBuf,
+    pub bytes: Option<u64>,
+    /// Of the written destination, when it is local.
+    pub hash: Option<String>,
+    pub seconds: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// The run of `specs` with its statistics and a table of its files.
+pub struct Report<'a> {
+    pub specs: &'a [String],
+    pub stats: &'a Stats,
+    /// Name of the algorithm of the hashes.
+    pub algorithm: &'a str,
+    pub dry_run: bool,
+    pub rows: &'a [Row],
+}
+
+impl Report<'_> {
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let text = match ReportFormat::of(path) {
+            ReportFormat::Markdown => self.markdown(),
+            ReportFormat::Html => self.html(),
+        };
+        std::fs::write(path, text)
+    }
+
+    fn title(&self) -> String {
+        match self.dry_run {
+            true => format!("cpx dry run {}", self.specs.join(", ")),
+            false => format!("cpx run {}", self.specs.join(", ")),
+        }
+    }
+
+    /// Name and value of each line of the summary.
+    fn summary(&self) -> Vec<(String, String)> {
+        let s = self.stats;
+        let mut lines = vec![("run".to_owned(), s.run_id.clone())];
+        lines.extend(s.counts.iter().map(|(k, n)| (k.clone(), n.to_string())));
+        lines.push(("bytes".to_owned(), stats::human(s.bytes as f64)));
+        lines.push(("elapsed".to_owned(), format!("{:.3}s", s.elapsed)));
+        lines.push((
+            "throughput".to_owned(),
+            format!("{}/s", stats::human(s.throughput)),
+        ));
+        lines
+    }
+
+    /// Cells of the file table, the header first.
+    fn cells(&self) -> Vec<[String; 7]> {
+        let header = [
+            "status".to_owned(),
+            "source".to_owned(),
+            "destination".to_owned(),
+            "size".to_owned(),
+            self.algorithm.to_owned(),
+            "time".to_owned(),
+            "error".to_owned(),
+        ];
+        let rows = self.rows.iter().map(|r| {
+            [
+                r.status.name().to_owned(),
+                r.src
+                    .as_ref()
+                    .map(|x| x.display().to_string())
+                    .unwrap_or_default(),
+                r.dst.display().to_string(),
+                r.bytes.map(|x| stats::human(x as f64)).unwrap_or_default(),
+                r.hash.clone().unwrap_or_default(),
+                r.seconds.map(|x| format!("{:.3}s", x)).unwrap_or_default(),
+                r.error.clone().unwrap_or_default(),
+            ]
+        });
+        std::iter::once(header).chain(rows).collect()
+    }
+
+    fn markdown(&self) -> String {
+        // a `|` or a line break would end the cell
+        let cell = |x: &str| x.replace('|', "\\|").replace(['\r', '\n'], " ");
+
+        let mut out = format!("# {}\n\n", cell(&self.title()));
+        out.push_str("| | |\n|---|---|\n");
+        for (name, value) in self.summary() {
+            let _ = writeln!(out, "| {} | {} |", name, cell(&value));
+        }
+        out.push('\n');
+
+        let cells = self.cells();
+        for (i, row) in cells.iter().enumerate() {
+            let row: Vec<_> = row.iter().map(|x| cell(x)).collect();
+            let _ = writeln!(out, "| {} |", row.join(" | "));
+            if i == 0 {
+                let _ = writeln!(out, "|{}", "---|".repeat(row.len()));
+            }
+        }
+        out
+    }
+
+    fn html(&self) -> String {
+        let title = escape(&self.title());
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n{}</head>\n<body>\n<h1>{}</h1>\n",
+            title, STYLE, title
+        );
+        out.push_str("<table class=\"summary\">\n");
+        for (name, value) in self.summary() {
+            let _ = writeln!(
+                out,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape(&name),
+                escape(&value)
+            );
+        }
+        out.push_str("</table>\n<table class=\"files\">\n");
+
+        let cells = self.cells();
+        for (i, row) in cells.iter().enumerate() {
+            let tag = if i == 0 { "th" } else { "td" };
+            let class = match i {
+                0 => String::new(),
+                _ => format!(" class=\"{}\"", self.rows[i - 1].status.name()),
+            };
+            let _ = write!(out, "<tr{}>", class);
+            for x in row {
+                let _ = write!(out, "<{}>{}</{}>", tag, escape(x), tag);
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        out
+    }
+}
+
+/// Colors like the statuses of the terminal lines.
+const STYLE: &str = "<style>
+body { font-family: sans-serif; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: left; }
+td { font-family: monospace; }
+.copied td:first-child, .linked td:first-child, .new td:first-child, .overwrite td:first-child { color: green; }
+.skipped td:first-child, .skip td:first-child, .identical td:first-child, .missing td:first-child, .changed td:first-child { color: olive; }
+.failed td:first-child, .circuit_open td:first-child, .conflict td:first-child, .delete td:first-child, .deleted td:first-child { color: red; }
+</style>
+";
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,54 @@
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+
+/// Wait before the first retry, doubled for each one after.
+const FIRST_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Whether a copy failing with `e` may succeed when tried again. Copies
+/// rewrite the whole destination, so trying again is always safe, but a
+/// missing source or a full disk stays that way.
+pub fn transient(e: &io::Error) -> bool {
+    !matches!(
+        e.kind(),
+        ErrorKind::NotFound
+            | ErrorKind::PermissionDenied
+            | ErrorKind::AlreadyExists
+            | ErrorKind::InvalidInput
+            | ErrorKind::InvalidData
+            | ErrorKind::Unsupported
+            | ErrorKind::StorageFull
+            | ErrorKind::ReadOnlyFilesystem
+            | ErrorKind::IsADirectory
+            | ErrorKind::NotADirectory
+    )
+}
+
+/// Delay before retry number `n`, counting from 1.
+pub fn backoff(n: u32) -> Duration {
+    FIRST_DELAY
+        .saturating_mul(1 << n.saturating_sub(1).min(16))
+        .min(MAX_DELAY)
+}
+
+/// What is done with a local source modified while it was copied,
+/// --on-source-change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnChange {
+    /// Copy it again, the copy may mix old and new content.
+    Retry,
+    /// Keep the copy and report it as `changed`.
+    Flag,
+}
+
+impl OnChange {
+    pub const NAMES: [&'static str; 2] = ["retry", "flag"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "retry" => Some(OnChange::Retry),
+            "flag" => Some(OnChange::Flag),
+            _ => None,
+        }
+    }
+}
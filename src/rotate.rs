@@ -0,0 +1,90 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::copy;
+
+/// Name of the link to the newest snapshot.
+pub const LATEST: &str = "latest";
+
+/// A new timestamped directory of a destination a run copies into,
+/// --rotate, with the ones before it next to it.
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    base: PathBuf,
+    name: String,
+    keep: Option<usize>,
+}
+
+impl Rotation {
+    /// The snapshot `name`, a `{date}_{time}`, of the destination `base`,
+    /// `keep` of them are kept.
+    pub fn new(base: &Path, name: String, keep: Option<usize>) -> Result<Self, String> {
+        let rotation = Self {
+            base: base.to_path_buf(),
+            name,
+            keep,
+        };
+        if rotation.path().exists() {
+            return Err(format!("{} exists already", rotation.path().display()));
+        }
+        Ok(rotation)
+    }
+
+    /// The directory the run copies into.
+    pub fn path(&self) -> PathBuf {
+        self.base.join(&self.name)
+    }
+
+    /// Point `latest` at the snapshot and remove the oldest ones beyond
+    /// `keep`, which are returned.
+    pub fn finish(&self) -> io::Result<Vec<PathBuf>> {
+        let link = self.base.join(LATEST);
+        match std::fs::symlink_metadata(&link) {
+            Ok(x) if x.file_type().is_symlink() => copy::remove_link(&link)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} is not a symlink", link.display()),
+                ))
+            }
+            Err(_) => {}
+        }
+        // relative, the destination can be moved or mounted elsewhere
+        copy::symlink(Path::new(&self.name), &link)?;
+
+        let keep = match self.keep {
+            Some(x) => x.max(1),
+            None => return Ok(vec![]),
+        };
+        let mut names = vec![];
+        for entry in std::fs::read_dir(&self.base)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if is_snapshot(&name) && entry.file_type()?.is_dir() && name != self.name {
+                names.push(name);
+            }
+        }
+        // the names sort by time, the one just copied is the newest
+        names.sort_unstable();
+        let old = names.len().saturating_sub(keep - 1);
+
+        let mut removed = vec![];
+        for name in &names[..old] {
+            let path = self.base.join(name);
+            std::fs::remove_dir_all(&path)?;
+            removed.push(path);
+        }
+        Ok(removed)
+    }
+}
+
+/// Whether `name` is of a snapshot, `2024-05-31_143000`.
+fn is_snapshot(name: &str) -> bool {
+    let b = name.as_bytes();
+    b.len() == 17
+        && b.iter().enumerate().all(|(i, c)| match i {
+            4 | 7 => *c == b'-',
+            10 => *c == b'_',
+            _ => c.is_ascii_digit(),
+        })
+}
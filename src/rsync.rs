@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::compare::Strategy;
+use crate::remote::{quote, ssh_args, SshOptions};
+
+/// How the files of a path are transferred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Cpx,
+    Rsync,
+}
+
+impl Transport {
+    pub const NAMES: [&'static str; 2] = ["cpx", "rsync"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "cpx" => Some(Transport::Cpx),
+            "rsync" => Some(Transport::Rsync),
+            _ => None,
+        }
+    }
+}
+
+/// A file rsync reported as transferred, or in a dry run as to be.
+pub struct Transferred {
+    pub relative: PathBuf,
+    pub new: bool,
+    pub bytes: u64,
+}
+
+/// One rsync invocation copying a list of files between two roots, either
+/// of which may be `host:path`.
+pub struct Rsync<'a> {
+    pub src: &'a Path,
+    pub dst: &'a Path,
+    /// Options of ssh when one of the roots is remote.
+    pub ssh: Option<SshOptions>,
+    pub dry_run: bool,
+    pub bwlimit: Option<u64>,
+    pub compare: Option<Strategy>,
+    /// Copy extended attributes and ACLs too.
+    pub xattrs: bool,
+}
+
+impl Rsync<'_> {
+    /// Run rsync for `files` relative to both roots, calling `report` for
+    /// every file it transfers. Files it does not report were unchanged.
+    pub fn run(&self, files: &[PathBuf], mut report: impl FnMut(Transferred)) -> io::Result<()> {
+        let mut cmd = Command::new("rsync");
+        // --protect-args keeps remote names away from the remote shell
+        cmd.args(["--archive", "--partial", "--protect-args"])
+            .args(["--from0", "--files-from=-"])
+            .arg("--out-format=%i %l %n");
+        if self.dry_run {
+            cmd.arg("--dry-run");
+        }
+        match self.compare {
+            Some(Strategy::Hash) => {
+                cmd.arg("--checksum");
+            }
+            Some(Strategy::Always) => {
+                cmd.arg("--ignore-times");
+            }
+            _ => {}
+        }
+        if self.xattrs {
+            cmd.args(["--xattrs", "--acls"]);
+        }
+        if let Some(x) = self.bwlimit {
+            // rsync takes KiB per second
+            cmd.arg(format!("--bwlimit={}", (x / 1024).max(1)));
+        }
+        if let Some(options) = &self.ssh {
+            std::fs::create_dir_all(crate::state::state_dir())?;
+            let args: Vec<_> = ssh_args(options).iter().map(quote).collect();
+            cmd.arg("-e").arg(format!("ssh {}", args.join(" ")));
+        }
+        // the trailing slash copies the contents, not the directory
+        cmd.arg(format!("{}/", self.src.display()))
+            .arg(format!("{}/", self.dst.display()));
+
+        log::debug!("Run {:?}", cmd);
+        let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+
+        let mut list = vec![];
+        for f in files {
+            list.extend_from_slice(f.to_string_lossy().as_bytes());
+            list.push(0);
+        }
+        // a thread, rsync may report before it read the whole list
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = std::thread::spawn(move || stdin.write_all(&list));
+
+        for line in BufReader::new(child.stdout.take().unwrap()).lines() {
+            let line = line?;
+            let mut parts = line.splitn(3, ' ');
+            let (item, bytes, name) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(i), Some(b), Some(n)) => (i, b, n),
+                _ => continue,
+            };
+            // files only, not directories or attribute changes
+            if item.as_bytes().get(1) != Some(&b'f') || !matches!(item.as_bytes()[0], b'<' | b'>') {
+                continue;
+            }
+
+            report(Transferred {
+                relative: PathBuf::from(name),
+                new: item.contains("+++++++"),
+                bytes: bytes.replace(',', "").parse().unwrap_or(0),
+            });
+        }
+
+        let _ = writer.join();
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(io::Error::other(format!("rsync failed, {}", status)));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,666 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::compare::{FileStatus, Strategy};
+use crate::hashcache::HashCache;
+use crate::manifest::hex;
+use crate::md5::Md5;
+use crate::throttle::RateLimit;
+use crate::{cleanup, timeout};
+
+/// Settings of an `s3://bucket/prefix` path.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct S3Options {
+    /// Endpoint of an S3 compatible store like MinIO.
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+    #[serde(default)]
+    pub profile: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub storage_class: Option<String>,
+    /// Size of the parts larger files are uploaded in, like `16M`.
+    #[serde(default)]
+    pub part_size: Option<String>,
+    /// Parts of a file uploaded at once.
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    /// Content types by file extension, over the built-in ones.
+    #[serde(default)]
+    pub content_types: HashMap<String, String>,
+}
+
+/// A key prefix in a bucket, accessed through the aws cli.
+#[derive(Debug)]
+pub struct Bucket {
+    bucket: String,
+    prefix: PathBuf,
+    options: S3Options,
+}
+
+#[derive(Deserialize)]
+struct Head {
+    #[serde(rename = "ContentLength", default)]
+    len: u64,
+    #[serde(rename = "Metadata", default)]
+    metadata: HashMap<String, String>,
+    #[serde(rename = "ETag", default)]
+    etag: Option<String>,
+    #[serde(rename = "LastModified", default)]
+    modified: Option<String>,
+}
+
+/// A stored object, `sha256` is only known for objects cpx uploaded.
+struct Object {
+    len: u64,
+    sha256: Option<String>,
+    etag: Option<String>,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Deserialize)]
+struct Upload {
+    #[serde(rename = "UploadId")]
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Part {
+    #[serde(rename = "ETag")]
+    etag: String,
+}
+
+/// Part size of multipart uploads by default, that of the aws cli.
+const PART_SIZE: u64 = 8 * 1024 * 1024;
+
+/// The smallest part S3 takes, but for the last one.
+const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+
+/// The most parts of an upload S3 takes.
+const MAX_PARTS: u64 = 10_000;
+
+/// Parts of a file uploaded at once by default.
+const CONCURRENCY: usize = 4;
+
+/// Numbers the temporary part files of the process.
+static PART_FILES: AtomicU64 = AtomicU64::new(0);
+
+/// Whether `etag` is that of `path` uploaded in one part, or in parts of
+/// `part_size`. None for an object uploaded in parts of another size,
+/// whose ETag cannot be computed.
+fn etag_matches(etag: &str, path: &Path, part_size: u64) -> io::Result<Option<bool>> {
+    let etag = etag.trim_matches('"');
+    let len = std::fs::metadata(path)?.len();
+    let parts = match etag.split_once('-') {
+        Some((_, n)) => match n.parse::<u64>() {
+            Ok(n) if n == len.div_ceil(part_size).max(1) => Some(n),
+            _ => return Ok(None),
+        },
+        None => None,
+    };
+
+    // a single part is the whole file
+    let part_size = parts.map_or(u64::MAX, |_| part_size);
+    let mut f = File::open(path)?;
+    let mut buf = vec![0; 128 * 1024];
+    let mut digests = vec![];
+    for _ in 0..parts.unwrap_or(1) {
+        let mut part = Md5::new();
+        let mut reader = (&mut f).take(part_size);
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            part.update(&buf[..n]);
+        }
+        digests.extend_from_slice(&part.finish());
+    }
+
+    let expected = match parts {
+        Some(n) => {
+            let mut md5 = Md5::new();
+            md5.update(&digests);
+            format!("{}-{}", hex(&md5.finish()), n)
+        }
+        None => hex(&digests),
+    };
+    Ok(Some(etag == expected))
+}
+
+/// `2024-05-01T12:00:00+00:00` or with `Z`, as the aws cli prints times.
+fn iso_time(s: &str) -> Option<SystemTime> {
+    let (date, time) = s.split_once('T')?;
+    let ymd: Vec<i64> = date.split('-').filter_map(|x| x.parse().ok()).collect();
+    let hms: Vec<u64> = time
+        .get(..8)?
+        .split(':')
+        .filter_map(|x| x.parse().ok())
+        .collect();
+    let (&[y, m, d], &[h, min, sec]) = (&ymd[..], &hms[..]) else {
+        return None;
+    };
+
+    let days = crate::filter::days_from_civil(y, m, d);
+    let secs = u64::try_from(days).ok()? * 86_400 + h * 3600 + min * 60 + sec;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+fn content_type(ext: &str) -> &'static str {
+    match ext {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "txt" | "log" => "text/plain",
+        "csv" => "text/csv",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/vnd.microsoft.icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "mp4" => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+impl Bucket {
+    pub fn parse(path: &Path, options: Option<&S3Options>) -> Option<Self> {
+        let rest = path.to_str()?.strip_prefix("s3://")?;
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if bucket.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            bucket: bucket.to_owned(),
+            prefix: PathBuf::from(prefix),
+            options: options.cloned().unwrap_or_default(),
+        })
+    }
+
+    /// Whether the options are valid.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(x) = &self.options.part_size {
+            match crate::filter::parse_size(x)? {
+                n if n < MIN_PART_SIZE => {
+                    return Err(format!("part_size `{}` is below the 5M S3 takes", x))
+                }
+                _ => {}
+            }
+        }
+        match self.options.concurrency {
+            Some(0) => Err("concurrency must be at least 1".to_owned()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Files larger than it are uploaded in parts of its size.
+    pub fn part_size(&self) -> u64 {
+        self.options
+            .part_size
+            .as_deref()
+            .and_then(|x| crate::filter::parse_size(x).ok())
+            .unwrap_or(PART_SIZE)
+    }
+
+    /// Whether `len` bytes are uploaded in parts. Smaller files are one
+    /// part, which `aws s3 cp` keeps them in up to its own 8M.
+    pub fn in_parts(&self, len: u64) -> bool {
+        len > self.part_size().min(PART_SIZE)
+    }
+
+    /// Key prefix, the root files are placed under.
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// `/` separated object key of `path` below the prefix.
+    fn key(path: &Path) -> String {
+        path.components()
+            .filter_map(|x| match x {
+                Component::Normal(x) => Some(x.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    /// `path` as an `s3://` url for logs and output.
+    pub fn display(&self, path: &Path) -> PathBuf {
+        PathBuf::from(format!("s3://{}/{}", self.bucket, Self::key(path)))
+    }
+
+    fn content_type(&self, path: &Path) -> String {
+        let ext = path
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match self.options.content_types.get(&ext) {
+            Some(x) => x.clone(),
+            None => content_type(&ext).to_owned(),
+        }
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new("aws");
+        cmd.args(args);
+
+        let o = &self.options;
+        if let Some(x) = &o.endpoint_url {
+            cmd.arg("--endpoint-url").arg(x);
+        }
+        if let Some(x) = &o.profile {
+            cmd.arg("--profile").arg(x);
+        }
+        if let Some(x) = &o.region {
+            cmd.arg("--region").arg(x);
+        }
+
+        log::debug!("Run {:?}", cmd);
+        cmd
+    }
+
+    /// The object at `path`, `None` if there is none.
+    fn head(&self, path: &Path) -> io::Result<Option<Object>> {
+        let key = Self::key(path);
+        let out = self
+            .command(&[
+                "s3api",
+                "head-object",
+                "--bucket",
+                &self.bucket,
+                "--key",
+                &key,
+            ])
+            .stdin(Stdio::null())
+            .output()?;
+
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            if stderr.contains("Not Found") || stderr.contains("404") {
+                return Ok(None);
+            }
+            return Err(io::Error::other(format!(
+                "head {} failed, {}",
+                self.display(path).display(),
+                stderr.trim()
+            )));
+        }
+
+        let head: Head = serde_json::from_slice(&out.stdout)?;
+        Ok(Some(Object {
+            len: head.len,
+            sha256: head.metadata.get("sha256").cloned(),
+            etag: head.etag,
+            modified: head.modified.as_deref().and_then(iso_time),
+        }))
+    }
+
+    /// Compare what an upload of `src` would do to the object `dst`.
+    /// Objects cpx did not upload carry no hash, only a size mismatch
+    /// tells them apart and an equal size counts as differing.
+    pub fn status_put(
+        &self,
+        src: &Path,
+        dst: &Path,
+        strategy: Option<Strategy>,
+        hashes: &HashCache,
+    ) -> io::Result<FileStatus> {
+        let object = match self.head(dst)? {
+            Some(x) => x,
+            None => return Ok(FileStatus::New),
+        };
+        Self::status(object, src, strategy, hashes, false, self.part_size())
+    }
+
+    /// Compare what a download of the object `src` would do to `dst`.
+    pub fn status_get(
+        &self,
+        src: &Path,
+        dst: &Path,
+        strategy: Option<Strategy>,
+        hashes: &HashCache,
+    ) -> io::Result<FileStatus> {
+        let object = self.head(src)?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} not found", self.display(src).display()),
+            )
+        })?;
+        if !dst.exists() {
+            return Ok(FileStatus::New);
+        }
+        Self::status(object, dst, strategy, hashes, true, self.part_size())
+    }
+
+    /// The copy is the object when `download`, else the local file.
+    fn status(
+        object: Object,
+        local: &Path,
+        strategy: Option<Strategy>,
+        hashes: &HashCache,
+        download: bool,
+        part_size: u64,
+    ) -> io::Result<FileStatus> {
+        let meta = std::fs::metadata(local)?;
+        if object.len != meta.len() {
+            return Ok(FileStatus::Differs);
+        }
+
+        let newer = || -> io::Result<bool> {
+            let local = meta.modified()?;
+            Ok(object
+                .modified
+                .is_some_and(|x| if download { local >= x } else { x >= local }))
+        };
+        let identical = match strategy {
+            Some(Strategy::Always) => false,
+            Some(Strategy::SizeMtime) => newer()?,
+            // parts of another size leave the size and time to go by
+            Some(Strategy::Etag) => match &object.etag {
+                Some(etag) => match etag_matches(etag, local, part_size)? {
+                    Some(x) => x,
+                    None => newer()?,
+                },
+                None => false,
+            },
+            _ => match &object.sha256 {
+                Some(hash) => *hash == hashes.sha256(local)?,
+                None => false,
+            },
+        };
+        match identical {
+            true => Ok(FileStatus::Identical),
+            false => Ok(FileStatus::Differs),
+        }
+    }
+
+    /// Start uploading `len` bytes from stdin to `path`, tagged with the
+    /// `sha256` of the content for later comparisons.
+    pub fn put(&self, path: &Path, len: u64, sha256: &str) -> io::Result<Child> {
+        let url = self.display(path);
+        let mut cmd = self.command(&["s3", "cp", "-"]);
+        cmd.arg(url)
+            .arg("--expected-size")
+            .arg(len.to_string())
+            .arg("--content-type")
+            .arg(self.content_type(path))
+            .arg("--metadata")
+            .arg(format!("sha256={}", sha256))
+            .arg("--only-show-errors");
+        if let Some(x) = &self.options.storage_class {
+            cmd.arg("--storage-class").arg(x);
+        }
+
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+
+    /// Upload `len` bytes of `src` to `path` in parts, tagged like `put`.
+    /// An upload failing part way is aborted, its parts would be kept and
+    /// billed otherwise.
+    pub fn put_parts(
+        &self,
+        src: &Path,
+        path: &Path,
+        len: u64,
+        sha256: &str,
+        limit: Option<&RateLimit>,
+    ) -> io::Result<()> {
+        let size = self.part_size();
+        if len.div_ceil(size) > MAX_PARTS {
+            return Err(io::Error::other(format!(
+                "{} bytes take more than {} parts of {} bytes, raise the part_size",
+                len, MAX_PARTS, size
+            )));
+        }
+
+        let key = Self::key(path);
+        let mut cmd = self.command(&[
+            "s3api",
+            "create-multipart-upload",
+            "--bucket",
+            &self.bucket,
+            "--key",
+            &key,
+        ]);
+        cmd.arg("--content-type")
+            .arg(self.content_type(path))
+            .arg("--metadata")
+            .arg(format!("sha256={}", sha256));
+        if let Some(x) = &self.options.storage_class {
+            cmd.arg("--storage-class").arg(x);
+        }
+        let upload: Upload = serde_json::from_slice(&Self::output(cmd)?)?;
+
+        let result = self
+            .upload_parts(src, &key, &upload.id, len, limit)
+            .and_then(|etags| self.complete(&key, &upload.id, &etags));
+        if result.is_err() {
+            let abort = self.command(&[
+                "s3api",
+                "abort-multipart-upload",
+                "--bucket",
+                &self.bucket,
+                "--key",
+                &key,
+                "--upload-id",
+                &upload.id,
+            ]);
+            if let Err(e) = Self::output(abort) {
+                log::warn!(
+                    "Abort the upload to {} failed, {}",
+                    self.display(path).display(),
+                    e
+                );
+            }
+        }
+        result
+    }
+
+    /// Upload the parts of `src` by concurrent workers each taking the next
+    /// part left, their ETags in order.
+    fn upload_parts(
+        &self,
+        src: &Path,
+        key: &str,
+        id: &str,
+        len: u64,
+        limit: Option<&RateLimit>,
+    ) -> io::Result<Vec<String>> {
+        let size = self.part_size();
+        let count = len.div_ceil(size);
+        let workers = self.options.concurrency.unwrap_or(CONCURRENCY) as u64;
+        let next = AtomicU64::new(0);
+        let failed = AtomicBool::new(false);
+        let etags = Mutex::new(vec![String::new(); count as usize]);
+        // the parts are interrupted with the file when it times out
+        let copying = timeout::current();
+
+        let results: Vec<io::Result<()>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers.clamp(1, count))
+                .map(|_| {
+                    let (next, failed, etags) = (&next, &failed, &etags);
+                    let copying = copying.clone();
+                    scope.spawn(move || {
+                        let _entered = timeout::enter(copying);
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            // the others stop at their next part once one failed
+                            if i >= count || failed.load(Ordering::Relaxed) {
+                                return Ok(());
+                            }
+                            let offset = i * size;
+                            let part = (offset, size.min(len - offset));
+                            match self.upload_part(src, key, id, i + 1, part, limit) {
+                                Ok(x) => etags.lock().unwrap()[i as usize] = x,
+                                Err(e) => {
+                                    failed.store(true, Ordering::Relaxed);
+                                    return Err(e);
+                                }
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            handles.into_iter().map(|x| x.join().unwrap()).collect()
+        });
+
+        results.into_iter().collect::<io::Result<()>>()?;
+        Ok(etags.into_inner().unwrap())
+    }
+
+    /// Upload part `number` of `src`, its `(offset, len)`, from a temporary
+    /// file, the aws cli takes no part from stdin.
+    fn upload_part(
+        &self,
+        src: &Path,
+        key: &str,
+        id: &str,
+        number: u64,
+        (offset, len): (u64, u64),
+        limit: Option<&RateLimit>,
+    ) -> io::Result<String> {
+        let tmp = std::env::temp_dir().join(format!(
+            ".cpx-part.{}.{}.cpx-tmp",
+            std::process::id(),
+            PART_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        cleanup::register(&tmp);
+        let result = (|| {
+            let mut reader = File::open(src)?;
+            reader.seek(SeekFrom::Start(offset))?;
+            let mut reader = reader.take(len);
+            let mut writer = File::create(&tmp)?;
+            let mut buf = vec![0; 128 * 1024];
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                timeout::write_all(|x| writer.write(x), &buf[..n])?;
+                timeout::check()?;
+                if let Some(limit) = limit {
+                    limit.consume(n as u64);
+                }
+            }
+
+            let cmd = self.command(&[
+                "s3api",
+                "upload-part",
+                "--bucket",
+                &self.bucket,
+                "--key",
+                key,
+                "--upload-id",
+                id,
+                "--part-number",
+                &number.to_string(),
+                "--body",
+                &tmp.to_string_lossy(),
+            ]);
+            let part: Part = serde_json::from_slice(&Self::output(cmd)?)?;
+            Ok(part.etag)
+        })();
+        let _ = std::fs::remove_file(&tmp);
+        cleanup::release(&tmp);
+        result
+    }
+
+    /// Join the uploaded parts into the object.
+    fn complete(&self, key: &str, id: &str, etags: &[String]) -> io::Result<()> {
+        let parts: Vec<_> = etags
+            .iter()
+            .zip(1..)
+            .map(|(etag, n)| serde_json::json!({ "ETag": etag, "PartNumber": n }))
+            .collect();
+        // from a file, the parts of a large file overflow the command line
+        let tmp = std::env::temp_dir().join(format!(
+            ".cpx-parts.{}.{}.cpx-tmp",
+            std::process::id(),
+            PART_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        cleanup::register(&tmp);
+        let result = std::fs::write(&tmp, serde_json::json!({ "Parts": parts }).to_string())
+            .and_then(|_| {
+                let cmd = self.command(&[
+                    "s3api",
+                    "complete-multipart-upload",
+                    "--bucket",
+                    &self.bucket,
+                    "--key",
+                    key,
+                    "--upload-id",
+                    id,
+                    "--multipart-upload",
+                    &format!("file://{}", tmp.display()),
+                ]);
+                Self::output(cmd).map(drop)
+            });
+        let _ = std::fs::remove_file(&tmp);
+        cleanup::release(&tmp);
+        result
+    }
+
+    /// Run `cmd` to its end, its stdout or its stderr as the error.
+    fn output(mut cmd: Command) -> io::Result<Vec<u8>> {
+        let out = cmd.stdin(Stdio::null()).output()?;
+        if out.status.success() {
+            Ok(out.stdout)
+        } else {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            Err(io::Error::other(format!("aws failed, {}", stderr.trim())))
+        }
+    }
+
+    /// Start downloading `path` to stdout.
+    pub fn get(&self, path: &Path) -> io::Result<Child> {
+        let url = self.display(path);
+        let mut cmd = self.command(&["s3", "cp"]);
+        cmd.arg(url)
+            .arg("-")
+            .arg("--only-show-errors")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+
+    /// Wait for a transfer, its stderr becomes the error message.
+    pub fn finish(&self, mut child: Child) -> io::Result<()> {
+        drop(child.stdin.take());
+        let mut stderr = String::new();
+        if let Some(mut x) = child.stderr.take() {
+            x.read_to_string(&mut stderr)?;
+        }
+
+        if child.wait()?.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("aws failed, {}", stderr.trim())))
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::IgnoreList;
+
+/// Files below `root` matching `patterns`, by their relative path, that
+/// are not `listed` or `excluded`. Sorted, symlinked directories are not
+/// followed.
+pub fn unlisted(
+    root: &Path,
+    patterns: &IgnoreList,
+    excluded: &IgnoreList,
+    listed: &HashSet<PathBuf>,
+) -> io::Result<Vec<PathBuf>> {
+    walk(root, excluded, true, |x| {
+        patterns.is_ignored(x) && !listed.contains(x)
+    })
+}
+
+/// Every file below `root` that is not `excluded`, by its relative path,
+/// for `cpx mirror`. Sorted, symlinked directories are not followed.
+pub fn all(root: &Path, excluded: &IgnoreList) -> io::Result<Vec<PathBuf>> {
+    walk(root, excluded, false, |_| true)
+}
+
+fn walk(
+    root: &Path,
+    excluded: &IgnoreList,
+    skip_git: bool,
+    wanted: impl Fn(&Path) -> bool,
+) -> io::Result<Vec<PathBuf>> {
+    let mut found = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            if (skip_git && entry.file_name() == ".git") || excluded.is_ignored(&relative) {
+                continue;
+            }
+            let kind = entry.file_type()?;
+            if kind.is_dir() {
+                dirs.push(relative);
+            } else if wanted(&relative) {
+                found.push(relative);
+            }
+        }
+    }
+
+    found.sort_unstable();
+    Ok(found)
+}
+
+/// A `file_list` key for `relative` not among `taken`, its path with the
+/// extension dropped, `conf/app.conf` as `conf-app`.
+pub fn key(relative: &Path, taken: &HashSet<String>) -> String {
+    let stem = relative.with_extension("");
+    let mut base = String::new();
+    for c in stem.to_string_lossy().chars() {
+        match c.is_alphanumeric() || c == '_' {
+            true => base.push(c),
+            false if !base.is_empty() && !base.ends_with('-') => base.push('-'),
+            false => {}
+        }
+    }
+    let base = match base.trim_end_matches('-') {
+        "" => "file".to_owned(),
+        x => x.to_owned(),
+    };
+
+    std::iter::once(base.clone())
+        .chain((2..).map(|i| format!("{}-{}", base, i)))
+        .find(|x| !taken.contains(x))
+        .unwrap()
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A copy `cpx schedule run` starts whenever `cron` matches.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// `minute hour day-of-month month day-of-week` in local time.
+    pub cron: String,
+    /// `src:dst` as on the command line.
+    pub spec: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// More options of the copy, like `--changed-only`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// The values each of the five fields matches.
+#[derive(Debug)]
+pub struct Cron {
+    fields: [Vec<bool>; 5],
+    /// Whether day of month and day of week were both restricted, then
+    /// either matching is enough, as in cron.
+    either_day: bool,
+}
+
+/// Ranges of the fields, day of week 7 is Sunday too.
+const RANGES: [(u32, u32); 5] = [(0, 59), (0, 23), (1, 31), (1, 12), (0, 7)];
+
+impl Cron {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let parts: Vec<_> = expr.split_whitespace().collect();
+        if parts.len() != 5 {
+            return Err(format!("`{}` needs 5 fields", expr));
+        }
+
+        let mut fields: [Vec<bool>; 5] = Default::default();
+        for (i, part) in parts.iter().enumerate() {
+            fields[i] = parse_field(part, RANGES[i])
+                .ok_or_else(|| format!("invalid field `{}` in `{}`", part, expr))?;
+        }
+        // Sunday is both 0 and 7
+        if fields[4][7] {
+            fields[4][0] = true;
+        }
+
+        Ok(Self {
+            fields,
+            either_day: parts[2] != "*" && parts[4] != "*",
+        })
+    }
+
+    fn matches(&self, t: &Time) -> bool {
+        let [minute, hour, mday, month, wday] = &self.fields;
+        let day = match self.either_day {
+            true => mday[t.mday as usize] || wday[t.wday as usize],
+            false => mday[t.mday as usize] && wday[t.wday as usize],
+        };
+
+        minute[t.minute as usize] && hour[t.hour as usize] && month[t.month as usize] && day
+    }
+}
+
+/// Parse `*`, `5`, `1-5`, `*/15`, `1-30/2` and lists of them.
+fn parse_field(s: &str, (min, max): (u32, u32)) -> Option<Vec<bool>> {
+    let mut values = vec![false; max as usize + 1];
+    for item in s.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((r, step)) => (r, step.parse().ok().filter(|x| *x > 0)?),
+            None => (item, 1),
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (a.parse().ok()?, b.parse().ok()?),
+                None => {
+                    let x = range.parse().ok()?;
+                    (x, x)
+                }
+            },
+        };
+        if from < min || to > max || from > to {
+            return None;
+        }
+        for x in (from..=to).step_by(step) {
+            values[x as usize] = true;
+        }
+    }
+
+    Some(values)
+}
+
+/// The fields of a point in time cron looks at, and those of a date.
+pub struct Time {
+    pub year: i64,
+    pub second: u32,
+    pub minute: u32,
+    pub hour: u32,
+    pub mday: u32,
+    pub month: u32,
+    wday: u32,
+}
+
+#[cfg(unix)]
+pub fn local_time(secs: u64) -> Time {
+    let t = secs as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&t, &mut tm) };
+
+    Time {
+        year: tm.tm_year as i64 + 1900,
+        second: tm.tm_sec as u32,
+        minute: tm.tm_min as u32,
+        hour: tm.tm_hour as u32,
+        mday: tm.tm_mday as u32,
+        month: tm.tm_mon as u32 + 1,
+        wday: tm.tm_wday as u32,
+    }
+}
+
+/// Without localtime_r the schedule is in UTC.
+#[cfg(not(unix))]
+pub fn local_time(secs: u64) -> Time {
+    let days = (secs / 86400) as i64;
+    let (year, month, mday) = crate::history::civil_from_days(days);
+
+    Time {
+        year,
+        second: (secs % 60) as u32,
+        minute: (secs % 3600 / 60) as u32,
+        hour: (secs % 86400 / 3600) as u32,
+        mday: mday as u32,
+        month: month as u32,
+        // 1970-01-01 was a Thursday
+        wday: ((days + 4).rem_euclid(7)) as u32,
+    }
+}
+
+/// Run `entries` of the layered `configs` whenever they are due, one
+/// at a time, until killed. A copy running into the next due time makes
+/// that one be skipped, as with cron.
+pub fn run(configs: &[String], entries: &[Entry]) -> Result<(), String> {
+    let crons = entries
+        .iter()
+        .map(|x| Cron::parse(&x.cron))
+        .collect::<Result<Vec<_>, _>>()?;
+    if entries.is_empty() {
+        return Err("no `schedule` entries in the config".to_owned());
+    }
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    log::info!("Schedule {} copies", entries.len());
+
+    let now = || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+    };
+    // from the next minute on, not one already begun
+    let mut last = now().as_secs() / 60;
+    loop {
+        let now = now();
+        let minute = now.as_secs() / 60;
+        if minute == last {
+            // sleep to the start of the next minute
+            let left = 60 - now.as_secs() % 60;
+            std::thread::sleep(
+                Duration::from_secs(left) - Duration::from_nanos(now.subsec_nanos().into()),
+            );
+            continue;
+        }
+        last = minute;
+
+        let time = local_time(minute * 60);
+        for (entry, cron) in entries.iter().zip(&crons) {
+            if !cron.matches(&time) {
+                continue;
+            }
+
+            // a process per copy, so one failing does not stop the schedule
+            let mut cmd = Command::new(&exe);
+            // each copy is a run of its own, with its own journal
+            cmd.env_remove("CPX_RUN_ID");
+            for x in configs {
+                cmd.arg("-c").arg(x);
+            }
+            cmd.arg(&entry.spec);
+            for x in &entry.tags {
+                cmd.arg("--tag").arg(x);
+            }
+            for x in &entry.files {
+                cmd.arg("--file").arg(x);
+            }
+            cmd.args(&entry.args);
+
+            log::info!("Run {} ({})", entry.spec, entry.cron);
+            log::debug!("Run {:?}", cmd);
+            match cmd.status() {
+                Ok(x) if x.success() => {}
+                Ok(x) => log::error!("Copy {} failed, {}", entry.spec, x),
+                Err(e) => log::error!("Copy {} failed, {}", entry.spec, e),
+            }
+        }
+    }
+}
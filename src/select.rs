@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+/// A boolean expression over tags, `web & !tests | (docs & release)`.
+///
+/// `!` binds tightest, then `&`, then `|`. A tag stands for the keys of
+/// its files, `!` for the other keys of `file_list`.
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    Tag(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(_) => Err(parser.error("`&`, `|` or the end")),
+        }
+    }
+
+    /// The file keys selected, with `files(tag)` the keys of a tag and
+    /// `all` those of `file_list`.
+    pub fn eval<F>(&self, files: &F, all: &HashSet<String>) -> Result<HashSet<String>, String>
+    where
+        F: Fn(&str) -> Option<HashSet<String>>,
+    {
+        Ok(match self {
+            Expr::Tag(x) => files(x).ok_or_else(|| format!("no tag {} in tag_list", x))?,
+            Expr::Not(x) => all.difference(&x.eval(files, all)?).cloned().collect(),
+            Expr::And(x, y) => {
+                let x = x.eval(files, all)?;
+                x.intersection(&y.eval(files, all)?).cloned().collect()
+            }
+            Expr::Or(x, y) => {
+                let mut x = x.eval(files, all)?;
+                x.extend(y.eval(files, all)?);
+                x
+            }
+        })
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += c.len_utf8();
+        }
+        found
+    }
+
+    fn error(&self, expected: &str) -> String {
+        format!(
+            "expected {} at column {} of `{}`",
+            expected,
+            self.pos + 1,
+            self.text
+        )
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat('|') {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.eat('&') {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('!') {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat('(') {
+            let expr = self.or()?;
+            return match self.eat(')') {
+                true => Ok(expr),
+                false => Err(self.error("`)`")),
+            };
+        }
+
+        self.peek();
+        let rest = &self.text[self.pos..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || "&|!()".contains(c))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("a tag"));
+        }
+        self.pos += len;
+        Ok(Expr::Tag(rest[..len].to_owned()))
+    }
+}
@@ -0,0 +1,205 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Auto,
+    Btrfs,
+    Zfs,
+}
+
+impl Kind {
+    pub const NAMES: [&'static str; 3] = ["auto", "btrfs", "zfs"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Kind::Auto),
+            "btrfs" => Some(Kind::Btrfs),
+            "zfs" => Some(Kind::Zfs),
+            _ => None,
+        }
+    }
+}
+
+/// A read-only point-in-time view of the source, removed again on drop.
+pub struct Snapshot {
+    kind: Kind,
+    /// btrfs snapshot directory or zfs `dataset@name`.
+    handle: String,
+    path: PathBuf,
+}
+
+fn run(cmd: &mut Command) -> io::Result<String> {
+    log::debug!("Run {:?}", cmd);
+    let out = cmd.output()?;
+    if !out.status.success() {
+        return Err(io::Error::other(format!(
+            "{:?} failed, {}",
+            cmd,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+fn fs_type(path: &Path) -> io::Result<String> {
+    Ok(
+        run(Command::new("stat").args(["-f", "-c", "%T"]).arg(path))?
+            .trim()
+            .to_owned(),
+    )
+}
+
+/// Root of the btrfs subvolume holding `path`, subvolume roots always
+/// have inode 256.
+#[cfg(unix)]
+fn btrfs_subvolume(path: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut dir = std::fs::canonicalize(path)?;
+    while std::fs::metadata(&dir)?.ino() != 256 {
+        if !dir.pop() {
+            return Err(io::Error::other("btrfs subvolume not found"));
+        }
+    }
+
+    Ok(dir)
+}
+
+#[cfg(not(unix))]
+fn btrfs_subvolume(_path: &Path) -> io::Result<PathBuf> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "btrfs snapshots are not supported on this platform",
+    ))
+}
+
+impl Snapshot {
+    /// Snapshot the filesystem holding `src`, named after the run.
+    pub fn create(kind: Kind, src: &Path, run_id: &str) -> io::Result<Self> {
+        let kind = match kind {
+            Kind::Auto => match fs_type(src)?.as_str() {
+                "btrfs" => Kind::Btrfs,
+                "zfs" => Kind::Zfs,
+                x => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        format!("no snapshot support for {} filesystems", x),
+                    ))
+                }
+            },
+            x => x,
+        };
+        let name = format!("cpx-{}", run_id);
+
+        let snapshot = match kind {
+            Kind::Btrfs => {
+                let subvolume = btrfs_subvolume(src)?;
+                let handle = subvolume.with_file_name(format!(".{}", name));
+                run(Command::new("btrfs")
+                    .args(["subvolume", "snapshot", "-r"])
+                    .arg(&subvolume)
+                    .arg(&handle))?;
+
+                let relative = std::fs::canonicalize(src)?
+                    .strip_prefix(&subvolume)
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default();
+                Snapshot {
+                    kind,
+                    path: handle.join(relative),
+                    handle: handle.to_string_lossy().into_owned(),
+                }
+            }
+            _ => {
+                let out = run(Command::new("df").args(["--output=source,target"]).arg(src))?;
+                let (dataset, mountpoint) = out
+                    .lines()
+                    .nth(1)
+                    .and_then(|x| x.split_once(char::is_whitespace))
+                    .ok_or_else(|| io::Error::other("zfs dataset not found"))?;
+                let mountpoint = Path::new(mountpoint.trim());
+                let handle = format!("{}@{}", dataset, name);
+                run(Command::new("zfs").arg("snapshot").arg(&handle))?;
+
+                let relative = std::fs::canonicalize(src)?
+                    .strip_prefix(mountpoint)
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default();
+                Snapshot {
+                    kind,
+                    path: mountpoint.join(".zfs/snapshot").join(&name).join(relative),
+                    handle,
+                }
+            }
+        };
+
+        log::info!("Copy from snapshot {}", snapshot.path.display());
+        Ok(snapshot)
+    }
+
+    /// Source path inside the snapshot.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Snapshots left of the filesystem holding `src` by runs that never
+/// removed them, as kind, handle and run id.
+pub fn leftovers(src: &Path) -> io::Result<Vec<(Kind, String, String)>> {
+    let mut found = vec![];
+
+    match fs_type(src)?.as_str() {
+        "btrfs" => {
+            let subvolume = btrfs_subvolume(src)?;
+            let parent = subvolume.parent().unwrap_or(&subvolume);
+            for entry in std::fs::read_dir(parent)? {
+                let path = entry?.path();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                if let Some(id) = name.strip_prefix(".cpx-") {
+                    found.push((
+                        Kind::Btrfs,
+                        path.to_string_lossy().into_owned(),
+                        id.to_owned(),
+                    ));
+                }
+            }
+        }
+        "zfs" => {
+            let out = run(Command::new("df").args(["--output=source"]).arg(src))?;
+            let dataset = out.lines().nth(1).unwrap_or_default().trim();
+            let out = run(Command::new("zfs")
+                .args(["list", "-H", "-t", "snapshot", "-o", "name", "-d", "1"])
+                .arg(dataset))?;
+            for handle in out.lines() {
+                if let Some((_, id)) = handle.split_once("@cpx-") {
+                    found.push((Kind::Zfs, handle.to_owned(), id.to_owned()));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(found)
+}
+
+/// Remove the snapshot `handle` of `kind`.
+pub fn destroy(kind: Kind, handle: &str) -> io::Result<()> {
+    match kind {
+        Kind::Btrfs => run(Command::new("btrfs")
+            .args(["subvolume", "delete"])
+            .arg(handle)),
+        _ => run(Command::new("zfs").arg("destroy").arg(handle)),
+    }
+    .map(|_| ())
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        if let Err(e) = destroy(self.kind, &self.handle) {
+            log::warn!("Remove snapshot {} failed, {}", self.handle, e);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::PathInfo;
+
+/// Source and destination of a copy, `from:to`.
+///
+/// Each side is the name of a `path_list` entry, optionally followed by a
+/// subpath below it, `build/debug:server/bin` copies from debug under the
+/// build path into bin under the server path. The source can be several
+/// of them joined by `+`, `base+prod:site` merges their files.
+pub fn parse(spec: &str) -> Result<(String, String), String> {
+    match parse_sides(spec)? {
+        (Some(from), to) => Ok((from, to)),
+        (None, _) => Err(format!(
+            "`{}` has no `:` between the source and the destination",
+            spec
+        )),
+    }
+}
+
+/// A spec of a destination alone, `to`, or of a copy, `from:to`.
+pub fn parse_dst(spec: &str) -> Result<(Option<String>, String), String> {
+    parse_sides(spec)
+}
+
+fn parse_sides(spec: &str) -> Result<(Option<String>, String), String> {
+    let mut sides = spec.split(':');
+    let (first, second) = (sides.next().unwrap(), sides.next());
+    if sides.next().is_some() {
+        return Err(format!("`{}` has more than one `:`", spec));
+    }
+
+    match second {
+        Some(to) => {
+            for x in first.split('+') {
+                side(spec, x, "source")?;
+            }
+            Ok((Some(first.to_owned()), side(spec, to, "destination")?))
+        }
+        None => Ok((None, side(spec, first, "destination")?)),
+    }
+}
+
+fn side(spec: &str, side: &str, what: &str) -> Result<String, String> {
+    let mut names = side.split('/');
+    if names.next().unwrap().is_empty() {
+        return Err(format!("`{}` has no {} path", spec, what));
+    }
+    // a subpath always stays below its path
+    if let Some(x) = names.find(|x| x.is_empty() || *x == "." || *x == "..") {
+        let why = match x {
+            "" => "an empty name",
+            _ => "`.` or `..`",
+        };
+        return Err(format!(
+            "the subpath of `{}` in `{}` has {}",
+            side, spec, why
+        ));
+    }
+
+    Ok(side.to_owned())
+}
+
+/// Make `name` of a spec an entry of `paths`: a subpath is added as a
+/// copy of its path, with the subpath joined to the path, and paths joined
+/// by `+` as a path merging them.
+pub fn resolve(paths: &mut HashMap<String, PathInfo>, name: &str) -> Result<(), String> {
+    // names with a `/` or `+` of their own come first
+    if paths.contains_key(name) {
+        return Ok(());
+    }
+    if name.contains('+') {
+        let names: Vec<_> = name.split('+').map(String::from).collect();
+        for x in &names {
+            resolve(paths, x)?;
+        }
+        let info = PathInfo {
+            collect: vec![],
+            merge: names.clone(),
+            ..paths[&names[0]].clone()
+        };
+        paths.insert(name.to_owned(), info);
+        return Ok(());
+    }
+
+    let (base, sub) = name.split_once('/').unwrap_or((name, ""));
+    let info = match paths.get(base) {
+        Some(x) if x.collect.is_empty() && x.merge.is_empty() => x,
+        Some(x) if x.merge.is_empty() => {
+            return Err(format!("`collect` path {} has no subpaths", base))
+        }
+        Some(_) => return Err(format!("`merge` path {} has no subpaths", base)),
+        None => {
+            let mut names: Vec<_> = paths.keys().map(|x| x.as_str()).collect();
+            names.sort_unstable();
+            return Err(format!(
+                "no path {} in path_list, there are {}",
+                base,
+                names.join(", ")
+            ));
+        }
+    };
+
+    let info = PathInfo {
+        path: info.path.join(sub),
+        ..info.clone()
+    };
+    paths.insert(name.to_owned(), info);
+    Ok(())
+}
+
+/// Values of the `{date}`, `{time}`, `{hostname}` and `{tag}` in paths,
+/// taken once at the start of a run so all its files land together.
+#[derive(Debug, Clone)]
+pub struct Vars {
+    date: String,
+    time: String,
+    hostname: Option<String>,
+    tag: Option<String>,
+}
+
+impl Vars {
+    /// Those of now, `{tag}` is the selected `tags` joined by `+`.
+    pub fn new(tags: &Option<Vec<String>>) -> Self {
+        let t = crate::schedule::local_time(crate::history::now());
+        Self {
+            date: format!("{:04}-{:02}-{:02}", t.year, t.month, t.mday),
+            time: format!("{:02}{:02}{:02}", t.hour, t.minute, t.second),
+            hostname: crate::config::hostname(),
+            tag: tags.as_ref().filter(|x| !x.is_empty()).map(|x| x.join("+")),
+        }
+    }
+
+    /// `{date}_{time}`, the name of a --rotate snapshot.
+    pub fn stamp(&self) -> String {
+        format!("{}_{}", self.date, self.time)
+    }
+
+    /// `path` with its variables replaced. Other `{...}`, like the GUIDs
+    /// of Windows volumes, are left as they are.
+    pub fn expand(&self, path: &Path) -> Result<PathBuf, String> {
+        let text = match path.to_str() {
+            Some(x) if x.contains('{') => x,
+            _ => return Ok(path.to_path_buf()),
+        };
+
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find('}').map_or(rest.len(), |x| x + 1);
+            let value = match &rest[..end] {
+                "{date}" => Some(&self.date),
+                "{time}" => Some(&self.time),
+                "{hostname}" => Some(
+                    self.hostname
+                        .as_ref()
+                        .ok_or("no hostname for `{hostname}`")?,
+                ),
+                "{tag}" => Some(
+                    self.tag
+                        .as_ref()
+                        .ok_or_else(|| format!("`{{tag}}` in {} needs a --tag", text))?,
+                ),
+                _ => None,
+            };
+            match value {
+                Some(x) => {
+                    out.push_str(x);
+                    rest = &rest[end..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+
+        Ok(PathBuf::from(out))
+    }
+}
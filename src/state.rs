@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory where cpx keeps data between runs.
+pub fn state_dir() -> PathBuf {
+    dirs::data_local_dir()
+        .map(|x| x.join("cpx"))
+        .unwrap_or_else(|| PathBuf::from(".cpx"))
+}
+
+/// Stable FNV-1a hash, used to derive file names from job descriptions.
+pub fn fnv1a<T: AsRef<[u8]>>(data: T) -> u64 {
+    data.as_ref().iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Identifier of this run, shared with child processes through `CPX_RUN_ID`.
+///
+/// An id inherited from the environment is kept, so a run started by a
+/// pipeline that already has an id is traceable under that id.
+pub fn run_id() -> String {
+    if let Some(id) = std::env::var("CPX_RUN_ID").ok().filter(|x| !x.is_empty()) {
+        return id;
+    }
+
+    let id = new_run_id();
+    std::env::set_var("CPX_RUN_ID", &id);
+    id
+}
+
+/// A fresh identifier, for each run of a process starting several, like
+/// the daemon. It neither reads nor sets `CPX_RUN_ID`.
+pub fn new_run_id() -> String {
+    let mut b = [0u8; 16];
+    let random = File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut b));
+    if random.is_err() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let seed = format!("{:?} {}", now, std::process::id());
+        b[..8].copy_from_slice(&fnv1a(&seed).to_le_bytes());
+        b[8..].copy_from_slice(&fnv1a(seed + "#").to_le_bytes());
+    }
+
+    // random UUID, version 4 / variant 1
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex: String = b.iter().map(|x| format!("{:02x}", x)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    #[serde(default)]
+    pub run_id: String,
+    pub files: BTreeSet<PathBuf>,
+}
+
+impl RunRecord {
+    fn path(job: &str) -> PathBuf {
+        state_dir()
+            .join("runs")
+            .join(format!("{:016x}.yaml", fnv1a(job)))
+    }
+
+    pub fn load(job: &str) -> Option<Self> {
+        File::open(Self::path(job))
+            .ok()
+            .and_then(|f| serde_yaml::from_reader(f).ok())
+    }
+
+    pub fn save(&self, job: &str) -> std::io::Result<()> {
+        let data = serde_yaml::to_vec(self).map_err(std::io::Error::other)?;
+        crate::store::write(&Self::path(job), &data)
+    }
+
+    /// Save as the record of `job`, returns the one it replaces. Runs of
+    /// the same job ending together each see the record of another.
+    pub fn replace(&self, job: &str) -> std::io::Result<Option<Self>> {
+        let _lock = crate::store::lock()?;
+        let previous = Self::load(job);
+        self.save(job)?;
+        Ok(previous)
+    }
+
+    /// Print what changed since `previous`, to stderr when stdout carries
+    /// machine-readable output.
+    pub fn print_diff(&self, previous: &Option<RunRecord>, to_stderr: bool) {
+        let print = |line: String| {
+            if to_stderr {
+                eprintln!("{}", line)
+            } else {
+                println!("{}", line)
+            }
+        };
+
+        let previous = match previous {
+            Some(x) => x,
+            None => {
+                print("No previous run recorded for this job".to_owned());
+                return;
+            }
+        };
+
+        let added: Vec<_> = self.files.difference(&previous.files).collect();
+        let removed: Vec<_> = previous.files.difference(&self.files).collect();
+
+        if added.is_empty() && removed.is_empty() {
+            print("No changes since previous run".to_owned());
+            return;
+        }
+
+        print("Changes since previous run:".to_owned());
+        for f in added {
+            print(format!("  + {}", f.display()));
+        }
+        for f in removed {
+            print(format!("  - {}", f.display()));
+        }
+    }
+}
+
+/// Modification time and size of a source file, as last copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    /// Nanoseconds since the epoch.
+    pub mtime: u64,
+    pub size: u64,
+}
+
+impl Stamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            mtime: u64::try_from(mtime.as_nanos()).ok()?,
+            size: meta.len(),
+        })
+    }
+}
+
+/// Stamps of the sources of the last successful run of a job, what
+/// --since-last-run compares against.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceStamps {
+    pub files: BTreeMap<PathBuf, Stamp>,
+}
+
+impl SourceStamps {
+    fn path(job: &str) -> PathBuf {
+        state_dir()
+            .join("sources")
+            .join(format!("{:016x}.yaml", fnv1a(job)))
+    }
+
+    /// Those of `job`, none before its first successful run.
+    pub fn load(job: &str) -> Self {
+        File::open(Self::path(job))
+            .ok()
+            .and_then(|f| serde_yaml::from_reader(f).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, job: &str) -> std::io::Result<()> {
+        let data = serde_yaml::to_vec(self).map_err(std::io::Error::other)?;
+        crate::store::write(&Self::path(job), &data)
+    }
+
+    /// Whether the file at `path` changed since, or is new.
+    pub fn changed(&self, path: &Path) -> bool {
+        Stamp::of(path).is_none_or(|x| self.files.get(path) != Some(&x))
+    }
+}
+
+/// Stamps of both sides of the files of a `cpx sync` job as it left
+/// them, a side differing from its stamp changed since.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncState {
+    pub files: BTreeMap<PathBuf, (Stamp, Stamp)>,
+}
+
+impl SyncState {
+    fn path(job: &str) -> PathBuf {
+        state_dir()
+            .join("sync")
+            .join(format!("{:016x}.yaml", fnv1a(job)))
+    }
+
+    /// That of `job`, empty before its first sync.
+    pub fn load(job: &str) -> Self {
+        File::open(Self::path(job))
+            .ok()
+            .and_then(|f| serde_yaml::from_reader(f).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, job: &str) -> std::io::Result<()> {
+        let data = serde_yaml::to_vec(self).map_err(std::io::Error::other)?;
+        crate::store::write(&Self::path(job), &data)
+    }
+}
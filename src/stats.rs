@@ -0,0 +1,82 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// What a run did, for `--stats` and `--stats-json`.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub run_id: String,
+    pub copied: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Records per status, the three above among them.
+    pub counts: BTreeMap<String, usize>,
+    /// Bytes written by the copied files.
+    pub bytes: u64,
+    /// Seconds.
+    pub elapsed: f64,
+    /// Bytes per second.
+    pub throughput: f64,
+}
+
+impl Stats {
+    pub fn new(
+        run_id: &str,
+        counts: BTreeMap<String, usize>,
+        bytes: u64,
+        elapsed: Duration,
+    ) -> Self {
+        let count = |x: &str| counts.get(x).copied().unwrap_or(0);
+        let secs = elapsed.as_secs_f64();
+
+        Self {
+            run_id: run_id.to_owned(),
+            copied: count("copied"),
+            skipped: count("skipped"),
+            failed: count("failed") + count("circuit_open"),
+            bytes,
+            elapsed: secs,
+            throughput: if secs > 0.0 { bytes as f64 / secs } else { 0.0 },
+            counts,
+        }
+    }
+
+    /// Print to stderr, stdout may carry the records.
+    pub fn print(&self) {
+        eprintln!("{:<10} {}", "copied", self.copied);
+        eprintln!("{:<10} {}", "skipped", self.skipped);
+        eprintln!("{:<10} {}", "failed", self.failed);
+        for (status, n) in &self.counts {
+            if !["copied", "skipped", "failed", "circuit_open"].contains(&status.as_str()) {
+                eprintln!("{:<10} {}", status, n);
+            }
+        }
+        eprintln!("{:<10} {}", "bytes", human(self.bytes as f64));
+        eprintln!("{:<10} {:.3}s", "elapsed", self.elapsed);
+        eprintln!("{:<10} {}/s", "throughput", human(self.throughput));
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut data = serde_json::to_vec_pretty(self)?;
+        data.push(b'\n');
+        std::fs::write(path, data)
+    }
+}
+
+/// `1.5 MiB` and the like.
+pub fn human(bytes: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", value as u64),
+        _ => format!("{:.1} {}", value, units[unit]),
+    }
+}
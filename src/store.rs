@@ -0,0 +1,110 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+use crate::state::{fnv1a, state_dir};
+
+/// Exclusive hold on the state shared by the cpx processes of a user, run
+/// records, history and journals, released on drop. Held only around
+/// reading and updating them, never while copying.
+pub struct Lock {
+    _file: File,
+}
+
+/// Wait until no other cpx process holds the state lock.
+pub fn lock() -> io::Result<Lock> {
+    std::fs::create_dir_all(state_dir())?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(state_dir().join("state.lock"))?;
+
+    flock(&file, true)?;
+    Ok(Lock { _file: file })
+}
+
+/// Hold on the destination `dst` against other cpx runs copying to it,
+/// released on drop. With `wait` a run already copying there is waited
+/// for, without it that is an error naming its process.
+pub fn lock_destination(dst: &str, wait: bool) -> io::Result<Lock> {
+    let dir = state_dir().join("locks");
+    std::fs::create_dir_all(&dir)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(dir.join(format!("{:016x}.lock", fnv1a(dst))))?;
+
+    if !flock(&file, false)? {
+        let mut holder = String::new();
+        let _ = file.read_to_string(&mut holder);
+        let holder = match holder.split_whitespace().next() {
+            Some(pid) => format!("cpx {}", pid),
+            None => "another cpx".to_owned(),
+        };
+        if !wait {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} is copying to {}", holder, dst),
+            ));
+        }
+        log::info!("Wait for {} copying to {}", holder, dst);
+        flock(&file, true)?;
+    }
+
+    // who holds it, for the runs that find it taken
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{} {}", std::process::id(), dst)?;
+    Ok(Lock { _file: file })
+}
+
+/// Take an exclusive `flock` on `file`, waiting for it with `block`.
+/// Whether it was taken, always on platforms without `flock`.
+#[cfg(unix)]
+fn flock(file: &File, block: bool) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let op = match block {
+        true => libc::LOCK_EX,
+        false => libc::LOCK_EX | libc::LOCK_NB,
+    };
+    while unsafe { libc::flock(file.as_raw_fd(), op) } != 0 {
+        let e = io::Error::last_os_error();
+        match e.kind() {
+            io::ErrorKind::Interrupted => {}
+            io::ErrorKind::WouldBlock => return Ok(false),
+            _ => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+fn flock(_file: &File, _block: bool) -> io::Result<bool> {
+    Ok(true)
+}
+
+/// Replace `path` with `data` so readers see either the old or the new
+/// content, never a partial one.
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // a name of this process, another one may write the same file
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = path.with_file_name(name);
+
+    let result = File::create(&tmp)
+        .and_then(|mut f| f.write_all(data))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+
+    result
+}
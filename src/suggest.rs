@@ -0,0 +1,48 @@
+/// The name of `candidates` closest to the unknown `name`, if one is close
+/// enough to be a typo of it.
+pub fn closest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    // about a third of the name may be wrong
+    let limit = (name.chars().count() + 1) / 3;
+    candidates
+        .into_iter()
+        .map(|x| (distance(name, x), x))
+        .filter(|(d, _)| *d <= limit)
+        .min()
+        .map(|(_, x)| x.as_str())
+}
+
+/// ` (did you mean `x`?)` for the closest candidate, or nothing.
+pub fn hint<'a, I>(name: &str, candidates: I) -> String
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    closest(name, candidates)
+        .map(|x| format!(" (did you mean `{}`?)", x))
+        .unwrap_or_default()
+}
+
+/// Edits turning `a` into `b`, counting a swap of neighbours as one.
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
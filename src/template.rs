@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::io;
+
+/// The value of `name`, from the environment or else from `vars`.
+fn value(name: &str, vars: &HashMap<String, String>) -> Option<String> {
+    std::env::var(name).ok().or_else(|| vars.get(name).cloned())
+}
+
+fn is_name(x: &str) -> bool {
+    let mut chars = x.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace each `{{NAME}}` in `data`, spaces inside the braces allowed.
+/// Braces around anything but a name are kept, an unknown name fails.
+pub fn render(data: &[u8], vars: &HashMap<String, String>) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut rest = data;
+
+    while let Some(start) = rest.windows(2).position(|x| x == b"{{") {
+        out.extend_from_slice(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.windows(2).position(|x| x == b"}}") {
+            Some(x) => x,
+            None => break,
+        };
+        let name = std::str::from_utf8(&rest[2..end]).map(str::trim);
+        match name {
+            Ok(name) if is_name(name) => {
+                let value = value(name, vars).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("no value for {{{{{}}}}} in the template", name),
+                    )
+                })?;
+                out.extend_from_slice(value.as_bytes());
+            }
+            _ => out.extend_from_slice(&rest[..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    out.extend_from_slice(rest);
+
+    Ok(out)
+}
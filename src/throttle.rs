@@ -0,0 +1,37 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Paces writes of all copy workers to a shared bytes per second budget.
+pub struct RateLimit {
+    rate: u64,
+    next: Mutex<Instant>,
+}
+
+impl RateLimit {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: rate.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Parse a rate like `500K`, `10M` or `1.5G/s`, units are powers of 1024.
+    pub fn parse(s: &str) -> Result<u64, String> {
+        match crate::filter::parse_size(s.trim().trim_end_matches("/s")) {
+            Ok(0) => Err(format!("invalid rate `{}`", s)),
+            x => x,
+        }
+    }
+
+    /// Account `n` bytes just written and sleep until they fit the rate.
+    pub fn consume(&self, n: u64) {
+        let wait = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            *next = (*next).max(now) + Duration::from_secs_f64(n as f64 / self.rate as f64);
+            *next - now
+        };
+
+        std::thread::sleep(wait);
+    }
+}
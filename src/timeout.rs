@@ -0,0 +1,271 @@
+use std::cell::RefCell;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How often the watchdog looks at the files being copied.
+const TICK: Duration = Duration::from_millis(100);
+
+/// A copy still running this long after it was interrupted is stuck in
+/// the kernel, like a read of a hard NFS mount, which no signal ends.
+const GRACE: Duration = Duration::from_secs(10);
+
+/// Whether the run reached its --run-timeout.
+static RUN_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The file the thread is copying, of its worker or of the worker
+    /// it copies chunks for.
+    static CURRENT: RefCell<Option<Arc<Copying>>> = const { RefCell::new(None) };
+}
+
+/// A file being copied by threads, and why it timed out once it did.
+#[derive(Debug)]
+pub struct Copying {
+    dst: PathBuf,
+    started: Instant,
+    #[cfg(unix)]
+    threads: Mutex<Vec<libc::pthread_t>>,
+    timed_out: OnceLock<(String, Instant)>,
+}
+
+/// Interrupts the copies running past --timeout, or past the
+/// --run-timeout of the run, so a dead mount fails the file instead of
+/// hanging the run.
+#[derive(Debug)]
+pub struct Watchdog {
+    limit: Option<Duration>,
+    deadline: Option<Instant>,
+    files: Mutex<Vec<Arc<Copying>>>,
+    done: AtomicBool,
+}
+
+impl Watchdog {
+    /// One for --timeout `limit` of each file and the `deadline` of the
+    /// run, none without them.
+    pub fn new(limit: Option<Duration>, deadline: Option<Instant>) -> Option<Self> {
+        if limit.is_none() && deadline.is_none() {
+            return None;
+        }
+        #[cfg(unix)]
+        signals::install();
+        Some(Self {
+            limit,
+            deadline,
+            files: Mutex::new(vec![]),
+            done: AtomicBool::new(false),
+        })
+    }
+
+    /// The calling thread starts copying to `dst`, until the returned
+    /// guard is dropped.
+    pub fn start(&self, dst: &Path) -> Guard<'_> {
+        let file = Arc::new(Copying {
+            dst: dst.to_path_buf(),
+            started: Instant::now(),
+            #[cfg(unix)]
+            threads: Mutex::new(vec![]),
+            timed_out: OnceLock::new(),
+        });
+        self.files.lock().unwrap().push(file.clone());
+        Guard {
+            watchdog: self,
+            _entered: enter(Some(file)),
+        }
+    }
+
+    /// Look at the files being copied until `stop` is called.
+    pub fn run(&self) {
+        while !self.done.load(Ordering::Relaxed) {
+            std::thread::sleep(TICK);
+            let files = self.files.lock().unwrap();
+            let now = Instant::now();
+            for file in files.iter() {
+                let why = match (self.limit, self.deadline) {
+                    (Some(x), _) if file.started + x <= now => {
+                        format!("timed out after {}s", x.as_secs_f64())
+                    }
+                    (_, Some(x)) if x <= now => {
+                        RUN_TIMED_OUT.store(true, Ordering::Relaxed);
+                        "the run reached its --run-timeout".to_owned()
+                    }
+                    _ => continue,
+                };
+                if file.timed_out.set((why, now)).is_ok() {
+                    log::warn!("Copy {} timed out, interrupting it", file.dst.display());
+                }
+                // again each tick, a call retried after the signal blocks
+                // again
+                #[cfg(unix)]
+                for thread in file.threads.lock().unwrap().iter() {
+                    unsafe { libc::pthread_kill(*thread, signals::SIGNAL) };
+                }
+            }
+
+            let stuck =
+                |x: &Arc<Copying>| x.timed_out.get().is_some_and(|(_, at)| *at + GRACE <= now);
+            if !files.is_empty() && files.iter().all(stuck) {
+                for file in files.iter() {
+                    log::error!(
+                        "Copy {} is stuck, interrupting it failed",
+                        file.dst.display()
+                    );
+                }
+                log::error!("Exit, the files not copied yet are left for the next run");
+                crate::cleanup::clean();
+                std::process::exit(1);
+            }
+        }
+    }
+
+    pub fn stop(&self) {
+        self.done.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the run is past its deadline, which stops it.
+    pub fn past_deadline(&self) -> bool {
+        if self.deadline.is_some_and(|x| x <= Instant::now()) {
+            RUN_TIMED_OUT.store(true, Ordering::Relaxed);
+        }
+        run_timed_out()
+    }
+}
+
+/// While alive, the thread copies a file of the watchdog.
+pub struct Guard<'a> {
+    watchdog: &'a Watchdog,
+    _entered: Entered,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        let current = current();
+        let mut files = self.watchdog.files.lock().unwrap();
+        files.retain(|x| !current.as_ref().is_some_and(|y| Arc::ptr_eq(x, y)));
+    }
+}
+
+/// The file the thread is copying, for the threads it copies chunks with.
+pub fn current() -> Option<Arc<Copying>> {
+    CURRENT.with(|x| x.borrow().clone())
+}
+
+/// The thread helps copying `file` until the returned guard is dropped.
+pub fn enter(file: Option<Arc<Copying>>) -> Entered {
+    #[cfg(unix)]
+    if let Some(file) = &file {
+        file.threads
+            .lock()
+            .unwrap()
+            .push(unsafe { libc::pthread_self() });
+    }
+    CURRENT.with(|x| *x.borrow_mut() = file);
+    Entered(())
+}
+
+pub struct Entered(());
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        if let Some(file) = CURRENT.with(|x| x.borrow_mut().take()) {
+            #[cfg(unix)]
+            {
+                let me = unsafe { libc::pthread_self() };
+                file.threads.lock().unwrap().retain(|x| *x != me);
+            }
+        }
+    }
+}
+
+/// Whether the run reached its --run-timeout.
+pub fn run_timed_out() -> bool {
+    RUN_TIMED_OUT.load(Ordering::Relaxed)
+}
+
+/// Why the file of the thread timed out, none if it did not.
+fn reason() -> Option<String> {
+    CURRENT.with(|x| {
+        x.borrow()
+            .as_ref()
+            .and_then(|f| f.timed_out.get().map(|(why, _)| why.clone()))
+    })
+}
+
+/// Whether the file of the thread timed out.
+pub fn expired() -> bool {
+    reason().is_some()
+}
+
+/// An error once the file of the thread timed out, checked between the
+/// reads and writes of a copy whose calls still complete.
+pub fn check() -> io::Result<()> {
+    match reason() {
+        Some(why) => Err(io::Error::new(io::ErrorKind::TimedOut, why)),
+        None => Ok(()),
+    }
+}
+
+/// `e` as the timeout of the file of the thread when it timed out, the
+/// interrupted call is not what went wrong.
+pub fn explain(e: io::Error) -> io::Error {
+    match reason() {
+        Some(why) => io::Error::new(io::ErrorKind::TimedOut, why),
+        None => e,
+    }
+}
+
+/// `write` until all of `buf` is written, like `write_all` but giving up
+/// on an interrupted call once the file timed out.
+pub fn write_all(
+    mut write: impl FnMut(&[u8]) -> io::Result<usize>,
+    mut buf: &[u8],
+) -> io::Result<()> {
+    while !buf.is_empty() {
+        match write(buf) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => buf = &buf[n..],
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => check()?,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Like `read_to_end`, giving up on an interrupted call once the file
+/// timed out.
+pub fn read_to_end(mut reader: impl Read, buf: &mut Vec<u8>) -> io::Result<usize> {
+    let mut chunk = [0; 8192];
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => return Ok(buf.len()),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => check()?,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::sync::Once;
+
+    /// Sent to the threads of a timed out copy, its handler does nothing
+    /// but end the blocking call with EINTR.
+    pub const SIGNAL: libc::c_int = libc::SIGALRM;
+
+    extern "C" fn on_signal(_sig: libc::c_int) {}
+
+    pub fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            // no SA_RESTART, the call is not started again
+            action.sa_flags = 0;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(SIGNAL, &action, std::ptr::null_mut());
+        });
+    }
+}
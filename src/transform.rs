@@ -0,0 +1,55 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Files with a NUL byte this early are not text, they are copied as is.
+const SNIFF: usize = 8000;
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A source file as it is to be written, at a temporary path removed when
+/// dropped.
+pub struct Transformed {
+    path: PathBuf,
+}
+
+impl Transformed {
+    /// Write `data`, the transformed content of `src`, with the
+    /// permissions of `src`. The file is new, with --compare size-mtime a
+    /// change of the transform is not missed.
+    pub fn write(src: &Path, data: &[u8]) -> io::Result<Self> {
+        let meta = std::fs::metadata(src)?;
+        let path = std::env::temp_dir().join(format!(
+            "cpx-{}-{}.transformed",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        crate::cleanup::register(&path);
+        let transformed = Self { path };
+
+        // only readable by the owner while written, created anew
+        let mut options = std::fs::File::options();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(transformed.path())?.write_all(data)?;
+        std::fs::set_permissions(transformed.path(), meta.permissions())?;
+
+        Ok(transformed)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Transformed {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        crate::cleanup::release(&self.path);
+    }
+}
+
+pub fn is_text(data: &[u8]) -> bool {
+    !data[..data.len().min(SNIFF)].contains(&0)
+}
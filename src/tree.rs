@@ -0,0 +1,34 @@
+/// One line of `cpx tree` and the lines below it.
+#[derive(Debug, Default)]
+pub struct Node {
+    pub label: String,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            children: vec![],
+        }
+    }
+
+    /// The node and below it its children, drawn like tree(1) does.
+    pub fn render(&self) -> String {
+        let mut out = format!("{}\n", self.label);
+        render_children(&self.children, "", &mut out);
+        out
+    }
+}
+
+fn render_children(children: &[Node], prefix: &str, out: &mut String) {
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, more) = match last {
+            true => ("└── ", "    "),
+            false => ("├── ", "│   "),
+        };
+        out.push_str(&format!("{}{}{}\n", prefix, branch, child.label));
+        render_children(&child.children, &format!("{}{}", prefix, more), out);
+    }
+}
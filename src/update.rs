@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::http;
+
+fn default_identity() -> String {
+    "cpx-release".to_owned()
+}
+
+/// Where `cpx self-update` finds new releases and whom it trusts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateConfig {
+    /// URL of the release description, `{target}` is replaced by the
+    /// platform like `x86_64-linux`.
+    pub endpoint: String,
+    /// ssh-keygen allowed signers file with the release keys.
+    pub allowed_signers: PathBuf,
+    /// Principal the releases are signed as.
+    #[serde(default = "default_identity")]
+    pub identity: String,
+}
+
+/// The release description at the endpoint.
+#[derive(Debug, Deserialize)]
+struct Release {
+    version: String,
+    url: String,
+    /// `ssh-keygen -Y sign -n cpx` signature of the binary.
+    signature_url: String,
+}
+
+/// Namespace of release signatures, so no other signature of the key
+/// passes as one.
+const NAMESPACE: &str = "cpx";
+
+fn target() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+/// Compare dotted versions by their numeric parts.
+fn is_newer(version: &str, than: &str) -> bool {
+    let parse = |x: &str| -> Vec<u64> {
+        x.trim_start_matches('v')
+            .split(['.', '-', '+'])
+            .map_while(|x| x.parse().ok())
+            .collect()
+    };
+    parse(version) > parse(than)
+}
+
+/// Check `path` against its `signature` with ssh-keygen.
+fn verify(config: &UpdateConfig, path: &Path, signature: &Path) -> io::Result<()> {
+    let out = Command::new("ssh-keygen")
+        .args(["-Y", "verify", "-n", NAMESPACE])
+        .arg("-f")
+        .arg(&config.allowed_signers)
+        .arg("-I")
+        .arg(&config.identity)
+        .arg("-s")
+        .arg(signature)
+        .stdin(std::fs::File::open(path)?)
+        .output()?;
+
+    if out.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "bad signature, {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ),
+    ))
+}
+
+/// Whether the binary at `path` runs at all on this machine.
+fn runs(path: &Path) -> io::Result<()> {
+    let status = Command::new(path)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other("the new binary does not run")),
+    }
+}
+
+/// Give `new` the permissions of the running binary `exe`.
+#[cfg(unix)]
+fn copy_mode(exe: &Path, new: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = std::fs::metadata(exe)?.permissions().mode() & 0o7777;
+    std::fs::set_permissions(new, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn copy_mode(_exe: &Path, _new: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Put `new` in place of the running binary `exe`, renaming is atomic.
+#[cfg(unix)]
+fn replace(new: &Path, exe: &Path) -> io::Result<()> {
+    std::fs::rename(new, exe)
+}
+
+/// A running binary cannot be replaced but can be renamed away.
+#[cfg(not(unix))]
+fn replace(new: &Path, exe: &Path) -> io::Result<()> {
+    let old = exe.with_extension("old");
+    let _ = std::fs::remove_file(&old);
+    std::fs::rename(exe, &old)?;
+    std::fs::rename(new, exe)
+}
+
+/// Update the running binary to the latest release. `check` only reports
+/// it, `force` installs it even when not newer. Returns the version that
+/// is available or was installed.
+pub fn run(config: &UpdateConfig, check: bool, force: bool) -> io::Result<String> {
+    let current = env!("CARGO_PKG_VERSION");
+    let endpoint = config.endpoint.replace("{target}", &target());
+    let release: Release = serde_json::from_slice(&http::fetch(&endpoint)?)?;
+
+    if !force && !is_newer(&release.version, current) {
+        log::info!("cpx {} is up to date", current);
+        return Ok(current.to_owned());
+    }
+    if check {
+        println!("{:<10} {} -> {}", "update", current, release.version);
+        return Ok(release.version);
+    }
+
+    // next to the binary, so the rename stays on one file system
+    let exe = std::env::current_exe()?;
+    let tmp = crate::copy::temp_path(&exe, std::process::id());
+    let sig = crate::copy::temp_path(&exe.with_extension("sig"), std::process::id());
+    crate::cleanup::register(&tmp);
+    crate::cleanup::register(&sig);
+
+    let result = (|| {
+        std::fs::File::create(&tmp)?.write_all(&http::fetch(&release.url)?)?;
+        std::fs::write(&sig, http::fetch(&release.signature_url)?)?;
+        verify(config, &tmp, &sig)?;
+        copy_mode(&exe, &tmp)?;
+        runs(&tmp)?;
+        replace(&tmp, &exe)
+    })();
+
+    let _ = std::fs::remove_file(&sig);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    crate::cleanup::release(&tmp);
+    crate::cleanup::release(&sig);
+    result?;
+
+    log::info!("Updated cpx {} to {}", current, release.version);
+    Ok(release.version)
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Waits for changes to a fixed set of files. Their directories are
+/// watched rather than the files, so editors replacing a file on save
+/// are seen too.
+#[cfg(target_os = "linux")]
+pub struct Watcher {
+    fd: libc::c_int,
+    /// Directory of each watch descriptor.
+    dirs: std::collections::HashMap<libc::c_int, PathBuf>,
+    files: HashSet<PathBuf>,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    pub fn new(files: &[PathBuf]) -> io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut watcher = Self {
+            fd,
+            dirs: Default::default(),
+            files: files.iter().cloned().collect(),
+        };
+
+        let mut dirs: Vec<_> = files.iter().filter_map(|x| x.parent()).collect();
+        dirs.sort_unstable();
+        dirs.dedup();
+        for dir in dirs {
+            let name = std::ffi::CString::new(dir.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_DELETE;
+            let wd = unsafe { libc::inotify_add_watch(fd, name.as_ptr(), mask) };
+            if wd < 0 {
+                log::warn!(
+                    "Watch {} failed, {}",
+                    dir.display(),
+                    io::Error::last_os_error()
+                );
+                continue;
+            }
+            watcher.dirs.insert(wd, dir.to_path_buf());
+        }
+
+        Ok(watcher)
+    }
+
+    /// Block until a file changed and then none for `debounce`, returns
+    /// the changed files.
+    pub fn wait(&mut self, debounce: Duration) -> io::Result<Vec<PathBuf>> {
+        let mut changed = HashSet::new();
+        while changed.is_empty() {
+            self.read(&mut changed)?;
+        }
+
+        let timeout = debounce.as_millis().min(i32::MAX as u128) as libc::c_int;
+        loop {
+            let mut pfd = libc::pollfd {
+                fd: self.fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut pfd, 1, timeout) } {
+                0 => break,
+                n if n < 0 => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+                _ => self.read(&mut changed)?,
+            }
+        }
+
+        let mut changed: Vec<_> = changed.into_iter().collect();
+        changed.sort_unstable();
+        Ok(changed)
+    }
+
+    /// Read the pending events, adding the watched files they touch.
+    fn read(&self, changed: &mut HashSet<PathBuf>) -> io::Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut buf = [0u8; 4096];
+        let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::Interrupted => Ok(()),
+                _ => Err(e),
+            };
+        }
+
+        // struct inotify_event: wd, mask, cookie, len and the name
+        let mut i = 0;
+        while i + 16 <= n as usize {
+            let field = |at: usize| {
+                [
+                    buf[i + at],
+                    buf[i + at + 1],
+                    buf[i + at + 2],
+                    buf[i + at + 3],
+                ]
+            };
+            let wd = libc::c_int::from_ne_bytes(field(0));
+            let mask = u32::from_ne_bytes(field(4));
+            let len = u32::from_ne_bytes(field(12)) as usize;
+            let name = &buf[i + 16..(i + 16 + len).min(n as usize)];
+            let name = &name[..name.iter().position(|x| *x == 0).unwrap_or(name.len())];
+            i += 16 + len;
+
+            // events were dropped, any file may have changed
+            if mask & libc::IN_Q_OVERFLOW != 0 {
+                changed.extend(self.files.iter().cloned());
+                continue;
+            }
+            if let Some(dir) = self.dirs.get(&wd) {
+                let path = dir.join(std::ffi::OsStr::from_bytes(name));
+                if self.files.contains(&path) {
+                    changed.insert(path);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+/// How often files are checked without inotify.
+#[cfg(not(target_os = "linux"))]
+const POLL: Duration = Duration::from_secs(1);
+
+/// Waits for changes to a fixed set of files by polling their size and
+/// modification time.
+#[cfg(not(target_os = "linux"))]
+pub struct Watcher {
+    files: Vec<(PathBuf, Option<(u64, std::time::SystemTime)>)>,
+}
+
+#[cfg(not(target_os = "linux"))]
+fn stat(path: &std::path::Path) -> Option<(u64, std::time::SystemTime)> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some((meta.len(), meta.modified().ok()?))
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    pub fn new(files: &[PathBuf]) -> io::Result<Self> {
+        Ok(Self {
+            files: files.iter().map(|x| (x.clone(), stat(x))).collect(),
+        })
+    }
+
+    /// Block until a file changed and then none for `debounce`, returns
+    /// the changed files.
+    pub fn wait(&mut self, debounce: Duration) -> io::Result<Vec<PathBuf>> {
+        let mut changed = HashSet::new();
+        loop {
+            std::thread::sleep(if changed.is_empty() { POLL } else { debounce });
+
+            let mut quiet = true;
+            for (path, last) in &mut self.files {
+                let now = stat(path);
+                if now != *last {
+                    *last = now;
+                    changed.insert(path.clone());
+                    quiet = false;
+                }
+            }
+            if quiet && !changed.is_empty() {
+                break;
+            }
+        }
+
+        let mut changed: Vec<_> = changed.into_iter().collect();
+        changed.sort_unstable();
+        Ok(changed)
+    }
+}
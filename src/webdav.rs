@@ -0,0 +1,345 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::compare::FileStatus;
+use crate::state::state_dir;
+
+fn default_user_env() -> String {
+    "CPX_WEBDAV_USER".to_owned()
+}
+
+fn default_password_env() -> String {
+    "CPX_WEBDAV_PASSWORD".to_owned()
+}
+
+/// Credentials of a `dav://` or `davs://` path. The environment variables
+/// win over `credentials_file`, a netrc file, which falls back to
+/// `~/.netrc`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebDavOptions {
+    #[serde(default = "default_user_env")]
+    pub user_env: String,
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+    #[serde(default)]
+    pub credentials_file: Option<PathBuf>,
+}
+
+impl Default for WebDavOptions {
+    fn default() -> Self {
+        Self {
+            user_env: default_user_env(),
+            password_env: default_password_env(),
+            credentials_file: None,
+        }
+    }
+}
+
+/// A destination collection on a WebDAV server like Nextcloud or
+/// SharePoint, written through curl.
+#[derive(Debug)]
+pub struct Dav {
+    /// `https://host[:port]` without a path.
+    origin: String,
+    path: PathBuf,
+    options: WebDavOptions,
+    /// curl config holding the credentials, kept off the command line.
+    auth: Option<PathBuf>,
+    /// Collections known to exist.
+    created: Mutex<HashSet<PathBuf>>,
+}
+
+/// Percent-encode `path` for a URL, keeping the `/` separators.
+fn encode(path: &Path) -> String {
+    let mut out = String::new();
+    for c in path.components() {
+        if let Component::Normal(x) = c {
+            out.push('/');
+            for b in x.to_string_lossy().bytes() {
+                match b {
+                    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                        out.push(b as char)
+                    }
+                    _ => out.push_str(&format!("%{:02X}", b)),
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Value of the XML element `name` in any namespace.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!(":{}>", name))? + name.len() + 2;
+    let len = xml[start..].find('<')?;
+    Some(xml[start..start + len].trim())
+}
+
+/// Parse an HTTP date like `Tue, 14 Oct 2026 11:19:00 GMT`.
+fn http_date(s: &str) -> Option<SystemTime> {
+    let parts: Vec<_> = s.split_whitespace().collect();
+    let (day, month, year, time) = match parts[..] {
+        [_, d, m, y, t, "GMT"] => (d, m, y, t),
+        _ => return None,
+    };
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let month = MONTHS.iter().position(|x| *x == month)? as i64 + 1;
+    let hms: Vec<u64> = time.split(':').filter_map(|x| x.parse().ok()).collect();
+    let (h, m, sec) = match hms[..] {
+        [h, m, s] => (h, m, s),
+        _ => return None,
+    };
+
+    let days = crate::filter::days_from_civil(year.parse().ok()?, month, day.parse().ok()?);
+    let secs = u64::try_from(days).ok()? * 86_400 + h * 3600 + m * 60 + sec;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Credentials left by processes that were killed before cleaning up.
+fn remove_stale_logins() {
+    let entries = match std::fs::read_dir(state_dir()) {
+        Ok(x) => x,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let pid = name
+            .strip_prefix("webdav-")
+            .and_then(|x| x.strip_suffix(".conf"))
+            .and_then(|x| x.parse().ok());
+        if pid.is_some_and(|pid| !crate::checkpoint::pid_alive(pid)) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+impl Dav {
+    /// Split `davs://host/path`, `dav://` is plain HTTP.
+    pub fn parse(path: &Path, options: Option<&WebDavOptions>) -> Option<Self> {
+        let s = path.to_str()?;
+        let (scheme, rest) = match s.split_once("://")? {
+            ("davs", rest) => ("https", rest),
+            ("dav", rest) => ("http", rest),
+            _ => return None,
+        };
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            origin: format!("{}://{}", scheme, host),
+            path: PathBuf::from("/").join(path),
+            options: options.cloned().unwrap_or_default(),
+            auth: None,
+            created: Mutex::new(HashSet::new()),
+        })
+    }
+
+    /// Write the credentials from the environment to a private curl
+    /// config, so they never show up in the process list.
+    pub fn login(&mut self) -> io::Result<()> {
+        let user = match std::env::var(&self.options.user_env) {
+            Ok(x) => x,
+            Err(_) => return Ok(()),
+        };
+        let password = std::env::var(&self.options.password_env).unwrap_or_default();
+
+        std::fs::create_dir_all(state_dir())?;
+        remove_stale_logins();
+        let path = state_dir().join(format!("webdav-{}.conf", std::process::id()));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let user = format!("{}:{}", user, password);
+        let user = user.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(options.open(&path)?, "user = \"{}\"", user)?;
+        self.auth = Some(path);
+
+        Ok(())
+    }
+
+    /// Destination root on the server.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn url(&self, path: &Path) -> String {
+        format!("{}{}", self.origin, encode(path))
+    }
+
+    /// `path` on the server as shown in logs and output.
+    pub fn display(&self, path: &Path) -> PathBuf {
+        PathBuf::from(format!("{}{}", self.origin, path.display()))
+    }
+
+    /// curl requesting `path` with `method`, writing the status code as
+    /// the last line of stderr.
+    fn command(&self, method: &str, path: &Path) -> Command {
+        let mut cmd = Command::new("curl");
+        cmd.args(["--silent", "--show-error", "--proto", "=http,https"])
+            .args(["--write-out", "%{stderr}%{http_code}\n"])
+            .arg("--request")
+            .arg(method);
+
+        match (&self.auth, &self.options.credentials_file) {
+            (Some(auth), _) => {
+                cmd.arg("--config").arg(auth);
+            }
+            (None, Some(netrc)) => {
+                cmd.arg("--netrc-file").arg(netrc);
+            }
+            (None, None) => {
+                cmd.arg("--netrc-optional");
+            }
+        }
+
+        cmd.arg("--url").arg(self.url(path));
+        log::debug!("Run {:?}", cmd);
+        cmd
+    }
+
+    /// Run a request, returns the status code and the body.
+    fn request(&self, cmd: &mut Command) -> io::Result<(String, String)> {
+        let out = cmd.stdin(Stdio::null()).output()?;
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        let stderr = stderr.trim_end();
+        let (message, code) = stderr.rsplit_once('\n').unwrap_or(("", stderr));
+        if !out.status.success() {
+            return Err(io::Error::other(format!("curl failed, {}", message.trim())));
+        }
+
+        Ok((
+            code.to_owned(),
+            String::from_utf8_lossy(&out.stdout).into_owned(),
+        ))
+    }
+
+    fn expect(&self, what: &str, path: &Path, code: &str, ok: &[&str]) -> io::Result<()> {
+        if code.starts_with('2') || ok.contains(&code) {
+            return Ok(());
+        }
+
+        // the kind tells --retries whether trying again may help
+        let kind = match code {
+            "401" | "403" => io::ErrorKind::PermissionDenied,
+            "404" | "409" => io::ErrorKind::NotFound,
+            "507" => io::ErrorKind::StorageFull,
+            "408" | "429" => io::ErrorKind::Other,
+            _ if code.starts_with('4') => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::Other,
+        };
+        Err(io::Error::new(
+            kind,
+            format!(
+                "{} {} failed with HTTP {}",
+                what,
+                self.display(path).display(),
+                code
+            ),
+        ))
+    }
+
+    /// Compare what an upload of `src` would do to `dst` by size and
+    /// modification time, WebDAV has no standard checksums.
+    pub fn status(&self, src: &Path, dst: &Path) -> io::Result<FileStatus> {
+        let (code, body) = self.request(self.command("PROPFIND", dst).args(["-H", "Depth: 0"]))?;
+        if code == "404" {
+            return Ok(FileStatus::New);
+        }
+        self.expect("Look up", dst, &code, &[])?;
+
+        let meta = std::fs::metadata(src)?;
+        let len = element(&body, "getcontentlength").and_then(|x| x.parse().ok());
+        let modified = element(&body, "getlastmodified").and_then(http_date);
+        let local = meta.modified()?;
+        if len == Some(meta.len()) && modified.is_some_and(|x| x + Duration::from_secs(1) > local) {
+            return Ok(FileStatus::Identical);
+        }
+
+        Ok(FileStatus::Differs)
+    }
+
+    /// Create the collection above `dst` unless known to exist, walking up
+    /// only as far as the server reports missing parents.
+    pub fn create_parents(&self, dst: &Path) -> io::Result<()> {
+        let dir = match dst.parent().filter(|x| x.parent().is_some()) {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        if self.created.lock().unwrap().contains(dir) {
+            return Ok(());
+        }
+
+        let mut code = self.request(&mut self.command("MKCOL", dir))?.0;
+        // 409 is a missing parent, 405 a collection that already exists
+        if code == "409" {
+            self.create_parents(dir)?;
+            code = self.request(&mut self.command("MKCOL", dir))?.0;
+        }
+        self.expect("Create", dir, &code, &["405"])?;
+        self.created.lock().unwrap().insert(dir.to_path_buf());
+
+        Ok(())
+    }
+
+    /// Remove `paths` if they exist.
+    pub fn delete(&self, paths: &[PathBuf]) -> io::Result<()> {
+        for x in paths {
+            let (code, _) = self.request(&mut self.command("DELETE", x))?;
+            self.expect("Delete", x, &code, &["404"])?;
+        }
+
+        Ok(())
+    }
+
+    /// Start writing `len` bytes from stdin to `dst`, keeping the
+    /// modification time `mtime` on servers that support it.
+    pub fn put(&self, dst: &Path, len: u64, mtime: Option<u64>) -> io::Result<Child> {
+        let mut cmd = self.command("PUT", dst);
+        // a known length instead of chunked encoding, which not every
+        // server accepts
+        cmd.args(["--fail", "--upload-file", "-"])
+            .args(["-H", "Transfer-Encoding:", "-H"])
+            .arg(format!("Content-Length: {}", len));
+        if let Some(x) = mtime {
+            cmd.arg("-H").arg(format!("X-OC-Mtime: {}", x));
+        }
+
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+
+    /// Move `src` over `dst` on the server.
+    pub fn rename(&self, src: &Path, dst: &Path) -> io::Result<()> {
+        let mut cmd = self.command("MOVE", src);
+        cmd.arg("-H")
+            .arg(format!("Destination: {}", self.url(dst)))
+            .args(["-H", "Overwrite: T"]);
+        let (code, _) = self.request(&mut cmd)?;
+        self.expect("Move", src, &code, &[])
+    }
+}
+
+impl Drop for Dav {
+    fn drop(&mut self) {
+        if let Some(auth) = &self.auth {
+            let _ = std::fs::remove_file(auth);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+
+/// Whether extended attributes can be copied on this platform.
+pub const SUPPORTED: bool = cfg!(any(target_os = "linux", target_os = "macos"));
+
+/// Give `dst` the extended attributes of `src` and drop those it has of
+/// its own. POSIX ACLs are the `system.posix_acl_*` attributes on Linux,
+/// resource forks `com.apple.ResourceFork` on macOS.
+///
+/// Attributes refused by the destination, for lack of privileges or of
+/// support in its filesystem, are skipped and returned.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn copy(src: &Path, dst: &Path) -> io::Result<Vec<OsString>> {
+    let names = imp::list(src)?;
+    let mut refused = vec![];
+    for name in &names {
+        let value = match imp::get(src, name) {
+            Ok(x) => x,
+            // removed since listed
+            Err(e) if e.raw_os_error() == Some(imp::ENOATTR) => continue,
+            Err(e) => return Err(e),
+        };
+        match imp::set(dst, name, &value) {
+            Ok(()) => {}
+            Err(e) if imp::is_refused(&e) => refused.push(name.clone()),
+            Err(e) => return Err(e),
+        }
+    }
+
+    for name in imp::list(dst)? {
+        if names.contains(&name) {
+            continue;
+        }
+        match imp::remove(dst, &name) {
+            Ok(()) => {}
+            Err(e) if imp::is_refused(&e) || e.raw_os_error() == Some(imp::ENOATTR) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(refused)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn copy(_src: &Path, _dst: &Path) -> io::Result<Vec<OsString>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes are not supported on this platform",
+    ))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod imp {
+    use std::ffi::{CString, OsString};
+    use std::io;
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+    use std::path::Path;
+
+    pub use sys::ENOATTR;
+
+    pub fn is_refused(e: &io::Error) -> bool {
+        matches!(
+            e.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::Unsupported
+        )
+    }
+
+    fn c_path(path: &Path) -> io::Result<CString> {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    fn c_name(name: &OsString) -> io::Result<CString> {
+        CString::new(name.as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Names of the attributes of `path`, none where its filesystem has no
+    /// support for them.
+    pub fn list(path: &Path) -> io::Result<Vec<OsString>> {
+        let path = c_path(path)?;
+        let buf = match read(|buf, len| unsafe { sys::list(path.as_ptr(), buf, len) }) {
+            Ok(x) => x,
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+
+        Ok(buf
+            .split(|x| *x == 0)
+            .filter(|x| !x.is_empty())
+            .map(|x| OsString::from_vec(x.to_vec()))
+            .collect())
+    }
+
+    pub fn get(path: &Path, name: &OsString) -> io::Result<Vec<u8>> {
+        let (path, name) = (c_path(path)?, c_name(name)?);
+        read(|buf, len| unsafe { sys::get(path.as_ptr(), name.as_ptr(), buf, len) })
+    }
+
+    pub fn set(path: &Path, name: &OsString, value: &[u8]) -> io::Result<()> {
+        let (path, name) = (c_path(path)?, c_name(name)?);
+        let ret = unsafe { sys::set(path.as_ptr(), name.as_ptr(), value.as_ptr(), value.len()) };
+        match ret {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    pub fn remove(path: &Path, name: &OsString) -> io::Result<()> {
+        let (path, name) = (c_path(path)?, c_name(name)?);
+        match unsafe { sys::remove(path.as_ptr(), name.as_ptr()) } {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Call `f` first for the size, then for the data, again if it grew
+    /// in between.
+    fn read(f: impl Fn(*mut u8, usize) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let len = f(std::ptr::null_mut(), 0);
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut buf = vec![0; len as usize];
+            match f(buf.as_mut_ptr(), buf.len()) {
+                n if n >= 0 => {
+                    buf.truncate(n as usize);
+                    return Ok(buf);
+                }
+                _ => {
+                    let e = io::Error::last_os_error();
+                    if e.raw_os_error() != Some(libc::ERANGE) {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The attribute calls, on symlinks themselves and not their targets.
+    #[cfg(target_os = "linux")]
+    mod sys {
+        use libc::c_char;
+
+        pub const ENOATTR: i32 = libc::ENODATA;
+
+        pub unsafe fn list(path: *const c_char, buf: *mut u8, len: usize) -> isize {
+            libc::llistxattr(path, buf as *mut c_char, len)
+        }
+
+        pub unsafe fn get(
+            path: *const c_char,
+            name: *const c_char,
+            buf: *mut u8,
+            len: usize,
+        ) -> isize {
+            libc::lgetxattr(path, name, buf as *mut _, len)
+        }
+
+        pub unsafe fn set(
+            path: *const c_char,
+            name: *const c_char,
+            buf: *const u8,
+            len: usize,
+        ) -> i32 {
+            libc::lsetxattr(path, name, buf as *const _, len, 0)
+        }
+
+        pub unsafe fn remove(path: *const c_char, name: *const c_char) -> i32 {
+            libc::lremovexattr(path, name)
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    mod sys {
+        use libc::{c_char, XATTR_NOFOLLOW};
+
+        pub const ENOATTR: i32 = libc::ENOATTR;
+
+        pub unsafe fn list(path: *const c_char, buf: *mut u8, len: usize) -> isize {
+            libc::listxattr(path, buf as *mut c_char, len, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn get(
+            path: *const c_char,
+            name: *const c_char,
+            buf: *mut u8,
+            len: usize,
+        ) -> isize {
+            libc::getxattr(path, name, buf as *mut _, len, 0, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn set(
+            path: *const c_char,
+            name: *const c_char,
+            buf: *const u8,
+            len: usize,
+        ) -> i32 {
+            libc::setxattr(path, name, buf as *const _, len, 0, XATTR_NOFOLLOW)
+        }
+
+        pub unsafe fn remove(path: *const c_char, name: *const c_char) -> i32 {
+            libc::removexattr(path, name, XATTR_NOFOLLOW)
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::convert::TryInto;
+
+const PRIME32_1: u64 = 0x9e37_79b1;
+const PRIME32_2: u64 = 0x85eb_ca77;
+const PRIME32_3: u64 = 0xc2b2_ae3d;
+const PRIME64_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME64_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME64_5: u64 = 0x27d4_eb2f_1656_67c5;
+const PRIME_MX1: u64 = 0x1656_6791_9e37_79f9;
+const PRIME_MX2: u64 = 0x9fb2_1c65_1e98_df25;
+
+const SECRET: [u8; 192] = [
+    0xb8, 0xfe, 0x6c, 0x39, 0x23, 0xa4, 0x4b, 0xbe, 0x7c, 0x01, 0x81, 0x2c, 0xf7, 0x21, 0xad, 0x1c,
+    0xde, 0xd4, 0x6d, 0xe9, 0x83, 0x90, 0x97, 0xdb, 0x72, 0x40, 0xa4, 0xa4, 0xb7, 0xb3, 0x67, 0x1f,
+    0xcb, 0x79, 0xe6, 0x4e, 0xcc, 0xc0, 0xe5, 0x78, 0x82, 0x5a, 0xd0, 0x7d, 0xcc, 0xff, 0x72, 0x21,
+    0xb8, 0x08, 0x46, 0x74, 0xf7, 0x43, 0x24, 0x8e, 0xe0, 0x35, 0x90, 0xe6, 0x81, 0x3a, 0x26, 0x4c,
+    0x3c, 0x28, 0x52, 0xbb, 0x91, 0xc3, 0x00, 0xcb, 0x88, 0xd0, 0x65, 0x8b, 0x1b, 0x53, 0x2e, 0xa3,
+    0x71, 0x64, 0x48, 0x97, 0xa2, 0x0d, 0xf9, 0x4e, 0x38, 0x19, 0xef, 0x46, 0xa9, 0xde, 0xac, 0xd8,
+    0xa8, 0xfa, 0x76, 0x3f, 0xe3, 0x9c, 0x34, 0x3f, 0xf9, 0xdc, 0xbb, 0xc7, 0xc7, 0x0b, 0x4f, 0x1d,
+    0x8a, 0x51, 0xe0, 0x4b, 0xcd, 0xb4, 0x59, 0x31, 0xc8, 0x9f, 0x7e, 0xc9, 0xd9, 0x78, 0x73, 0x64,
+    0xea, 0xc5, 0xac, 0x83, 0x34, 0xd3, 0xeb, 0xc3, 0xc5, 0x81, 0xa0, 0xff, 0xfa, 0x13, 0x63, 0xeb,
+    0x17, 0x0d, 0xdd, 0x51, 0xb7, 0xf0, 0xda, 0x49, 0xd3, 0x16, 0x55, 0x26, 0x29, 0xd4, 0x68, 0x9e,
+    0x2b, 0x16, 0xbe, 0x58, 0x7d, 0x47, 0xa1, 0xfc, 0x8f, 0xf8, 0xb8, 0xd1, 0x7a, 0xd0, 0x31, 0xce,
+    0x45, 0xcb, 0x3a, 0x8f, 0x95, 0x16, 0x04, 0x28, 0xaf, 0xd7, 0xfb, 0xca, 0xbb, 0x4b, 0x40, 0x7e,
+];
+
+const STRIPE_LEN: usize = 64;
+/// Stripes between two scrambles of the accumulators.
+const STRIPES: usize = (SECRET.len() - STRIPE_LEN) / 8;
+/// Longest input hashed without the accumulators.
+const MIDSIZE_MAX: usize = 240;
+
+fn read32(x: &[u8], at: usize) -> u64 {
+    u32::from_le_bytes(x[at..at + 4].try_into().unwrap()) as u64
+}
+
+fn read64(x: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(x[at..at + 8].try_into().unwrap())
+}
+
+fn fold64(a: u64, b: u64) -> u64 {
+    let x = a as u128 * b as u128;
+    x as u64 ^ (x >> 64) as u64
+}
+
+fn xxh64_avalanche(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(PRIME64_2);
+    h ^= h >> 29;
+    h = h.wrapping_mul(PRIME64_3);
+    h ^ (h >> 32)
+}
+
+fn avalanche(mut h: u64) -> u64 {
+    h ^= h >> 37;
+    h = h.wrapping_mul(PRIME_MX1);
+    h ^ (h >> 32)
+}
+
+fn rrmxmx(mut h: u64, len: u64) -> u64 {
+    h ^= h.rotate_left(49) ^ h.rotate_left(24);
+    h = h.wrapping_mul(PRIME_MX2);
+    h ^= (h >> 35).wrapping_add(len);
+    h = h.wrapping_mul(PRIME_MX2);
+    h ^ (h >> 28)
+}
+
+fn mix16(x: &[u8], at: usize, secret: usize) -> u64 {
+    fold64(
+        read64(x, at) ^ read64(&SECRET, secret),
+        read64(x, at + 8) ^ read64(&SECRET, secret + 8),
+    )
+}
+
+/// The hash of up to `MIDSIZE_MAX` bytes.
+fn short(x: &[u8]) -> u64 {
+    let len = x.len();
+    let len64 = len as u64;
+    match len {
+        0 => xxh64_avalanche(read64(&SECRET, 56) ^ read64(&SECRET, 64)),
+        1..=3 => {
+            let combined = ((x[0] as u64) << 16)
+                | ((x[len >> 1] as u64) << 24)
+                | x[len - 1] as u64
+                | (len64 << 8);
+            xxh64_avalanche(combined ^ (read32(&SECRET, 0) ^ read32(&SECRET, 4)))
+        }
+        4..=8 => {
+            let input = read32(x, len - 4).wrapping_add(read32(x, 0) << 32);
+            rrmxmx(input ^ (read64(&SECRET, 8) ^ read64(&SECRET, 16)), len64)
+        }
+        9..=16 => {
+            let lo = read64(x, 0) ^ (read64(&SECRET, 24) ^ read64(&SECRET, 32));
+            let hi = read64(x, len - 8) ^ (read64(&SECRET, 40) ^ read64(&SECRET, 48));
+            let acc = len64
+                .wrapping_add(lo.swap_bytes())
+                .wrapping_add(hi)
+                .wrapping_add(fold64(lo, hi));
+            avalanche(acc)
+        }
+        17..=128 => {
+            let mut acc = len64.wrapping_mul(PRIME64_1);
+            let rounds = (len - 1) / 32;
+            for i in (0..=rounds).rev() {
+                acc = acc
+                    .wrapping_add(mix16(x, 16 * i, 32 * i))
+                    .wrapping_add(mix16(x, len - 16 * (i + 1), 32 * i + 16));
+            }
+            avalanche(acc)
+        }
+        _ => {
+            let mut acc = len64.wrapping_mul(PRIME64_1);
+            for i in 0..8 {
+                acc = acc.wrapping_add(mix16(x, 16 * i, 16 * i));
+            }
+            acc = avalanche(acc);
+            for i in 8..len / 16 {
+                acc = acc.wrapping_add(mix16(x, 16 * i, 16 * (i - 8) + 3));
+            }
+            // the minimum secret size, 136, less 17
+            avalanche(acc.wrapping_add(mix16(x, len - 16, 119)))
+        }
+    }
+}
+
+fn accumulate(acc: &mut [u64; 8], stripe: &[u8], secret: usize) {
+    for i in 0..8 {
+        let value = read64(stripe, 8 * i);
+        let key = value ^ read64(&SECRET, secret + 8 * i);
+        acc[i ^ 1] = acc[i ^ 1].wrapping_add(value);
+        acc[i] = acc[i].wrapping_add((key & 0xffff_ffff).wrapping_mul(key >> 32));
+    }
+}
+
+fn scramble(acc: &mut [u64; 8]) {
+    let secret = SECRET.len() - STRIPE_LEN;
+    for (i, x) in acc.iter_mut().enumerate() {
+        *x ^= *x >> 47;
+        *x ^= read64(&SECRET, secret + 8 * i);
+        *x = x.wrapping_mul(PRIME32_1);
+    }
+}
+
+/// XXH3, 64 bit, with the default secret and no seed.
+pub struct Xxh3 {
+    acc: [u64; 8],
+    /// Input not accumulated yet, all of it while it may be short.
+    buf: Vec<u8>,
+    /// The last stripe accumulated, the final stripe may reach into it.
+    last: [u8; STRIPE_LEN],
+    stripes: usize,
+    len: u64,
+}
+
+impl Default for Xxh3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Xxh3 {
+    pub fn new() -> Self {
+        Self {
+            acc: [
+                PRIME32_3, PRIME64_1, PRIME64_2, PRIME64_3, PRIME64_4, PRIME32_2, PRIME64_5,
+                PRIME32_1,
+            ],
+            buf: Vec::with_capacity(MIDSIZE_MAX + STRIPE_LEN),
+            last: [0; STRIPE_LEN],
+            stripes: 0,
+            len: 0,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.len += data.len() as u64;
+        self.buf.extend_from_slice(data);
+        if self.len as usize <= MIDSIZE_MAX {
+            return;
+        }
+
+        // a stripe is accumulated once input follows it, the final one is
+        // done differently
+        let mut done = 0;
+        while self.buf.len() - done > STRIPE_LEN {
+            let stripe = &self.buf[done..done + STRIPE_LEN];
+            accumulate(&mut self.acc, stripe, 8 * self.stripes);
+            self.stripes += 1;
+            if self.stripes == STRIPES {
+                scramble(&mut self.acc);
+                self.stripes = 0;
+            }
+            done += STRIPE_LEN;
+        }
+        if done > 0 {
+            self.last
+                .copy_from_slice(&self.buf[done - STRIPE_LEN..done]);
+            self.buf.drain(..done);
+        }
+    }
+
+    pub fn finish(mut self) -> u64 {
+        if self.len as usize <= MIDSIZE_MAX {
+            return short(&self.buf);
+        }
+
+        let mut stripe = self.last.to_vec();
+        stripe.extend_from_slice(&self.buf);
+        let stripe = &stripe[stripe.len() - STRIPE_LEN..];
+        accumulate(&mut self.acc, stripe, SECRET.len() - STRIPE_LEN - 7);
+
+        let mut h = self.len.wrapping_mul(PRIME64_1);
+        for i in 0..4 {
+            h = h.wrapping_add(fold64(
+                self.acc[2 * i] ^ read64(&SECRET, 11 + 16 * i),
+                self.acc[2 * i + 1] ^ read64(&SECRET, 19 + 16 * i),
+            ));
+        }
+        avalanche(h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The input of the xxHash sanity tests, bytes taken from a
+    /// multiplicative generator.
+    fn sanity_buffer(len: usize) -> Vec<u8> {
+        let mut gen: u64 = 2654435761;
+        (0..len)
+            .map(|_| {
+                let byte = (gen >> 56) as u8;
+                gen = gen.wrapping_mul(11400714785074694797);
+                byte
+            })
+            .collect()
+    }
+
+    #[test]
+    fn known_answers() {
+        // XXH3_64bits with seed 0 from the xxHash sanity tests, one length
+        // for each branch of the short and medium inputs and blocks past
+        // 1024 bytes
+        let cases = [
+            (0, "2d06800538d394c2"),
+            (1, "c44bdff4074eecdb"),
+            (6, "27b56a84cd2d7325"),
+            (12, "a713daf0dfbb77e7"),
+            (24, "a3fe70bf9d3510eb"),
+            (48, "397da259ecba1f11"),
+            (80, "bcdefbbb2c47c90a"),
+            (195, "cd94217ee362ec3a"),
+            (403, "cdeb804d65c6dea4"),
+            (512, "617e49599013cb6b"),
+            (2048, "dd59e2c3a5f038e0"),
+            (2240, "6e73a90539cf2948"),
+            (2367, "cb37aeb9e5d361ed"),
+        ];
+        for (len, expected) in cases {
+            let mut h = Xxh3::new();
+            h.update(&sanity_buffer(len));
+            assert_eq!(format!("{:016x}", h.finish()), expected, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn updates_split_anywhere() {
+        let data: Vec<u8> = (0..5000).map(|i| (i % 251) as u8).collect();
+        let mut whole = Xxh3::new();
+        whole.update(&data);
+        let whole = whole.finish();
+        for split in [1, 16, 64, 240, 1024, 4096] {
+            let mut parts = Xxh3::new();
+            for part in data.chunks(split) {
+                parts.update(part);
+            }
+            assert_eq!(parts.finish(), whole, "updates of {} bytes", split);
+        }
+    }
+}